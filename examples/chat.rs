@@ -0,0 +1,38 @@
+//! A chat server: every line a client sends goes to every other client.
+//!
+//! Listens on the address given as the first argument, or 127.0.0.1:7879;
+//! try it with a few `nc 127.0.0.1 7879`. Ctrl-c stops it, once every
+//! client got what was sent before.
+
+#![cfg(unix)]
+
+use std::env;
+use std::time::Duration;
+
+use playground_future_2_0::examples_support::chat::Room;
+use playground_future_2_0::net::serve;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::signal::ctrl_c;
+use playground_future_2_0::tcp::AsyncTcpListener;
+
+fn main() -> std::io::Result<()> {
+    let addr = env::args().nth(1).unwrap_or("127.0.0.1:7879".into());
+    let mut poller = Poller::open()?;
+    let listener = AsyncTcpListener::bind(&*addr)?;
+    println!("listening on {}", listener.local_addr()?);
+
+    // drop clients which went ten minutes without saying anything
+    let room = Room::new().idle_timeout(Duration::from_secs(10 * 60));
+    poller.spawn(room.shutdown_after(ctrl_c()));
+
+    let server = serve(listener, {
+        let room = room.clone();
+        move |stream| room.connection(stream)
+    })
+    .shutdown_on(room.shutdown_event());
+    poller.block_on(server)??;
+
+    // the connections which were open wind down on their own
+    poller.run()?;
+    Ok(())
+}
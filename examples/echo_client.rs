@@ -0,0 +1,45 @@
+//! Sends a message to the `echo_server` example, and prints what comes
+//! back.
+//!
+//! Takes the message as the first argument and the server's address as the
+//! second, defaulting to "hello, world!" and 127.0.0.1:7878.
+
+#![cfg(unix)]
+
+use std::env;
+use std::net::ToSocketAddrs;
+
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+
+fn main() -> std::io::Result<()> {
+    let mut args = env::args().skip(1);
+    let message = args.next().unwrap_or("hello, world!".into());
+    let addr = args.next().unwrap_or("127.0.0.1:7878".into());
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .expect("no address to connect to");
+
+    let mut poller = Poller::open()?;
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+
+    let mut written = 0;
+    while written < message.len() {
+        written += poller.block_on(client.write(&message.as_bytes()[written..]))??;
+    }
+    // the server echoes in pieces as large as it reads, so keep reading
+    // until all of it came back
+    let mut echoed = vec![0; message.len()];
+    let mut read = 0;
+    while read < echoed.len() {
+        match poller.block_on(client.read(&mut echoed[read..]))?? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    println!("{}", String::from_utf8_lossy(&echoed[..read]));
+
+    poller.block_on(client.disconnect())??;
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! An echo server: everything a client sends comes back to it.
+//!
+//! Listens on the address given as the first argument, or 127.0.0.1:7878;
+//! try it with the `echo_client` example.
+
+#![cfg(unix)]
+
+use std::env;
+use std::time::Duration;
+
+use playground_future_2_0::net::{echo, serve};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpListener;
+
+fn main() -> std::io::Result<()> {
+    let addr = env::args().nth(1).unwrap_or("127.0.0.1:7878".into());
+    let mut poller = Poller::open()?;
+    let listener = AsyncTcpListener::bind(&*addr)?;
+    println!("listening on {}", listener.local_addr()?);
+
+    // drop clients which went a minute without sending anything, and keep
+    // at most 1024 around at once
+    let server = serve(listener, echo)
+        .max_connections(1024)
+        .idle_timeout(Duration::from_secs(60));
+    poller.block_on(server)??;
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! The `main` and `test` attributes of `playground-future-2-0`, which
+//! re-exports them with its `macros` feature.
+//!
+//! The crate's futures aren't `async fn`s, so the annotated function isn't
+//! either: it returns the future to run, as `impl IntoFuture<Output = T>` or
+//! any type implementing `IntoFuture`. The attribute turns it into a function
+//! returning `T`, which runs the future on a poller.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use syn::parse::Parser;
+use syn::{
+    GenericArgument, ItemFn, LitInt, PathArguments, ReturnType, Signature, Type, TypeImplTrait,
+    TypeParamBound,
+};
+
+/// Run `fn main` on a poller, returning the output of the future it
+/// returns, which can be an `io::Result` like `main` returns usually.
+///
+/// ```ignore
+/// #[playground_future_2_0::main]
+/// fn main() -> impl IntoFuture<Output = Result<(), Elapsed>> {
+///     timeout(Duration::from_secs(1), sleep(Duration::from_millis(10)))
+/// }
+/// ```
+///
+/// The future runs through `runtime::block_on`, so it can spawn tasks. If
+/// the poller fails, `main` panics.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand_or_fail(args.into(), item.into(), Kind::Main).into()
+}
+
+/// Run a test on a poller of its own, returning the output of the future it
+/// returns, so tests can return `io::Result` as usual.
+///
+/// ```ignore
+/// #[playground_future_2_0::test]
+/// fn sleeps() -> impl IntoFuture<Output = ()> {
+///     sleep(Duration::from_millis(10))
+/// }
+/// ```
+///
+/// A test which hangs fails after 60 seconds rather than stalling the run,
+/// or after as many as `#[test(timeout_secs = N)]` says. If the poller
+/// fails, the test panics.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand_or_fail(args.into(), item.into(), Kind::Test).into()
+}
+
+/// Which of the attributes is expanded.
+#[derive(Clone, Copy)]
+enum Kind {
+    Main,
+    Test,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Main => "main",
+            Kind::Test => "test",
+        }
+    }
+}
+
+/// How long a test may take by default, in seconds.
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Expand the attribute, or fail with the function left in place, so the
+/// error is the only one the user sees.
+fn expand_or_fail(args: TokenStream2, item: TokenStream2, kind: Kind) -> TokenStream2 {
+    match expand(args, item.clone(), kind) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            let error = e.into_compile_error();
+            let item = match kind {
+                // Whatever `main` returns would fail to compile too.
+                Kind::Main => match syn::parse2::<ItemFn>(item) {
+                    Ok(item) => {
+                        let ident = item.sig.ident;
+                        quote!(fn #ident() {})
+                    }
+                    Err(_) => TokenStream2::new(),
+                },
+                Kind::Test => quote! {
+                    #[allow(dead_code)]
+                    #item
+                },
+            };
+            quote!(#error #item)
+        }
+    }
+}
+
+fn expand(args: TokenStream2, item: TokenStream2, kind: Kind) -> syn::Result<TokenStream2> {
+    let timeout_secs = parse_args(args, kind)?;
+    let item: ItemFn = syn::parse2(item)?;
+    let name = kind.name();
+    let sig = &item.sig;
+    if let Some(asyncness) = sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            format!(
+                "`#[{name}]` doesn't take an `async fn`: the crate's futures aren't `async`, \
+                 so return `impl IntoFuture<Output = T>` instead"
+            ),
+        ));
+    }
+    if !sig.generics.params.is_empty() || sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            format!("`#[{name}]` functions can't be generic"),
+        ));
+    }
+    if !sig.inputs.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.inputs,
+            format!("`#[{name}]` functions can't take arguments"),
+        ));
+    }
+    let output = output_type(sig, name)?;
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = &item;
+    let ident = &sig.ident;
+    // The function as written goes inside, where calling it by name finds it
+    // rather than the function wrapping it. Its attributes stay outside,
+    // where `#[should_panic]` and the like mean something.
+    let inner = quote!(#sig #block);
+    let body = match kind {
+        Kind::Main => quote! {
+            match ::playground_future_2_0::runtime::block_on(#ident()) {
+                ::core::result::Result::Ok(output) => output,
+                ::core::result::Result::Err(e) => ::core::panic!("the runtime failed: {}", e),
+            }
+        },
+        Kind::Test => quote! {
+            let mut poller = match ::playground_future_2_0::runtime::Poller::open() {
+                ::core::result::Result::Ok(poller) => poller,
+                ::core::result::Result::Err(e) => ::core::panic!("couldn't open a poller: {}", e),
+            };
+            let timeout = ::std::time::Duration::from_secs(#timeout_secs);
+            match poller.block_on_timeout(#ident(), timeout) {
+                ::core::result::Result::Ok(::core::result::Result::Ok(output)) => output,
+                ::core::result::Result::Ok(::core::result::Result::Err(_)) => {
+                    ::core::panic!("the test timed out after {}s", #timeout_secs)
+                }
+                ::core::result::Result::Err(e) => ::core::panic!("the runtime failed: {}", e),
+            }
+        },
+    };
+    let test_attr = match kind {
+        Kind::Main => None,
+        Kind::Test => Some(quote!(#[::core::prelude::v1::test])),
+    };
+    Ok(quote! {
+        #test_attr
+        #(#attrs)*
+        #vis fn #ident() -> #output {
+            #inner
+            #body
+        }
+    })
+}
+
+/// `main` takes no arguments, and `test` takes an optional `timeout_secs`.
+fn parse_args(args: TokenStream2, kind: Kind) -> syn::Result<LitInt> {
+    let mut timeout_secs = LitInt::new(&DEFAULT_TIMEOUT_SECS.to_string(), Span::call_site());
+    let parser = syn::meta::parser(|meta| match kind {
+        Kind::Test if meta.path.is_ident("timeout_secs") => {
+            let secs: LitInt = meta.value()?.parse()?;
+            secs.base10_parse::<u64>()?;
+            timeout_secs = secs;
+            Ok(())
+        }
+        Kind::Test => Err(meta.error("unknown argument, expected `timeout_secs = N`")),
+        Kind::Main => Err(meta.error("`#[main]` takes no arguments")),
+    });
+    parser.parse2(args)?;
+    Ok(timeout_secs)
+}
+
+/// The output of the future the function returns, which the function it's
+/// turned into returns instead.
+fn output_type(sig: &Signature, name: &str) -> syn::Result<TokenStream2> {
+    let ty = match &sig.output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => {
+            return Err(syn::Error::new_spanned(
+                &sig.ident,
+                format!(
+                    "`#[{name}]` functions return the future to run, \
+                     as `impl IntoFuture<Output = T>`"
+                ),
+            ))
+        }
+    };
+    match &**ty {
+        Type::ImplTrait(bounds) => match impl_output(bounds) {
+            Some(output) => Ok(output.to_token_stream()),
+            None => Err(syn::Error::new_spanned(
+                ty,
+                "can't tell what the future outputs: spell it out, \
+                 as in `impl IntoFuture<Output = T>`",
+            )),
+        },
+        ty => Ok(quote!(<#ty as ::playground_future_2_0::future::IntoFuture>::Output)),
+    }
+}
+
+/// The `Output = T` of an `impl Trait`, if one of its bounds has it.
+fn impl_output(bounds: &TypeImplTrait) -> Option<&Type> {
+    bounds.bounds.iter().find_map(|bound| {
+        let TypeParamBound::Trait(bound) = bound else {
+            return None;
+        };
+        let PathArguments::AngleBracketed(args) = &bound.path.segments.last()?.arguments else {
+            return None;
+        };
+        args.args.iter().find_map(|arg| match arg {
+            GenericArgument::AssocType(assoc) if assoc.ident == "Output" => Some(&assoc.ty),
+            _ => None,
+        })
+    })
+}
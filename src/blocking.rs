@@ -0,0 +1,206 @@
+//! Running blocking work off the reactor.
+//!
+//! Jobs run on a small pool of threads which are spawned on demand and exit
+//! again after being idle for a while. A finished job pushes its result onto
+//! a [`CompletionQueue`] of its own, which wakes up the poller its
+//! [`BlockingTask`] waits on.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::future::{Future, Waitable};
+use crate::runtime::queue::Pop;
+use crate::runtime::CompletionQueue;
+
+/// How many threads the shared pool runs at most.
+const MAX_THREADS: usize = 8;
+
+/// How long a thread waits for a new job before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A pool of threads for blocking work.
+#[derive(Debug)]
+pub struct Pool {
+    shared: Arc<Shared>,
+    max_threads: usize,
+    /// How many jobs were ever spawned on the pool.
+    spawned: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    /// How many threads are running, busy or not.
+    threads: usize,
+    /// How many threads are waiting for a job.
+    idle: usize,
+    shutdown: bool,
+}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("jobs", &self.jobs.len())
+            .field("threads", &self.threads)
+            .field("idle", &self.idle)
+            .field("shutdown", &self.shutdown)
+            .finish()
+    }
+}
+
+impl Pool {
+    /// Create a pool running at most `max_threads` threads. No threads are
+    /// spawned until there's work for them.
+    pub fn new(max_threads: usize) -> Self {
+        assert!(max_threads > 0, "a pool needs at least one thread");
+        Self {
+            shared: Arc::default(),
+            max_threads,
+            spawned: AtomicU64::new(0),
+        }
+    }
+
+    /// The pool shared by the whole process.
+    pub fn shared() -> &'static Pool {
+        static POOL: OnceLock<Pool> = OnceLock::new();
+        POOL.get_or_init(|| Pool::new(MAX_THREADS))
+    }
+
+    /// Run `f` on the pool, resolving with what it returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool was shut down.
+    pub fn spawn<F, T>(&self, f: F) -> BlockingTask<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+        let queue = CompletionQueue::new();
+        let handle = queue.handle();
+        self.push(Box::new(move || {
+            // A panic is handed over to the task, which resumes it.
+            handle.push(panic::catch_unwind(AssertUnwindSafe(f)));
+        }));
+        BlockingTask {
+            queue,
+            output: None,
+        }
+    }
+
+    /// How many jobs were ever spawned on the pool.
+    pub fn spawned(&self) -> u64 {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
+    /// How many jobs are waiting for a thread.
+    pub fn queued(&self) -> usize {
+        self.shared.lock().jobs.len()
+    }
+
+    fn push(&self, job: Job) {
+        let mut state = self.shared.lock();
+        assert!(!state.shutdown, "the blocking pool was shut down");
+        state.jobs.push_back(job);
+        if state.idle > 0 {
+            self.shared.condvar.notify_one();
+        } else if state.threads < self.max_threads {
+            state.threads += 1;
+            let shared = self.shared.clone();
+            thread::Builder::new()
+                .name("blocking".into())
+                .spawn(move || shared.run())
+                .expect("failed to spawn a blocking pool thread");
+        }
+    }
+
+    /// Stop accepting jobs. The jobs which were already queued still run,
+    /// after which the threads exit.
+    pub fn shutdown(&self) {
+        self.shared.lock().shutdown = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The loop each pool thread runs, until it's been idle for too long or
+    /// the pool shuts down.
+    fn run(&self) {
+        let mut state = self.lock();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.lock();
+                continue;
+            }
+            if state.shutdown {
+                break;
+            }
+            state.idle += 1;
+            let (next, timeout) = self
+                .condvar
+                .wait_timeout(state, KEEP_ALIVE)
+                .unwrap_or_else(|e| e.into_inner());
+            state = next;
+            state.idle -= 1;
+            if timeout.timed_out() && state.jobs.is_empty() {
+                break;
+            }
+        }
+        state.threads -= 1;
+    }
+}
+
+/// Future for a job running on a [`Pool`].
+///
+/// If the job panicked, taking the output resumes the panic. Dropping the
+/// task doesn't stop the job, but its result is discarded.
+#[derive(Debug)]
+pub struct BlockingTask<T> {
+    /// Where the job pushes its result.
+    queue: CompletionQueue<thread::Result<T>>,
+    output: Option<thread::Result<T>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.queue.pop() {
+                Pop::Value(output) => self.output = Some(output),
+                // The job was dropped without running, so there's nothing
+                // to wait for, nor any output.
+                Pop::Closed => {}
+                Pop::Empty(waitable) => pending = Some(waitable),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.output.take()? {
+            Ok(output) => Some(output),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Turning byte streams into frames and back.
+//!
+//! A [`Decoder`] cuts frames out of the bytes read so far, an [`Encoder`]
+//! serializes frames into bytes to be written, and [`Framed`] drives both on
+//! top of a transport.
+
+use std::io;
+
+use crate::future::{Future, Waitable};
+use crate::io::{AsyncRead, AsyncWrite, Step};
+use crate::stream::Stream;
+
+mod length_delimited;
+mod lines;
+
+pub use length_delimited::LengthDelimitedCodec;
+pub use lines::{LineTooLong, LinesCodec};
+
+/// How much spare room to make in the read buffer before every read.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Cut frames out of a buffer of bytes.
+pub trait Decoder {
+    type Item;
+
+    /// Remove the next frame from the start of `src`, or return `None` if
+    /// `src` doesn't hold a whole frame yet.
+    ///
+    /// `src` holds everything read since the last frame. Implementations may
+    /// reserve space in it as a hint of how many bytes they're expecting.
+    fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Self::Item>>;
+
+    /// Like [`Decoder::decode`], but called once the transport reached EOF
+    /// and no more bytes will arrive.
+    ///
+    /// By default a partial frame left in `src` is an `UnexpectedEof` error.
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Self::Item>> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "bytes remaining on stream",
+            )),
+        }
+    }
+}
+
+/// Serialize frames into a buffer of bytes.
+pub trait Encoder<Item> {
+    /// Append the encoded `item` to `dst`.
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// A transport together with a codec, reading and writing whole frames.
+///
+/// Reading happens through the [`Stream`] impl, which ends once the
+/// transport reaches EOF or after the first error. Writing happens through
+/// [`Framed::send`].
+pub struct Framed<T, U: Decoder> {
+    io: T,
+    codec: U,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    item: Option<io::Result<<U as Decoder>::Item>>,
+    eof: bool,
+    done: bool,
+}
+
+impl<T, U: Decoder> Framed<T, U> {
+    pub fn new(io: T, codec: U) -> Self {
+        Self {
+            io,
+            codec,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            item: None,
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Reading from or writing to the transport directly bypasses the
+    /// buffered data.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    pub fn codec(&self) -> &U {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut U {
+        &mut self.codec
+    }
+
+    /// Unwrap the transport. Buffered data is lost.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Encode `item` and write it to the transport.
+    ///
+    /// The item is encoded right away. If the future is dropped before it
+    /// completes, the remaining bytes are written by the next `send`.
+    pub fn send<I>(&mut self, item: I) -> SendFuture<'_, T, U>
+    where
+        T: AsyncWrite,
+        U: Encoder<I>,
+    {
+        let output = match self.codec.encode(item, &mut self.write_buf) {
+            Ok(()) => None,
+            Err(e) => Some(Err(e)),
+        };
+        SendFuture {
+            framed: self,
+            output,
+        }
+    }
+}
+
+impl<T: AsyncWrite, U: Decoder> Framed<T, U> {
+    /// Write the encoded frames to the transport until none are left.
+    fn poll_flush_write(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<T, U>, ()> {
+        while !self.write_buf.is_empty() {
+            match self.io.poll_write(ready, &self.write_buf) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(0) => {
+                    let e = io::Error::new(io::ErrorKind::WriteZero, "failed to write frame");
+                    return Step::Error(e);
+                }
+                Step::Done(n) => {
+                    self.write_buf.drain(..n);
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+        Step::Done(())
+    }
+}
+
+impl<T: AsyncRead, U: Decoder> Stream for Framed<T, U> {
+    type Item = io::Result<U::Item>;
+
+    fn poll_next(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<T, U> {
+        let mut pending = None;
+        while self.item.is_none() && !self.done {
+            if self.eof {
+                match self.codec.decode_eof(&mut self.read_buf) {
+                    Ok(Some(frame)) => self.item = Some(Ok(frame)),
+                    Ok(None) => self.done = true,
+                    Err(e) => {
+                        self.item = Some(Err(e));
+                        self.done = true;
+                    }
+                }
+                break;
+            }
+
+            match self.codec.decode(&mut self.read_buf) {
+                Ok(Some(frame)) => {
+                    self.item = Some(Ok(frame));
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.item = Some(Err(e));
+                    self.done = true;
+                    break;
+                }
+            }
+
+            // Not a whole frame yet, so read into the spare capacity.
+            self.read_buf.reserve(READ_CHUNK);
+            let len = self.read_buf.len();
+            self.read_buf.resize(self.read_buf.capacity(), 0);
+            let step = self.io.poll_read(ready, &mut self.read_buf[len..]);
+            let read = match step {
+                Step::Done(n) => n,
+                _ => 0,
+            };
+            self.read_buf.truncate(len + read);
+            match step {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(0) => self.eof = true,
+                Step::Done(_) => {}
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => {
+                    self.item = Some(Err(e));
+                    self.done = true;
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
+
+/// Future for [`Framed::send`].
+pub struct SendFuture<'a, T, U: Decoder> {
+    framed: &'a mut Framed<T, U>,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, T: AsyncWrite, U: Decoder> Future for SendFuture<'a, T, U> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.framed.poll_flush_write(ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => self.output = Some(Ok(())),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,134 @@
+use std::io;
+
+use super::{Decoder, Encoder};
+
+/// Frames prefixed with their length.
+///
+/// By default the length is a 4-byte big-endian integer, and frames may be at
+/// most 8MiB. The length only counts the payload, not the prefix itself.
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec {
+    length_field_length: usize,
+    big_endian: bool,
+    max_frame_length: usize,
+}
+
+impl LengthDelimitedCodec {
+    pub fn new() -> Self {
+        Self {
+            length_field_length: 4,
+            big_endian: true,
+            max_frame_length: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Set the size of the length prefix in bytes. Defaults to `4`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `length` isn't between 1 and 8.
+    pub fn length_field_length(mut self, length: usize) -> Self {
+        assert!(
+            (1..=8).contains(&length),
+            "length field must be between 1 and 8 bytes"
+        );
+        self.length_field_length = length;
+        self
+    }
+
+    /// Read and write the length prefix as big-endian. This is the default.
+    pub fn big_endian(mut self) -> Self {
+        self.big_endian = true;
+        self
+    }
+
+    /// Read and write the length prefix as little-endian.
+    pub fn little_endian(mut self) -> Self {
+        self.big_endian = false;
+        self
+    }
+
+    /// Set the largest frame which may be decoded or encoded. Defaults to
+    /// 8MiB.
+    ///
+    /// Decoding a larger frame fails with `InvalidData` before any of it is
+    /// buffered, so a peer can't make us allocate arbitrary amounts of memory.
+    pub fn max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+
+    /// The largest length which fits in the length prefix.
+    fn max_encodable(&self) -> u64 {
+        u64::MAX >> (64 - 8 * self.length_field_length)
+    }
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for LengthDelimitedCodec {
+    type Item = Vec<u8>;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Self::Item>> {
+        let n = self.length_field_length;
+        let Some(field) = src.get(..n) else {
+            return Ok(None);
+        };
+        let mut bytes = [0; 8];
+        let len = if self.big_endian {
+            bytes[8 - n..].copy_from_slice(field);
+            u64::from_be_bytes(bytes)
+        } else {
+            bytes[..n].copy_from_slice(field);
+            u64::from_le_bytes(bytes)
+        };
+        if len > self.max_frame_length as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame of {len} bytes exceeds the maximum of {}",
+                    self.max_frame_length
+                ),
+            ));
+        }
+
+        let end = n + len as usize;
+        if src.len() < end {
+            src.reserve(end - src.len());
+            return Ok(None);
+        }
+        let frame = src[n..end].to_vec();
+        src.drain(..end);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<Vec<u8>> for LengthDelimitedCodec {
+    fn encode(&mut self, item: Vec<u8>, dst: &mut Vec<u8>) -> io::Result<()> {
+        self.encode(&item[..], dst)
+    }
+}
+
+impl Encoder<&[u8]> for LengthDelimitedCodec {
+    fn encode(&mut self, item: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        let len = item.len() as u64;
+        if item.len() > self.max_frame_length || len > self.max_encodable() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame of {len} bytes is too large to encode"),
+            ));
+        }
+        let n = self.length_field_length;
+        if self.big_endian {
+            dst.extend_from_slice(&len.to_be_bytes()[8 - n..]);
+        } else {
+            dst.extend_from_slice(&len.to_le_bytes()[..n]);
+        }
+        dst.extend_from_slice(item);
+        Ok(())
+    }
+}
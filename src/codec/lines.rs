@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::{Decoder, Encoder};
+
+/// Lines of UTF-8 text, terminated by `\n` or `\r\n`.
+///
+/// Decoded lines have their terminator stripped, and encoded lines have `\n`
+/// appended. If the transport reaches EOF in the middle of a line, that line
+/// is still decoded.
+#[derive(Debug, Clone)]
+pub struct LinesCodec {
+    max_length: usize,
+    /// How far into the buffer we've already looked for a newline.
+    next_index: usize,
+}
+
+impl LinesCodec {
+    pub fn new() -> Self {
+        Self {
+            max_length: 64 * 1024,
+            next_index: 0,
+        }
+    }
+
+    /// Set the longest line which may be decoded, not counting the
+    /// terminator. Defaults to 64KiB.
+    ///
+    /// Once more bytes than that arrive without a newline, decoding fails
+    /// with an `InvalidData` error wrapping [`LineTooLong`], so a peer which
+    /// never sends a newline can't make us buffer arbitrary amounts of data.
+    pub fn max_length(mut self, max: usize) -> Self {
+        self.max_length = max;
+        self
+    }
+
+    fn line_too_long(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, LineTooLong(self.max_length))
+    }
+}
+
+impl Default for LinesCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for LinesCodec {
+    type Item = String;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Self::Item>> {
+        let newline = src[self.next_index..].iter().position(|b| *b == b'\n');
+        let Some(i) = newline.map(|i| self.next_index + i) else {
+            self.next_index = src.len();
+            if src.len() > self.max_length {
+                return Err(self.line_too_long());
+            }
+            return Ok(None);
+        };
+        self.next_index = 0;
+
+        let mut line: Vec<u8> = src.drain(..=i).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if line.len() > self.max_length {
+            return Err(self.line_too_long());
+        }
+        let line =
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(line))
+    }
+
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Self::Item>> {
+        if let Some(line) = self.decode(src)? {
+            return Ok(Some(line));
+        }
+        if src.is_empty() {
+            return Ok(None);
+        }
+        self.next_index = 0;
+        let line = std::mem::take(src);
+        let line =
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(line))
+    }
+}
+
+impl Encoder<String> for LinesCodec {
+    fn encode(&mut self, item: String, dst: &mut Vec<u8>) -> io::Result<()> {
+        self.encode(&item[..], dst)
+    }
+}
+
+impl Encoder<&str> for LinesCodec {
+    fn encode(&mut self, item: &str, dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.reserve(item.len() + 1);
+        dst.extend_from_slice(item.as_bytes());
+        dst.push(b'\n');
+        Ok(())
+    }
+}
+
+/// A line exceeded [`LinesCodec::max_length`], which it holds.
+#[derive(Debug)]
+pub struct LineTooLong(pub usize);
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line exceeds the maximum length of {} bytes", self.0)
+    }
+}
+
+impl Error for LineTooLong {}
@@ -0,0 +1,301 @@
+//! Running the crate's futures on other executors.
+//!
+//! [`CompatFuture`] implements [`std::future::Future`] for any of the
+//! crate's futures, so they can be awaited from tokio, async-std, or
+//! `futures::executor::block_on`. It brings a [`Poller`] of its own, and a
+//! driver thread which waits for the poller to have events and wakes the
+//! executor's task up then.
+//!
+//! [`StdCompat`] goes the other way, implementing the crate's [`Future`]
+//! for any [`std::future::Future`], so `async fn`s can run on a [`Poller`].
+//!
+//! With the `futures-io` feature, `FuturesIoCompat` does the same for the
+//! IO traits, in both directions.
+
+use rustix::event::{PollFd, PollFlags};
+use std::fmt;
+use std::os::fd::OwnedFd;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Duration;
+
+use crate::future::{Future, IntoFuture, Waitable};
+use crate::runtime::{completion, Notify, Poller, RuntimeError, Stepped};
+
+#[cfg(feature = "futures-io")]
+mod io;
+#[cfg(feature = "futures-io")]
+pub use io::FuturesIoCompat;
+
+/// Wrap `future` so other executors can run it; see [`CompatFuture`].
+pub fn compat<Fut: IntoFuture>(future: Fut) -> CompatFuture<Fut::IntoFuture> {
+    CompatFuture {
+        future: Some(Stepped::new(future.into_future())),
+        reactor: Reactor::default(),
+    }
+}
+
+/// A [`std::future::Future`] running one of the crate's futures, created
+/// with [`compat`].
+///
+/// Every poll takes a turn of the future's own poller, without waiting. When
+/// the future is pending, the driver thread waits for the poller to have
+/// events, or for the future's next timer, and then wakes the task up. The
+/// poller is opened on the first poll, and resolves with
+/// [`RuntimeError::Io`] if it can't be. Dropping the future deregisters
+/// whatever it was waiting on, and stops the driver thread.
+pub struct CompatFuture<F: Future> {
+    /// `None` once it finished.
+    future: Option<Stepped<F>>,
+    reactor: Reactor,
+}
+
+impl<F: Future> fmt::Debug for CompatFuture<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompatFuture")
+            .field("finished", &self.future.is_none())
+            .finish()
+    }
+}
+
+// Nothing is pinned: the crate's futures are polled through `&mut`.
+impl<F: Future> Unpin for CompatFuture<F> {}
+
+impl<F: Future> std::future::Future for CompatFuture<F> {
+    type Output = Result<F::Output, RuntimeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Some(future) = &mut this.future else {
+            panic!("`CompatFuture` polled after completion");
+        };
+        let polled = this.reactor.poll(future, cx);
+        if polled.is_ready() {
+            this.future = None;
+        }
+        polled
+    }
+}
+
+impl<F: Future> Drop for CompatFuture<F> {
+    fn drop(&mut self) {
+        if let Some(future) = self.future.take() {
+            self.reactor.close(future.into_waiting_on());
+        }
+    }
+}
+
+/// A poller of its own, and the driver thread waking up whoever waits on it.
+/// Both are started on the first poll.
+#[derive(Default)]
+struct Reactor {
+    // Dropped first, so the driver doesn't outlive the poller.
+    driver: Option<Driver>,
+    poller: Option<Poller>,
+}
+
+impl Reactor {
+    /// Step `future`, arranging for `cx` to be woken up once it's worth
+    /// stepping again if it's still pending.
+    fn poll<F: Future>(
+        &mut self,
+        future: &mut Stepped<F>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<F::Output, RuntimeError>> {
+        let poller = match &mut self.poller {
+            Some(poller) => poller,
+            None => self.poller.insert(Poller::open()?),
+        };
+        if let Some(output) = poller.step(future)? {
+            return Poll::Ready(Ok(output));
+        }
+        match poller.step_timeout(future) {
+            Some(Duration::ZERO) => cx.waker().wake_by_ref(),
+            timeout => {
+                let driver = match &mut self.driver {
+                    Some(driver) => driver,
+                    None => self.driver.insert(Driver::start(poller)?),
+                };
+                driver.arm(cx.waker().clone(), timeout);
+            }
+        }
+        Poll::Pending
+    }
+
+    /// Stop the driver, and deregister what a future which won't be stepped
+    /// again waits on.
+    fn close(&mut self, waiting_on: Vec<Waitable>) {
+        // Stop the driver first, so it doesn't wait on a closed queue.
+        self.driver.take();
+        if let Some(poller) = &mut self.poller {
+            let _ = poller.cancel_waiting(waiting_on);
+        }
+    }
+}
+
+/// The thread which wakes the task up once the poller has events.
+struct Driver {
+    shared: Arc<Shared>,
+    notify: Notify,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Who to wake up, and how long to wait for events before doing so
+    /// anyway. Set when there's something to wait for.
+    armed: Option<(Waker, Option<Duration>)>,
+    closed: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Driver {
+    fn start(poller: &Poller) -> std::io::Result<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            condvar: Condvar::new(),
+        });
+        let queue = poller.queue();
+        let driven = shared.clone();
+        let thread = thread::Builder::new()
+            .name("compat".into())
+            .spawn(move || drive(&driven, &queue))?;
+        Ok(Self {
+            shared,
+            notify: poller.notifier(),
+            thread: Some(thread),
+        })
+    }
+
+    fn arm(&self, waker: Waker, timeout: Option<Duration>) {
+        self.shared.lock().armed = Some((waker, timeout));
+        self.shared.condvar.notify_one();
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.condvar.notify_one();
+        // Cut a wait on the queue short.
+        let _ = self.notify.notify();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn drive(shared: &Shared, queue: &OwnedFd) {
+    let mut state = shared.lock();
+    loop {
+        if state.closed {
+            return;
+        }
+        let Some((waker, timeout)) = state.armed.take() else {
+            state = shared
+                .condvar
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+            continue;
+        };
+        drop(state);
+        let timeout = timeout.map_or(-1, |timeout| {
+            let millis = timeout.as_nanos().div_ceil(1_000_000);
+            i32::try_from(millis).unwrap_or(i32::MAX)
+        });
+        // Whether the queue became readable or the time ran out, the task
+        // needs polling. Errors would only repeat, so they wake it up too.
+        let _ = rustix::event::poll(&mut [PollFd::new(queue, PollFlags::IN)], timeout);
+        waker.wake();
+        state = shared.lock();
+    }
+}
+
+/// One of the crate's futures running a [`std::future::Future`].
+///
+/// The future's waker completes a completion which the poller waits on, so
+/// the future is polled again once it's woken up, from any thread. Besides
+/// that completion, it doesn't wait on anything: whatever it does IO with
+/// has to wake it up on its own.
+pub struct StdCompat<F: std::future::Future> {
+    future: Pin<Box<F>>,
+    waker: Waker,
+    /// The completion the waker completes.
+    id: u64,
+    output: Option<F::Output>,
+}
+
+impl<F: std::future::Future> StdCompat<F> {
+    pub fn new(future: F) -> Self {
+        let id = completion::register();
+        Self {
+            future: Box::pin(future),
+            waker: Waker::from(Arc::new(Wakeup(id))),
+            id,
+            output: None,
+        }
+    }
+}
+
+impl<F: std::future::Future> fmt::Debug for StdCompat<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StdCompat")
+            .field("id", &self.id)
+            .field("finished", &self.output.is_some())
+            .finish()
+    }
+}
+
+impl<F: std::future::Future> Future for StdCompat<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            // Reset before polling, so a wakeup during the poll isn't lost.
+            completion::reset(self.id);
+            let mut cx = Context::from_waker(&self.waker);
+            match self.future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => self.output = Some(output),
+                Poll::Pending => pending = Some(Waitable::Completion(self.id)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+impl<F: std::future::Future> Drop for StdCompat<F> {
+    fn drop(&mut self) {
+        completion::remove(self.id);
+    }
+}
+
+/// The waker of a [`StdCompat`], completing its completion. Once the future
+/// is gone, waking it does nothing.
+struct Wakeup(u64);
+
+impl Wake for Wakeup {
+    fn wake(self: Arc<Self>) {
+        completion::complete(self.0);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        completion::complete(self.0);
+    }
+}
@@ -0,0 +1,273 @@
+//! Adapters between the crate's IO traits and those of `futures-io`.
+
+use std::fmt;
+use std::io;
+use std::iter;
+use std::mem;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use super::{Reactor, Wakeup};
+use crate::future::{Future, Waitable};
+use crate::io::{AsyncRead, AsyncWrite, ReadStep, Step, WriteStep};
+use crate::runtime::{completion, Stepped};
+
+/// An IO object implementing the traits of `futures-io` on top of the
+/// crate's, or the crate's on top of those of `futures-io`, whichever `T`
+/// implements.
+///
+/// In the first direction every read and write is an attempt taking a turn
+/// of a poller, which a driver thread waits on for the task to be woken up,
+/// like [`CompatFuture`](super::CompatFuture) does. Reads and writes get a
+/// poller and a driver each, and flushing shares the one of writes. Closing
+/// does nothing, since the crate's [`AsyncWrite`] has no way to close.
+///
+/// In the other direction `T` is polled with a waker completing a completion
+/// which the poller waits on, like [`StdCompat`](super::StdCompat) does.
+pub struct FuturesIoCompat<T> {
+    io: T,
+    /// Reads and writes are polled from tasks of their own once the object
+    /// is split, so each gets a poller and a waker of its own.
+    reader: Reactor,
+    writer: Reactor,
+    /// What the last pending read and write waited on.
+    reading: Vec<Waitable>,
+    writing: Vec<Waitable>,
+    /// The completion `T`'s waker completes, and the waker, once `T` was
+    /// polled.
+    wakeup: Option<(u64, Waker)>,
+}
+
+impl<T> FuturesIoCompat<T> {
+    pub fn new(io: T) -> Self {
+        Self {
+            io,
+            reader: Reactor::default(),
+            writer: Reactor::default(),
+            reading: Vec::new(),
+            writing: Vec::new(),
+            wakeup: None,
+        }
+    }
+
+    /// Get a reference to the wrapped IO object.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Get a mutable reference to the wrapped IO object.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    fn close(&mut self) {
+        self.reader.close(mem::take(&mut self.reading));
+        self.writer.close(mem::take(&mut self.writing));
+        if let Some((id, _)) = self.wakeup.take() {
+            completion::remove(id);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for FuturesIoCompat<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FuturesIoCompat")
+            .field("io", &self.io)
+            .finish()
+    }
+}
+
+// Nothing is pinned: the crate's IO objects are polled through `&mut`, and
+// the `futures-io` ones need to be `Unpin`.
+impl<T> Unpin for FuturesIoCompat<T> {}
+
+impl<T> Drop for FuturesIoCompat<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T: AsyncRead> futures_io::AsyncRead for FuturesIoCompat<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = ReadAttempt {
+            io: &mut this.io,
+            buf,
+            output: None,
+        };
+        let mut read = Stepped::resume(read, mem::take(&mut this.reading));
+        let polled = this.reader.poll(&mut read, cx);
+        this.reading = read.into_waiting_on();
+        polled.map(|output| output?)
+    }
+}
+
+impl<T: AsyncWrite> futures_io::AsyncWrite for FuturesIoCompat<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let write = WriteAttempt {
+            io: &mut this.io,
+            buf,
+            output: None,
+        };
+        let mut write = Stepped::resume(write, mem::take(&mut this.writing));
+        let polled = this.writer.poll(&mut write, cx);
+        this.writing = write.into_waiting_on();
+        polled.map(|output| output?)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let flush = FlushAttempt {
+            io: &mut this.io,
+            output: None,
+        };
+        let mut flush = Stepped::resume(flush, mem::take(&mut this.writing));
+        let polled = this.writer.poll(&mut flush, cx);
+        this.writing = flush.into_waiting_on();
+        polled.map(|output| output?)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> FuturesIoCompat<T> {
+    /// Poll `T` with the waker completing our completion, resetting it first
+    /// so a wakeup during the poll isn't lost.
+    fn poll_io<R>(
+        &mut self,
+        poll: impl FnOnce(Pin<&mut T>, &mut Context<'_>) -> Poll<io::Result<R>>,
+    ) -> Step<iter::Once<Waitable>, R>
+    where
+        T: Unpin,
+    {
+        let (id, waker) = self.wakeup.get_or_insert_with(|| {
+            let id = completion::register();
+            (id, Waker::from(Arc::new(Wakeup(id))))
+        });
+        completion::reset(*id);
+        match poll(Pin::new(&mut self.io), &mut Context::from_waker(waker)) {
+            Poll::Ready(Ok(n)) => Step::Done(n),
+            Poll::Ready(Err(e)) => Step::Error(e),
+            Poll::Pending => Step::Pending(iter::once(Waitable::Completion(*id))),
+        }
+    }
+}
+
+impl<T: futures_io::AsyncRead + Unpin> AsyncRead for FuturesIoCompat<T> {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T>> {
+        self.poll_io(|io, cx| io.poll_read(cx, buf))
+    }
+}
+
+impl<T: futures_io::AsyncWrite + Unpin> AsyncWrite for FuturesIoCompat<T> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<T>> {
+        self.poll_io(|io, cx| io.poll_write(cx, buf))
+    }
+
+    fn poll_flush(
+        &mut self,
+        _ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<T>, ()> {
+        self.poll_io(|io, cx| io.poll_flush(cx))
+    }
+}
+
+/// One attempt at a read, stepped on the poller of a [`FuturesIoCompat`].
+struct ReadAttempt<'a, 'b, T> {
+    io: &'a mut T,
+    buf: &'b mut [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<T: AsyncRead> Future for ReadAttempt<'_, '_, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.io.poll_read(ready, self.buf) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// One attempt at a write, stepped on the poller of a [`FuturesIoCompat`].
+struct WriteAttempt<'a, 'b, T> {
+    io: &'a mut T,
+    buf: &'b [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<T: AsyncWrite> Future for WriteAttempt<'_, '_, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.io.poll_write(ready, self.buf) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// One attempt at a flush, stepped on the poller of a [`FuturesIoCompat`].
+struct FlushAttempt<'a, T> {
+    io: &'a mut T,
+    output: Option<io::Result<()>>,
+}
+
+impl<T: AsyncWrite> Future for FlushAttempt<'_, T> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.io.poll_flush(ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => self.output = Some(Ok(())),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
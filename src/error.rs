@@ -0,0 +1,196 @@
+//! The error type of the futures the crate ships, which says what failed
+//! besides why.
+//!
+//! An [`Error`] is an [`io::Error`] with the [`Operation`] which produced
+//! it, and the fd and peer address involved where those are known. It
+//! converts to and from `io::Error` either way, and the context survives a
+//! round trip, so `?` works in functions returning `io::Result` as before.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::RawFd;
+
+use crate::future::{Future, Waitable};
+
+/// What was being done when an [`Error`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    Connect,
+    Accept,
+    Read,
+    Write,
+    /// Registering an fd with the poller, or deregistering it.
+    Register,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Connect => "connect",
+            Operation::Accept => "accept",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::Register => "register",
+        })
+    }
+}
+
+/// An [`io::Error`], along with what was being done when it happened.
+///
+/// Displaying it includes the context, as in
+/// `connect failed (peer 127.0.0.1:1): Connection refused (os error 111)`.
+/// [`Error::kind`] passes the kind of the `io::Error` through, so matching
+/// on it keeps working.
+///
+/// When the `io::Error` came from an `Error` to begin with, the context it
+/// had is kept over what's added later, since it's the more specific one.
+#[derive(Debug)]
+pub struct Error {
+    error: io::Error,
+    operation: Option<Operation>,
+    fd: Option<RawFd>,
+    peer: Option<SocketAddr>,
+}
+
+impl Error {
+    /// Note that `error` happened during `operation`.
+    pub fn new(operation: Operation, error: io::Error) -> Self {
+        let mut error = Self::from(error);
+        error.operation.get_or_insert(operation);
+        error
+    }
+
+    /// Note the fd the operation was on.
+    pub fn with_fd(mut self, fd: RawFd) -> Self {
+        self.fd.get_or_insert(fd);
+        self
+    }
+
+    /// Note the address of the peer the operation involved.
+    pub fn with_peer(mut self, peer: SocketAddr) -> Self {
+        self.peer.get_or_insert(peer);
+        self
+    }
+
+    /// The kind of the underlying `io::Error`.
+    pub fn kind(&self) -> io::ErrorKind {
+        self.error.kind()
+    }
+
+    /// The OS error code of the underlying `io::Error`, if it has one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.error.raw_os_error()
+    }
+
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    pub fn fd(&self) -> Option<RawFd> {
+        self.fd
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Get a reference to the underlying `io::Error`.
+    pub fn get_ref(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Drop the context, keeping the underlying `io::Error`.
+    pub fn into_inner(self) -> io::Error {
+        self.error
+    }
+
+    fn has_context(&self) -> bool {
+        self.operation.is_some() || self.fd.is_some() || self.peer.is_some()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operation {
+            Some(operation) => write!(f, "{operation} failed")?,
+            None if self.has_context() => f.write_str("failed")?,
+            None => return write!(f, "{}", self.error),
+        }
+        match (self.fd, self.peer) {
+            (Some(fd), Some(peer)) => write!(f, " (fd {fd}, peer {peer})")?,
+            (Some(fd), None) => write!(f, " (fd {fd})")?,
+            (None, Some(peer)) => write!(f, " (peer {peer})")?,
+            (None, None) => {}
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// An `io::Error` which came from an [`Error`] gets its context back.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        if error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = error.into_inner().expect("checked there's an inner error");
+            return *inner.downcast().expect("checked it's an `Error`");
+        }
+        Self {
+            error,
+            operation: None,
+            fd: None,
+            peer: None,
+        }
+    }
+}
+
+/// The context goes along as the `io::Error`'s inner error, with the same
+/// kind.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error.has_context() {
+            true => io::Error::new(error.kind(), error),
+            false => error.error,
+        }
+    }
+}
+
+/// A future resolving with an `io::Result`, whose error gets the context of
+/// what it was doing attached. Created by the IO objects' own `read`,
+/// `write` and the like.
+#[derive(Debug)]
+pub struct WithContext<F> {
+    future: F,
+    operation: Operation,
+    fd: RawFd,
+}
+
+impl<F> WithContext<F> {
+    pub(crate) fn new(future: F, operation: Operation, fd: RawFd) -> Self {
+        Self {
+            future,
+            operation,
+            fd,
+        }
+    }
+}
+
+impl<F: Future<Output = io::Result<T>>, T> Future for WithContext<F> {
+    type Output = Result<T, Error>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.future.poll(ready)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        let output = self.future.take()?;
+        Some(output.map_err(|e| Error::new(self.operation, e).with_fd(self.fd)))
+    }
+}
@@ -0,0 +1,7 @@
+//! The parts of the examples worth testing, kept in the library so the
+//! tests can reach them.
+//!
+//! The examples themselves only parse their arguments and set things up;
+//! what happens to a connection lives here.
+
+pub mod chat;
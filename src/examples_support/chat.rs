@@ -0,0 +1,301 @@
+//! A chat room: every line a client sends goes to every other client.
+//!
+//! Each connection is split in two. Lines read from one half are published
+//! to the [`Room`], which queues them up for every other member, and the
+//! other half writes out whatever is queued up for it, prefixed with the
+//! number of the client who sent it. Clients are numbered from 1, in the
+//! order they joined.
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::codec::{Encoder, Framed, LinesCodec};
+use crate::future::{Future, Waitable};
+use crate::io::{AsyncWrite, Step};
+use crate::net::IdleTimeout;
+use crate::runtime::queue::Pop;
+use crate::runtime::{CompletionHandle, CompletionQueue};
+use crate::stream::Stream;
+use crate::sync::Event;
+use crate::tcp::{AsyncTcpStream, OwnedReadHalf, OwnedWriteHalf};
+
+/// How long a client may go without sending anything, by default.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The clients which are connected, and what's queued up for each.
+///
+/// Clones refer to the same room.
+#[derive(Debug, Clone)]
+pub struct Room {
+    members: Arc<Mutex<Members>>,
+    shutdown: Event,
+    idle_timeout: Duration,
+}
+
+#[derive(Debug)]
+struct Members {
+    next_id: u64,
+    /// Each member's number, and the handle to its inbox.
+    inboxes: Vec<(u64, CompletionHandle<Arc<str>>)>,
+}
+
+impl Room {
+    /// An empty room, dropping clients which go five minutes without
+    /// sending anything.
+    pub fn new() -> Self {
+        Self {
+            members: Arc::new(Mutex::new(Members {
+                next_id: 1,
+                inboxes: Vec::new(),
+            })),
+            shutdown: Event::new(),
+            idle_timeout: IDLE_TIMEOUT,
+        }
+    }
+
+    /// Drop clients once they went `timeout` without sending anything.
+    /// Only connections handled after this are affected.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Join `stream` to the room, as the next client.
+    ///
+    /// The client is a member from here on, so it hears of every line sent
+    /// after this, even before the future is first polled.
+    pub fn connection(&self, stream: AsyncTcpStream) -> Connection {
+        let inbox = CompletionQueue::new();
+        let id = {
+            let mut members = self.lock();
+            let id = members.next_id;
+            members.next_id += 1;
+            members.inboxes.push((id, inbox.handle()));
+            id
+        };
+        let (reader, writer) = stream.into_split();
+        Connection {
+            id,
+            room: self.clone(),
+            lines: Framed::new(
+                IdleTimeout::new(reader, self.idle_timeout),
+                LinesCodec::new(),
+            ),
+            writer,
+            codec: LinesCodec::new(),
+            inbox,
+            outgoing: Vec::new(),
+            closing: false,
+            waiting_on: Vec::new(),
+            output: None,
+        }
+    }
+
+    /// Close every connection, once it wrote out the lines it already took
+    /// from its inbox.
+    pub fn shutdown(&self) {
+        self.shutdown.set();
+    }
+
+    /// The event [`Room::shutdown`] sets, say to stop accepting with
+    /// [`Serve::shutdown_on`](crate::net::Serve::shutdown_on) too.
+    pub fn shutdown_event(&self) -> Event {
+        self.shutdown.clone()
+    }
+
+    /// Shut the room down once `future` resolves, such as
+    /// [`ctrl_c`](crate::signal::ctrl_c).
+    pub fn shutdown_after<F: Future>(&self, future: F) -> ShutdownAfter<F> {
+        ShutdownAfter {
+            future,
+            room: self.clone(),
+            waiting_on: Vec::new(),
+            output: None,
+        }
+    }
+
+    /// How many clients are connected.
+    pub fn len(&self) -> usize {
+        self.lock().inboxes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue `line` up for everyone but its sender.
+    fn publish(&self, sender: u64, line: &str) {
+        let message: Arc<str> = format!("{sender}: {line}").into();
+        for (id, inbox) in &self.lock().inboxes {
+            if *id != sender {
+                inbox.push(message.clone());
+            }
+        }
+    }
+
+    fn leave(&self, id: u64) {
+        self.lock().inboxes.retain(|(member, _)| *member != id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Members> {
+        self.members.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future for [`Room::connection`], handling one client until it leaves.
+///
+/// It resolves once the client closes its side of the connection or the
+/// room shuts down, and fails if the client goes quiet for longer than the
+/// room's idle timeout or the connection fails. Either way the client
+/// leaves the room once the future is dropped.
+pub struct Connection {
+    id: u64,
+    room: Room,
+    lines: Framed<IdleTimeout<OwnedReadHalf>, LinesCodec>,
+    writer: OwnedWriteHalf,
+    codec: LinesCodec,
+    inbox: CompletionQueue<Arc<str>>,
+    /// Lines taken from the inbox and not written yet.
+    outgoing: Vec<u8>,
+    /// Whether the client left or the room shut down, after which only
+    /// `outgoing` is written out.
+    closing: bool,
+    waiting_on: Vec<Waitable>,
+    output: Option<io::Result<()>>,
+}
+
+impl Connection {
+    /// The client's number in the room.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn poll_inner(&mut self, ready: &[Waitable]) -> io::Result<()> {
+        if !self.closing {
+            match self.room.shutdown.is_set() {
+                true => self.closing = true,
+                false => self
+                    .waiting_on
+                    .extend(self.room.shutdown.wait().poll(ready)),
+            }
+        }
+
+        while !self.closing {
+            let waitables = self.lines.poll_next(ready);
+            match self.lines.take_next() {
+                Some(line) => self.room.publish(self.id, &line?),
+                None => {
+                    let len = self.waiting_on.len();
+                    self.waiting_on.extend(waitables);
+                    // Nothing to wait on means the client closed its side.
+                    self.closing = self.waiting_on.len() == len;
+                    break;
+                }
+            }
+        }
+
+        while !self.closing {
+            match self.inbox.pop() {
+                Pop::Value(line) => self.codec.encode(&*line, &mut self.outgoing)?,
+                Pop::Closed => break,
+                Pop::Empty(waitable) => {
+                    self.waiting_on.push(waitable);
+                    break;
+                }
+            }
+        }
+
+        while !self.outgoing.is_empty() {
+            match self.writer.poll_write(ready, &self.outgoing) {
+                Step::Pending(waitables) => {
+                    self.waiting_on.extend(waitables);
+                    break;
+                }
+                Step::Done(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Step::Done(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => return Err(e),
+            }
+        }
+
+        if self.closing && self.outgoing.is_empty() {
+            self.output = Some(Ok(()));
+        }
+        Ok(())
+    }
+}
+
+impl Future for Connection {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_none() {
+            let waited_on = std::mem::take(&mut self.waiting_on);
+            if let Err(e) = self.poll_inner(ready) {
+                self.output = Some(Err(e));
+            }
+            if self.output.is_some() {
+                // A shutdown can end the connection while a read is still
+                // pending, so drop what's registered for it.
+                let waiting_on = std::mem::take(&mut self.waiting_on);
+                self.waiting_on = waited_on
+                    .into_iter()
+                    .chain(waiting_on)
+                    .filter_map(Waitable::cancel)
+                    .collect();
+            }
+        } else {
+            self.waiting_on.clear();
+        }
+        self.waiting_on.iter().copied()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.room.leave(self.id);
+    }
+}
+
+/// Future for [`Room::shutdown_after`].
+pub struct ShutdownAfter<F: Future> {
+    future: F,
+    room: Room,
+    waiting_on: Vec<Waitable>,
+    output: Option<F::Output>,
+}
+
+impl<F: Future> Future for ShutdownAfter<F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.waiting_on.clear();
+        if self.output.is_none() {
+            self.waiting_on.extend(self.future.poll(ready));
+            if self.waiting_on.is_empty() {
+                self.output = self.future.take();
+                if self.output.is_some() {
+                    self.room.shutdown();
+                }
+            }
+        }
+        self.waiting_on.iter().copied()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,292 @@
+//! Files, and filesystem notifications.
+//!
+//! Regular files are always ready as far as kqueue is concerned, so actual
+//! file IO is handed off to the blocking pool instead.
+//!
+//! The runtime may hand the same ready set to a stream several times, so
+//! changes to watched files aren't read from it. Instead the runtime records
+//! them in a process-wide table, and each [`Watcher`] takes its own out of
+//! it.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::ops::BitOr;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::blocking::BlockingTask;
+use crate::future::{Future, Waitable};
+use crate::runtime;
+use crate::stream::Stream;
+
+/// A file, read and written on the blocking pool.
+///
+/// Handles are cheap to clone, and clones share one open file.
+#[derive(Debug, Clone)]
+pub struct File {
+    inner: Arc<fs::File>,
+}
+
+impl File {
+    /// Open the file at `path` for reading.
+    pub fn open(path: impl AsRef<Path>) -> BlockingTask<io::Result<File>> {
+        let path = path.as_ref().to_owned();
+        runtime::spawn_blocking(move || fs::File::open(path).map(File::from_std))
+    }
+
+    /// Open the file at `path` for writing, creating it if it doesn't exist
+    /// and truncating it if it does.
+    pub fn create(path: impl AsRef<Path>) -> BlockingTask<io::Result<File>> {
+        let path = path.as_ref().to_owned();
+        runtime::spawn_blocking(move || fs::File::create(path).map(File::from_std))
+    }
+
+    pub fn from_std(file: fs::File) -> Self {
+        Self {
+            inner: Arc::new(file),
+        }
+    }
+
+    /// Read into `buf` starting at `offset` in the file, resolving with how
+    /// many bytes were read. The file's cursor doesn't move.
+    ///
+    /// The pool reads into a buffer of its own, which is copied into `buf`
+    /// once it's done.
+    pub fn read_at<'a>(&self, buf: &'a mut [u8], offset: u64) -> ReadAtFuture<'a> {
+        let file = self.inner.clone();
+        let len = buf.len();
+        let task = runtime::spawn_blocking(move || {
+            let mut data = vec![0; len];
+            let n = file.read_at(&mut data, offset)?;
+            data.truncate(n);
+            Ok(data)
+        });
+        ReadAtFuture {
+            buf,
+            task,
+            output: None,
+        }
+    }
+
+    /// Write `data` starting at `offset` in the file, resolving with how many
+    /// bytes were written. The file's cursor doesn't move.
+    ///
+    /// `data` is copied up front, so it needn't outlive the future.
+    pub fn write_at(&self, data: &[u8], offset: u64) -> BlockingTask<io::Result<usize>> {
+        let file = self.inner.clone();
+        let data = data.to_vec();
+        runtime::spawn_blocking(move || file.write_at(&data, offset))
+    }
+
+    /// Read from the file's cursor to the end of the file.
+    pub fn read_to_end(&self) -> BlockingTask<io::Result<Vec<u8>>> {
+        let file = self.inner.clone();
+        runtime::spawn_blocking(move || {
+            let mut data = Vec::new();
+            (&*file).read_to_end(&mut data)?;
+            Ok(data)
+        })
+    }
+
+    /// Flush the file's data and metadata to disk.
+    pub fn sync_all(&self) -> BlockingTask<io::Result<()>> {
+        let file = self.inner.clone();
+        runtime::spawn_blocking(move || file.sync_all())
+    }
+}
+
+/// Future for [`File::read_at`].
+pub struct ReadAtFuture<'a> {
+    buf: &'a mut [u8],
+    task: BlockingTask<io::Result<Vec<u8>>>,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a> Future for ReadAtFuture<'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            // The task waits on at most its own completion.
+            let waitable = self.task.poll(ready).next();
+            match self.task.take() {
+                Some(Ok(data)) => {
+                    self.buf[..data.len()].copy_from_slice(&data);
+                    self.output = Some(Ok(data.len()));
+                }
+                Some(Err(e)) => self.output = Some(Err(e)),
+                None => pending = waitable,
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+// The kinds of change, as the bits of kqueue's `NOTE_*` flags, which is
+// what `Waitable::Vnode` carries.
+const NOTE_DELETE: u32 = 0x01;
+const NOTE_WRITE: u32 = 0x02;
+const NOTE_EXTEND: u32 = 0x04;
+const NOTE_RENAME: u32 = 0x20;
+
+/// Changes the runtime saw, by fd, which no watcher has taken yet.
+static CHANGES: Mutex<BTreeMap<RawFd, u32>> = Mutex::new(BTreeMap::new());
+
+fn changes() -> MutexGuard<'static, BTreeMap<RawFd, u32>> {
+    CHANGES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record that the runtime saw the file behind `fd` change.
+pub(crate) fn deliver(fd: RawFd, fired: u32) {
+    *changes().entry(fd).or_insert(0) |= fired;
+}
+
+/// Which kinds of change a [`Watcher`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchKinds(u32);
+
+impl WatchKinds {
+    /// The file was written to. For a directory, an entry was added, removed
+    /// or renamed.
+    pub const WRITE: Self = Self(NOTE_WRITE);
+    /// The file grew.
+    pub const EXTEND: Self = Self(NOTE_EXTEND);
+    /// The file was deleted.
+    pub const DELETE: Self = Self(NOTE_DELETE);
+    /// The file was renamed.
+    pub const RENAME: Self = Self(NOTE_RENAME);
+    /// All of the above.
+    pub const ALL: Self = Self(NOTE_WRITE | NOTE_EXTEND | NOTE_DELETE | NOTE_RENAME);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for WatchKinds {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// A change reported by a [`Watcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    Write,
+    Extend,
+    Delete,
+    Rename,
+}
+
+impl WatchEvent {
+    /// Whether the watched path no longer refers to the file being watched.
+    fn is_final(self) -> bool {
+        matches!(self, WatchEvent::Delete | WatchEvent::Rename)
+    }
+}
+
+/// Watch the file or directory at `path` for the given kinds of change.
+///
+/// The watch follows the file rather than the path: once the file is
+/// deleted or renamed, the stream yields that event and then ends.
+pub fn watch(path: &Path, kinds: WatchKinds) -> io::Result<Watcher> {
+    if kinds.0 == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no kinds of change to watch for",
+        ));
+    }
+    let mut options = OpenOptions::new();
+    options.read(true);
+    // Only open the file for events, so the watch doesn't keep its volume
+    // from being unmounted.
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_EVTONLY);
+    }
+    let file = options.open(path)?;
+    Ok(Watcher {
+        fd: Some(file.into()),
+        kinds,
+        events: VecDeque::new(),
+        item: None,
+    })
+}
+
+/// Stream for [`watch`].
+#[derive(Debug)]
+pub struct Watcher {
+    /// `None` once the stream has ended.
+    fd: Option<OwnedFd>,
+    kinds: WatchKinds,
+    /// Changes which were reported together, to yield one by one.
+    events: VecDeque<WatchEvent>,
+    item: Option<io::Result<WatchEvent>>,
+}
+
+impl Watcher {
+    /// End the stream. Closing the fd drops its registration, but a change
+    /// might have been recorded for it in the meantime.
+    fn close(&mut self) {
+        if let Some(fd) = self.fd.take() {
+            changes().remove(&fd.as_raw_fd());
+        }
+        self.events.clear();
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl Stream for Watcher {
+    type Item = io::Result<WatchEvent>;
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<> {
+        let mut pending = None;
+        if let (Some(fd), None) = (&self.fd, &self.item) {
+            let fd = fd.as_raw_fd();
+            if let Some(fired) = changes().remove(&fd) {
+                // Report the final event last, since nothing comes after it.
+                let kinds = [
+                    (NOTE_WRITE, WatchEvent::Write),
+                    (NOTE_EXTEND, WatchEvent::Extend),
+                    (NOTE_RENAME, WatchEvent::Rename),
+                    (NOTE_DELETE, WatchEvent::Delete),
+                ];
+                for (kind, event) in kinds {
+                    if fired & kind != 0 && self.kinds.0 & kind != 0 {
+                        self.events.push_back(event);
+                    }
+                }
+            }
+            match self.events.pop_front() {
+                Some(event) => {
+                    if event.is_final() {
+                        self.close();
+                    }
+                    self.item = Some(Ok(event));
+                }
+                None => pending = Some(Waitable::Vnode(fd, self.kinds.0)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
@@ -1,15 +1,121 @@
 use std::os::fd::RawFd;
+use std::time::Instant;
 
-#[derive(Debug)]
+#[cfg(feature = "time")]
+mod retry;
+
+#[cfg(feature = "time")]
+pub use retry::{retry, Retry, RetryPolicy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Interest {
     Read,
+    Write,
+    /// Stop receiving read events for the fd.
+    CloseRead,
+    /// Stop receiving write events for the fd.
+    CloseWrite,
+    /// Stop receiving any events for the fd.
     Close,
+    /// Wait for the other end of a pipe or socket to hang up. Waiting for it
+    /// registers for read events, and it's reported alongside the read event
+    /// which says the fd hit EOF.
+    Hangup,
+    /// Wait for an exceptional condition on the fd, such as TCP urgent data
+    /// or a status change of a pty in packet mode, which read events don't
+    /// tell apart from the rest. It's reported as an event of its own.
+    Priority,
+    /// Stop receiving priority events for the fd.
+    ClosePriority,
+}
+
+impl Interest {
+    /// Whether this is about the fd as a whole, rather than reading or
+    /// writing it.
+    fn either_way(self) -> bool {
+        matches!(
+            self,
+            Interest::Hangup
+                | Interest::Close
+                | Interest::CloseRead
+                | Interest::CloseWrite
+                | Interest::ClosePriority
+        )
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Waitable {
     /// Registered file descriptor.
     Fd(RawFd, Interest),
+    /// A registered file descriptor, with a deadline. If the fd isn't ready
+    /// by then, the poller reports this waitable as ready instead, meaning
+    /// it timed out. Each registration is reported as one or the other,
+    /// never both.
+    FdUntil(RawFd, Interest, Instant),
+    /// A point in time. Ready once it has passed.
+    Timer(Instant),
+    /// A child process, by pid. With `Interest::Read` it's ready once the
+    /// process has exited; the `Close*` interests drop the registration.
+    Process(u32, Interest),
+    /// Changes to the file behind an fd, as `NOTE_*` bits. When waiting, the
+    /// kinds of change to wait for; no kinds at all drops the registration.
+    Vnode(RawFd, u32),
+    /// Something completing on another thread, such as a blocking job, by
+    /// id. Ready once it has completed.
+    Completion(u64),
+    /// A signal, by number. Ready once it has been delivered.
+    Signal(i32),
+}
+
+impl Waitable {
+    /// The waitable to yield instead when we stop waiting for this one, so
+    /// the poller can drop what it registered for it.
+    pub(crate) fn cancel(self) -> Option<Waitable> {
+        match self {
+            Waitable::Fd(fd, Interest::Read | Interest::Hangup) => {
+                Some(Waitable::Fd(fd, Interest::CloseRead))
+            }
+            Waitable::Fd(fd, Interest::Write) => Some(Waitable::Fd(fd, Interest::CloseWrite)),
+            Waitable::Fd(fd, Interest::Priority) => Some(Waitable::Fd(fd, Interest::ClosePriority)),
+            Waitable::Fd(..) => Some(self),
+            Waitable::FdUntil(fd, interest, _) => Waitable::Fd(fd, interest).cancel(),
+            Waitable::Process(pid, _) => Some(Waitable::Process(pid, Interest::Close)),
+            Waitable::Vnode(fd, _) => Some(Waitable::Vnode(fd, 0)),
+            // Signal registrations outlive any one wait; the runtime drops
+            // them once nobody listens anymore.
+            Waitable::Timer(_) | Waitable::Signal(_) => None,
+            // Whatever it is completes either way.
+            Waitable::Completion(_) => None,
+        }
+    }
+
+    /// Whether this ready waitable is news to a future which last waited
+    /// on `waiting`: they refer to the same fd, process, and so on. Fd
+    /// events only wake whoever waits for the same direction, but hangups
+    /// and the `Close*` interests go both ways. Priority events only wake
+    /// whoever waits for them, and only them, and timeouts only whoever
+    /// waits with a deadline.
+    pub(crate) fn wakes(self, waiting: Waitable) -> bool {
+        match (self, waiting) {
+            (Waitable::FdUntil(a, i, _), Waitable::FdUntil(b, j, _)) => a == b && i == j,
+            (Waitable::FdUntil(..), _) => false,
+            (ready, Waitable::FdUntil(fd, interest, _)) => ready.wakes(Waitable::Fd(fd, interest)),
+            (Waitable::Fd(a, Interest::Priority), Waitable::Fd(b, Interest::Priority)) => a == b,
+            (Waitable::Fd(_, Interest::Priority), _) | (_, Waitable::Fd(_, Interest::Priority)) => {
+                false
+            }
+            (Waitable::Fd(a, i), Waitable::Fd(b, j)) => {
+                a == b && (i == j || i.either_way() || j.either_way())
+            }
+            (Waitable::Timer(a), Waitable::Timer(b)) => a == b,
+            (Waitable::Process(a, _), Waitable::Process(b, _)) => a == b,
+            (Waitable::Vnode(a, _), Waitable::Vnode(b, _)) => a == b,
+            (Waitable::Completion(a), Waitable::Completion(b)) => a == b,
+            (Waitable::Signal(a), Waitable::Signal(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 pub trait Future {
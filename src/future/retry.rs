@@ -0,0 +1,184 @@
+//! Running a future again until it succeeds.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::future::{Future, IntoFuture, Waitable};
+use crate::time::{sleep, Sleep};
+
+/// Run the future `make_future` returns until it succeeds, making a fresh
+/// one for each attempt and sleeping between them as `policy` says.
+///
+/// It resolves with the first `Ok`, or with the last `Err` once `policy`
+/// runs out of attempts. By default every error is retried; see
+/// [`Retry::retry_if`].
+pub fn retry<M, F, T, E>(policy: RetryPolicy, make_future: M) -> Retry<M, F, fn(&E) -> bool>
+where
+    M: FnMut() -> F,
+    F: IntoFuture<Output = Result<T, E>>,
+{
+    Retry {
+        make_future,
+        policy,
+        should_retry: |_: &E| true,
+        attempts: 0,
+        attempt: None,
+        backoff: None,
+        waiting_on: Vec::new(),
+        output: None,
+    }
+}
+
+/// How often [`retry`] tries, and how long it waits in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    delay: Duration,
+    exponential: bool,
+    max_delay: Duration,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    /// Wait `delay` between attempts.
+    pub fn fixed(delay: Duration) -> Self {
+        Self {
+            max_attempts: 3,
+            delay,
+            exponential: false,
+            max_delay: Duration::MAX,
+            jitter: false,
+        }
+    }
+
+    /// Wait `initial` after the first attempt, and twice as long after each
+    /// one after that.
+    pub fn exponential(initial: Duration) -> Self {
+        Self {
+            exponential: true,
+            ..Self::fixed(initial)
+        }
+    }
+
+    /// Make at most `attempts` attempts, the first one included. Defaults
+    /// to 3. The first attempt is made either way.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Never wait longer than `delay` between attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Wait a random amount between half of the delay and all of it, so
+    /// clients which failed together don't all retry together.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// How long to wait after `attempts` attempts failed.
+    fn delay(&self, attempts: u32) -> Duration {
+        let mut delay = match self.exponential {
+            true => {
+                let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+                self.delay.saturating_mul(factor)
+            }
+            false => self.delay,
+        };
+        delay = delay.min(self.max_delay);
+        if self.jitter {
+            // A fresh `RandomState` hashes with fresh keys, which is random
+            // enough to spread retries without a dependency on `rand`.
+            let random = RandomState::new().build_hasher().finish();
+            let fraction = (random >> 11) as f64 / (1u64 << 53) as f64;
+            delay = delay / 2 + delay.mul_f64(fraction) / 2;
+        }
+        delay
+    }
+}
+
+/// Future for [`retry`].
+pub struct Retry<M, F: IntoFuture, P> {
+    make_future: M,
+    policy: RetryPolicy,
+    should_retry: P,
+    /// How many attempts were started.
+    attempts: u32,
+    attempt: Option<F::IntoFuture>,
+    /// The wait before the next attempt.
+    backoff: Option<Sleep>,
+    waiting_on: Vec<Waitable>,
+    output: Option<F::Output>,
+}
+
+impl<M, F: IntoFuture, P> Retry<M, F, P> {
+    /// Only retry errors `should_retry` returns `true` for; others fail
+    /// right away.
+    pub fn retry_if<Q>(self, should_retry: Q) -> Retry<M, F, Q> {
+        Retry {
+            make_future: self.make_future,
+            policy: self.policy,
+            should_retry,
+            attempts: self.attempts,
+            attempt: self.attempt,
+            backoff: self.backoff,
+            waiting_on: self.waiting_on,
+            output: self.output,
+        }
+    }
+
+    /// How many attempts were made so far, the one running included.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+impl<M, F, P, T, E> Future for Retry<M, F, P>
+where
+    M: FnMut() -> F,
+    F: IntoFuture<Output = Result<T, E>>,
+    P: FnMut(&E) -> bool,
+{
+    type Output = Result<T, E>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.waiting_on.clear();
+        while self.output.is_none() && self.waiting_on.is_empty() {
+            if let Some(backoff) = &mut self.backoff {
+                self.waiting_on.extend(backoff.poll(ready));
+                if !self.waiting_on.is_empty() {
+                    break;
+                }
+                self.backoff = None;
+            }
+            let attempt = self.attempt.get_or_insert_with(|| {
+                self.attempts += 1;
+                (self.make_future)().into_future()
+            });
+            self.waiting_on.extend(attempt.poll(ready));
+            if !self.waiting_on.is_empty() {
+                break;
+            }
+            let Some(output) = attempt.take() else {
+                break;
+            };
+            self.attempt = None;
+            match output {
+                Err(e) if self.attempts < self.policy.max_attempts && (self.should_retry)(&e) => {
+                    self.backoff = Some(sleep(self.policy.delay(self.attempts)));
+                }
+                output => self.output = Some(output),
+            }
+        }
+        self.waiting_on.iter().copied()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
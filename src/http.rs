@@ -0,0 +1,373 @@
+//! A minimal HTTP/1.1 client.
+//!
+//! This exists to exercise the rest of the crate end to end more than to be
+//! a serious client: it speaks plain HTTP only, sends `GET` requests with
+//! `Connection: close`, and reads bodies delimited by `Content-Length` or by
+//! the server closing the connection. Chunked bodies are rejected. Wrap the
+//! future in [`time::timeout`](crate::time::timeout) to bound how long a
+//! request may take.
+
+use std::io;
+
+use crate::future::{Future, Waitable};
+use crate::io::{AsyncWrite, BufReader, Step};
+use crate::tcp::{AsyncTcpStream, ConnectFuture};
+
+/// The most bytes the status line and headers may take up together.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+/// A response to a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    /// Header names and values, in the order they were received.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Fetch `url`, which must look like `http://host[:port][/path]`.
+///
+/// The host name is resolved on the blocking pool.
+pub fn get(url: &str) -> GetFuture {
+    let state = match prepare(url) {
+        Ok((host, port, request)) => State::Connecting {
+            connect: AsyncTcpStream::connect_host(host, port),
+            request,
+        },
+        Err(e) => State::Failed(Some(e)),
+    };
+    GetFuture {
+        state,
+        output: None,
+    }
+}
+
+/// Find the host and port to connect to and build the request for `url`.
+fn prepare(url: &str) -> io::Result<(&str, u16, Vec<u8>)> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some(_) => return Err(invalid("only http urls are supported")),
+        None => return Err(invalid("url has no scheme")),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(invalid("url has no host"));
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(']') || authority.starts_with('[') => {
+            let port = port.parse().map_err(|_| invalid("invalid port"))?;
+            (host.trim_start_matches('[').trim_end_matches(']'), port)
+        }
+        _ => (authority, 80),
+    };
+
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\nUser-Agent: playground-future\r\n\r\n"
+    );
+    Ok((host, port, request.into_bytes()))
+}
+
+/// Future for [`get`].
+pub struct GetFuture {
+    state: State,
+    output: Option<io::Result<Response>>,
+}
+
+enum State {
+    Connecting {
+        connect: ConnectFuture,
+        request: Vec<u8>,
+    },
+    Writing {
+        reader: BufReader<AsyncTcpStream>,
+        request: Vec<u8>,
+        written: usize,
+    },
+    Head {
+        reader: BufReader<AsyncTcpStream>,
+        line: Vec<u8>,
+        head_len: usize,
+        response: Option<Response>,
+    },
+    Body {
+        reader: BufReader<AsyncTcpStream>,
+        response: Response,
+        /// How much of the body is left, or `None` if it ends at EOF.
+        remaining: Option<usize>,
+    },
+    Failed(Option<io::Error>),
+    Done,
+}
+
+impl Future for GetFuture {
+    type Output = io::Result<Response>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut connecting = None;
+        let mut writing = None;
+        let mut reading_head = None;
+        let mut reading_body = None;
+        while self.output.is_none() {
+            match &mut self.state {
+                State::Connecting { connect, request } => match connect.poll_connect() {
+                    Step::Pending(waitables) => {
+                        connecting = Some(waitables);
+                        break;
+                    }
+                    Step::Done(stream) => {
+                        self.state = State::Writing {
+                            reader: BufReader::new(stream),
+                            request: std::mem::take(request),
+                            written: 0,
+                        }
+                    }
+                    Step::Error(e) => self.output = Some(Err(e)),
+                },
+                State::Writing {
+                    reader,
+                    request,
+                    written,
+                } => {
+                    if *written == request.len() {
+                        let State::Writing { reader, .. } =
+                            std::mem::replace(&mut self.state, State::Done)
+                        else {
+                            unreachable!()
+                        };
+                        self.state = State::Head {
+                            reader,
+                            line: Vec::new(),
+                            head_len: 0,
+                            response: None,
+                        };
+                        continue;
+                    }
+                    match reader.get_mut().poll_write(ready, &request[*written..]) {
+                        Step::Pending(waitables) => {
+                            writing = Some(waitables);
+                            break;
+                        }
+                        Step::Done(0) => {
+                            let e =
+                                io::Error::new(io::ErrorKind::WriteZero, "failed to send request");
+                            self.output = Some(Err(e));
+                        }
+                        Step::Done(n) => *written += n,
+                        Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Step::Error(e) => self.output = Some(Err(e)),
+                    }
+                }
+                State::Head {
+                    reader,
+                    line,
+                    head_len,
+                    response,
+                } => {
+                    match reader.poll_read_until(ready, b'\n', line) {
+                        Step::Pending(waitables) => {
+                            reading_head = Some(waitables);
+                            break;
+                        }
+                        Step::Done(false) => {
+                            let e = io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "connection closed before the response head ended",
+                            );
+                            self.output = Some(Err(e));
+                            continue;
+                        }
+                        Step::Done(true) => {}
+                        Step::Error(e) => {
+                            self.output = Some(Err(e));
+                            continue;
+                        }
+                    }
+                    *head_len += line.len();
+                    if *head_len > MAX_HEAD_LEN {
+                        self.output = Some(Err(invalid_data("response head is too long")));
+                        continue;
+                    }
+                    let text = match parse_line(line) {
+                        Ok(text) => text.to_string(),
+                        Err(e) => {
+                            self.output = Some(Err(e));
+                            continue;
+                        }
+                    };
+                    line.clear();
+
+                    let result = match response {
+                        None => parse_status_line(&text).map(|status| *response = Some(status)),
+                        Some(response) if !text.is_empty() => {
+                            parse_header(&text).map(|header| response.headers.push(header))
+                        }
+                        // An empty line ends the head.
+                        Some(_) => {
+                            let State::Head {
+                                reader, response, ..
+                            } = std::mem::replace(&mut self.state, State::Done)
+                            else {
+                                unreachable!()
+                            };
+                            let response = response.unwrap();
+                            body_length(&response).map(|remaining| {
+                                self.state = State::Body {
+                                    reader,
+                                    response,
+                                    remaining,
+                                };
+                            })
+                        }
+                    };
+                    if let Err(e) = result {
+                        self.output = Some(Err(e));
+                    }
+                }
+                State::Body {
+                    reader,
+                    response,
+                    remaining,
+                } => {
+                    if *remaining == Some(0) {
+                        let State::Body { response, .. } =
+                            std::mem::replace(&mut self.state, State::Done)
+                        else {
+                            unreachable!()
+                        };
+                        self.output = Some(Ok(response));
+                        continue;
+                    }
+                    match reader.poll_fill_buf(ready) {
+                        Step::Pending(waitables) => {
+                            reading_body = Some(waitables);
+                            break;
+                        }
+                        Step::Done(()) => {}
+                        Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Step::Error(e) => {
+                            self.output = Some(Err(e));
+                            continue;
+                        }
+                    }
+                    let available = reader.buffer();
+                    if available.is_empty() {
+                        match remaining {
+                            Some(_) => {
+                                let e = io::Error::new(
+                                    io::ErrorKind::UnexpectedEof,
+                                    "connection closed before the body ended",
+                                );
+                                self.output = Some(Err(e));
+                            }
+                            None => *remaining = Some(0),
+                        }
+                        continue;
+                    }
+                    let n = remaining.map_or(available.len(), |r| r.min(available.len()));
+                    response.body.extend_from_slice(&available[..n]);
+                    reader.consume(n);
+                    if let Some(remaining) = remaining {
+                        *remaining -= n;
+                    }
+                }
+                State::Failed(e) => {
+                    let e = e.take().expect("failed state holds its error");
+                    self.state = State::Done;
+                    self.output = Some(Err(e));
+                }
+                State::Done => break,
+            }
+        }
+        if self.output.is_some() {
+            // Whatever connection we had is dropped here.
+            self.state = State::Done;
+        }
+        let connecting = connecting.into_iter().flatten();
+        let writing = writing.into_iter().flatten();
+        let reading_head = reading_head.into_iter().flatten();
+        let reading_body = reading_body.into_iter().flatten();
+        connecting
+            .chain(writing)
+            .chain(reading_head)
+            .chain(reading_body)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Strip the line terminator and check the line is text.
+fn parse_line(line: &[u8]) -> io::Result<&str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    std::str::from_utf8(line).map_err(|_| invalid_data("response head is not valid UTF-8"))
+}
+
+/// Parse `HTTP/1.1 200 OK` into an empty response.
+fn parse_status_line(line: &str) -> io::Result<Response> {
+    let malformed = || invalid_data("malformed status line");
+    let (version, rest) = line.split_once(' ').ok_or_else(malformed)?;
+    if !version.starts_with("HTTP/1.") {
+        return Err(malformed());
+    }
+    let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+    if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(malformed());
+    }
+    Ok(Response {
+        status: code.parse().map_err(|_| malformed())?,
+        reason: reason.to_string(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    })
+}
+
+fn parse_header(line: &str) -> io::Result<(String, String)> {
+    let (name, value) = line
+        .split_once(':')
+        .ok_or_else(|| invalid_data("malformed header"))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+/// How long the body of `response` is, or `None` if it ends at EOF.
+fn body_length(response: &Response) -> io::Result<Option<usize>> {
+    if matches!(response.status, 100..=199 | 204 | 304) {
+        return Ok(Some(0));
+    }
+    if response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| !encoding.eq_ignore_ascii_case("identity"))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "chunked responses are not supported",
+        ));
+    }
+    match response.header("content-length") {
+        Some(len) => len
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid_data("invalid content-length")),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,1068 @@
+//! Traits and adapters for asynchronous IO.
+//!
+//! IO objects implement [`AsyncRead`] and [`AsyncWrite`] in the same style as
+//! [`Future`]: each attempt either completes, or reports which waitables need
+//! to become ready before it's worth trying again.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::iter;
+#[cfg(feature = "net")]
+use std::net::Shutdown;
+use std::os::fd::RawFd;
+use std::os::fd::{AsFd, AsRawFd};
+
+use crate::future::{Future, Interest, Waitable};
+use crate::runtime::{BufferPool, PoolBuf};
+#[cfg(feature = "net")]
+use crate::tcp::AsyncTcpStream;
+
+mod async_fd;
+mod buf_reader;
+mod buf_writer;
+mod chunks;
+pub mod mem;
+mod read_buf;
+mod stdin;
+mod tee;
+mod throttle;
+
+#[cfg(feature = "mio-compat")]
+pub use async_fd::MioReadiness;
+pub use async_fd::{AsyncFd, DeregisterFuture, Readiness, TryIoFuture};
+pub use buf_reader::{
+    BufReader, FillBufFuture, Lines, ReadLineFuture, ReadUntilFuture, SkipFuture,
+};
+pub use buf_writer::{BufWriter, FlushBufFuture, IntoInnerError, IntoInnerFuture};
+pub use chunks::{chunks, Chunks};
+pub use read_buf::ReadBuf;
+pub use stdin::{stdin, AsyncStdin};
+pub use tee::{Direction, Tee};
+pub use throttle::Throttle;
+
+/// The outcome of a single attempt at an IO operation.
+#[derive(Debug)]
+pub enum Step<W, T = usize> {
+    /// The operation would block. Try again once any of the waitables is ready.
+    Pending(W),
+    /// The operation completed.
+    Done(T),
+    /// The operation failed.
+    Error(io::Error),
+}
+
+/// The outcome of an attempt to read.
+pub type ReadStep<W> = Step<W>;
+
+/// The outcome of an attempt to write.
+pub type WriteStep<W> = Step<W>;
+
+impl<T> Step<iter::Once<Waitable>, T> {
+    /// Interpret the result of a nonblocking syscall on `fd`, waiting for
+    /// `interest` if it would block.
+    pub(crate) fn from_syscall(result: io::Result<T>, fd: RawFd, interest: Interest) -> Self {
+        match result {
+            Ok(value) => Step::Done(value),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                Step::Pending(iter::once(Waitable::Fd(fd, interest)))
+            }
+            Err(e) => Step::Error(e),
+        }
+    }
+}
+
+/// Read from `stream` into the unfilled part of `buf`, without zeroing it
+/// first.
+pub(crate) fn read_buf(stream: impl AsFd, buf: &mut ReadBuf<'_>) -> ReadStep<iter::Once<Waitable>> {
+    // SAFETY: `read_uninit` only ever writes initialized bytes.
+    let unfilled = unsafe { buf.unfilled() };
+    let result = rustix::io::read_uninit(&stream, unfilled)
+        .map(|(read, _)| read.len())
+        .map_err(io::Error::from);
+    let step = Step::from_syscall(result, stream.as_fd().as_raw_fd(), Interest::Read);
+    if let Step::Done(n) = step {
+        // SAFETY: `read_uninit` initialized the first `n` unfilled bytes.
+        unsafe { buf.assume_init(n) };
+        buf.advance(n);
+    }
+    step
+}
+
+/// What a write future waits on, and the fds it waited on to become
+/// writable along the way.
+///
+/// Sockets are writable nearly all the time, so a write registration left
+/// behind by a future which is done would wake the poller on every turn.
+/// Once it's done, the future yields `CloseWrite` for those fds instead, and
+/// resolves the turn after.
+#[derive(Debug, Default)]
+struct WriteWaits {
+    waiting_on: Vec<Waitable>,
+    writable: Vec<RawFd>,
+}
+
+impl WriteWaits {
+    /// Wait on `waitables`, noting the fds waited on for writes.
+    fn wait(&mut self, waitables: impl Iterator<Item = Waitable>) {
+        self.waiting_on.clear();
+        for waitable in waitables {
+            if let Waitable::Fd(fd, Interest::Write) | Waitable::FdUntil(fd, Interest::Write, _) =
+                waitable
+            {
+                if !self.writable.contains(&fd) {
+                    self.writable.push(fd);
+                }
+            }
+            self.waiting_on.push(waitable);
+        }
+    }
+
+    /// Stop waiting, dropping the write registrations if there are any.
+    fn finish(&mut self) {
+        self.waiting_on.clear();
+        let close = self
+            .writable
+            .drain(..)
+            .map(|fd| Waitable::Fd(fd, Interest::CloseWrite));
+        self.waiting_on.extend(close);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Waitable> + '_ {
+        self.waiting_on.iter().copied()
+    }
+}
+
+/// Read bytes asynchronously.
+pub trait AsyncRead {
+    /// Attempt to read into `buf`, returning the number of bytes read.
+    ///
+    /// `ready` holds the waitables which became ready since the last attempt.
+    /// The returned waitables may not borrow from `self`, so callers are free
+    /// to retry in a loop.
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<Self>>;
+
+    /// Attempt to read into the unfilled part of `buf`, advancing it and
+    /// returning the number of bytes read.
+    ///
+    /// By default this zeroes the uninitialized part of `buf` and calls
+    /// [`AsyncRead::poll_read`]. Readers which can read into uninitialized
+    /// memory should override it.
+    fn poll_read_buf(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<Self>> {
+        let step = self.poll_read(ready, buf.initialize_unfilled());
+        if let Step::Done(n) = step {
+            buf.advance(n);
+        }
+        step
+    }
+}
+
+/// Write bytes asynchronously.
+pub trait AsyncWrite {
+    /// Attempt to write from `buf`, returning the number of bytes written.
+    ///
+    /// `ready` holds the waitables which became ready since the last attempt.
+    /// The returned waitables may not borrow from `self`, so callers are free
+    /// to retry in a loop.
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<Self>>;
+
+    /// Attempt to write out whatever the writer buffered, down to the
+    /// underlying IO object.
+    ///
+    /// By default there's nothing to flush, as for sockets, which hand
+    /// everything over to the kernel as they write it. Writers which buffer
+    /// should override it.
+    fn poll_flush(
+        &mut self,
+        _ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<Self>, ()> {
+        Step::<iter::Empty<Waitable>, ()>::Done(())
+    }
+}
+
+impl<'a, T: AsyncRead + ?Sized> AsyncRead for &'a mut T {
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a, T>> {
+        (**self).poll_read(ready, buf)
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a, T>> {
+        (**self).poll_read_buf(ready, buf)
+    }
+}
+
+impl<'a, T: AsyncWrite + ?Sized> AsyncWrite for &'a mut T {
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<'a, T>> {
+        (**self).poll_write(ready, buf)
+    }
+
+    fn poll_flush(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<'a, T>, ()> {
+        (**self).poll_flush(ready)
+    }
+}
+
+/// Copy all bytes from `reader` into `writer` until `reader` reaches EOF,
+/// then flush `writer`, resolving with the number of bytes copied.
+pub fn copy<'a, R, W>(reader: &'a mut R, writer: &'a mut W) -> CopyFuture<'a, R, W>
+where
+    R: AsyncRead + ?Sized,
+    W: AsyncWrite + ?Sized,
+{
+    CopyFuture {
+        reader,
+        writer,
+        buffer: CopyBuffer::new(),
+        waits: WriteWaits::default(),
+        output: None,
+    }
+}
+
+/// Like [`copy`], with a buffer from `pool` rather than one of its own,
+/// which goes back to the pool once the future is dropped.
+pub fn copy_pooled<'a, R, W>(
+    reader: &'a mut R,
+    writer: &'a mut W,
+    pool: &BufferPool,
+) -> CopyFuture<'a, R, W>
+where
+    R: AsyncRead + ?Sized,
+    W: AsyncWrite + ?Sized,
+{
+    CopyFuture {
+        reader,
+        writer,
+        buffer: CopyBuffer::with_buffer(pool.acquire()),
+        waits: WriteWaits::default(),
+        output: None,
+    }
+}
+
+/// Copy data in both directions between `a` and `b` until both reach EOF,
+/// resolving with the number of bytes copied from `a` to `b` and from `b` to
+/// `a` respectively.
+///
+/// When one side reaches EOF the write half of the other side is shut down,
+/// so the peer observes the EOF too. The other direction keeps copying until
+/// it reaches EOF as well.
+#[cfg(feature = "net")]
+pub fn copy_bidirectional<'a>(
+    a: &'a mut AsyncTcpStream,
+    b: &'a mut AsyncTcpStream,
+) -> CopyBidiFuture<'a> {
+    CopyBidiFuture {
+        a,
+        b,
+        a_to_b: OneWay::Copying(CopyBuffer::new()),
+        b_to_a: OneWay::Copying(CopyBuffer::new()),
+        output: None,
+    }
+}
+
+/// Like [`copy_bidirectional`], with two buffers from `pool` rather than
+/// ones of its own. Each goes back to the pool once its direction is done.
+#[cfg(feature = "net")]
+pub fn copy_bidirectional_pooled<'a>(
+    a: &'a mut AsyncTcpStream,
+    b: &'a mut AsyncTcpStream,
+    pool: &BufferPool,
+) -> CopyBidiFuture<'a> {
+    CopyBidiFuture {
+        a,
+        b,
+        a_to_b: OneWay::Copying(CopyBuffer::with_buffer(pool.acquire())),
+        b_to_a: OneWay::Copying(CopyBuffer::with_buffer(pool.acquire())),
+        output: None,
+    }
+}
+
+/// Convenience futures for everything implementing [`AsyncRead`].
+pub trait AsyncReadExt: AsyncRead {
+    /// Read some bytes into `buf`, resolving with the number of bytes read.
+    fn read<'a>(&mut self, buf: &'a mut [u8]) -> ReadFuture<'_, 'a, Self> {
+        ReadFuture::new(self, buf)
+    }
+
+    /// Read some bytes into the unfilled part of `buf`, resolving with the
+    /// number of bytes read.
+    fn read_buf<'a, 'b>(&mut self, buf: &'a mut ReadBuf<'b>) -> ReadBufFuture<'_, 'a, 'b, Self> {
+        ReadBufFuture {
+            io: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Read exactly enough bytes to fill `buf`.
+    ///
+    /// Fails with `UnexpectedEof` if the reader runs out of data first.
+    fn read_exact<'a>(&mut self, buf: &'a mut [u8]) -> ReadExactFuture<'_, 'a, Self> {
+        ReadExactFuture {
+            io: self,
+            buffer: buf,
+            filled: 0,
+            output: None,
+        }
+    }
+
+    /// Read until EOF, appending to `buf` and resolving with the number of
+    /// bytes read.
+    fn read_to_end<'a>(&mut self, buf: &'a mut Vec<u8>) -> ReadToEndFuture<'_, 'a, Self> {
+        ReadToEndFuture {
+            io: self,
+            start: buf.len(),
+            buffer: buf,
+            output: None,
+        }
+    }
+}
+
+impl<T: AsyncRead + ?Sized> AsyncReadExt for T {}
+
+/// Convenience futures for everything implementing [`AsyncWrite`].
+pub trait AsyncWriteExt: AsyncWrite {
+    /// Write some bytes from `buf`, resolving with the number of bytes written.
+    fn write<'a>(&mut self, buf: &'a [u8]) -> WriteFuture<'_, 'a, Self> {
+        WriteFuture::new(self, buf)
+    }
+
+    /// Write all of `buf`.
+    ///
+    /// Fails with `WriteZero` if the writer stops accepting data first.
+    fn write_all<'a>(&mut self, buf: &'a [u8]) -> WriteAllFuture<'_, 'a, Self> {
+        WriteAllFuture {
+            io: self,
+            buffer: buf,
+            waits: WriteWaits::default(),
+            output: None,
+        }
+    }
+
+    /// Write all of `s`.
+    fn write_str<'a>(&mut self, s: &'a str) -> WriteAllFuture<'_, 'a, Self> {
+        self.write_all(s.as_bytes())
+    }
+
+    /// Format `args` and write all of it. This is what `write!` and
+    /// `writeln!` expand to, so `write!(stream, "{status}\r\n")` is a future
+    /// like any other. Writers which are an `io::Write` too, such as
+    /// `Vec<u8>`, take `AsyncWriteExt::write_fmt(w, format_args!(..))`
+    /// instead while both traits are in scope.
+    ///
+    /// `args` is formatted into a buffer the thread reuses, rather than a
+    /// `String` of its own, before anything is written. If formatting fails
+    /// the future resolves with [`WriteFmtError::Format`] and nothing is
+    /// written. [`BufWriter`] formats straight into its own buffer instead.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> WriteFmtFuture<'_, Self> {
+        let mut buffer = SPARE.with(Cell::take);
+        let output = match format_into(&mut buffer, args) {
+            Ok(()) => None,
+            Err(e) => Some(Err(e)),
+        };
+        WriteFmtFuture {
+            io: self,
+            buffer,
+            written: 0,
+            waits: WriteWaits::default(),
+            output,
+        }
+    }
+
+    /// Write out whatever the writer buffered; see
+    /// [`AsyncWrite::poll_flush`].
+    fn flush(&mut self) -> FlushFuture<'_, Self> {
+        FlushFuture {
+            io: self,
+            waits: WriteWaits::default(),
+            output: None,
+        }
+    }
+}
+
+impl<T: AsyncWrite + ?Sized> AsyncWriteExt for T {}
+
+/// Future for reading from an [`AsyncRead`] once.
+///
+/// Once a read would block, the future only tries again after one of the
+/// waitables it's blocked on shows up in the ready set. Wakeups meant for
+/// other futures, or spurious ones, don't cost a syscall.
+pub struct ReadFuture<'a, 'b, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b mut [u8],
+    /// What the last attempt was blocked on, `None` before the first one.
+    /// The first waitable, usually the only one, is kept inline, so a read
+    /// blocking doesn't allocate.
+    blocked_on: Option<Waitable>,
+    /// The rest of them.
+    also_blocked_on: Vec<Waitable>,
+    attempts: usize,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, T: AsyncRead + ?Sized> ReadFuture<'a, 'b, T> {
+    pub fn new(io: &'a mut T, buffer: &'b mut [u8]) -> Self {
+        Self {
+            io,
+            buffer,
+            blocked_on: None,
+            also_blocked_on: Vec::new(),
+            attempts: 0,
+            output: None,
+        }
+    }
+
+    /// How many times the future has tried to read so far.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+}
+
+impl<'a, 'b, T: AsyncRead + ?Sized> Future for ReadFuture<'a, 'b, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let woken = self
+            .blocked_on
+            .iter()
+            .chain(&self.also_blocked_on)
+            .any(|waitable| ready.contains(waitable));
+        if self.output.is_none() && (self.blocked_on.is_none() || woken) {
+            self.attempts += 1;
+            self.also_blocked_on.clear();
+            match self.io.poll_read(ready, self.buffer) {
+                Step::Pending(mut waitables) => {
+                    self.blocked_on = waitables.next();
+                    self.also_blocked_on.extend(waitables);
+                }
+                Step::Done(n) => {
+                    self.blocked_on = None;
+                    self.output = Some(Ok(n));
+                }
+                Step::Error(e) => {
+                    self.blocked_on = None;
+                    self.output = Some(Err(e));
+                }
+            }
+        }
+        let also_blocked_on = self.also_blocked_on.iter().copied();
+        self.blocked_on.into_iter().chain(also_blocked_on)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for writing to an [`AsyncWrite`] once.
+pub struct WriteFuture<'a, 'b, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b [u8],
+    waits: WriteWaits,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, T: AsyncWrite + ?Sized> WriteFuture<'a, 'b, T> {
+    pub fn new(io: &'a mut T, buffer: &'b [u8]) -> Self {
+        Self {
+            io,
+            buffer,
+            waits: WriteWaits::default(),
+            output: None,
+        }
+    }
+}
+
+impl<'a, 'b, T: AsyncWrite + ?Sized> Future for WriteFuture<'a, 'b, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_none() {
+            match self.io.poll_write(ready, self.buffer) {
+                Step::Pending(waitables) => self.waits.wait(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if self.output.is_some() {
+            self.waits.finish();
+        }
+        self.waits.iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncReadExt::read_buf`].
+pub struct ReadBufFuture<'a, 'b, 'c, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b mut ReadBuf<'c>,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, 'c, T: AsyncRead + ?Sized> Future for ReadBufFuture<'a, 'b, 'c, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.io.poll_read_buf(ready, self.buffer) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncReadExt::read_exact`].
+pub struct ReadExactFuture<'a, 'b, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b mut [u8],
+    filled: usize,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, 'b, T: AsyncRead + ?Sized> Future for ReadExactFuture<'a, 'b, T> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            if self.filled == self.buffer.len() {
+                self.output = Some(Ok(()));
+                break;
+            }
+            match self.io.poll_read(ready, &mut self.buffer[self.filled..]) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(0) => {
+                    let e = io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill buffer");
+                    self.output = Some(Err(e));
+                }
+                Step::Done(n) => self.filled += n,
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncReadExt::read_to_end`].
+pub struct ReadToEndFuture<'a, 'b, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b mut Vec<u8>,
+    start: usize,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, T: AsyncRead + ?Sized> Future for ReadToEndFuture<'a, 'b, T> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            // Read into the spare capacity, growing the buffer when it's full.
+            if self.buffer.len() == self.buffer.capacity() {
+                self.buffer.reserve(32);
+            }
+            let len = self.buffer.len();
+            let mut buf = ReadBuf::uninit(self.buffer.spare_capacity_mut());
+            let step = self.io.poll_read_buf(ready, &mut buf);
+            let filled = buf.filled().len();
+            // SAFETY: `ReadBuf` only counts initialized bytes as filled.
+            unsafe { self.buffer.set_len(len + filled) };
+            match step {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(0) => self.output = Some(Ok(len - self.start)),
+                Step::Done(_) => {}
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncWriteExt::write_all`].
+pub struct WriteAllFuture<'a, 'b, T: ?Sized> {
+    io: &'a mut T,
+    buffer: &'b [u8],
+    waits: WriteWaits,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, 'b, T: AsyncWrite + ?Sized> Future for WriteAllFuture<'a, 'b, T> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        while self.output.is_none() {
+            if self.buffer.is_empty() {
+                self.output = Some(Ok(()));
+                break;
+            }
+            match self.io.poll_write(ready, self.buffer) {
+                Step::Pending(waitables) => {
+                    self.waits.wait(waitables);
+                    break;
+                }
+                Step::Done(0) => {
+                    let e =
+                        io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer");
+                    self.output = Some(Err(e));
+                }
+                Step::Done(n) => self.buffer = &self.buffer[n..],
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if self.output.is_some() {
+            self.waits.finish();
+        }
+        self.waits.iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Buffers of this size or smaller are kept around for the next
+/// [`AsyncWriteExt::write_fmt`] on the thread, bigger ones are freed.
+const SPARE_CAPACITY: usize = 4 * 1024;
+
+thread_local! {
+    static SPARE: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// Append `args` to `buffer`, leaving it as it was if formatting fails.
+pub(crate) fn format_into(
+    buffer: &mut Vec<u8>,
+    args: fmt::Arguments<'_>,
+) -> Result<(), WriteFmtError> {
+    struct Adapter<'a>(&'a mut Vec<u8>);
+
+    impl fmt::Write for Adapter<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.extend_from_slice(s.as_bytes());
+            Ok(())
+        }
+    }
+
+    let len = buffer.len();
+    fmt::Write::write_fmt(&mut Adapter(buffer), args).map_err(|_| {
+        buffer.truncate(len);
+        WriteFmtError::Format
+    })
+}
+
+/// Future for [`AsyncWriteExt::write_fmt`] and [`BufWriter::write_fmt`].
+pub struct WriteFmtFuture<'a, T: ?Sized> {
+    io: &'a mut T,
+    /// The formatted bytes, of which the first `written` are written.
+    buffer: Vec<u8>,
+    written: usize,
+    waits: WriteWaits,
+    output: Option<Result<(), WriteFmtError>>,
+}
+
+impl<'a, T: ?Sized> WriteFmtFuture<'a, T> {
+    /// A future which already wrote `args`, or failed to.
+    pub(crate) fn done(io: &'a mut T, output: Result<(), WriteFmtError>) -> Self {
+        Self {
+            io,
+            buffer: Vec::new(),
+            written: 0,
+            waits: WriteWaits::default(),
+            output: Some(output),
+        }
+    }
+}
+
+impl<'a, T: AsyncWrite + ?Sized> Future for WriteFmtFuture<'a, T> {
+    type Output = Result<(), WriteFmtError>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        while self.output.is_none() {
+            if self.written == self.buffer.len() {
+                self.output = Some(Ok(()));
+                break;
+            }
+            match self.io.poll_write(ready, &self.buffer[self.written..]) {
+                Step::Pending(waitables) => {
+                    self.waits.wait(waitables);
+                    break;
+                }
+                Step::Done(0) => {
+                    let e =
+                        io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer");
+                    self.output = Some(Err(WriteFmtError::Io(e)));
+                }
+                Step::Done(n) => self.written += n,
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(WriteFmtError::Io(e))),
+            }
+        }
+        if self.output.is_some() {
+            self.waits.finish();
+        }
+        self.waits.iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+impl<T: ?Sized> Drop for WriteFmtFuture<'_, T> {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        if buffer.capacity() <= SPARE_CAPACITY {
+            buffer.clear();
+            SPARE.with(|spare| spare.set(buffer));
+        }
+    }
+}
+
+/// Error from [`AsyncWriteExt::write_fmt`], telling a formatting trait
+/// implementation which failed apart from the writer failing.
+///
+/// It converts to and from `io::Error`, so `?` works in functions returning
+/// `io::Result`. A failed formatting becomes an error of kind `Other`.
+#[derive(Debug)]
+pub enum WriteFmtError {
+    /// Formatting the arguments failed, so nothing was written.
+    Format,
+    /// Writing failed, after writing part of the output maybe.
+    Io(io::Error),
+}
+
+impl fmt::Display for WriteFmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteFmtError::Format => f.write_str("formatting the output failed"),
+            WriteFmtError::Io(e) => write!(f, "writing the formatted output failed: {e}"),
+        }
+    }
+}
+
+impl Error for WriteFmtError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WriteFmtError::Format => None,
+            WriteFmtError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for WriteFmtError {
+    fn from(e: io::Error) -> Self {
+        WriteFmtError::Io(e)
+    }
+}
+
+impl From<WriteFmtError> for io::Error {
+    fn from(e: WriteFmtError) -> Self {
+        match e {
+            WriteFmtError::Format => io::Error::other(e),
+            WriteFmtError::Io(e) => e,
+        }
+    }
+}
+
+/// Future for [`AsyncWriteExt::flush`].
+pub struct FlushFuture<'a, T: ?Sized> {
+    io: &'a mut T,
+    waits: WriteWaits,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, T: AsyncWrite + ?Sized> Future for FlushFuture<'a, T> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_none() {
+            match self.io.poll_flush(ready) {
+                Step::Pending(waitables) => self.waits.wait(waitables),
+                Step::Done(()) => self.output = Some(Ok(())),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if self.output.is_some() {
+            self.waits.finish();
+        }
+        self.waits.iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`copy`].
+pub struct CopyFuture<'a, R: ?Sized, W: ?Sized> {
+    reader: &'a mut R,
+    writer: &'a mut W,
+    buffer: CopyBuffer,
+    waits: WriteWaits,
+    output: Option<io::Result<u64>>,
+}
+
+impl<'a, R, W> Future for CopyFuture<'a, R, W>
+where
+    R: AsyncRead + ?Sized,
+    W: AsyncWrite + ?Sized,
+{
+    type Output = io::Result<u64>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_none() {
+            match self.buffer.poll_copy(ready, self.reader, self.writer) {
+                // Only the writer waits for writes.
+                Step::Pending(waitables) => self.waits.wait(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if self.output.is_some() {
+            self.waits.finish();
+        }
+        self.waits.iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`copy_bidirectional`].
+#[cfg(feature = "net")]
+pub struct CopyBidiFuture<'a> {
+    a: &'a mut AsyncTcpStream,
+    b: &'a mut AsyncTcpStream,
+    a_to_b: OneWay,
+    b_to_a: OneWay,
+    output: Option<io::Result<(u64, u64)>>,
+}
+
+#[cfg(feature = "net")]
+enum OneWay {
+    Copying(CopyBuffer),
+    Done(u64),
+}
+
+#[cfg(feature = "net")]
+impl OneWay {
+    /// Make progress copying from `reader` to `writer`. Once the reader
+    /// reaches EOF, shut down the writer and request to stop receiving write
+    /// events for it.
+    fn poll_copy(
+        &mut self,
+        ready: &[Waitable],
+        reader: &mut AsyncTcpStream,
+        writer: &mut AsyncTcpStream,
+    ) -> Step<impl Iterator<Item = Waitable> + use<>, ()> {
+        let OneWay::Copying(buffer) = self else {
+            return Step::Done(());
+        };
+        match buffer.poll_copy(ready, reader, writer) {
+            Step::Pending(waitables) => Step::Pending(Either::Left(waitables)),
+            Step::Done(n) => {
+                *self = OneWay::Done(n);
+                if let Err(e) = writer.as_std().shutdown(Shutdown::Write) {
+                    return Step::Error(e);
+                }
+                let close = Waitable::Fd(writer.as_raw_fd(), Interest::CloseWrite);
+                Step::Pending(Either::Right(iter::once(close)))
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl<'a> Future for CopyBidiFuture<'a> {
+    type Output = io::Result<(u64, u64)>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut a_to_b = None;
+        let mut b_to_a = None;
+        if self.output.is_none() {
+            match self.a_to_b.poll_copy(ready, self.a, self.b) {
+                Step::Pending(waitables) => a_to_b = Some(waitables),
+                Step::Done(()) => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if self.output.is_none() {
+            match self.b_to_a.poll_copy(ready, self.b, self.a) {
+                Step::Pending(waitables) => b_to_a = Some(waitables),
+                Step::Done(()) => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        if let (OneWay::Done(a_to_b), OneWay::Done(b_to_a)) = (&self.a_to_b, &self.b_to_a) {
+            self.output.get_or_insert(Ok((*a_to_b, *b_to_a)));
+        }
+        let a_to_b = a_to_b.into_iter().flatten();
+        a_to_b.chain(b_to_a.into_iter().flatten())
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// The buffer and bookkeeping shared by the copy futures.
+struct CopyBuffer {
+    buffer: PoolBuf,
+    /// Start of the bytes in `buffer` that still need to be written.
+    pos: usize,
+    /// End of the bytes in `buffer` that still need to be written.
+    cap: usize,
+    copied: u64,
+    read_done: bool,
+}
+
+impl CopyBuffer {
+    const SIZE: usize = 8 * 1024;
+
+    fn new() -> Self {
+        Self::with_buffer(PoolBuf::unpooled(Self::SIZE))
+    }
+
+    fn with_buffer(buffer: PoolBuf) -> Self {
+        Self {
+            buffer,
+            pos: 0,
+            cap: 0,
+            copied: 0,
+            read_done: false,
+        }
+    }
+
+    /// Alternate between filling the buffer from `reader` and draining it
+    /// into `writer`, until `reader` reaches EOF and everything it produced
+    /// has been written and flushed.
+    fn poll_copy<R, W>(
+        &mut self,
+        ready: &[Waitable],
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Step<impl Iterator<Item = Waitable> + use<R, W>, u64>
+    where
+        R: AsyncRead + ?Sized,
+        W: AsyncWrite + ?Sized,
+    {
+        loop {
+            // Drain the buffer before filling it up again.
+            if self.pos < self.cap {
+                match writer.poll_write(ready, &self.buffer[self.pos..self.cap]) {
+                    Step::Pending(waitables) => {
+                        return Step::Pending(Either::Right(Either::Left(waitables)))
+                    }
+                    Step::Done(0) => {
+                        let e =
+                            io::Error::new(io::ErrorKind::WriteZero, "write zero byte into writer");
+                        return Step::Error(e);
+                    }
+                    Step::Done(n) => {
+                        self.pos += n;
+                        self.copied += n as u64;
+                    }
+                    Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Step::Error(e) => return Step::Error(e),
+                }
+                continue;
+            }
+
+            if self.read_done {
+                return match writer.poll_flush(ready) {
+                    Step::Pending(waitables) => {
+                        Step::Pending(Either::Right(Either::Right(waitables)))
+                    }
+                    Step::Done(()) => Step::Done(self.copied),
+                    Step::Error(e) => Step::Error(e),
+                };
+            }
+
+            match reader.poll_read(ready, &mut self.buffer) {
+                Step::Pending(waitables) => return Step::Pending(Either::Left(waitables)),
+                Step::Done(0) => self.read_done = true,
+                Step::Done(n) => {
+                    self.pos = 0;
+                    self.cap = n;
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+    }
+}
+
+/// One of two iterators, for when a step can be pending on different things.
+pub(crate) enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> Iterator for Either<A, B>
+where
+    A: Iterator<Item = Waitable>,
+    B: Iterator<Item = Waitable>,
+{
+    type Item = Waitable;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Either::Left(a) => a.next(),
+            Either::Right(b) => b.next(),
+        }
+    }
+}
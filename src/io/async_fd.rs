@@ -0,0 +1,288 @@
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+
+use crate::future::{Future, Interest, Waitable};
+
+/// Adapt any object owning an fd to the poller, for fds the crate has no
+/// type of its own for: a PTY, a D-Bus socket, and so on.
+///
+/// The fd must already be in nonblocking mode; `AsyncFd` doesn't touch its
+/// flags, since they may be shared with whoever handed it over. Use
+/// [`AsyncFd::deregister`] to get the object back out once the poller has
+/// forgotten about it.
+#[derive(Debug)]
+pub struct AsyncFd<T: AsRawFd> {
+    inner: T,
+}
+
+impl<T: AsRawFd> AsRawFd for AsyncFd<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<T: AsRawFd> AsyncFd<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Wait for the fd to become readable.
+    ///
+    /// Readiness is only a hint: a read may still find nothing to read and
+    /// fail with `WouldBlock`. [`AsyncFd::try_io`] takes care of that.
+    pub fn readable(&self) -> Readiness<'_, T> {
+        Readiness::new(self, Interest::Read)
+    }
+
+    /// Wait for the fd to become writable, with the same caveat as
+    /// [`AsyncFd::readable`].
+    pub fn writable(&self) -> Readiness<'_, T> {
+        Readiness::new(self, Interest::Write)
+    }
+
+    /// Wait for an exceptional condition on the fd, such as urgent data on a
+    /// socket or a status change of a pty in packet mode, with the same
+    /// caveat as [`AsyncFd::readable`]. Ordinary readability doesn't count.
+    pub fn priority(&self) -> Readiness<'_, T> {
+        Readiness::new(self, Interest::Priority)
+    }
+
+    /// Run `f` on the inner object until it doesn't fail with `WouldBlock`,
+    /// waiting for `interest` in between, and resolve with what it returns.
+    ///
+    /// `interest` must be `Interest::Read`, `Interest::Write`, or
+    /// `Interest::Priority`.
+    pub fn try_io<F, R>(&mut self, interest: Interest, f: F) -> TryIoFuture<'_, T, F, R>
+    where
+        F: FnMut(&mut T) -> io::Result<R>,
+    {
+        assert!(
+            matches!(
+                interest,
+                Interest::Read | Interest::Write | Interest::Priority
+            ),
+            "can only wait for an fd to become readable, writable, or to have priority events"
+        );
+        TryIoFuture {
+            fd: self,
+            interest,
+            f,
+            output: None,
+        }
+    }
+
+    /// Deregister the fd from the poller, resolving with the inner object.
+    pub fn deregister(self) -> DeregisterFuture<T> {
+        DeregisterFuture {
+            inner: Some(self.inner),
+            deregistered: false,
+        }
+    }
+}
+
+/// Future for [`AsyncFd::readable`] and [`AsyncFd::writable`].
+pub struct Readiness<'a, T: AsRawFd> {
+    fd: &'a AsyncFd<T>,
+    interest: Interest,
+    /// Whether we asked to be told about the fd yet. Only readiness reported
+    /// after that counts.
+    waiting: bool,
+    output: Option<()>,
+}
+
+impl<'a, T: AsRawFd> Readiness<'a, T> {
+    fn new(fd: &'a AsyncFd<T>, interest: Interest) -> Self {
+        Self {
+            fd,
+            interest,
+            waiting: false,
+            output: None,
+        }
+    }
+}
+
+impl<'a, T: AsRawFd> Future for Readiness<'a, T> {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            let waitable = Waitable::Fd(self.fd.as_raw_fd(), self.interest);
+            match self.waiting && ready.contains(&waitable) {
+                true => self.output = Some(()),
+                false => {
+                    self.waiting = true;
+                    pending = Some(waitable);
+                }
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncFd::try_io`].
+pub struct TryIoFuture<'a, T: AsRawFd, F, R> {
+    fd: &'a mut AsyncFd<T>,
+    interest: Interest,
+    f: F,
+    output: Option<io::Result<R>>,
+}
+
+impl<'a, T, F, R> Future for TryIoFuture<'a, T, F, R>
+where
+    T: AsRawFd,
+    F: FnMut(&mut T) -> io::Result<R>,
+{
+    type Output = io::Result<R>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match (self.f)(&mut self.fd.inner) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    pending = Some(Waitable::Fd(self.fd.as_raw_fd(), self.interest));
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => self.output = Some(result),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncFd::deregister`].
+pub struct DeregisterFuture<T> {
+    inner: Option<T>,
+    deregistered: bool,
+}
+
+impl<T: AsRawFd> Future for DeregisterFuture<T> {
+    type Output = T;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let (Some(inner), false) = (&self.inner, self.deregistered) {
+            self.deregistered = true;
+            pending = Some(Waitable::Fd(inner.as_raw_fd(), Interest::Close));
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.deregistered {
+            true => self.inner.take(),
+            false => None,
+        }
+    }
+}
+
+#[cfg(feature = "mio-compat")]
+impl<T: mio::event::Source + AsRawFd> AsyncFd<T> {
+    /// Adapt a `mio` event source, such as a `mio::net::TcpStream`.
+    ///
+    /// `mio`'s sources are nonblocking already. The source mustn't be
+    /// registered with a `mio::Registry` while the poller drives it, since
+    /// both would be waiting on the same fd.
+    pub fn from_mio(source: T) -> Self {
+        Self::new(source)
+    }
+
+    /// Wait for the fd to become ready for any of `interest`, resolving with
+    /// the ones which did, with the same caveat as [`AsyncFd::readable`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interest` is neither readable, writable, nor priority: the
+    /// poller has nothing to wait for AIO or LIO events with.
+    pub fn ready(&self, interest: mio::Interest) -> MioReadiness<'_, T> {
+        let interests = [
+            (interest.is_readable(), Interest::Read),
+            (interest.is_writable(), Interest::Write),
+            (interest.is_priority(), Interest::Priority),
+        ]
+        .into_iter()
+        .filter_map(|(wanted, interest)| wanted.then_some(interest))
+        .collect::<Vec<_>>();
+        assert!(
+            !interests.is_empty(),
+            "can only wait for an fd to become readable, writable, or to have priority events"
+        );
+        MioReadiness {
+            fd: self,
+            interests,
+            waiting: false,
+            output: None,
+        }
+    }
+}
+
+/// Future for [`AsyncFd::ready`].
+#[cfg(feature = "mio-compat")]
+pub struct MioReadiness<'a, T: AsRawFd> {
+    fd: &'a AsyncFd<T>,
+    interests: Vec<Interest>,
+    /// Whether we asked to be told about the fd yet, as in [`Readiness`].
+    waiting: bool,
+    output: Option<mio::Interest>,
+}
+
+#[cfg(feature = "mio-compat")]
+impl<'a, T: AsRawFd> Future for MioReadiness<'a, T> {
+    type Output = mio::Interest;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let fd = self.fd.as_raw_fd();
+        let mut pending = Vec::new();
+        if self.output.is_none() {
+            let became_ready = self
+                .interests
+                .iter()
+                .filter(|&&interest| self.waiting && ready.contains(&Waitable::Fd(fd, interest)))
+                .map(|interest| match interest {
+                    Interest::Read => mio::Interest::READABLE,
+                    Interest::Priority => priority(),
+                    _ => mio::Interest::WRITABLE,
+                })
+                .reduce(mio::Interest::add);
+            match became_ready {
+                Some(interest) => self.output = Some(interest),
+                None => {
+                    self.waiting = true;
+                    let waitables = self.interests.iter().map(|&i| Waitable::Fd(fd, i));
+                    pending.extend(waitables);
+                }
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// `mio` only has a priority interest where it's backed by epoll.
+#[cfg(feature = "mio-compat")]
+fn priority() -> mio::Interest {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return mio::Interest::PRIORITY;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    unreachable!("mio has no priority interest to ask for here")
+}
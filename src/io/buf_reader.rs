@@ -0,0 +1,419 @@
+use std::io;
+
+use super::{AsyncRead, Either, ReadStep, Step};
+use crate::future::{Future, Waitable};
+use crate::stream::Stream;
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// Adds buffering to an [`AsyncRead`].
+///
+/// Small reads are served from an internal buffer, which is refilled with a
+/// single large read from the inner reader once it runs dry.
+pub struct BufReader<R> {
+    inner: R,
+    buffer: Box<[u8]>,
+    /// Start of the bytes in `buffer` that haven't been consumed yet.
+    pos: usize,
+    /// End of the bytes in `buffer` that haven't been consumed yet.
+    cap: usize,
+}
+
+impl<R: AsyncRead> BufReader<R> {
+    /// Create a new `BufReader` with a default capacity of 8KiB.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Create a new `BufReader` with the specified buffer capacity.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Self {
+            inner,
+            buffer: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reading directly from the inner reader skips over buffered data.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner reader. Buffered data is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The currently buffered data, without attempting a read.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer[self.pos..self.cap]
+    }
+
+    /// Return the buffered data, reading more from the inner reader if the
+    /// buffer is empty. An empty slice means the inner reader reached EOF.
+    ///
+    /// Call [`BufReader::consume`] to mark data as used.
+    pub fn fill_buf(&mut self) -> FillBufFuture<'_, R> {
+        FillBufFuture {
+            reader: Some(self),
+            output: None,
+        }
+    }
+
+    /// Mark `amt` bytes of the buffer as consumed, so they won't be returned
+    /// by subsequent reads.
+    pub fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.cap);
+    }
+
+    /// Read all bytes until and including a newline or EOF, appending them
+    /// to `buf` and resolving with the number of bytes read.
+    ///
+    /// If the line isn't valid UTF-8 the future fails with `InvalidData` and
+    /// `buf` is left untouched. The line is still consumed, but everything
+    /// buffered after it is kept for subsequent reads.
+    pub fn read_line<'a>(&mut self, buf: &'a mut String) -> ReadLineFuture<'_, 'a, R> {
+        ReadLineFuture {
+            reader: self,
+            buf,
+            line: Vec::new(),
+            output: None,
+        }
+    }
+
+    /// Read all bytes until and including `delim` or EOF, appending them to
+    /// `buf` and resolving with the number of bytes read.
+    ///
+    /// Like [`BufReader::read_line`] for any delimiter, such as the `\0`
+    /// between records. If reading fails, what was read up to then is left
+    /// in `buf`.
+    pub fn read_until<'a>(
+        &mut self,
+        delim: u8,
+        buf: &'a mut Vec<u8>,
+    ) -> ReadUntilFuture<'_, 'a, R> {
+        ReadUntilFuture {
+            reader: self,
+            delim,
+            start: buf.len(),
+            buf,
+            output: None,
+        }
+    }
+
+    /// Discard the next `n` bytes, failing with `UnexpectedEof` if the
+    /// reader ends first.
+    ///
+    /// The bytes go through the internal buffer only, however many refills
+    /// that takes, and are never copied anywhere.
+    pub fn skip(&mut self, n: u64) -> SkipFuture<'_, R> {
+        SkipFuture {
+            reader: self,
+            remaining: n,
+            output: None,
+        }
+    }
+
+    /// Turn the reader into a stream over its lines.
+    ///
+    /// Each item has its trailing `\n` or `\r\n` stripped. A final line
+    /// without a trailing newline is still yielded. Lines longer than the
+    /// buffer capacity are accumulated across refills, so there is no limit
+    /// on line length; use the lines codec if you need one. An IO error is
+    /// yielded as an `Err` item, after which the stream ends.
+    pub fn lines(self) -> Lines<R> {
+        Lines {
+            reader: self,
+            line: Vec::new(),
+            item: None,
+            done: false,
+        }
+    }
+
+    /// Fill the buffer if it's empty.
+    pub(crate) fn poll_fill_buf(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<R>, ()> {
+        if self.pos < self.cap {
+            return Step::Done(());
+        }
+        match self.inner.poll_read(ready, &mut self.buffer) {
+            Step::Pending(waitables) => Step::Pending(waitables),
+            Step::Done(n) => {
+                self.pos = 0;
+                self.cap = n;
+                Step::Done(())
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+
+    /// Move bytes into `line` until and including `delim`, resolving with
+    /// `true` once the delimiter has been found and `false` on EOF.
+    pub(crate) fn poll_read_until(
+        &mut self,
+        ready: &[Waitable],
+        delim: u8,
+        line: &mut Vec<u8>,
+    ) -> Step<impl Iterator<Item = Waitable> + use<R>, bool> {
+        loop {
+            match self.poll_fill_buf(ready) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Step::Error(e) => return Step::Error(e),
+                Step::Done(()) => {}
+            }
+            let available = self.buffer();
+            if available.is_empty() {
+                return Step::Done(false);
+            }
+            match available.iter().position(|b| *b == delim) {
+                Some(i) => {
+                    line.extend_from_slice(&available[..=i]);
+                    self.consume(i + 1);
+                    return Step::Done(true);
+                }
+                None => {
+                    line.extend_from_slice(available);
+                    let len = available.len();
+                    self.consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for BufReader<R> {
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<R>> {
+        // Skip the buffer entirely for reads at least as large as it is.
+        if self.pos == self.cap && buf.len() >= self.buffer.len() {
+            return match self.inner.poll_read(ready, buf) {
+                Step::Pending(waitables) => Step::Pending(Either::Left(waitables)),
+                Step::Done(n) => Step::Done(n),
+                Step::Error(e) => Step::Error(e),
+            };
+        }
+        match self.poll_fill_buf(ready) {
+            Step::Pending(waitables) => Step::Pending(Either::Right(waitables)),
+            Step::Done(()) => {
+                let available = self.buffer();
+                let n = available.len().min(buf.len());
+                buf[..n].copy_from_slice(&available[..n]);
+                self.consume(n);
+                Step::Done(n)
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+/// Future for [`BufReader::fill_buf`].
+pub struct FillBufFuture<'a, R> {
+    reader: Option<&'a mut BufReader<R>>,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, R: AsyncRead> Future for FillBufFuture<'a, R> {
+    type Output = io::Result<&'a [u8]>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let (None, Some(reader)) = (&self.output, &mut self.reader) {
+            match reader.poll_fill_buf(ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => self.output = Some(Ok(())),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.output.take()? {
+            Ok(()) => {
+                let reader = self.reader.take()?;
+                Some(Ok(&reader.buffer[reader.pos..reader.cap]))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Future for [`BufReader::read_until`].
+pub struct ReadUntilFuture<'a, 'b, R> {
+    reader: &'a mut BufReader<R>,
+    delim: u8,
+    buf: &'b mut Vec<u8>,
+    /// How long `buf` was to begin with.
+    start: usize,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, R: AsyncRead> Future for ReadUntilFuture<'a, 'b, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.reader.poll_read_until(ready, self.delim, self.buf) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(_) => self.output = Some(Ok(self.buf.len() - self.start)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`BufReader::skip`].
+pub struct SkipFuture<'a, R> {
+    reader: &'a mut BufReader<R>,
+    /// How many bytes are left to discard.
+    remaining: u64,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, R: AsyncRead> Future for SkipFuture<'a, R> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() && pending.is_none() {
+            if self.remaining == 0 {
+                self.output = Some(Ok(()));
+                break;
+            }
+            match self.reader.poll_fill_buf(ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+                Step::Done(()) => {
+                    let available = self.reader.buffer().len();
+                    if available == 0 {
+                        self.output = Some(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "stream ended before the bytes to skip",
+                        )));
+                        break;
+                    }
+                    let n = available.min(self.remaining.try_into().unwrap_or(usize::MAX));
+                    self.reader.consume(n);
+                    self.remaining -= n as u64;
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`BufReader::read_line`].
+pub struct ReadLineFuture<'a, 'b, R> {
+    reader: &'a mut BufReader<R>,
+    buf: &'b mut String,
+    /// The raw bytes of the line, validated once we've found its end.
+    line: Vec<u8>,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, R: AsyncRead> Future for ReadLineFuture<'a, 'b, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.reader.poll_read_until(ready, b'\n', &mut self.line) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(_) => {
+                    let line = std::mem::take(&mut self.line);
+                    let output = match String::from_utf8(line) {
+                        Ok(line) => {
+                            self.buf.push_str(&line);
+                            Ok(line.len())
+                        }
+                        Err(_) => Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "stream did not contain valid UTF-8",
+                        )),
+                    };
+                    self.output = Some(output);
+                }
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`BufReader::lines`].
+pub struct Lines<R> {
+    reader: BufReader<R>,
+    /// The raw bytes of the line, validated once we've found its end.
+    line: Vec<u8>,
+    item: Option<io::Result<String>>,
+    done: bool,
+}
+
+impl<R: AsyncRead> Stream for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<R> {
+        let mut pending = None;
+        if self.item.is_none() && !self.done {
+            match self.reader.poll_read_until(ready, b'\n', &mut self.line) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(false) if self.line.is_empty() => self.done = true,
+                Step::Done(_) => {
+                    let mut line = std::mem::take(&mut self.line);
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                        if line.last() == Some(&b'\r') {
+                            line.pop();
+                        }
+                    }
+                    let item = String::from_utf8(line).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "stream did not contain valid UTF-8",
+                        )
+                    });
+                    self.item = Some(item);
+                }
+                Step::Error(e) => {
+                    self.item = Some(Err(e));
+                    self.done = true;
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::{format_into, AsyncWrite, Either, Step, WriteFmtFuture, WriteStep};
+use crate::future::{Future, Waitable};
+
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// Adds buffering to an [`AsyncWrite`].
+///
+/// Small writes are collected in an internal buffer, which is written to the
+/// inner writer once it fills up. Writes at least as large as the buffer skip
+/// it entirely. Call [`BufWriter::flush`] to write out whatever is left.
+///
+/// Dropping a `BufWriter` makes a single nonblocking attempt to write out the
+/// buffer, and ignores any errors. Whatever the inner writer doesn't accept
+/// right away is lost, so flush explicitly or use [`BufWriter::into_inner`].
+pub struct BufWriter<W: AsyncWrite> {
+    /// Only `None` once [`BufWriter::into_inner`] has taken it.
+    inner: Option<W>,
+    buffer: Vec<u8>,
+    /// What the buffer holds before it's written out. Formatted writes can
+    /// make it grow past that for a while.
+    capacity: usize,
+}
+
+impl<W: AsyncWrite> BufWriter<W> {
+    /// Create a new `BufWriter` with a default capacity of 8KiB.
+    pub fn new(inner: W) -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, inner)
+    }
+
+    /// Create a new `BufWriter` with the specified buffer capacity.
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        Self {
+            inner: Some(inner),
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("inner writer already taken")
+    }
+
+    /// Writing directly to the inner writer skips over buffered data.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().expect("inner writer already taken")
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The data which hasn't been written to the inner writer yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Format `args` straight into the buffer, like
+    /// [`AsyncWriteExt::write_fmt`](super::AsyncWriteExt::write_fmt) but
+    /// without a buffer in between.
+    ///
+    /// The output is buffered whole even if it doesn't fit, and written out
+    /// along with the next write which doesn't fit either, or the next
+    /// flush. If formatting fails, the buffer is left as it was.
+    pub fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> WriteFmtFuture<'_, Self> {
+        let output = format_into(&mut self.buffer, args);
+        WriteFmtFuture::done(self, output)
+    }
+
+    /// Write all buffered data to the inner writer, then flush it.
+    pub fn flush(&mut self) -> FlushBufFuture<'_, W> {
+        FlushBufFuture {
+            writer: self,
+            output: None,
+        }
+    }
+
+    /// Flush the buffer, then resolve with the inner writer.
+    ///
+    /// If flushing fails the `BufWriter` is handed back along with the
+    /// error, so the buffered data isn't lost.
+    pub fn into_inner(self) -> IntoInnerFuture<W> {
+        IntoInnerFuture {
+            writer: Some(self),
+            output: None,
+        }
+    }
+
+    /// Write the buffer to the inner writer until it's empty.
+    fn poll_flush_buf(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<W>, ()> {
+        let inner = self.inner.as_mut().expect("inner writer already taken");
+        while !self.buffer.is_empty() {
+            match inner.poll_write(ready, &self.buffer) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(0) => {
+                    let e = io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the buffered data",
+                    );
+                    return Step::Error(e);
+                }
+                Step::Done(n) => {
+                    self.buffer.drain(..n);
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+        // Let go of whatever formatted writes made it grow by.
+        self.buffer.shrink_to(self.capacity);
+        Step::Done(())
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for BufWriter<W> {
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<W>> {
+        // Make room first if the data doesn't fit next to what's buffered.
+        if self.buffer.len() + buf.len() > self.capacity {
+            match self.poll_flush_buf(ready) {
+                Step::Pending(waitables) => return Step::Pending(Either::Left(waitables)),
+                Step::Done(()) => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+        // Skip the buffer entirely for writes at least as large as it is.
+        if buf.len() >= self.capacity {
+            let inner = self.inner.as_mut().expect("inner writer already taken");
+            return match inner.poll_write(ready, buf) {
+                Step::Pending(waitables) => Step::Pending(Either::Right(waitables)),
+                Step::Done(n) => Step::Done(n),
+                Step::Error(e) => Step::Error(e),
+            };
+        }
+        self.buffer.extend_from_slice(buf);
+        Step::Done(buf.len())
+    }
+
+    /// Write out the buffer, then flush the inner writer.
+    fn poll_flush(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<W>, ()> {
+        match self.poll_flush_buf(ready) {
+            Step::Pending(waitables) => return Step::Pending(Either::Left(waitables)),
+            Step::Done(()) => {}
+            Step::Error(e) => return Step::Error(e),
+        }
+        let inner = self.inner.as_mut().expect("inner writer already taken");
+        match inner.poll_flush(ready) {
+            Step::Pending(waitables) => Step::Pending(Either::Right(waitables)),
+            Step::Done(()) => Step::Done(()),
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+impl<W: AsyncWrite> Drop for BufWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.poll_flush_buf(&[]);
+        }
+    }
+}
+
+/// Future for [`BufWriter::flush`].
+pub struct FlushBufFuture<'a, W: AsyncWrite> {
+    writer: &'a mut BufWriter<W>,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a, W: AsyncWrite> Future for FlushBufFuture<'a, W> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match AsyncWrite::poll_flush(self.writer, ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => self.output = Some(Ok(())),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`BufWriter::into_inner`].
+pub struct IntoInnerFuture<W: AsyncWrite> {
+    writer: Option<BufWriter<W>>,
+    output: Option<Result<W, IntoInnerError<W>>>,
+}
+
+impl<W: AsyncWrite> Future for IntoInnerFuture<W> {
+    type Output = Result<W, IntoInnerError<W>>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let Some(writer) = &mut self.writer {
+            match writer.poll_flush_buf(ready) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => {
+                    let mut writer = self.writer.take().unwrap();
+                    self.output = writer.inner.take().map(Ok);
+                }
+                Step::Error(e) => {
+                    let writer = self.writer.take().unwrap();
+                    self.output = Some(Err(IntoInnerError(writer, e)));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Error returned by [`BufWriter::into_inner`] when flushing fails, holding
+/// on to the writer and its buffered data.
+pub struct IntoInnerError<W: AsyncWrite>(pub BufWriter<W>, pub io::Error);
+
+impl<W: AsyncWrite> fmt::Debug for IntoInnerError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("IntoInnerError").field(&self.1).finish()
+    }
+}
+
+impl<W: AsyncWrite> fmt::Display for IntoInnerError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to flush the buffered data: {}", self.1)
+    }
+}
+
+impl<W: AsyncWrite> Error for IntoInnerError<W> {}
@@ -0,0 +1,95 @@
+use std::io;
+
+use super::{AsyncRead, Step};
+use crate::future::Waitable;
+use crate::stream::Stream;
+
+/// Turn `reader` into a stream of blocks of exactly `chunk_size` bytes,
+/// each assembled from however many reads it takes.
+///
+/// At EOF the bytes left over make up a last, shorter chunk, unless there
+/// are none. An IO error is yielded as an `Err` item, after which the
+/// stream ends.
+///
+/// # Panics
+///
+/// Panics if `chunk_size` is zero.
+pub fn chunks<R: AsyncRead>(reader: R, chunk_size: usize) -> Chunks<R> {
+    assert!(chunk_size > 0, "chunk size must be nonzero");
+    Chunks {
+        reader,
+        chunk_size,
+        chunk: Vec::new(),
+        item: None,
+        done: false,
+    }
+}
+
+/// Stream for [`chunks`].
+pub struct Chunks<R> {
+    reader: R,
+    chunk_size: usize,
+    /// The chunk being assembled.
+    chunk: Vec<u8>,
+    item: Option<io::Result<Vec<u8>>>,
+    done: bool,
+}
+
+impl<R> Chunks<R> {
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Reading from the inner reader directly skips those bytes.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwrap the inner reader. The bytes of a partial chunk are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead> Stream for Chunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<R> {
+        let mut pending = None;
+        while self.item.is_none() && !self.done {
+            let len = self.chunk.len();
+            if len == self.chunk_size {
+                let chunk = Vec::with_capacity(self.chunk_size);
+                self.item = Some(Ok(std::mem::replace(&mut self.chunk, chunk)));
+                break;
+            }
+            self.chunk.resize(self.chunk_size, 0);
+            let step = self.reader.poll_read(ready, &mut self.chunk[len..]);
+            let read = match step {
+                Step::Done(n) => n,
+                _ => 0,
+            };
+            self.chunk.truncate(len + read);
+            match step {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(0) => {
+                    self.done = true;
+                    if !self.chunk.is_empty() {
+                        self.item = Some(Ok(std::mem::take(&mut self.chunk)));
+                    }
+                }
+                Step::Done(_) => {}
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => {
+                    self.item = Some(Err(e));
+                    self.done = true;
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
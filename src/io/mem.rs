@@ -0,0 +1,204 @@
+//! IO objects in memory, for testing protocols without sockets.
+//!
+//! Byte slices and `Cursor<Vec<u8>>` can be read from, and `Vec<u8>` and
+//! `Cursor<&mut [u8]>` written to, as they can with `std::io`. They're
+//! always ready, so they never yield waitables. [`duplex`] makes a pair of
+//! connected streams which do wait, on each other.
+
+use std::collections::VecDeque;
+use std::io::{self, Cursor};
+use std::iter;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{AsyncRead, AsyncWrite, ReadStep, Step, WriteStep};
+use crate::future::Waitable;
+use crate::runtime::completion;
+
+impl<'a> AsyncRead for &'a [u8] {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a>> {
+        ready(io::Read::read(self, buf))
+    }
+}
+
+impl AsyncRead for Cursor<Vec<u8>> {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        ready(io::Read::read(self, buf))
+    }
+}
+
+impl AsyncWrite for Vec<u8> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        self.extend_from_slice(buf);
+        Step::<iter::Empty<Waitable>>::Done(buf.len())
+    }
+}
+
+/// Writing past the end of the slice writes nothing, rather than blocking.
+impl<'a> AsyncWrite for Cursor<&'a mut [u8]> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<'a>> {
+        ready(io::Write::write(self, buf))
+    }
+}
+
+// The step of an operation which never blocks.
+fn ready(result: io::Result<usize>) -> Step<iter::Empty<Waitable>> {
+    match result {
+        Ok(n) => Step::Done(n),
+        Err(e) => Step::Error(e),
+    }
+}
+
+/// Create a pair of connected in-memory streams: what's written to one is
+/// read from the other.
+///
+/// Each direction buffers up to `capacity` bytes. Writes wait while the
+/// buffer is full, and reads while it's empty, on a completion the other
+/// end completes, so they work on any poller and across threads. Once one
+/// end is dropped, reads from the other drain the buffer and then reach
+/// EOF, and writes to it fail with `BrokenPipe`.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "capacity must be nonzero");
+    let a_to_b = Arc::new(Mutex::new(Buffer::new(capacity)));
+    let b_to_a = Arc::new(Mutex::new(Buffer::new(capacity)));
+    let a = DuplexStream {
+        read: b_to_a.clone(),
+        write: a_to_b.clone(),
+    };
+    let b = DuplexStream {
+        read: a_to_b,
+        write: b_to_a,
+    };
+    (a, b)
+}
+
+/// One end of a [`duplex`] pair.
+#[derive(Debug)]
+pub struct DuplexStream {
+    read: Arc<Mutex<Buffer>>,
+    write: Arc<Mutex<Buffer>>,
+}
+
+/// One direction of a [`duplex`] pair.
+#[derive(Debug)]
+struct Buffer {
+    bytes: VecDeque<u8>,
+    capacity: usize,
+    /// Whether the ends which read and write it are gone.
+    reader_closed: bool,
+    writer_closed: bool,
+    /// Complete while there's something to read, or the writer is gone.
+    readable: u64,
+    /// Complete while there's room to write, or the reader is gone.
+    writable: u64,
+}
+
+impl Buffer {
+    fn new(capacity: usize) -> Self {
+        let writable = completion::register();
+        completion::complete(writable);
+        Self {
+            bytes: VecDeque::with_capacity(capacity),
+            capacity,
+            reader_closed: false,
+            writer_closed: false,
+            readable: completion::register(),
+            writable,
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        completion::remove(self.readable);
+        completion::remove(self.writable);
+    }
+}
+
+fn lock(buffer: &Mutex<Buffer>) -> MutexGuard<'_, Buffer> {
+    buffer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        let mut buffer = lock(&self.read);
+        if buf.is_empty() {
+            return Step::Done(0);
+        }
+        if buffer.bytes.is_empty() {
+            if buffer.writer_closed {
+                return Step::Done(0);
+            }
+            // The writer completes it again once it writes, which it can
+            // only do after we let go of the lock.
+            completion::reset(buffer.readable);
+            return Step::Pending(iter::once(Waitable::Completion(buffer.readable)));
+        }
+        let n = buf.len().min(buffer.bytes.len());
+        for (byte, read) in buf.iter_mut().zip(buffer.bytes.drain(..n)) {
+            *byte = read;
+        }
+        completion::complete(buffer.writable);
+        Step::Done(n)
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        let mut buffer = lock(&self.write);
+        if buffer.reader_closed {
+            return Step::Error(io::ErrorKind::BrokenPipe.into());
+        }
+        if buf.is_empty() {
+            return Step::Done(0);
+        }
+        let room = buffer.capacity - buffer.bytes.len();
+        if room == 0 {
+            completion::reset(buffer.writable);
+            return Step::Pending(iter::once(Waitable::Completion(buffer.writable)));
+        }
+        let n = buf.len().min(room);
+        buffer.bytes.extend(&buf[..n]);
+        completion::complete(buffer.readable);
+        Step::Done(n)
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        let mut read = lock(&self.read);
+        read.reader_closed = true;
+        completion::complete(read.writable);
+        drop(read);
+        let mut write = lock(&self.write);
+        write.writer_closed = true;
+        completion::complete(write.readable);
+    }
+}
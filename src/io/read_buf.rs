@@ -0,0 +1,110 @@
+use std::mem::MaybeUninit;
+
+/// A buffer to read into which may be partially uninitialized.
+///
+/// The buffer is split into three regions: the filled part, which holds
+/// data, the initialized part past it, which holds garbage but may be read,
+/// and the rest, which may not be read at all. Reads go into everything past
+/// the filled part, so readers which don't need zeroed memory can skip the
+/// cost of zeroing it.
+pub struct ReadBuf<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+    initialized: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    /// Wrap a buffer which is fully initialized.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        let initialized = buf.len();
+        // SAFETY: `u8` and `MaybeUninit<u8>` have the same layout, and we
+        // never write uninitialized bytes into the initialized region.
+        let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        Self {
+            buf,
+            filled: 0,
+            initialized,
+        }
+    }
+
+    /// Wrap a buffer which is entirely uninitialized, such as the spare
+    /// capacity of a `Vec`.
+    pub fn uninit(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self {
+            buf,
+            filled: 0,
+            initialized: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// The data read so far.
+    pub fn filled(&self) -> &[u8] {
+        // SAFETY: everything up to `filled` is initialized.
+        unsafe { &*(&self.buf[..self.filled] as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    /// How many bytes, filled or not, are known to be initialized.
+    pub fn initialized_len(&self) -> usize {
+        self.initialized
+    }
+
+    /// How many more bytes can be read into the buffer.
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.filled
+    }
+
+    /// Forget about the data read so far, keeping the memory initialized.
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+
+    /// The part of the buffer past the filled data, which may be
+    /// uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// The caller may not de-initialize any of the returned bytes, for
+    /// example by writing `MaybeUninit::uninit()` into them.
+    pub unsafe fn unfilled(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.buf[self.filled..]
+    }
+
+    /// The part of the buffer past the filled data, zeroing whatever of it
+    /// isn't initialized yet.
+    pub fn initialize_unfilled(&mut self) -> &mut [u8] {
+        for byte in &mut self.buf[self.initialized..] {
+            byte.write(0);
+        }
+        self.initialized = self.buf.len();
+        // SAFETY: we just initialized everything.
+        unsafe { &mut *(&mut self.buf[self.filled..] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    /// Mark `n` more bytes past the filled data as filled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if those bytes aren't initialized.
+    pub fn advance(&mut self, n: usize) {
+        let filled = self.filled.checked_add(n).expect("filled overflow");
+        assert!(
+            filled <= self.initialized,
+            "advanced past the initialized part of the buffer"
+        );
+        self.filled = filled;
+    }
+
+    /// Mark `n` bytes past the filled data as initialized.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have initialized those bytes, for example by reading
+    /// into [`ReadBuf::unfilled`].
+    pub unsafe fn assume_init(&mut self, n: usize) {
+        self.initialized = self.initialized.max(self.filled + n);
+    }
+}
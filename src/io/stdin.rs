@@ -0,0 +1,83 @@
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+use super::{read_buf, AsyncRead, AsyncReadExt, ReadBuf, ReadFuture, ReadStep};
+use crate::future::Waitable;
+
+/// Get a handle to the standard input of the process, switching it to
+/// nonblocking mode.
+///
+/// The fd's original flags are restored when the handle is dropped. Those
+/// flags belong to the open file description rather than the fd, so they're
+/// shared with the shell and anything else reading the same terminal or pipe.
+/// Keep a single handle around for as long as needed rather than making a
+/// new one per read, and don't read from [`std::io::stdin`] meanwhile: it
+/// would see `WouldBlock` errors.
+///
+/// If stdin is a regular file, reads never block and the poller considers it
+/// always ready; reading it works all the same.
+pub fn stdin() -> io::Result<AsyncStdin> {
+    let fd = io::stdin().as_raw_fd();
+    // Not `FIONBIO`, since we need to know the original flags to restore them.
+    // SAFETY: `F_GETFL` and `F_SETFL` take and return plain integers.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(AsyncStdin { fd, flags })
+}
+
+/// Handle to the standard input of the process, created by [`stdin`].
+#[derive(Debug)]
+pub struct AsyncStdin {
+    fd: RawFd,
+    /// The file status flags from before we set `O_NONBLOCK`.
+    flags: libc::c_int,
+}
+
+impl AsRawFd for AsyncStdin {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl AsFd for AsyncStdin {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // SAFETY: stdin stays open for the life of the process.
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+impl AsyncStdin {
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> ReadFuture<'_, 'a, Self> {
+        AsyncReadExt::read(self, data)
+    }
+}
+
+impl AsyncRead for AsyncStdin {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(self.as_fd(), &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(self.as_fd(), buf)
+    }
+}
+
+impl Drop for AsyncStdin {
+    fn drop(&mut self) {
+        // SAFETY: see `stdin`.
+        unsafe { libc::fcntl(self.fd, libc::F_SETFL, self.flags) };
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Write};
+use std::time::Instant;
+
+use super::{AsyncRead, AsyncWrite, ReadBuf, ReadStep, Step, WriteStep};
+use crate::future::Waitable;
+
+/// Which way bytes went through a [`Tee`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// Shows the bytes going through a reader or writer, for debugging.
+///
+/// Every read and write is forwarded to the inner value as is, so pending,
+/// partial, and failed operations behave the same as without the `Tee`.
+/// Each completed one is passed to the callback as well, with its direction
+/// and when it completed; reaching EOF is an empty read.
+pub struct Tee<T, F> {
+    inner: T,
+    inspect: F,
+}
+
+impl<T, F> Tee<T, F>
+where
+    F: FnMut(Direction, Instant, &[u8]),
+{
+    /// Call `inspect` with the bytes of every read and write.
+    pub fn new(inner: T, inspect: F) -> Self {
+        Self { inner, inspect }
+    }
+}
+
+impl<T> Tee<T, ()> {
+    /// Write a hexdump of every read and write to `sink`, timestamped from
+    /// when the `Tee` was created.
+    ///
+    /// Failing to write to the sink doesn't fail the operation.
+    pub fn hexdump<W: Write>(
+        inner: T,
+        mut sink: W,
+    ) -> Tee<T, impl FnMut(Direction, Instant, &[u8])> {
+        let start = Instant::now();
+        Tee::new(inner, move |direction, at: Instant, bytes: &[u8]| {
+            let _ = hexdump(
+                &mut sink,
+                at.duration_since(start).as_secs_f64(),
+                direction,
+                bytes,
+            );
+        })
+    }
+
+    /// Write a hexdump of every read and write to stderr; see
+    /// [`Tee::hexdump`].
+    pub fn hexdump_to_stderr(inner: T) -> Tee<T, impl FnMut(Direction, Instant, &[u8])> {
+        Tee::hexdump(inner, io::stderr())
+    }
+}
+
+impl<T, F> Tee<T, F> {
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Reading from or writing to the inner value directly isn't shown.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, F> AsyncRead for Tee<T, F>
+where
+    T: AsyncRead,
+    F: FnMut(Direction, Instant, &[u8]),
+{
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T, F>> {
+        let step = self.inner.poll_read(ready, buf);
+        if let Step::Done(n) = step {
+            (self.inspect)(Direction::Read, Instant::now(), &buf[..n]);
+        }
+        step
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T, F>> {
+        let filled = buf.filled().len();
+        let step = self.inner.poll_read_buf(ready, buf);
+        if let Step::Done(n) = step {
+            let read = &buf.filled()[filled..filled + n];
+            (self.inspect)(Direction::Read, Instant::now(), read);
+        }
+        step
+    }
+}
+
+impl<T, F> AsyncWrite for Tee<T, F>
+where
+    T: AsyncWrite,
+    F: FnMut(Direction, Instant, &[u8]),
+{
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<T, F>> {
+        let step = self.inner.poll_write(ready, buf);
+        if let Step::Done(n) = step {
+            (self.inspect)(Direction::Write, Instant::now(), &buf[..n]);
+        }
+        step
+    }
+
+    fn poll_flush(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<T, F>, ()> {
+        self.inner.poll_flush(ready)
+    }
+}
+
+/// How many bytes a line of a hexdump shows.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Write `bytes` as a header line, then lines of an offset, the bytes in
+/// hex, and the printable ones as ASCII.
+fn hexdump(sink: &mut impl Write, secs: f64, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+    match (direction, bytes.len()) {
+        (Direction::Read, 0) => writeln!(sink, "[{secs:10.6}s] read EOF")?,
+        (Direction::Read, n) => writeln!(sink, "[{secs:10.6}s] read {n} bytes")?,
+        (Direction::Write, n) => writeln!(sink, "[{secs:10.6}s] write {n} bytes")?,
+    }
+    for (line, chunk) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+        write!(sink, "{:08x} ", line * HEXDUMP_WIDTH)?;
+        for i in 0..HEXDUMP_WIDTH {
+            match chunk.get(i) {
+                Some(byte) => write!(sink, " {byte:02x}")?,
+                None => write!(sink, "   ")?,
+            }
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                true => byte as char,
+                false => '.',
+            })
+            .collect();
+        writeln!(sink, "  |{ascii}|")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::iter;
+use std::time::{Duration, Instant};
+
+use super::{AsyncRead, AsyncWrite, Either, ReadStep, Step, WriteStep};
+use crate::future::Waitable;
+
+/// Caps the throughput of a reader or writer.
+///
+/// Throughput is limited with a token bucket: every byte read or written
+/// costs a token, tokens refill at `bytes_per_sec`, and the bucket holds at
+/// most `burst` of them. The bucket starts out empty, so the cap applies from
+/// the first byte. Reads and writes draw from the same bucket.
+///
+/// Operations which run out of tokens are cut short, and once the bucket is
+/// empty they wait on a timer until it has refilled enough.
+#[derive(Debug)]
+pub struct Throttle<T> {
+    inner: T,
+    bytes_per_sec: u64,
+    burst: u64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl<T> Throttle<T> {
+    /// Create a new `Throttle`, with a burst of one second's worth of bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn new(inner: T, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "throughput must be nonzero");
+        Self {
+            inner,
+            bytes_per_sec,
+            burst: bytes_per_sec,
+            tokens: 0.0,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Set how many bytes may be transferred at once after a quiet period.
+    /// Defaults to `bytes_per_sec`.
+    pub fn burst(mut self, burst: u64) -> Self {
+        self.burst = burst.max(1);
+        self.tokens = self.tokens.min(self.burst as f64);
+        self
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Reading from or writing to the inner value directly bypasses the
+    /// limit.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Refill the bucket and return how many of `wanted` bytes may be
+    /// transferred now, or the timer to wait for if none may.
+    fn budget(&mut self, wanted: usize) -> Result<usize, Waitable> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec as f64).min(self.burst as f64);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 || wanted == 0 {
+            return Ok(wanted.min(self.tokens as usize));
+        }
+        // Wait until the whole operation fits, as far as the burst allows,
+        // rather than trickling out a byte at a time.
+        let needed = wanted.min(self.burst as usize) as f64 - self.tokens;
+        let wait = Duration::from_secs_f64(needed / self.bytes_per_sec as f64);
+        Err(Waitable::Timer(now + wait))
+    }
+
+    fn spend(&mut self, n: usize) {
+        self.tokens -= n as f64;
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Throttle<T> {
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T>> {
+        let n = match self.budget(buf.len()) {
+            Ok(n) => n,
+            Err(timer) => return Step::Pending(Either::Right(iter::once(timer))),
+        };
+        match self.inner.poll_read(ready, &mut buf[..n]) {
+            Step::Pending(waitables) => Step::Pending(Either::Left(waitables)),
+            Step::Done(n) => {
+                self.spend(n);
+                Step::Done(n)
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Throttle<T> {
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<T>> {
+        let n = match self.budget(buf.len()) {
+            Ok(n) => n,
+            Err(timer) => return Step::Pending(Either::Right(iter::once(timer))),
+        };
+        match self.inner.poll_write(ready, &buf[..n]) {
+            Step::Pending(waitables) => Step::Pending(Either::Left(waitables)),
+            Step::Done(n) => {
+                self.spend(n);
+                Step::Done(n)
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+
+    /// What the inner writer buffered was paid for as it was written, so
+    /// flushing it isn't throttled.
+    fn poll_flush(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<T>, ()> {
+        self.inner.poll_flush(ready)
+    }
+}
@@ -0,0 +1,90 @@
+//! Experimenting with a different formulation of Future.
+//!
+//! Futures here don't take a waker. Polling one hands it the [`Waitable`]s
+//! which became ready since the last poll, and it answers with the ones it
+//! needs to become ready before it can make progress. [`Poller::block_on`]
+//! drives a future to completion that way on top of kqueue, or on other
+//! platforms on top of a slower fallback of threads blocking in `poll(2)`.
+//! The `threads-backend` feature uses the fallback on macOS as well.
+//!
+//! That covers every Unix, but only Unix: on Windows and other non-Unix
+//! targets the crate compiles to nothing at all.
+//!
+//! The futures, the poller, and the IO traits are always there. The rest
+//! comes in cargo features, which are all on by default through `full`:
+//! `net` for sockets, `time` for timers, `process` for child processes, `fs`
+//! for files, and `sync` for channels and locks. `blocking` brings the
+//! blocking pool, which `net` and `fs` turn on themselves; `net` turns on
+//! `time` and `sync` too.
+//!
+//! The futures the crate ships fail with an [`Error`], which is an
+//! `io::Error` saying what was being done, on which fd, and with which peer.
+//!
+//! With the `macros` feature, the `main` and `test` attributes turn a function
+//! returning a future into one running it, which saves building a poller
+//! by hand.
+//!
+//! [`Waitable`]: future::Waitable
+//! [`Poller::block_on`]: runtime::Poller::block_on
+
+// Everything is built on fds, so there's nothing here for other platforms;
+// see the crate docs.
+#![cfg(unix)]
+
+/// Emit a `tracing` event at trace level with the `tracing` feature, and
+/// nothing without it. Arguments are only evaluated with the feature on.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod codec;
+pub mod compat;
+pub mod error;
+#[cfg(feature = "net")]
+pub mod examples_support;
+#[cfg(feature = "fs")]
+pub mod fs;
+pub mod future;
+#[cfg(feature = "net")]
+pub mod http;
+pub mod io;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod pipe;
+#[cfg(feature = "process")]
+pub mod process;
+#[cfg(feature = "net")]
+pub mod proxy;
+pub mod runtime;
+pub mod signal;
+pub mod stream;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(feature = "net")]
+pub mod tcp;
+#[cfg(feature = "time")]
+pub mod time;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "net")]
+pub mod udp;
+#[cfg(feature = "net")]
+pub mod unix;
+
+pub use error::Error;
+pub use runtime::RuntimeError;
+
+#[cfg(feature = "macros")]
+pub use playground_future_2_0_macros::{main, test};
+
+/// The traits needed to use most of the crate, for glob importing.
+pub mod prelude {
+    pub use crate::future::{Future, IntoFuture};
+    pub use crate::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    pub use crate::stream::{Stream, StreamExt};
+}
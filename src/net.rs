@@ -0,0 +1,67 @@
+//! Name resolution, serving connections, and other networking which
+//! doesn't fit elsewhere.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+use crate::blocking::BlockingTask;
+use crate::future::{Future, Waitable};
+use crate::runtime;
+
+mod icmp;
+mod idle;
+mod serve;
+
+pub use icmp::{checksum, AsyncIcmpSocket, EchoPacket, PingFuture, RecvFromFuture, SendToFuture};
+pub use idle::IdleTimeout;
+pub use serve::{echo, serve, Echo, Serve};
+
+/// Resolve `host` to the addresses it points to, paired with `port`.
+///
+/// `getaddrinfo` blocks, so it runs on the blocking pool. Literal IP
+/// addresses, including IPv6 addresses in brackets, resolve right away
+/// without going through the pool.
+pub fn resolve(host: &str, port: u16) -> ResolveFuture {
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = literal.parse::<IpAddr>() {
+        return ResolveFuture {
+            task: None,
+            output: Some(Ok(vec![SocketAddr::new(ip, port)])),
+        };
+    }
+    let host = host.to_owned();
+    ResolveFuture {
+        task: Some(runtime::spawn_blocking(move || {
+            Ok((host.as_str(), port).to_socket_addrs()?.collect())
+        })),
+        output: None,
+    }
+}
+
+/// Future for [`resolve`].
+pub struct ResolveFuture {
+    /// `None` for literal addresses.
+    task: Option<BlockingTask<io::Result<Vec<SocketAddr>>>>,
+    output: Option<io::Result<Vec<SocketAddr>>>,
+}
+
+impl Future for ResolveFuture {
+    type Output = io::Result<Vec<SocketAddr>>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let (Some(task), None) = (&mut self.task, &self.output) {
+            // The task waits on at most its own completion.
+            let waitable = task.poll(ready).next();
+            match task.take() {
+                Some(output) => self.output = Some(output),
+                None => pending = waitable,
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
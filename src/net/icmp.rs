@@ -0,0 +1,354 @@
+//! ICMP echo, for pinging hosts.
+//!
+//! The socket is an unprivileged `SOCK_DGRAM` socket for `IPPROTO_ICMP`, so
+//! no root is needed. macOS hands us the IP header along with each reply and
+//! delivers every reply on the host to every such socket, so replies are
+//! matched by identifier. Linux strips the header, and sets the identifier
+//! itself and only delivers our own replies.
+
+use rustix::io::FdFlags;
+use rustix::net::{ipproto, AddressFamily, SocketType};
+use std::io;
+use std::iter;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::future::{Future, Interest, Waitable};
+use crate::io::Step;
+use crate::time::{self, Timeout};
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+
+/// How long an ICMP echo header is.
+const HEADER_LEN: usize = 8;
+
+/// An ICMP echo request or reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoPacket {
+    /// Whether this is a reply rather than a request.
+    pub reply: bool,
+    pub identifier: u16,
+    pub sequence: u16,
+    pub payload: Vec<u8>,
+}
+
+impl EchoPacket {
+    pub fn request(identifier: u16, sequence: u16, payload: &[u8]) -> Self {
+        Self {
+            reply: false,
+            identifier,
+            sequence,
+            payload: payload.to_vec(),
+        }
+    }
+
+    /// Encode the packet, checksum included.
+    pub fn encode(&self) -> Vec<u8> {
+        let kind = match self.reply {
+            true => ECHO_REPLY,
+            false => ECHO_REQUEST,
+        };
+        let mut packet = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        packet.extend_from_slice(&[kind, 0, 0, 0]);
+        packet.extend_from_slice(&self.identifier.to_be_bytes());
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&self.payload);
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        packet
+    }
+
+    /// Decode an echo request or reply, skipping the IPv4 header in front
+    /// of it if there is one.
+    ///
+    /// Fails with `InvalidData` for truncated packets, bad checksums, and
+    /// ICMP messages other than echoes.
+    pub fn decode(packet: &[u8]) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        // Echo messages start with a type of 0 or 8, where an IPv4 header
+        // starts with a version of 4.
+        let packet = match packet.first() {
+            Some(byte) if byte >> 4 == 4 => {
+                let header_len = usize::from(byte & 0x0f) * 4;
+                packet
+                    .get(header_len..)
+                    .ok_or_else(|| invalid("truncated IP header"))?
+            }
+            _ => packet,
+        };
+        if packet.len() < HEADER_LEN {
+            return Err(invalid("truncated ICMP header"));
+        }
+        let reply = match packet[0] {
+            ECHO_REPLY => true,
+            ECHO_REQUEST => false,
+            _ => return Err(invalid("not an ICMP echo message")),
+        };
+        if packet[1] != 0 {
+            return Err(invalid("unexpected ICMP echo code"));
+        }
+        if checksum(packet) != 0 {
+            return Err(invalid("bad ICMP checksum"));
+        }
+        Ok(Self {
+            reply,
+            identifier: u16::from_be_bytes([packet[4], packet[5]]),
+            sequence: u16::from_be_bytes([packet[6], packet[7]]),
+            payload: packet[HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// The internet checksum of `data`, per RFC 1071. Checksumming a packet
+/// which includes its own correct checksum yields zero.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// An unprivileged ICMP socket, for sending echo requests and receiving the
+/// replies.
+#[derive(Debug)]
+pub struct AsyncIcmpSocket {
+    socket: UdpSocket,
+    /// The identifier our requests carry, to tell our replies apart.
+    identifier: u16,
+}
+
+impl AsRawFd for AsyncIcmpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl AsyncIcmpSocket {
+    /// Open a socket. This fails with `PermissionDenied` where unprivileged
+    /// ICMP is disabled, as it is in some sandboxes and by default on some
+    /// Linux distributions.
+    pub fn new() -> io::Result<Self> {
+        let socket =
+            rustix::net::socket(AddressFamily::INET, SocketType::DGRAM, Some(ipproto::ICMP))?;
+        rustix::io::fcntl_setfd(&socket, FdFlags::CLOEXEC)?;
+        rustix::io::ioctl_fionbio(&socket, true)?;
+        // Sockets in one process mostly get different identifiers, and
+        // processes mostly get different ones too.
+        static NEXT: AtomicU16 = AtomicU16::new(0);
+        let identifier = (std::process::id() as u16) ^ NEXT.fetch_add(0x9e37, Ordering::Relaxed);
+        Ok(Self {
+            socket: socket.into(),
+            identifier,
+        })
+    }
+
+    /// The identifier this socket's echo requests carry.
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Send an ICMP message, such as an encoded [`EchoPacket`], to `target`.
+    pub fn send_to<'a>(&self, packet: &'a [u8], target: Ipv4Addr) -> SendToFuture<'_, 'a> {
+        SendToFuture {
+            socket: self,
+            packet,
+            target,
+            output: None,
+        }
+    }
+
+    /// Receive an ICMP message, resolving with its length and sender. On
+    /// macOS the message starts with its IP header; [`EchoPacket::decode`]
+    /// skips it.
+    pub fn recv_from<'a>(&self, buf: &'a mut [u8]) -> RecvFromFuture<'_, 'a> {
+        RecvFromFuture {
+            socket: self,
+            buf,
+            output: None,
+        }
+    }
+
+    /// Send an echo request with `sequence` to `target`, resolving with the
+    /// round-trip time once the matching reply arrives. Fails with
+    /// [`Elapsed`](crate::time::Elapsed) if it doesn't within `timeout`.
+    ///
+    /// Replies for other sockets and sequence numbers are skipped.
+    pub fn ping(
+        &self,
+        target: Ipv4Addr,
+        sequence: u16,
+        timeout: Duration,
+    ) -> Timeout<PingFuture<'_>> {
+        let packet = EchoPacket::request(self.identifier, sequence, b"playground-future");
+        time::timeout(
+            timeout,
+            PingFuture {
+                socket: self,
+                target,
+                request: packet.encode(),
+                sequence,
+                sent: None,
+                output: None,
+            },
+        )
+    }
+
+    fn poll_send_to(&self, packet: &[u8], target: Ipv4Addr) -> Step<iter::Once<Waitable>> {
+        let target = SocketAddr::V4(SocketAddrV4::new(target, 0));
+        Step::from_syscall(
+            self.socket.send_to(packet, target),
+            self.as_raw_fd(),
+            Interest::Write,
+        )
+    }
+
+    fn poll_recv_from(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>, (usize, Ipv4Addr)> {
+        let result = self.socket.recv_from(buf).and_then(|(n, addr)| match addr {
+            SocketAddr::V4(addr) => Ok((n, *addr.ip())),
+            SocketAddr::V6(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "IPv6 sender on an IPv4 socket",
+            )),
+        });
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Read)
+    }
+
+    /// Whether `reply` answers our request with `sequence`.
+    fn is_ours(&self, reply: &EchoPacket, sequence: u16) -> bool {
+        // Linux already only delivers our replies, and rewrites the
+        // identifier on the way out.
+        let identifier_matches = cfg!(target_os = "linux") || reply.identifier == self.identifier;
+        reply.reply && identifier_matches && reply.sequence == sequence
+    }
+}
+
+/// Future for [`AsyncIcmpSocket::send_to`].
+pub struct SendToFuture<'s, 'a> {
+    socket: &'s AsyncIcmpSocket,
+    packet: &'a [u8],
+    target: Ipv4Addr,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'s, 'a> Future for SendToFuture<'s, 'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.socket.poll_send_to(self.packet, self.target) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncIcmpSocket::recv_from`].
+pub struct RecvFromFuture<'s, 'a> {
+    socket: &'s AsyncIcmpSocket,
+    buf: &'a mut [u8],
+    output: Option<io::Result<(usize, Ipv4Addr)>>,
+}
+
+impl<'s, 'a> Future for RecvFromFuture<'s, 'a> {
+    type Output = io::Result<(usize, Ipv4Addr)>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_recv_from(self.buf) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(received) => self.output = Some(Ok(received)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncIcmpSocket::ping`], inside its timeout.
+pub struct PingFuture<'s> {
+    socket: &'s AsyncIcmpSocket,
+    target: Ipv4Addr,
+    request: Vec<u8>,
+    sequence: u16,
+    /// When the request went out, once it has.
+    sent: Option<Instant>,
+    output: Option<io::Result<Duration>>,
+}
+
+impl<'s> Future for PingFuture<'s> {
+    type Output = io::Result<Duration>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            let Some(sent) = self.sent else {
+                match self.socket.poll_send_to(&self.request, self.target) {
+                    Step::Pending(waitables) => {
+                        pending = Some(waitables);
+                        break;
+                    }
+                    Step::Done(_) => self.sent = Some(Instant::now()),
+                    Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Step::Error(e) => self.output = Some(Err(e)),
+                }
+                continue;
+            };
+            // Room for the IP header and options in front of the echo.
+            let mut buf = [0; 60 + HEADER_LEN + 64];
+            match self.socket.poll_recv_from(&mut buf) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done((n, from)) => {
+                    // Anything other than our reply from our target is
+                    // somebody else's business.
+                    let ours = match EchoPacket::decode(&buf[..n]) {
+                        Ok(reply) => {
+                            from == self.target && self.socket.is_ours(&reply, self.sequence)
+                        }
+                        Err(_) => false,
+                    };
+                    if ours {
+                        self.output = Some(Ok(sent.elapsed()));
+                    }
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+use std::iter;
+use std::time::{Duration, Instant};
+
+use crate::future::Waitable;
+use crate::io::{AsyncRead, AsyncWrite, ReadBuf, ReadStep, Step, WriteStep};
+
+/// Closes a connection which goes quiet.
+///
+/// Whenever `dur` passes without a byte being read or written, the pending
+/// operation fails with [`io::ErrorKind::TimedOut`], or the next one does if
+/// none is, and so does every operation after it. Reads and writes share the
+/// deadline: traffic in either direction keeps the connection alive.
+#[derive(Debug)]
+pub struct IdleTimeout<T> {
+    inner: T,
+    dur: Duration,
+    /// Pushed back by every transfer.
+    deadline: Instant,
+    closed: bool,
+}
+
+impl<T> IdleTimeout<T> {
+    pub fn new(inner: T, dur: Duration) -> Self {
+        Self {
+            inner,
+            dur,
+            deadline: Instant::now() + dur,
+            closed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Reading from or writing to the inner value directly doesn't count as
+    /// activity.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Whether the connection went idle for too long.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Fail with `TimedOut` if the connection went idle for too long.
+    fn check(&mut self) -> io::Result<()> {
+        if !self.closed && Instant::now() >= self.deadline {
+            self.closed = true;
+        }
+        match self.closed {
+            true => Err(io::ErrorKind::TimedOut.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Push the deadline back if the operation transferred anything, and
+    /// wait on it alongside the operation if it's pending.
+    fn track<W>(&mut self, step: Step<W>) -> Step<iter::Chain<W, iter::Once<Waitable>>>
+    where
+        W: Iterator<Item = Waitable>,
+    {
+        match step {
+            Step::Pending(waitables) => {
+                Step::Pending(waitables.chain(iter::once(Waitable::Timer(self.deadline))))
+            }
+            Step::Done(n) => {
+                if n > 0 {
+                    self.deadline = Instant::now() + self.dur;
+                }
+                Step::Done(n)
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for IdleTimeout<T> {
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T>> {
+        if let Err(e) = self.check() {
+            return Step::Error(e);
+        }
+        let step = self.inner.poll_read(ready, buf);
+        self.track(step)
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<T>> {
+        if let Err(e) = self.check() {
+            return Step::Error(e);
+        }
+        let step = self.inner.poll_read_buf(ready, buf);
+        self.track(step)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for IdleTimeout<T> {
+    fn poll_write(
+        &mut self,
+        ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<T>> {
+        if let Err(e) = self.check() {
+            return Step::Error(e);
+        }
+        let step = self.inner.poll_write(ready, buf);
+        self.track(step)
+    }
+
+    fn poll_flush(
+        &mut self,
+        ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<T>, ()> {
+        if let Err(e) = self.check() {
+            return Step::Error(e);
+        }
+        match self.inner.poll_flush(ready) {
+            Step::Pending(waitables) => {
+                Step::Pending(waitables.chain(iter::once(Waitable::Timer(self.deadline))))
+            }
+            Step::Done(()) => Step::Done(()),
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Accepting connections and handing each to a task of its own.
+
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+
+use crate::error::{self, Operation};
+use crate::future::{Future, Interest, IntoFuture, Waitable};
+use crate::io::{AsyncRead, AsyncWrite, Step};
+use crate::runtime::{self, JoinHandle};
+use crate::sync::Event;
+use crate::tcp::{AcceptError, AsyncTcpListener, AsyncTcpStream};
+
+/// How much [`echo`] reads at once.
+const ECHO_BUFFER: usize = 4096;
+
+/// Accept connections on `listener`, spawning `handler(stream)` as a task
+/// for each on the poller running on this thread.
+///
+/// By default there's no limit on how many connections are handled at once,
+/// and they may sit idle for as long as they like; see
+/// [`Serve::max_connections`] and [`Serve::idle_timeout`]. Whatever the
+/// handler's future outputs is dropped.
+///
+/// The future resolves once [`Serve::shutdown_on`]'s event is set, or the
+/// listener is shut down, and fails with the first
+/// [`AcceptError::Fatal`] error. Running out of fds or memory waits out the
+/// listener's backoff instead. Connections which were accepted by then keep
+/// going.
+///
+/// # Panics
+///
+/// Polling it panics if no poller is running on this thread; see
+/// [`Handle::current`](crate::runtime::Handle::current).
+pub fn serve<H, Fut>(listener: AsyncTcpListener, handler: H) -> Serve<H>
+where
+    H: FnMut(AsyncTcpStream) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+{
+    Serve {
+        listener,
+        handler,
+        max_connections: None,
+        idle_timeout: None,
+        shutdown: None,
+        connections: Vec::new(),
+        waiting_on: Vec::new(),
+        deregistered: false,
+        output: None,
+    }
+}
+
+/// Future for [`serve`].
+pub struct Serve<H> {
+    listener: AsyncTcpListener,
+    handler: H,
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+    shutdown: Option<Event>,
+    /// The connections being handled, when there's a limit on how many.
+    connections: Vec<JoinHandle<()>>,
+    waiting_on: Vec<Waitable>,
+    /// Whether the listener was deregistered, which is the last thing to do
+    /// before resolving.
+    deregistered: bool,
+    output: Option<Result<(), error::Error>>,
+}
+
+impl<H> Serve<H> {
+    /// Handle at most `limit` connections at once. Once that many are open,
+    /// accepting waits for one of them to finish, and clients queue up in
+    /// the listener's backlog.
+    pub fn max_connections(mut self, limit: usize) -> Self {
+        self.max_connections = Some(limit);
+        self
+    }
+
+    /// Drop connections once their handler went `timeout` without anything
+    /// it waits on becoming ready.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Stop accepting once `event` is set.
+    pub fn shutdown_on(mut self, event: Event) -> Self {
+        self.shutdown = Some(event);
+        self
+    }
+
+    /// Whether accepting would take more connections than allowed, after
+    /// forgetting the ones which finished.
+    fn is_full(&mut self) -> bool {
+        let Some(limit) = self.max_connections else {
+            return false;
+        };
+        self.connections
+            .retain(|connection| !connection.is_finished());
+        self.connections.len() >= limit
+    }
+}
+
+impl<H, Fut> Future for Serve<H>
+where
+    H: FnMut(AsyncTcpStream) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+{
+    type Output = Result<(), error::Error>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.waiting_on.clear();
+        if let (Some(event), None) = (&self.shutdown, &self.output) {
+            let mut wait = event.wait();
+            self.waiting_on.extend(wait.poll(ready));
+            if self.waiting_on.is_empty() {
+                self.output = Some(Ok(()));
+            }
+        }
+        while self.output.is_none() {
+            if self.is_full() {
+                // Any of them finishing makes room.
+                for connection in &mut self.connections {
+                    self.waiting_on.extend(connection.poll(ready));
+                }
+                break;
+            }
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => {
+                    self.waiting_on.extend(waitables);
+                    break;
+                }
+                Step::Done((stream, _)) => {
+                    let connection = Connection {
+                        future: (self.handler)(stream).into_future(),
+                        idle_timeout: self.idle_timeout,
+                        deadline: None,
+                        waiting_on: Vec::new(),
+                        done: false,
+                    };
+                    let handle = runtime::spawn(connection);
+                    if self.max_connections.is_some() {
+                        self.connections.push(handle);
+                    }
+                }
+                Step::Error(e) => match AcceptError::new(e) {
+                    // Errors about the connection being accepted leave the
+                    // listener working. Give the other tasks a turn first,
+                    // in case they keep coming.
+                    AcceptError::Transient(_) => {
+                        let fd = self.listener.as_raw_fd();
+                        self.waiting_on.push(Waitable::Fd(fd, Interest::Read));
+                        break;
+                    }
+                    // The listener waits out its backoff on the next accept.
+                    AcceptError::ResourceExhausted(_) => {}
+                    // That's what accepting on a listener which was shut
+                    // down fails with.
+                    AcceptError::Fatal(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                        self.output = Some(Ok(()));
+                    }
+                    AcceptError::Fatal(e) => {
+                        let e = error::Error::new(Operation::Accept, e);
+                        self.output = Some(Err(e.with_fd(self.listener.as_raw_fd())));
+                    }
+                },
+            }
+        }
+        if self.output.is_some() && !self.deregistered {
+            self.deregistered = true;
+            let fd = self.listener.as_raw_fd();
+            self.waiting_on.clear();
+            self.waiting_on.push(Waitable::Fd(fd, Interest::Close));
+        }
+        self.waiting_on.iter().copied()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.deregistered {
+            true => self.output.take(),
+            false => None,
+        }
+    }
+}
+
+/// The task handling one connection for [`serve`], which drops it once it
+/// idled for too long.
+struct Connection<F> {
+    future: F,
+    idle_timeout: Option<Duration>,
+    /// When the connection counts as idle, unless something becomes ready
+    /// before. Set on the first poll.
+    deadline: Option<Instant>,
+    /// What the handler waited on as of the last poll.
+    waiting_on: Vec<Waitable>,
+    done: bool,
+}
+
+impl<F: Future> Future for Connection<F> {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut timer = None;
+        if !self.done {
+            let now = Instant::now();
+            // The handler is only polled once something it waits on became
+            // ready, so anything but our own timer is activity.
+            let idle = ready
+                .iter()
+                .all(|&waitable| Some(waitable) == self.deadline.map(Waitable::Timer));
+            match (self.idle_timeout, self.deadline) {
+                (Some(_), Some(deadline)) if idle && now >= deadline => {
+                    // Drop the connection, and what the handler waited on.
+                    self.done = true;
+                    let waiting_on = std::mem::take(&mut self.waiting_on);
+                    self.waiting_on = waiting_on
+                        .into_iter()
+                        .filter_map(Waitable::cancel)
+                        .collect();
+                    return self.waiting_on.iter().copied().chain(timer);
+                }
+                (Some(timeout), deadline) if !idle || deadline.is_none() => {
+                    self.deadline = Some(now + timeout);
+                }
+                _ => {}
+            }
+            self.waiting_on.clear();
+            self.waiting_on.extend(self.future.poll(ready));
+            match self.waiting_on.is_empty() {
+                true => {
+                    self.future.take();
+                    self.done = true;
+                }
+                false => timer = self.deadline.map(Waitable::Timer),
+            }
+        }
+        self.waiting_on.iter().copied().chain(timer)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.done.then_some(())
+    }
+}
+
+/// Echo everything read from `stream` back to it, until the peer hangs up,
+/// resolving with how many bytes were echoed. Handy as a handler for
+/// [`serve`].
+pub fn echo(stream: AsyncTcpStream) -> Echo {
+    Echo {
+        stream,
+        buf: vec![0; ECHO_BUFFER].into_boxed_slice(),
+        unwritten: 0..0,
+        echoed: 0,
+        output: None,
+    }
+}
+
+/// Future for [`echo`].
+pub struct Echo {
+    stream: AsyncTcpStream,
+    buf: Box<[u8]>,
+    /// What was read but not written back yet.
+    unwritten: std::ops::Range<usize>,
+    echoed: u64,
+    output: Option<Result<u64, error::Error>>,
+}
+
+impl Future for Echo {
+    type Output = Result<u64, error::Error>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let (mut reading, mut writing) = (None, None);
+        while self.output.is_none() && reading.is_none() && writing.is_none() {
+            let fd = self.stream.as_raw_fd();
+            if self.unwritten.is_empty() {
+                match self.stream.poll_read(ready, &mut self.buf) {
+                    Step::Pending(waitables) => reading = Some(waitables),
+                    Step::Done(0) => self.output = Some(Ok(self.echoed)),
+                    Step::Done(n) => self.unwritten = 0..n,
+                    Step::Error(e) => {
+                        let e = error::Error::new(Operation::Read, e);
+                        self.output = Some(Err(e.with_fd(fd)));
+                    }
+                }
+            } else {
+                match self
+                    .stream
+                    .poll_write(ready, &self.buf[self.unwritten.clone()])
+                {
+                    Step::Pending(waitables) => writing = Some(waitables),
+                    Step::Done(n) => {
+                        self.unwritten.start += n;
+                        self.echoed += n as u64;
+                    }
+                    Step::Error(e) => {
+                        let e = error::Error::new(Operation::Write, e);
+                        self.output = Some(Err(e.with_fd(fd)));
+                    }
+                }
+            }
+        }
+        let reading = reading.into_iter().flatten();
+        reading.chain(writing.into_iter().flatten())
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,212 @@
+//! Anonymous pipes.
+
+use rustix::io::FdFlags;
+use std::io;
+use std::iter;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::error::{Operation, WithContext};
+use crate::future::{Future, Interest, Waitable};
+use crate::io::{
+    read_buf, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadFuture, ReadStep,
+    Step, WriteFuture, WriteStep,
+};
+
+/// Create a pipe, returning its read end and its write end. Both are
+/// nonblocking.
+///
+/// Once the writer is dropped, reads drain whatever is left in the pipe and
+/// then resolve with `Ok(0)`. Once the reader is dropped, writes fail with
+/// `BrokenPipe`. The latter relies on `SIGPIPE` being ignored, which the Rust
+/// runtime sets up before `main`; pipes have no per-fd way to suppress it.
+pub fn pipe() -> io::Result<(PipeReader, PipeWriter)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two fds `pipe` writes.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `pipe` just opened both fds, and nothing else owns them.
+    let (reader, writer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    // Apple platforms have no `pipe2`, so set the flags after the fact.
+    for fd in [&reader, &writer] {
+        rustix::io::fcntl_setfd(fd, FdFlags::CLOEXEC)?;
+        rustix::io::ioctl_fionbio(fd, true)?;
+    }
+    Ok((PipeReader::new(reader), PipeWriter(writer)))
+}
+
+/// The read end of a pipe, created by [`pipe`].
+///
+/// Once the poller reports that the write end hung up, reads which would
+/// block resolve with `Ok(0)` instead, so reading to the end finishes
+/// without waiting for one more wakeup.
+#[derive(Debug)]
+pub struct PipeReader {
+    fd: OwnedFd,
+    /// Whether the poller reported that the write end hung up.
+    closed: bool,
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for PipeReader {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl From<PipeReader> for OwnedFd {
+    fn from(reader: PipeReader) -> Self {
+        reader.fd
+    }
+}
+
+impl PipeReader {
+    fn new(fd: OwnedFd) -> Self {
+        Self { fd, closed: false }
+    }
+
+    /// Adopt the read end of a pipe, switching it to nonblocking mode.
+    #[cfg(feature = "process")]
+    pub(crate) fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        rustix::io::ioctl_fionbio(&fd, true)?;
+        Ok(Self::new(fd))
+    }
+
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+
+    /// Wait for the write end to hang up, which happens once every copy of
+    /// it is closed, for instance because the child process holding it
+    /// exited. Data may still be left to read afterwards.
+    pub fn hangup(&mut self) -> HangupFuture<'_> {
+        HangupFuture {
+            reader: self,
+            output: None,
+        }
+    }
+
+    /// Whether the poller reported that the write end hung up, as of the
+    /// last time this reader was polled.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn observe(&mut self, ready: &[Waitable]) {
+        if ready.contains(&Waitable::Fd(self.as_raw_fd(), Interest::Hangup)) {
+            self.closed = true;
+        }
+    }
+
+    fn poll_read_inner(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        self.observe(ready);
+        match read_buf(&self.fd, buf) {
+            Step::Pending(_) if self.closed => Step::Done(0),
+            step => step,
+        }
+    }
+}
+
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        self.poll_read_inner(ready, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        self.poll_read_inner(ready, buf)
+    }
+}
+
+/// Future for [`PipeReader::hangup`].
+pub struct HangupFuture<'a> {
+    reader: &'a mut PipeReader,
+    output: Option<()>,
+}
+
+impl<'a> Future for HangupFuture<'a> {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            self.reader.observe(ready);
+            match self.reader.closed {
+                true => self.output = Some(()),
+                false => pending = Some(Waitable::Fd(self.reader.as_raw_fd(), Interest::Hangup)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// The write end of a pipe, created by [`pipe`].
+#[derive(Debug)]
+pub struct PipeWriter(OwnedFd);
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsFd for PipeWriter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<PipeWriter> for OwnedFd {
+    fn from(writer: PipeWriter) -> Self {
+        writer.0
+    }
+}
+
+impl PipeWriter {
+    /// Adopt the write end of a pipe, switching it to nonblocking mode.
+    #[cfg(feature = "process")]
+    pub(crate) fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        rustix::io::ioctl_fionbio(&fd, true)?;
+        Ok(Self(fd))
+    }
+
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+}
+
+fn write(fd: &OwnedFd, buf: &[u8]) -> WriteStep<iter::Once<Waitable>> {
+    let result = rustix::io::write(fd, buf).map_err(io::Error::from);
+    Step::from_syscall(result, fd.as_raw_fd(), Interest::Write)
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        write(&self.0, buf)
+    }
+}
@@ -0,0 +1,340 @@
+//! Child processes.
+//!
+//! [`Command`] mirrors [`std::process::Command`], except that spawning yields
+//! an [`AsyncChild`] whose exit can be awaited, and whose piped stdio handles
+//! are nonblocking.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::process::{self, ExitStatus, Output, Stdio};
+
+use crate::future::{Future, Interest, Waitable};
+use crate::io::{AsyncRead, ReadBuf, Step};
+use crate::pipe::{PipeReader, PipeWriter};
+
+/// How much spare room to make in the output buffers before every read.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// A builder for child processes.
+#[derive(Debug)]
+pub struct Command {
+    inner: process::Command,
+    kill_on_drop: bool,
+}
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self {
+            inner: process::Command::new(program),
+            kill_on_drop: false,
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Self {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.inner.env_remove(key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.inner.env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    /// Whether dropping the [`AsyncChild`] should kill the process if it's
+    /// still running. Defaults to `false`, like in std.
+    ///
+    /// The process is reaped right away after being killed, which blocks
+    /// the thread until the kernel has torn it down.
+    pub fn kill_on_drop(&mut self, kill_on_drop: bool) -> &mut Self {
+        self.kill_on_drop = kill_on_drop;
+        self
+    }
+
+    /// Start the process.
+    ///
+    /// Spawning happens right away and may block briefly, like it does in
+    /// std.
+    pub fn spawn(&mut self) -> io::Result<AsyncChild> {
+        let mut child = self.inner.spawn()?;
+        let stdin = child.stdin.take().map(|s| PipeWriter::from_fd(s.into()));
+        let stdout = child.stdout.take().map(|s| PipeReader::from_fd(s.into()));
+        let stderr = child.stderr.take().map(|s| PipeReader::from_fd(s.into()));
+        Ok(AsyncChild {
+            stdin: stdin.transpose()?,
+            stdout: stdout.transpose()?,
+            stderr: stderr.transpose()?,
+            child,
+            kill_on_drop: self.kill_on_drop,
+        })
+    }
+
+    /// Run the process to completion, collecting its output.
+    ///
+    /// This sets stdin to null and captures stdout and stderr whatever the
+    /// command was configured with, and leaves it configured that way.
+    /// Both pipes are drained while waiting for the process to exit, so a
+    /// child which writes more than fits in a pipe buffer doesn't deadlock.
+    pub fn output(&mut self) -> OutputFuture {
+        self.inner
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let (child, output) = match self.spawn() {
+            Ok(child) => (Some(child), None),
+            Err(e) => (None, Some(Err(e))),
+        };
+        OutputFuture {
+            child,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            status: None,
+            output,
+        }
+    }
+}
+
+/// A running child process, created by [`Command::spawn`].
+///
+/// Like in std, dropping an `AsyncChild` doesn't kill or reap the process,
+/// unless [`Command::kill_on_drop`] was set.
+#[derive(Debug)]
+pub struct AsyncChild {
+    child: process::Child,
+    /// The child's stdin, if it was configured as piped.
+    pub stdin: Option<PipeWriter>,
+    /// The child's stdout, if it was configured as piped.
+    ///
+    /// [`PipeReader::hangup`] resolves once the child and everyone it shared
+    /// the pipe with closed it.
+    pub stdout: Option<PipeReader>,
+    /// The child's stderr, if it was configured as piped.
+    pub stderr: Option<PipeReader>,
+    kill_on_drop: bool,
+}
+
+impl AsyncChild {
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Wait for the process to exit, resolving with its exit status.
+    ///
+    /// Like in std, stdin is closed first so a child reading it to the end
+    /// doesn't wait on us forever.
+    pub fn wait(&mut self) -> WaitFuture<'_> {
+        self.stdin = None;
+        WaitFuture {
+            child: self,
+            output: None,
+        }
+    }
+
+    /// Send `SIGKILL` to the process.
+    ///
+    /// This doesn't reap it; call [`AsyncChild::wait`] for that. Killing a
+    /// process which has already been reaped succeeds without doing
+    /// anything, like in std.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+
+    /// Check whether the process has exited, without waiting.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    /// Reap the process if it exited, or return the waitable for its exit.
+    fn poll_wait(&mut self) -> Step<Option<Waitable>, ExitStatus> {
+        // Checking before waiting covers a process which exits before the
+        // poller gets around to registering it.
+        match self.child.try_wait() {
+            Ok(Some(status)) => Step::Done(status),
+            Ok(None) => Step::Pending(Some(Waitable::Process(self.id(), Interest::Read))),
+            Err(e) => Step::Error(e),
+        }
+    }
+}
+
+impl Drop for AsyncChild {
+    fn drop(&mut self) {
+        // Once the process is reaped its kqueue registration is gone too, and
+        // an exit event for it which was already queued wakes nobody up.
+        if self.kill_on_drop {
+            if let Ok(None) = self.child.try_wait() {
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+            }
+        }
+    }
+}
+
+/// Future for [`AsyncChild::wait`].
+///
+/// When wrapped in a [`timeout`](crate::time::timeout) which fires first,
+/// the timeout drops the registration for the exit, so the runtime isn't
+/// woken up later for a process nobody waits on.
+pub struct WaitFuture<'a> {
+    child: &'a mut AsyncChild,
+    output: Option<io::Result<ExitStatus>>,
+}
+
+impl<'a> Future for WaitFuture<'a> {
+    type Output = io::Result<ExitStatus>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.child.poll_wait() {
+                Step::Pending(waitable) => pending = waitable,
+                Step::Done(status) => self.output = Some(Ok(status)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`Command::output`].
+pub struct OutputFuture {
+    /// Only `None` if spawning failed.
+    child: Option<AsyncChild>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    status: Option<ExitStatus>,
+    output: Option<io::Result<Output>>,
+}
+
+impl Future for OutputFuture {
+    type Output = io::Result<Output>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut stdout_pending = None;
+        let mut stderr_pending = None;
+        let mut exit_pending = None;
+        if let (Some(child), None) = (&mut self.child, &self.output) {
+            let result = (|| {
+                // Read both pipes to the end, dropping each once it's done.
+                if let Some(stdout) = &mut child.stdout {
+                    match poll_read_to_end(stdout, ready, &mut self.stdout) {
+                        Step::Pending(waitables) => stdout_pending = Some(waitables),
+                        Step::Done(()) => child.stdout = None,
+                        Step::Error(e) => return Err(e),
+                    }
+                }
+                if let Some(stderr) = &mut child.stderr {
+                    match poll_read_to_end(stderr, ready, &mut self.stderr) {
+                        Step::Pending(waitables) => stderr_pending = Some(waitables),
+                        Step::Done(()) => child.stderr = None,
+                        Step::Error(e) => return Err(e),
+                    }
+                }
+                if self.status.is_none() {
+                    match child.poll_wait() {
+                        Step::Pending(waitable) => exit_pending = waitable,
+                        Step::Done(status) => self.status = Some(status),
+                        Step::Error(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            })();
+            match (result, self.status) {
+                (Err(e), _) => self.output = Some(Err(e)),
+                (Ok(()), Some(status)) if child.stdout.is_none() && child.stderr.is_none() => {
+                    self.output = Some(Ok(Output {
+                        status,
+                        stdout: std::mem::take(&mut self.stdout),
+                        stderr: std::mem::take(&mut self.stderr),
+                    }));
+                }
+                (Ok(()), _) => {}
+            }
+        }
+        let stdout_pending = stdout_pending.into_iter().flatten();
+        let stderr_pending = stderr_pending.into_iter().flatten();
+        stdout_pending.chain(stderr_pending).chain(exit_pending)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Read from `reader` until EOF, appending to `buf`.
+fn poll_read_to_end(
+    reader: &mut PipeReader,
+    ready: &[Waitable],
+    buf: &mut Vec<u8>,
+) -> Step<impl Iterator<Item = Waitable> + use<>, ()> {
+    loop {
+        if buf.len() == buf.capacity() {
+            buf.reserve(READ_CHUNK);
+        }
+        let len = buf.len();
+        let mut spare = ReadBuf::uninit(buf.spare_capacity_mut());
+        let step = reader.poll_read_buf(ready, &mut spare);
+        let filled = spare.filled().len();
+        // SAFETY: `ReadBuf` only counts initialized bytes as filled.
+        unsafe { buf.set_len(len + filled) };
+        match step {
+            Step::Pending(waitables) => return Step::Pending(waitables),
+            Step::Done(0) => return Step::Done(()),
+            Step::Done(_) => {}
+            Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Step::Error(e) => return Step::Error(e),
+        }
+    }
+}
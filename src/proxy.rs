@@ -0,0 +1,321 @@
+//! Connecting through proxies.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::future::{Future, Waitable};
+use crate::io::{AsyncRead, AsyncWrite, Either, Step};
+use crate::tcp::{AsyncTcpStream, ConnectFuture};
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0x00;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Connect to `target_host:target_port` through the SOCKS5 proxy at
+/// `proxy_addr`, like the one `ssh -D` sets up.
+///
+/// Only the no-authentication method is offered. `target_host` may be an IP
+/// address or a domain name; names are resolved by the proxy. The future
+/// resolves with the stream once the proxy reports the tunnel is open, after
+/// which everything written to it reaches the target.
+pub fn socks5_connect(
+    proxy_addr: SocketAddr,
+    target_host: &str,
+    target_port: u16,
+) -> Socks5Connect {
+    let state = match connect_request(target_host, target_port) {
+        Ok(request) => State::Connecting {
+            connect: AsyncTcpStream::connect_async(proxy_addr),
+            request,
+        },
+        Err(e) => State::Failed(Some(e)),
+    };
+    Socks5Connect {
+        state,
+        output: None,
+    }
+}
+
+/// Build the CONNECT request for the target.
+fn connect_request(host: &str, port: u16) -> io::Result<Vec<u8>> {
+    let mut request = vec![VERSION, CONNECT, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .ok()
+                .filter(|&len| len > 0)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "target host must be between 1 and 255 bytes long",
+                    )
+                })?;
+            request.push(ATYP_DOMAIN);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+/// Future for [`socks5_connect`].
+pub struct Socks5Connect {
+    state: State,
+    output: Option<io::Result<AsyncTcpStream>>,
+}
+
+enum State {
+    Connecting {
+        connect: ConnectFuture,
+        request: Vec<u8>,
+    },
+    Handshake {
+        stream: AsyncTcpStream,
+        phase: Phase,
+        /// The bytes to send or being received in this phase.
+        buf: Vec<u8>,
+        /// How much of `buf` was sent or received so far.
+        pos: usize,
+        /// The CONNECT request, sent once the method is agreed on.
+        request: Vec<u8>,
+    },
+    Failed(Option<io::Error>),
+    Done,
+}
+
+/// The steps of the handshake, in order. Each phase either writes all of
+/// `buf` or reads exactly enough bytes to fill it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    SendGreeting,
+    ReadMethod,
+    SendRequest,
+    /// The version, reply code, a reserved byte, and the address type.
+    ReadReplyHead,
+    /// The length of a domain name bound address.
+    ReadDomainLen,
+    /// The bound address and port, which we don't need.
+    ReadBoundAddr,
+}
+
+impl Phase {
+    fn is_write(self) -> bool {
+        matches!(self, Phase::SendGreeting | Phase::SendRequest)
+    }
+}
+
+impl Future for Socks5Connect {
+    type Output = io::Result<AsyncTcpStream>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut connecting = None;
+        let mut handshaking = None;
+        while self.output.is_none() {
+            match &mut self.state {
+                State::Connecting { connect, request } => match connect.poll_connect() {
+                    Step::Pending(waitables) => {
+                        connecting = Some(waitables);
+                        break;
+                    }
+                    Step::Done(stream) => {
+                        self.state = State::Handshake {
+                            stream,
+                            phase: Phase::SendGreeting,
+                            buf: vec![VERSION, 1, NO_AUTH],
+                            pos: 0,
+                            request: std::mem::take(request),
+                        }
+                    }
+                    Step::Error(e) => self.output = Some(Err(e)),
+                },
+                State::Handshake {
+                    stream,
+                    phase,
+                    buf,
+                    pos,
+                    request,
+                } => {
+                    match poll_transfer(stream, ready, phase.is_write(), buf, pos) {
+                        Step::Pending(waitables) => {
+                            handshaking = Some(waitables);
+                            break;
+                        }
+                        Step::Done(()) => {}
+                        Step::Error(e) => {
+                            self.output = Some(Err(e));
+                            continue;
+                        }
+                    }
+                    let next = match *phase {
+                        Phase::SendGreeting => Ok(Some((Phase::ReadMethod, vec![0; 2]))),
+                        Phase::ReadMethod => check_method(buf)
+                            .map(|()| Some((Phase::SendRequest, std::mem::take(request)))),
+                        Phase::SendRequest => Ok(Some((Phase::ReadReplyHead, vec![0; 4]))),
+                        Phase::ReadReplyHead => check_reply(buf).map(|atyp| match atyp {
+                            ATYP_DOMAIN => Some((Phase::ReadDomainLen, vec![0; 1])),
+                            ATYP_IPV4 => Some((Phase::ReadBoundAddr, vec![0; 4 + 2])),
+                            _ => Some((Phase::ReadBoundAddr, vec![0; 16 + 2])),
+                        }),
+                        Phase::ReadDomainLen => {
+                            let len = buf[0] as usize;
+                            Ok(Some((Phase::ReadBoundAddr, vec![0; len + 2])))
+                        }
+                        Phase::ReadBoundAddr => Ok(None),
+                    };
+                    match next {
+                        Ok(Some((next, next_buf))) => {
+                            *phase = next;
+                            *buf = next_buf;
+                            *pos = 0;
+                        }
+                        Ok(None) => {
+                            let State::Handshake { stream, .. } =
+                                std::mem::replace(&mut self.state, State::Done)
+                            else {
+                                unreachable!()
+                            };
+                            self.output = Some(Ok(stream));
+                        }
+                        Err(e) => self.output = Some(Err(e)),
+                    }
+                }
+                State::Failed(e) => {
+                    let e = e.take().expect("failed state holds its error");
+                    self.output = Some(Err(e));
+                }
+                State::Done => break,
+            }
+        }
+        if self.output.is_some() {
+            // On failure the connection to the proxy is dropped here.
+            self.state = State::Done;
+        }
+        let connecting = connecting.into_iter().flatten();
+        connecting.chain(handshaking.into_iter().flatten())
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Write all of `buf`, or read exactly enough to fill it, picking up at
+/// `pos`.
+fn poll_transfer(
+    stream: &mut AsyncTcpStream,
+    ready: &[Waitable],
+    write: bool,
+    buf: &mut [u8],
+    pos: &mut usize,
+) -> Step<impl Iterator<Item = Waitable> + use<>, ()> {
+    while *pos < buf.len() {
+        let step = if write {
+            match stream.poll_write(ready, &buf[*pos..]) {
+                Step::Pending(waitables) => Step::Pending(Either::Left(waitables)),
+                Step::Done(n) => Step::Done(n),
+                Step::Error(e) => Step::Error(e),
+            }
+        } else {
+            match stream.poll_read(ready, &mut buf[*pos..]) {
+                Step::Pending(waitables) => Step::Pending(Either::Right(waitables)),
+                Step::Done(n) => Step::Done(n),
+                Step::Error(e) => Step::Error(e),
+            }
+        };
+        match step {
+            Step::Pending(waitables) => return Step::Pending(waitables),
+            Step::Done(0) if write => {
+                let e = io::Error::new(io::ErrorKind::WriteZero, "failed to write to the proxy");
+                return Step::Error(e);
+            }
+            Step::Done(0) => {
+                let e = io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "proxy closed the connection during the handshake",
+                );
+                return Step::Error(e);
+            }
+            Step::Done(n) => *pos += n,
+            Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Step::Error(e) => return Step::Error(e),
+        }
+    }
+    Step::Done(())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Check the method selection message picks no-authentication.
+fn check_method(reply: &[u8]) -> io::Result<()> {
+    match *reply {
+        [VERSION, NO_AUTH] => Ok(()),
+        [VERSION, NO_ACCEPTABLE_METHODS] => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "proxy requires authentication",
+        )),
+        [VERSION, _] => Err(invalid_data("proxy picked a method we didn't offer")),
+        _ => Err(invalid_data("proxy doesn't speak SOCKS5")),
+    }
+}
+
+/// Check the head of the reply to the CONNECT request, returning the type of
+/// the bound address which follows it.
+fn check_reply(head: &[u8]) -> io::Result<u8> {
+    let [version, code, _, atyp] = *head else {
+        unreachable!("the reply head is four bytes long")
+    };
+    if version != VERSION {
+        return Err(invalid_data("proxy doesn't speak SOCKS5"));
+    }
+    let (kind, msg) = match code {
+        0x00 => {
+            return match atyp {
+                ATYP_IPV4 | ATYP_DOMAIN | ATYP_IPV6 => Ok(atyp),
+                _ => Err(invalid_data("proxy replied with an unknown address type")),
+            };
+        }
+        0x01 => (io::ErrorKind::Other, "proxy server failure"),
+        0x02 => (
+            io::ErrorKind::PermissionDenied,
+            "connection not allowed by the proxy's ruleset",
+        ),
+        0x03 => (
+            io::ErrorKind::NetworkUnreachable,
+            "network unreachable from the proxy",
+        ),
+        0x04 => (
+            io::ErrorKind::HostUnreachable,
+            "host unreachable from the proxy",
+        ),
+        0x05 => (
+            io::ErrorKind::ConnectionRefused,
+            "connection refused by the target",
+        ),
+        0x06 => (
+            io::ErrorKind::TimedOut,
+            "TTL expired before reaching the target",
+        ),
+        0x07 => (io::ErrorKind::Unsupported, "proxy doesn't support CONNECT"),
+        0x08 => (
+            io::ErrorKind::Unsupported,
+            "proxy doesn't support the address type",
+        ),
+        _ => (io::ErrorKind::Other, "proxy replied with an unknown error"),
+    };
+    Err(io::Error::new(kind, msg))
+}
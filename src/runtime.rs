@@ -1,23 +1,201 @@
-use rustix::event::kqueue;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
-use std::os::fd::{AsFd, OwnedFd, RawFd};
-use std::time::Duration;
+use std::os::fd::{OwnedFd, RawFd};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "blocking")]
+use crate::blocking::{BlockingTask, Pool};
+use crate::error::{Error, Operation};
 use crate::future::{Future, Interest, IntoFuture, Waitable};
+#[cfg(feature = "time")]
+use crate::time::Elapsed;
+use builder::Hooks;
+use reactor::{Event, Reactor, Wakeup};
+#[cfg(feature = "process")]
+use reaper::ChildReaper;
+use task::{BlockOn, EnterGuard, Entry, Progress, Task};
+
+mod builder;
+pub(crate) mod completion;
+mod error;
+mod metrics;
+mod multi;
+mod owners;
+mod pool;
+pub(crate) mod queue;
+mod reactor;
+#[cfg(feature = "process")]
+mod reaper;
+mod scope;
+mod task;
+
+pub use builder::{Builder, MaxParkTime};
+pub use error::RuntimeError;
+#[cfg(feature = "metrics")]
+pub use metrics::LatencyHistogram;
+pub use metrics::RuntimeMetrics;
+pub use multi::MultiThread;
+pub use pool::{BufferPool, PoolBuf, PoolStats};
+pub use queue::{CompletionHandle, CompletionQueue, Completions, NextCompletion};
+pub use scope::{Scope, ScopedJoinHandle};
+pub use task::{Handle, JoinError, JoinHandle, TaskDump};
+
+/// The single-threaded runtime, under the name it goes by elsewhere.
+///
+/// A poller is a whole runtime, with its task table and timers, so this is
+/// the same type rather than a wrapper: [`Builder::build`] returns one,
+/// [`Runtime::new`] opens one with the defaults, and
+/// [`Runtime::new_multi_thread`] starts a [`MultiThread`] instead.
+pub type Runtime = Poller;
 
 pub struct Poller {
-    queue: OwnedFd,
-    events: Vec<kqueue::Event>,
+    /// Unique within the process; see [`Poller::id`].
+    id: u64,
+    reactor: Reactor,
+    /// Ids of jobs which completed on other threads since the last wakeup.
+    completed: Arc<Mutex<Vec<u64>>>,
+    registrations: HashMap<RawFd, Registration>,
+    /// When registrations with a deadline time out, by fd and what was
+    /// waited for.
+    deadlines: HashMap<(RawFd, Interest), Instant>,
+    #[cfg(feature = "process")]
+    reaper: ChildReaper,
+    /// Spawned tasks which didn't finish yet, by id.
+    tasks: BTreeMap<u64, Box<Entry<dyn Task>>>,
+    /// Which task waits on each fd for what, to catch two waiting on the
+    /// same thing, which would deregister it from under each other.
+    waiters: HashMap<(RawFd, Interest), u64>,
+    /// Where tasks spawned through handles wait to join `tasks`.
+    handle: Handle,
+    /// Whether `run_until` keeps unfinished tasks around.
+    detach: bool,
+    /// What timer timeouts are rounded up to a multiple of.
+    timer_resolution: Duration,
+    /// Every how many turns to poll every task regardless, if at all.
+    spurious_wakeups: Option<u32>,
+    /// How many turns in a row a task gets polled before the others go
+    /// first.
+    poll_budget: u32,
+    /// The running totals; the rest of the snapshot is filled in on demand.
+    metrics: RuntimeMetrics,
+    /// When the last wait for events returned, and how long tasks took to
+    /// be polled from then.
+    #[cfg(feature = "metrics")]
+    woke: Instant,
+    #[cfg(feature = "metrics")]
+    latency: LatencyHistogram,
+    buffers: Buffers,
+    hooks: Hooks,
+}
+
+/// Other pollers may register the fds once this one is gone.
+impl Drop for Poller {
+    fn drop(&mut self) {
+        for &fd in self.registrations.keys() {
+            owners::release(fd, self.id);
+        }
+    }
+}
+
+/// What the loop reuses from turn to turn, so turns in a steady state don't
+/// allocate. Each is taken out while it's in use, and put back cleared.
+#[derive(Debug, Default)]
+struct Buffers {
+    /// What became ready this turn.
+    ready: Vec<Waitable>,
+    /// The part of `ready` the task being polled waits on.
+    relevant: Vec<Waitable>,
+    /// What the task being polled waited on before, in case it panics.
+    previous: Vec<Waitable>,
+    /// What the last future passed to `block_on` waited on, for the next.
+    main: Vec<Waitable>,
+    /// Tasks which used up their poll budget this turn, and the ones which
+    /// finished.
+    hot: Vec<u64>,
+    finished: Vec<u64>,
+}
+
+// Which filters are registered for an fd.
+#[derive(Debug, Default)]
+struct Registration {
+    read: bool,
+    write: bool,
+    priority: bool,
 }
 
 impl Poller {
+    /// Open a poller with the default configuration; see [`Builder`] for
+    /// the alternatives.
     pub fn open() -> io::Result<Self> {
+        Builder::new().build()
+    }
+
+    /// The same as [`Poller::open`], for when it's spelled [`Runtime`].
+    pub fn new() -> io::Result<Self> {
+        Self::open()
+    }
+
+    /// Start a runtime with `workers` threads; see [`MultiThread::new`].
+    pub fn new_multi_thread(workers: usize) -> io::Result<MultiThread> {
+        MultiThread::new(workers)
+    }
+
+    fn with_builder(builder: Builder) -> io::Result<Self> {
+        let handle = Handle::new(
+            #[cfg(feature = "blocking")]
+            builder
+                .blocking_threads
+                .map(|threads| Arc::new(Pool::new(threads))),
+            builder.buffer_pool.unwrap_or_default(),
+        );
         Ok(Self {
-            queue: kqueue::kqueue()?,
-            events: Vec::with_capacity(1),
+            id: owners::next_id(),
+            reactor: Reactor::new(builder.event_capacity)?,
+            completed: Arc::new(Mutex::new(Vec::new())),
+            registrations: HashMap::new(),
+            deadlines: HashMap::new(),
+            #[cfg(feature = "process")]
+            reaper: ChildReaper::new()?,
+            tasks: BTreeMap::new(),
+            waiters: HashMap::new(),
+            handle,
+            detach: false,
+            timer_resolution: builder.timer_resolution,
+            spurious_wakeups: builder.spurious_wakeups,
+            poll_budget: builder.poll_budget,
+            metrics: RuntimeMetrics::default(),
+            #[cfg(feature = "metrics")]
+            woke: Instant::now(),
+            #[cfg(feature = "metrics")]
+            latency: LatencyHistogram::default(),
+            buffers: Buffers::default(),
+            hooks: builder.hooks,
         })
     }
 
+    /// An id telling the poller apart from the others in the process, as
+    /// [`RuntimeError::WrongRuntime`] reports it.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // Wake the poller up if it's waiting, or make its next wait return right
+    // away if it isn't.
+    pub fn notify(&self) -> io::Result<()> {
+        self.notifier().notify()
+    }
+
+    // A handle for calling `notify` from other threads.
+    pub fn notifier(&self) -> Notify {
+        Notify {
+            wakeup: self.reactor.wakeup(),
+            completed: self.completed.clone(),
+        }
+    }
+
     // Register the client for interest in read events, and don't wait for events to come in.
     //
     // Safety: we won't polling this after the TcpStream referred to closes, and we delete the
@@ -27,64 +205,1185 @@ impl Poller {
     // You could delete the event as well, and failing to do so isn't actually catastrophic - the
     // worst case is more spurious wakes.
     pub fn register_read(&mut self, fd: RawFd) -> io::Result<usize> {
-        let flags = kqueue::EventFlags::ADD;
-        let udata = 7;
-        let event = kqueue::Event::new(kqueue::EventFilter::Read(fd), flags, udata);
-        let timeout = None;
-        Ok(unsafe { kqueue::kevent(&self.queue, &[event], &mut self.events, timeout)? })
+        let n = self.reactor.register_read(fd)?;
+        self.deadlines.remove(&(fd, Interest::Read));
+        self.deadlines.remove(&(fd, Interest::Hangup));
+        self.registrations.entry(fd).or_default().read = true;
+        trace!(fd, interest = "read", "registered");
+        Ok(n)
+    }
+
+    // Register the client for interest in write events, with the same caveats as `register_read`.
+    pub fn register_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        let n = self.reactor.register_write(fd)?;
+        self.deadlines.remove(&(fd, Interest::Write));
+        self.registrations.entry(fd).or_default().write = true;
+        trace!(fd, interest = "write", "registered");
+        Ok(n)
+    }
+
+    // Register the client for interest in read events like `register_read`,
+    // until `deadline`. If the fd isn't readable by then, the poller stops
+    // waiting for it and reports `Waitable::FdUntil(fd, Interest::Read,
+    // deadline)` as ready instead of a read event, and never both. Waits end
+    // by the deadline at the latest. Registering again replaces it, and
+    // `register_read` drops it.
+    pub fn register_read_deadline(&mut self, fd: RawFd, deadline: Instant) -> io::Result<usize> {
+        let n = self.register_read(fd)?;
+        self.deadlines.insert((fd, Interest::Read), deadline);
+        Ok(n)
+    }
+
+    // Register the client for interest in write events until `deadline`,
+    // like `register_read_deadline`.
+    pub fn register_write_deadline(&mut self, fd: RawFd, deadline: Instant) -> io::Result<usize> {
+        let n = self.register_write(fd)?;
+        self.deadlines.insert((fd, Interest::Write), deadline);
+        Ok(n)
+    }
+
+    // Register the client for interest in exceptional conditions, such as
+    // TCP urgent data, with the same caveats as `register_read`.
+    pub fn register_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        let n = self.reactor.register_priority(fd)?;
+        self.deadlines.remove(&(fd, Interest::Priority));
+        self.registrations.entry(fd).or_default().priority = true;
+        trace!(fd, interest = "priority", "registered");
+        Ok(n)
+    }
+
+    // Register interest in changes to the file behind an fd. Registering it
+    // again replaces the kinds of change.
+    pub fn register_vnode(&mut self, fd: RawFd, kinds: u32) -> io::Result<usize> {
+        self.reactor.register_vnode(fd, kinds)
+    }
+
+    // Unregister interest in changes to the file behind an fd.
+    pub fn unregister_vnode(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.reactor.unregister_vnode(fd)
+    }
+
+    // Register interest in deliveries of a signal. Registering it again does
+    // nothing.
+    pub fn register_signal(&mut self, signum: i32) -> io::Result<usize> {
+        self.reactor.register_signal(signum)
+    }
+
+    // Unregister interest in deliveries of a signal.
+    pub fn unregister_signal(&mut self, signum: i32) -> io::Result<usize> {
+        self.reactor.unregister_signal(signum)
     }
 
     // Wait for some event to complete
     pub fn wait(&mut self) -> io::Result<usize> {
-        // safety: we are not modifying the list, just polling
-        Ok(unsafe { kqueue::kevent(self.queue.as_fd(), &[], &mut self.events, None)? })
+        self.wait_timeout(None)
+    }
+
+    // Wait for some event to complete, for the timeout to pass, or for the
+    // earliest deadline of a registration. The park hooks run first, and
+    // may shorten the timeout; the unpark hooks run once the wait returned.
+    pub fn wait_timeout(&mut self, mut timeout: Option<Duration>) -> io::Result<usize> {
+        if let Some(deadline) = self.deadlines.values().min() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            timeout = Some(timeout.map_or(left, |timeout| timeout.min(left)));
+        }
+        for hook in &self.hooks.park {
+            if let Some(MaxParkTime(max)) = hook() {
+                timeout = Some(timeout.map_or(max, |timeout| timeout.min(max)));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        let parked = Instant::now();
+        let n = self.reactor.wait(timeout);
+        for hook in &self.hooks.unpark {
+            hook();
+        }
+        let n = n?;
+        trace!(events = n, parked = ?parked.elapsed(), "woke up");
+        #[cfg(feature = "metrics")]
+        {
+            self.woke = Instant::now();
+        }
+        Ok(n)
     }
 
     // Unregister the client for interest in read events.
     pub fn unregister_read(&mut self, fd: RawFd) -> io::Result<usize> {
-        let flags = kqueue::EventFlags::DELETE;
-        let udata = 7;
-        let event = kqueue::Event::new(kqueue::EventFilter::Read(fd), flags, udata);
+        self.deadlines.remove(&(fd, Interest::Read));
+        self.deadlines.remove(&(fd, Interest::Hangup));
+        self.forget(fd, |registration| registration.read = false);
+        trace!(fd, interest = "read", "deregistered");
+        self.reactor.unregister_read(fd)
+    }
 
-        dbg!();
+    // Unregister the client for interest in write events.
+    pub fn unregister_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.deadlines.remove(&(fd, Interest::Write));
+        self.forget(fd, |registration| registration.write = false);
+        trace!(fd, interest = "write", "deregistered");
+        self.reactor.unregister_write(fd)
+    }
+
+    // Unregister the client for interest in exceptional conditions.
+    pub fn unregister_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.deadlines.remove(&(fd, Interest::Priority));
+        self.forget(fd, |registration| registration.priority = false);
+        trace!(fd, interest = "priority", "deregistered");
+        self.reactor.unregister_priority(fd)
+    }
+
+    // The number of fds with at least one registered filter.
+    pub fn registration_count(&self) -> usize {
+        self.registrations.len()
+    }
+
+    // Whether any filter is registered for the fd.
+    pub fn is_registered(&self, fd: RawFd) -> bool {
+        self.registrations.contains_key(&fd)
+    }
+
+    // Update the registration table, dropping fds which have nothing left.
+    fn forget(&mut self, fd: RawFd, f: impl FnOnce(&mut Registration)) {
+        if let Some(registration) = self.registrations.get_mut(&fd) {
+            f(registration);
+            if !registration.read && !registration.write && !registration.priority {
+                self.registrations.remove(&fd);
+                owners::release(fd, self.id);
+            }
+        }
+    }
 
-        let mut event_list = vec![];
-        let timeout = Some(Duration::ZERO);
-        Ok(unsafe { kqueue::kevent(&self.queue, &[event], &mut event_list, timeout)? })
+    // Unregister all interest in a file descriptor. Unlike the single-filter
+    // methods this tolerates filters which were never registered.
+    pub fn unregister(&mut self, fd: RawFd) -> io::Result<()> {
+        not_found_ok(self.unregister_read(fd))?;
+        not_found_ok(self.unregister_write(fd))?;
+        not_found_ok(self.unregister_priority(fd))
     }
 
-    fn events(&self) -> Vec<Waitable> {
-        self.events
+    // Add what the last wait came back with to `ready`.
+    fn events(&mut self, ready: &mut Vec<Waitable>) -> Result<(), RuntimeError> {
+        let mut unheard = Vec::new();
+        for event in self.reactor.events() {
+            match event? {
+                Event::Read { fd, hangup } => {
+                    ready.push(Waitable::Fd(fd, Interest::Read));
+                    if hangup {
+                        ready.push(Waitable::Fd(fd, Interest::Hangup));
+                    }
+                }
+                Event::Write(fd) => ready.push(Waitable::Fd(fd, Interest::Write)),
+                Event::Priority(fd) => ready.push(Waitable::Fd(fd, Interest::Priority)),
+                #[cfg(feature = "process")]
+                Event::Exit(pid) => self.reaper.notify(pid),
+                // Nothing watches for exits without the reaper.
+                #[cfg(not(feature = "process"))]
+                Event::Exit(_) => {}
+                Event::Vnode(fd, kinds) => {
+                    #[cfg(feature = "fs")]
+                    crate::fs::deliver(fd, kinds);
+                    ready.push(Waitable::Vnode(fd, kinds));
+                }
+                Event::Notify => {
+                    let mut completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+                    ready.extend(completed.drain(..).map(Waitable::Completion));
+                }
+                Event::Signal { signum, times } => match crate::signal::deliver(signum, times) {
+                    true => ready.push(Waitable::Signal(signum)),
+                    false => unheard.push(signum),
+                },
+            }
+        }
+        // Nobody listens for these anymore, so stop waking up for them.
+        for signum in unheard {
+            let _ = self.unregister_signal(signum);
+        }
+        #[cfg(feature = "process")]
+        self.reaper.dispatch(ready);
+        self.expire_deadlines(ready);
+        Ok(())
+    }
+
+    // Settle registrations with a deadline: those which got an event are
+    // done with it, and those which didn't by their deadline time out.
+    // Either way the deadline is dropped, so nothing reports both.
+    fn expire_deadlines(&mut self, ready: &mut Vec<Waitable>) {
+        if self.deadlines.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        self.deadlines.retain(|&(fd, interest), &mut deadline| {
+            if ready.iter().any(|r| r.wakes(Waitable::Fd(fd, interest))) {
+                return false;
+            }
+            if deadline > now {
+                return true;
+            }
+            trace!(fd, ?interest, "registration timed out");
+            ready.push(Waitable::FdUntil(fd, interest, deadline));
+            false
+        });
+    }
+
+    /// Spawn `future` as a task, which runs alongside the future passed to
+    /// [`Poller::block_on`], and any other tasks.
+    ///
+    /// The task is first polled on the next turn of the loop. Polling it
+    /// again only happens once something it waits on became ready, so tasks
+    /// don't see each other's events.
+    pub fn spawn<Fut>(&mut self, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: 'static,
+    {
+        self.handle.spawn(future)
+    }
+
+    /// Spawn `future` as a task like [`Poller::spawn`], under `name`, which
+    /// shows up in [`Poller::dump_tasks`].
+    pub fn spawn_named<Fut>(
+        &mut self,
+        name: impl Into<String>,
+        future: Fut,
+    ) -> JoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: 'static,
+    {
+        self.handle.spawn_named(name, future)
+    }
+
+    /// What every spawned task waits on, in the order they were spawned.
+    /// Tasks spawned since the last turn aren't in it yet.
+    pub fn dump_tasks(&self) -> Vec<TaskDump> {
+        self.tasks
             .iter()
-            .map(|event| match event.filter() {
-                kqueue::EventFilter::Read(fd) => Waitable::Fd(fd, Interest::Read),
-                _ => panic!("non-read filter found!"),
+            .map(|(&id, entry)| TaskDump {
+                id,
+                name: entry.task.name().map(String::from),
+                waiting_on: entry.waiting_on.clone(),
+                progressed: entry.progressed,
             })
             .collect()
     }
 
-    pub fn block_on<Fut: IntoFuture>(&mut self, future: Fut) -> io::Result<Fut::Output> {
-        let mut fut = future.into_future();
-        loop {
-            let mut should_wait = false;
-            for waitable in fut.poll(&self.events()) {
-                match waitable {
-                    Waitable::Fd(fd, Interest::Read) => {
-                        should_wait = true;
-                        self.register_read(fd)?
-                    }
-                    Waitable::Fd(fd, Interest::Close) => self.unregister_read(fd)?,
-                };
+    /// A handle for spawning tasks onto this poller while it's busy running
+    /// other ones. Futures it drives can also get it through
+    /// [`Handle::current`].
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// Run the poller until `future` completes, along with whichever spawned
+    /// tasks are ready in the meantime. Tasks which didn't finish yet stay
+    /// around for the next call.
+    ///
+    /// Fails with [`RuntimeError::FutureMisbehaved`] if `future` stops
+    /// waiting on anything without having output to take, and with
+    /// [`RuntimeError::Deadlock`] if nothing it or the spawned tasks wait on
+    /// could ever become ready.
+    ///
+    /// Fails with [`RuntimeError::AlreadyRunning`] if called from a future
+    /// some poller on this thread is driving, this one or another: the outer
+    /// poller couldn't make progress until the inner one returned, so the
+    /// two would likely wait on each other forever. Spawn the inner future
+    /// with [`spawn`] instead, and wait on its [`JoinHandle`] from the outer
+    /// future.
+    pub fn block_on<Fut: IntoFuture>(&mut self, future: Fut) -> Result<Fut::Output, RuntimeError> {
+        let _enter = self.enter()?;
+        let mut main = self.main_entry(future.into_future());
+        while !self.turn(Some(&mut main), None)? {}
+        self.reclaim(&mut main);
+        main.task.take_output()
+    }
+
+    /// Run the poller until `future` completes, like [`Poller::block_on`],
+    /// but for no longer than `duration` in total, failing with [`Elapsed`]
+    /// after that.
+    ///
+    /// Unlike wrapping `future` in [`time::timeout`](crate::time::timeout),
+    /// this bounds the poller's own waits, so it holds however `future` and
+    /// the spawned tasks behave. When the time is up, whatever `future` was
+    /// waiting on is deregistered before `future` is dropped.
+    #[cfg(feature = "time")]
+    pub fn block_on_timeout<Fut: IntoFuture>(
+        &mut self,
+        future: Fut,
+        duration: Duration,
+    ) -> Result<Result<Fut::Output, Elapsed>, RuntimeError> {
+        let _enter = self.enter()?;
+        let deadline = Instant::now() + duration;
+        let mut main = self.main_entry(future.into_future());
+        while !self.turn(Some(&mut main), Some(deadline))? {
+            if Instant::now() >= deadline {
+                self.cancel(&mut main)?;
+                return Ok(Err(Elapsed::new()));
             }
-            self.events.clear();
+        }
+        self.reclaim(&mut main);
+        main.task.take_output().map(Ok)
+    }
 
-            match should_wait {
-                true => self.wait()?,
-                false => match fut.take() {
-                    Some(output) => return Ok(output),
-                    None => panic!("No more events to wait on and no data present"),
-                },
+    /// Run `f` with a [`Scope`] to spawn tasks in which, unlike
+    /// [`Poller::spawn`], may borrow from the enclosing stack frame, then
+    /// run the poller until every one of those finished, like with
+    /// [`std::thread::scope`]. Resolves with what `f` returned.
+    ///
+    /// Other spawned tasks run in the meantime, as with [`Poller::block_on`],
+    /// which this fails like. If that happens, the scope's tasks which are
+    /// left are dropped before returning, cancelling them.
+    ///
+    /// If `f` panics, or one of the scope's tasks does and its
+    /// [`ScopedJoinHandle`] didn't take the panic while the scope ran, the
+    /// panic is resumed once all of them are done.
+    pub fn scope<'env, F, T>(&mut self, f: F) -> Result<T, RuntimeError>
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope::new();
+        let output = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        let driven = self.block_on(scope::Drive::new(&scope));
+        scope.clear();
+        let output = output.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        if let Some(payload) = driven? {
+            panic::resume_unwind(payload);
+        }
+        Ok(output)
+    }
+
+    // The entry for the future passed to `block_on`, waiting on things with
+    // the buffer the last one was done with.
+    fn main_entry<F: Future>(&mut self, future: F) -> Entry<BlockOn<F>> {
+        let mut main = Entry::new(BlockOn {
+            future,
+            output: None,
+        });
+        main.waiting_on = std::mem::take(&mut self.buffers.main);
+        main
+    }
+
+    // Keep the buffer of a finished `block_on` future for the next one.
+    fn reclaim(&mut self, main: &mut Entry<dyn Task + '_>) {
+        let mut waiting_on = std::mem::take(&mut main.waiting_on);
+        waiting_on.clear();
+        self.buffers.main = waiting_on;
+    }
+
+    /// Take a turn of the loop for `main` without waiting for anything
+    /// beyond what already came in, resolving with its output once it
+    /// finished. This is what [`compat`](crate::compat) drives futures with.
+    pub(crate) fn step<F: Future>(
+        &mut self,
+        main: &mut Stepped<F>,
+    ) -> Result<Option<F::Output>, RuntimeError> {
+        let _enter = self.enter()?;
+        match self.turn(Some(&mut main.0), Some(Instant::now()))? {
+            true => main.0.task.take_output().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    /// How long to wait before stepping `main` again, if there's any
+    /// deadline at all. Events already taken from the queue make it zero.
+    pub(crate) fn step_timeout<F: Future>(&self, main: &Stepped<F>) -> Option<Duration> {
+        match self.reactor.has_events() {
+            false => self.next_timeout(Some(&main.0)),
+            true => Some(Duration::ZERO),
+        }
+    }
+
+    /// The reactor's fd, which is readable while events are waiting in it.
+    pub(crate) fn queue(&self) -> Arc<OwnedFd> {
+        self.reactor.fd()
+    }
+
+    /// Run the poller until every spawned task has finished, including the
+    /// ones spawned while it runs. For a server whose accept loop never
+    /// finishes, that's forever.
+    ///
+    /// Like [`Poller::block_on`], fails with [`RuntimeError::AlreadyRunning`]
+    /// if a poller on this thread is already running.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        while self.task_count() > 0 {
+            self.poll_once(Duration::MAX)?;
+        }
+        Ok(())
+    }
+
+    /// Take a single turn of the loop, for driving the poller from another
+    /// event loop: handle the events which came in since last time, poll
+    /// the tasks they concern once, then wait for more for up to
+    /// `max_wait`. What comes in during the wait is handled next time.
+    ///
+    /// With nothing to wait for, this returns right away. The returned
+    /// [`Turn`] says when the next timer is due, which is when to come back
+    /// at the latest.
+    ///
+    /// Like [`Poller::run`], fails with [`RuntimeError::AlreadyRunning`] if
+    /// a poller on this thread is already running.
+    pub fn poll_once(&mut self, max_wait: Duration) -> Result<Turn, RuntimeError> {
+        let _enter = self.enter()?;
+        let events = self.metrics.events;
+        self.turn(None, Instant::now().checked_add(max_wait))?;
+        Ok(Turn {
+            events: (self.metrics.events - events) as usize,
+            live_tasks: self.task_count(),
+            next_timer: self.next_timer(None),
+        })
+    }
+
+    /// Run the poller until `future` completes, like [`Poller::block_on`],
+    /// with spawned tasks making progress in the background meanwhile.
+    ///
+    /// Once `future` completes, the tasks which are still running are
+    /// dropped, unless [`Poller::set_detach`] said to keep them. Their
+    /// [`JoinHandle`]s then resolve with [`JoinError::Aborted`].
+    pub fn run_until<Fut: IntoFuture>(&mut self, future: Fut) -> Result<Fut::Output, RuntimeError> {
+        let output = self.block_on(future);
+        if !self.detach {
+            self.adopt_spawned();
+            self.drop_tasks()?;
+        }
+        output
+    }
+
+    /// Make this the poller running on this thread, unless one already is.
+    fn enter(&self) -> Result<EnterGuard, RuntimeError> {
+        match Handle::try_current() {
+            Some(_) => Err(RuntimeError::AlreadyRunning),
+            None => Ok(self.handle.enter()),
+        }
+    }
+
+    /// A snapshot of the poller's metrics.
+    pub fn metrics(&mut self) -> RuntimeMetrics {
+        self.adopt_spawned();
+        #[cfg(feature = "blocking")]
+        let blocking_queue_depth = match self.handle.pool() {
+            Some(pool) => pool.queued(),
+            None => Pool::shared().queued(),
+        };
+        #[cfg(not(feature = "blocking"))]
+        let blocking_queue_depth = 0;
+        RuntimeMetrics {
+            live_tasks: self.tasks.len(),
+            blocking_queue_depth,
+            ..self.metrics
+        }
+    }
+
+    /// How long tasks took to be polled after the poller woke up for them.
+    #[cfg(feature = "metrics")]
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        self.latency
+    }
+
+    /// Shut the poller down: refuse new tasks, give the running ones `grace`
+    /// to finish, then drop the ones which are still running.
+    ///
+    /// Tasks spawned from then on never run, and their [`JoinHandle`]s
+    /// resolve with [`JoinError::ShutDown`]; the ones dropped at the end
+    /// resolve with [`JoinError::Aborted`], with whatever they were waiting
+    /// on deregistered.
+    pub fn shutdown(&mut self, grace: Duration) -> Result<Shutdown, RuntimeError> {
+        let _enter = self.enter()?;
+        self.handle.close();
+        self.adopt_spawned();
+        let total = self.tasks.len();
+        let deadline = Instant::now() + grace;
+        while !self.tasks.is_empty() && Instant::now() < deadline {
+            self.turn(None, Some(deadline))?;
+        }
+        let aborted = self.tasks.len();
+        self.drop_tasks()?;
+        Ok(Shutdown {
+            completed: total - aborted,
+            aborted,
+        })
+    }
+
+    /// Set whether [`Poller::run_until`] keeps the tasks which are still
+    /// running once its future completes, for later calls to drive. They're
+    /// dropped by default.
+    pub fn set_detach(&mut self, detach: bool) {
+        self.detach = detach;
+    }
+
+    // The number of spawned tasks which didn't finish yet.
+    pub fn task_count(&mut self) -> usize {
+        self.adopt_spawned();
+        self.tasks.len()
+    }
+
+    // Move the tasks spawned through handles into the task table.
+    fn adopt_spawned(&mut self) {
+        let pending = self.handle.take_pending();
+        self.metrics.tasks_spawned += pending.len() as u64;
+        #[cfg(feature = "tracing")]
+        for (id, _) in &pending {
+            trace!(task = id, "task spawned");
+        }
+        self.tasks.extend(pending);
+    }
+
+    // Drop every task, deregistering what they wait on.
+    fn drop_tasks(&mut self) -> io::Result<()> {
+        self.metrics.tasks_aborted += self.tasks.len() as u64;
+        #[cfg(feature = "tracing")]
+        for id in self.tasks.keys() {
+            trace!(task = id, "task aborted");
+        }
+        for (id, mut entry) in std::mem::take(&mut self.tasks) {
+            self.forget_waiter(id, &entry.waiting_on);
+            self.cancel(&mut entry)?;
+        }
+        Ok(())
+    }
+
+    // Drop the tasks which were aborted since the last turn.
+    fn remove_aborted(&mut self) -> io::Result<()> {
+        let aborted = self
+            .tasks
+            .iter()
+            .filter(|(_, entry)| entry.task.is_aborted())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in aborted {
+            if let Some(mut entry) = self.tasks.remove(&id) {
+                self.metrics.tasks_aborted += 1;
+                trace!(task = id, "task aborted");
+                self.forget_waiter(id, &entry.waiting_on);
+                self.cancel(&mut entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Drop a task which didn't finish, deregistering what it waited on.
+    fn cancel(&mut self, entry: &mut Entry<dyn Task + '_>) -> io::Result<()> {
+        self.cancel_waiting(std::mem::take(&mut entry.waiting_on))
+    }
+
+    /// Deregister what an attempt at an operation was left pending on, once
+    /// nothing will resume it; see [`Stepped::resume`].
+    pub(crate) fn cancel_waiting(&mut self, waiting_on: Vec<Waitable>) -> io::Result<()> {
+        for waitable in waiting_on.into_iter().filter_map(Waitable::cancel) {
+            self.register(waitable)?;
+        }
+        Ok(())
+    }
+
+    // Note that task `id` waits on what it yielded, rather than on what it
+    // waited on before, failing if another task waits on the same thing.
+    //
+    // Tasks each deregister what they wait on once they're done with it,
+    // without knowing about the others, so the one left waiting would hang.
+    fn track_waiter(
+        &mut self,
+        id: u64,
+        previous: &[Waitable],
+        waiting_on: &[Waitable],
+    ) -> Result<(), RuntimeError> {
+        if previous == waiting_on {
+            return Ok(());
+        }
+        self.forget_waiter(id, previous);
+        for &waitable in waiting_on {
+            let Some(key) = waiter_key(waitable) else {
+                continue;
             };
+            let other = *self.waiters.entry(key).or_insert(id);
+            if other != id {
+                let (fd, interest) = key;
+                let error = RuntimeError::DuplicateRegistration {
+                    fd,
+                    interest,
+                    tasks: (other, id),
+                };
+                debug_assert_eq!(other, id, "{error}");
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    // Note that task `id` no longer waits on `waiting_on`.
+    fn forget_waiter(&mut self, id: u64, waiting_on: &[Waitable]) {
+        for key in waiting_on.iter().copied().filter_map(waiter_key) {
+            if self.waiters.get(&key) == Some(&id) {
+                self.waiters.remove(&key);
+            }
+        }
+    }
+
+    // Take one turn of the loop: poll whoever has news, then wait for what
+    // they wait on. Returns whether `main` finished, in which case it's
+    // done before waiting.
+    fn turn(
+        &mut self,
+        mut main: Option<&mut Entry<dyn Task + '_>>,
+        deadline: Option<Instant>,
+    ) -> Result<bool, RuntimeError> {
+        self.adopt_spawned();
+        self.remove_aborted()?;
+        self.metrics.turns += 1;
+        let mut ready = std::mem::take(&mut self.buffers.ready);
+        let polled = self.poll_ready(main.as_deref_mut(), &mut ready);
+        ready.clear();
+        self.buffers.ready = ready;
+        if polled? {
+            return Ok(true);
+        }
+
+        // Tasks spawned from tasks get polled right away next turn.
+        self.adopt_spawned();
+        if main.is_none() && self.tasks.is_empty() {
+            // Nobody is left to wait for anything.
+            return Ok(false);
+        }
+        if let Some(every) = self.spurious_wakeups {
+            // Skip the wait, and poll everyone next turn regardless.
+            if self.metrics.turns.is_multiple_of(u64::from(every)) {
+                for entry in self.tasks.values_mut() {
+                    entry.repoll = true;
+                }
+                if let Some(main) = main.as_deref_mut() {
+                    main.repoll = true;
+                }
+            }
+        }
+        let mut timeout = self.next_timeout(main.as_deref());
+        if let Some(deadline) = deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            timeout = Some(timeout.map_or(left, |timeout| timeout.min(left)));
+        }
+        if timeout.is_none() && !self.can_wake(main.as_deref()) {
+            return Err(RuntimeError::Deadlock);
+        }
+        let parked = Instant::now();
+        self.wait_timeout(timeout)?;
+        self.metrics.parked += parked.elapsed();
+        Ok(false)
+    }
+
+    // Whether anything could end a wait without a timeout: a registered fd,
+    // something registered outside the fd table, or someone holding on to a
+    // notifier, including the completions we subscribed to.
+    fn can_wake(&self, main: Option<&Entry<dyn Task + '_>>) -> bool {
+        let entries = self.tasks.values().map(|entry| &**entry).chain(main);
+        let waits_elsewhere = entries.flat_map(|entry| &entry.waiting_on).any(|w| {
+            matches!(
+                w,
+                Waitable::Vnode(_, 1..)
+                    | Waitable::Signal(_)
+                    | Waitable::Process(_, Interest::Read | Interest::Write)
+            )
+        });
+        let completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+        !self.registrations.is_empty()
+            || waits_elsewhere
+            || !completed.is_empty()
+            || Arc::strong_count(&self.completed) > 1
+    }
+
+    // How long to wait for events before the next turn: until the earliest
+    // timer anyone waits on, or not at all if a task needs repolling.
+    fn next_timeout(&self, main: Option<&Entry<dyn Task + '_>>) -> Option<Duration> {
+        let entries = self.tasks.values().map(|entry| &**entry).chain(main);
+        #[cfg(feature = "process")]
+        if self.reaper.has_exited() {
+            return Some(Duration::ZERO);
+        }
+        if entries.clone().any(|entry| entry.repoll) {
+            return Some(Duration::ZERO);
+        }
+        self.next_timer(main)
+            .map(|d| self.round_up(d.saturating_duration_since(Instant::now())))
+    }
+
+    // The earliest deadline of the timers anyone waits on.
+    fn next_timer(&self, main: Option<&Entry<dyn Task + '_>>) -> Option<Instant> {
+        let entries = self.tasks.values().map(|entry| &**entry).chain(main);
+        entries
+            .flat_map(|entry| &entry.waiting_on)
+            .filter_map(|w| match w {
+                Waitable::Timer(deadline) => Some(*deadline),
+                _ => None,
+            })
+            .min()
+    }
+
+    // Round a timeout up to the timer resolution.
+    fn round_up(&self, timeout: Duration) -> Duration {
+        let resolution = self.timer_resolution.as_nanos();
+        if resolution == 0 {
+            return timeout;
+        }
+        let nanos = timeout.as_nanos().div_ceil(resolution) * resolution;
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    // Take what came in since the last turn into `ready`, along with the
+    // timers which are due, and poll whoever it concerns. Returns whether
+    // `main` finished, in which case the tasks aren't polled.
+    fn poll_ready(
+        &mut self,
+        main: Option<&mut Entry<dyn Task + '_>>,
+        ready: &mut Vec<Waitable>,
+    ) -> Result<bool, RuntimeError> {
+        self.events(ready)?;
+        let waiting_on = self.tasks.values().map(|entry| &entry.waiting_on);
+        due_timers(
+            waiting_on.chain(main.as_deref().map(|main| &main.waiting_on)),
+            ready,
+        );
+        self.metrics.events += ready.len() as u64;
+
+        let polling = Instant::now();
+        if let Some(main) = main {
+            let finished = self.poll_entry(main, None, ready)?;
+            if finished {
+                self.metrics.polling += polling.elapsed();
+                self.reactor.clear();
+                self.requeue_completions(ready);
+                return Ok(true);
+            }
+        }
+        let mut tasks = std::mem::take(&mut self.tasks);
+        let polled = self.poll_tasks(&mut tasks, ready);
+        self.tasks = tasks;
+        self.metrics.polling += polling.elapsed();
+        polled?;
+        self.reactor.clear();
+        Ok(false)
+    }
+
+    // Hand the completions in `ready` back for the next turn, which the
+    // tasks weren't polled with. Fd events come back on their own while the
+    // fds stay ready, but completions are only reported once.
+    fn requeue_completions(&self, ready: &[Waitable]) {
+        let mut completed = self.completed.lock().unwrap_or_else(|e| e.into_inner());
+        let len = completed.len();
+        completed.extend(ready.iter().filter_map(|waitable| match waitable {
+            Waitable::Completion(id) => Some(*id),
+            _ => None,
+        }));
+        if completed.len() > len {
+            let _ = self.reactor.wakeup().wake();
+        }
+    }
+
+    // Poll every task which has news, dropping the ones which finished.
+    fn poll_tasks(
+        &mut self,
+        tasks: &mut BTreeMap<u64, Box<Entry<dyn Task>>>,
+        ready: &mut Vec<Waitable>,
+    ) -> Result<(), RuntimeError> {
+        let mut hot = std::mem::take(&mut self.buffers.hot);
+        let mut finished = std::mem::take(&mut self.buffers.finished);
+        let budget = self.poll_budget;
+        // In id order, like the tasks, so it can be searched.
+        hot.extend(
+            tasks
+                .iter()
+                .filter(|(_, entry)| entry.streak >= budget)
+                .map(|(id, _)| *id),
+        );
+        // Each task is checked before it's polled, so polling doesn't move
+        // any from one batch to the other.
+        let fresh = tasks.iter_mut().filter(|(_, entry)| entry.streak < budget);
+        let mut result = self.poll_batch(fresh, ready, &mut finished);
+        if result.is_ok() && !hot.is_empty() {
+            // Polling the others took time, so more timers may be due.
+            due_timers(hot.iter().map(|id| &tasks[id].waiting_on), ready);
+            let hot = tasks
+                .iter_mut()
+                .filter(|(id, _)| hot.binary_search(id).is_ok());
+            result = self.poll_batch(hot, ready, &mut finished);
+        }
+        self.metrics.tasks_completed += finished.len() as u64;
+        for id in finished.drain(..) {
+            trace!(task = id, "task completed");
+            tasks.remove(&id);
+        }
+        hot.clear();
+        self.buffers.hot = hot;
+        self.buffers.finished = finished;
+        result
+    }
+
+    // Poll a batch of tasks, noting down the ones which finished.
+    fn poll_batch<'a>(
+        &mut self,
+        batch: impl Iterator<Item = (&'a u64, &'a mut Box<Entry<dyn Task>>)>,
+        ready: &[Waitable],
+        finished: &mut Vec<u64>,
+    ) -> Result<(), RuntimeError> {
+        for (id, entry) in batch {
+            if self.poll_entry(entry, Some(*id), ready)? {
+                finished.push(*id);
+            }
+        }
+        Ok(())
+    }
+
+    // Poll a task if anything it waits on is in `ready`, or it asked to be
+    // polled again, registering what it waits on next. Returns whether the
+    // task finished.
+    fn poll_entry(
+        &mut self,
+        entry: &mut Entry<dyn Task + '_>,
+        id: Option<u64>,
+        ready: &[Waitable],
+    ) -> Result<bool, RuntimeError> {
+        let mut relevant = std::mem::take(&mut self.buffers.relevant);
+        relevant.extend(
+            ready
+                .iter()
+                .filter(|r| entry.waiting_on.iter().any(|w| r.wakes(*w))),
+        );
+        let polled = self.poll_relevant(entry, id, &relevant);
+        relevant.clear();
+        self.buffers.relevant = relevant;
+        polled
+    }
+
+    // Poll a task with the part of `ready` it waits on, unless that's
+    // nothing and it didn't ask to be polled again. Spawned tasks come with
+    // their id, and the future passed to `block_on` without one.
+    fn poll_relevant(
+        &mut self,
+        entry: &mut Entry<dyn Task + '_>,
+        id: Option<u64>,
+        ready: &[Waitable],
+    ) -> Result<bool, RuntimeError> {
+        if ready.is_empty() && !entry.repoll {
+            entry.streak = 0;
+            return Ok(false);
         }
+        entry.streak = entry.streak.saturating_add(1);
+        entry.progressed = Instant::now();
+        self.metrics.polls += 1;
+        #[cfg(feature = "metrics")]
+        self.latency
+            .record(entry.progressed.saturating_duration_since(self.woke));
+        trace!(task = ?entry.task.task_id(), ?ready, "polling");
+        let mut previous = std::mem::take(&mut self.buffers.previous);
+        previous.append(&mut entry.waiting_on);
+        let progress = entry.task.poll_task(ready, &mut entry.waiting_on);
+        let tracked = match (id, &progress) {
+            (Some(id), Progress::Pending) => self.track_waiter(id, &previous, &entry.waiting_on),
+            (Some(id), _) => {
+                self.forget_waiter(id, &previous);
+                self.forget_waiter(id, &entry.waiting_on);
+                Ok(())
+            }
+            (None, _) => Ok(()),
+        };
+        if let Progress::Panicked = progress {
+            entry.waiting_on.append(&mut previous);
+        }
+        previous.clear();
+        self.buffers.previous = previous;
+        tracked?;
+        match progress {
+            Progress::Pending => {}
+            Progress::Finished => return Ok(true),
+            Progress::Panicked => {
+                // Whatever the task waited on before, it won't anymore.
+                self.cancel(entry)?;
+                return Ok(true);
+            }
+        }
+        // Deregistering doesn't give us anything to wait for, but the task
+        // may have more work to do once it's done.
+        let mut should_wait = false;
+        for &waitable in &entry.waiting_on {
+            should_wait |= self.register(waitable)?;
+        }
+        entry.repoll = !should_wait;
+        Ok(false)
+    }
+
+    // Register or deregister what a task yielded. Returns whether it's
+    // something to wait for, rather than something to stop waiting for.
+    //
+    // Fds registered with another poller are refused: their events would go
+    // to either one.
+    fn register(&mut self, waitable: Waitable) -> Result<bool, RuntimeError> {
+        let claimed = match waiter_key(waitable) {
+            Some((fd, _)) if !self.registrations.contains_key(&fd) => {
+                owners::claim(fd, self.id).map_err(|owner| RuntimeError::WrongRuntime {
+                    fd,
+                    registered_with: owner,
+                    polled_by: self.id,
+                })?;
+                Some(fd)
+            }
+            _ => None,
+        };
+        let registered = self.register_waitable(waitable).map_err(|e| {
+            if let Some(fd) = claimed {
+                owners::release(fd, self.id);
+            }
+            let e = Error::new(Operation::Register, e);
+            match waitable {
+                Waitable::Fd(fd, _) | Waitable::FdUntil(fd, ..) | Waitable::Vnode(fd, _) => {
+                    e.with_fd(fd)
+                }
+                _ => e,
+            }
+        })?;
+        Ok(registered)
+    }
+
+    fn register_waitable(&mut self, waitable: Waitable) -> io::Result<bool> {
+        match waitable {
+            Waitable::Timer(_) => return Ok(true),
+            #[cfg(feature = "process")]
+            Waitable::Process(pid, Interest::Read | Interest::Write) => {
+                self.reaper.subscribe(self.reactor.as_fd(), pid)?;
+                if let Some(fd) = self.reaper.wakeup_fd() {
+                    self.register_read(fd)?;
+                }
+                return Ok(true);
+            }
+            #[cfg(feature = "process")]
+            Waitable::Process(pid, _) => self.reaper.unsubscribe(self.reactor.as_fd(), pid)?,
+            #[cfg(not(feature = "process"))]
+            Waitable::Process(_, Interest::Read | Interest::Write) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "waiting for processes needs the `process` feature",
+                ));
+            }
+            // Nothing could have been subscribed.
+            #[cfg(not(feature = "process"))]
+            Waitable::Process(..) => {}
+            Waitable::Vnode(fd, 0) => not_found_ok(self.unregister_vnode(fd))?,
+            Waitable::Vnode(fd, kinds) => {
+                self.register_vnode(fd, kinds)?;
+                return Ok(true);
+            }
+            Waitable::Completion(id) => {
+                completion::subscribe(id, &self.notifier());
+                return Ok(true);
+            }
+            Waitable::Signal(signum) => {
+                self.register_signal(signum)?;
+                return Ok(true);
+            }
+            Waitable::Fd(fd, Interest::Read | Interest::Hangup) => {
+                self.register_read(fd)?;
+                return Ok(true);
+            }
+            Waitable::Fd(fd, Interest::Write) => {
+                self.register_write(fd)?;
+                return Ok(true);
+            }
+            Waitable::Fd(fd, Interest::CloseRead) => not_found_ok(self.unregister_read(fd))?,
+            Waitable::Fd(fd, Interest::Priority) => {
+                self.register_priority(fd)?;
+                return Ok(true);
+            }
+            Waitable::Fd(fd, Interest::CloseWrite) => not_found_ok(self.unregister_write(fd))?,
+            Waitable::Fd(fd, Interest::ClosePriority) => {
+                not_found_ok(self.unregister_priority(fd))?
+            }
+            Waitable::Fd(fd, Interest::Close) => self.unregister(fd)?,
+            Waitable::FdUntil(fd, interest, deadline) => {
+                let waits = self.register_waitable(Waitable::Fd(fd, interest))?;
+                if waits {
+                    self.deadlines.insert((fd, interest), deadline);
+                }
+                return Ok(waits);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Add the timers among what tasks wait on which are due to `ready`.
+fn due_timers<'a>(waiting_on: impl Iterator<Item = &'a Vec<Waitable>>, ready: &mut Vec<Waitable>) {
+    let now = Instant::now();
+    ready.extend(
+        waiting_on
+            .flatten()
+            .filter(|w| matches!(w, Waitable::Timer(t) if *t <= now)),
+    );
+}
+
+/// A future driven by [`Poller::step`], outside of the poller's own loops.
+pub(crate) struct Stepped<F: Future>(Entry<BlockOn<F>>);
+
+impl<F: Future> Stepped<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Self(Entry::new(BlockOn {
+            future,
+            output: None,
+        }))
+    }
+
+    /// Step `future` as the next attempt at an operation, still waiting on
+    /// what the last attempt was pending on, so the events for that reach it.
+    #[cfg(feature = "futures-io")]
+    pub(crate) fn resume(future: F, waiting_on: Vec<Waitable>) -> Self {
+        let mut stepped = Self::new(future);
+        stepped.0.waiting_on = waiting_on;
+        stepped
+    }
+
+    /// What the future waits on, to resume the next attempt with, or to
+    /// cancel.
+    pub(crate) fn into_waiting_on(self) -> Vec<Waitable> {
+        self.0.waiting_on
+    }
+}
+
+/// What came of [`Poller::poll_once`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Turn {
+    /// Events handled, counting timers which fired.
+    pub events: usize,
+    /// Spawned tasks which didn't finish yet.
+    pub live_tasks: usize,
+    /// The earliest deadline of the timers the tasks wait on, if any.
+    pub next_timer: Option<Instant>,
+}
+
+/// What came of [`Poller::shutdown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shutdown {
+    /// Tasks which finished within the grace period, or panicked.
+    pub completed: usize,
+    /// Tasks which were still running after it, and got dropped.
+    pub aborted: usize,
+}
+
+/// Spawn `future` onto the poller running on this thread, like
+/// [`Poller::spawn`].
+///
+/// # Panics
+///
+/// Panics if no poller is running on this thread; see [`Handle::current`].
+pub fn spawn<Fut>(future: Fut) -> JoinHandle<Fut::Output>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+{
+    Handle::current().spawn(future)
+}
+
+/// Spawn `future` under `name` onto the poller running on this thread, like
+/// [`Poller::spawn_named`].
+///
+/// # Panics
+///
+/// Panics if no poller is running on this thread; see [`Handle::current`].
+pub fn spawn_named<Fut>(name: impl Into<String>, future: Fut) -> JoinHandle<Fut::Output>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+{
+    Handle::current().spawn_named(name, future)
+}
+
+/// Run `future` to completion on this thread's poller, which is opened the
+/// first time around and reused after that, so tasks `future` spawns and
+/// doesn't wait for keep going on the next call.
+///
+/// Fails with [`RuntimeError::AlreadyRunning`] if a poller is already
+/// running on this thread, since the two would wait on each other; see
+/// [`Poller::block_on`]. From inside a running future, [`spawn`] the other
+/// future and wait on its [`JoinHandle`] instead.
+pub fn block_on<Fut: IntoFuture>(future: Fut) -> Result<Fut::Output, RuntimeError> {
+    thread_local! {
+        static POLLER: RefCell<Option<Poller>> = const { RefCell::new(None) };
+    }
+    if Handle::try_current().is_some() {
+        return Err(RuntimeError::AlreadyRunning);
+    }
+    POLLER.with(|poller| {
+        let mut poller = poller.borrow_mut();
+        let poller = match &mut *poller {
+            Some(poller) => poller,
+            None => poller.insert(Poller::open()?),
+        };
+        poller.block_on(future)
+    })
+}
+
+/// Run a blocking closure on the blocking pool, resolving with what it
+/// returns once it's done.
+///
+/// This is for work which would otherwise stall the poller, such as DNS
+/// lookups, password hashing, or calls into C libraries. The pool runs a
+/// handful of threads at most, so jobs beyond that queue up.
+///
+/// If the closure panics, the panic is resumed on the thread taking the
+/// future's output, as with [`std::thread::JoinHandle::join`] followed by
+/// an unwrap. Dropping the future doesn't stop the closure.
+///
+/// The closure runs on the pool of the poller running on this thread if it
+/// was built with [`Builder::blocking_threads`], and on the pool shared by
+/// the whole process otherwise.
+#[cfg(feature = "blocking")]
+pub fn spawn_blocking<F, T>(f: F) -> BlockingTask<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match Handle::try_current().and_then(|handle| handle.pool()) {
+        Some(pool) => pool.spawn(f),
+        None => Pool::shared().spawn(f),
+    }
+}
+
+/// A handle for waking a [`Poller`] up from another thread, created by
+/// [`Poller::notifier`].
+#[derive(Debug, Clone)]
+pub struct Notify {
+    wakeup: Wakeup,
+    completed: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Notify {
+    /// Wake the poller up, like [`Poller::notify`].
+    pub fn notify(&self) -> io::Result<()> {
+        self.wakeup.wake()
+    }
+
+    /// Report the job with `id` as completed, and wake the poller up so it
+    /// finds out.
+    pub(crate) fn complete(&self, id: u64) {
+        self.completed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(id);
+        // The only way this fails is the queue being gone, and then there's
+        // nobody left to tell.
+        let _ = self.notify();
+    }
+}
+
+// Deleting a filter which was never registered is not an error for our purposes.
+fn not_found_ok(result: io::Result<usize>) -> io::Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// The fd a waitable waits on, and for what, if it's an fd to wait on.
+fn waiter_key(waitable: Waitable) -> Option<(RawFd, Interest)> {
+    match waitable {
+        Waitable::Fd(fd, interest) | Waitable::FdUntil(fd, interest, _) => match interest {
+            Interest::Read | Interest::Write | Interest::Hangup | Interest::Priority => {
+                Some((fd, interest))
+            }
+            _ => None,
+        },
+        _ => None,
     }
 }
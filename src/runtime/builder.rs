@@ -0,0 +1,178 @@
+//! Configuring a [`Poller`] before opening it.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{BufferPool, Poller};
+
+/// How many events to take from the queue per wakeup, by default.
+const EVENT_CAPACITY: usize = 64;
+
+/// How many turns in a row a task gets polled before yielding to the rest,
+/// by default.
+const POLL_BUDGET: u32 = 16;
+
+/// Builder for a [`Poller`], created with [`Builder::new`].
+///
+/// [`Poller::open`] is the shortcut for a poller with the defaults.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    pub(super) event_capacity: usize,
+    pub(super) timer_resolution: Duration,
+    #[cfg(feature = "blocking")]
+    pub(super) blocking_threads: Option<usize>,
+    pub(super) spurious_wakeups: Option<u32>,
+    pub(super) poll_budget: u32,
+    pub(super) buffer_pool: Option<BufferPool>,
+    pub(super) hooks: Hooks,
+}
+
+/// What a hook passed to [`Builder::on_park`] can return, to have the
+/// poller wait for no longer than this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxParkTime(pub Duration);
+
+type ParkHook = dyn Fn() -> Option<MaxParkTime> + Send + Sync;
+type UnparkHook = dyn Fn() + Send + Sync;
+
+/// The callbacks run around the poller's waits for events, in the order
+/// they were added.
+#[derive(Clone, Default)]
+pub(super) struct Hooks {
+    pub(super) park: Vec<Arc<ParkHook>>,
+    pub(super) unpark: Vec<Arc<UnparkHook>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("park", &self.park.len())
+            .field("unpark", &self.unpark.len())
+            .finish()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            event_capacity: EVENT_CAPACITY,
+            timer_resolution: Duration::ZERO,
+            #[cfg(feature = "blocking")]
+            blocking_threads: None,
+            spurious_wakeups: None,
+            poll_budget: POLL_BUDGET,
+            buffer_pool: None,
+            hooks: Hooks::default(),
+        }
+    }
+
+    /// Set how many events the poller takes from the queue per wakeup.
+    /// Events beyond that are picked up on the next turn. Defaults to `64`.
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.event_capacity = capacity;
+        self
+    }
+
+    /// Round the time until the next timer fires up to a multiple of
+    /// `resolution`, so timers which are close together fire in the same
+    /// wakeup. Timers may fire up to `resolution` late. Defaults to zero,
+    /// which waits for exactly as long as the next timer needs.
+    pub fn timer_resolution(mut self, resolution: Duration) -> Self {
+        self.timer_resolution = resolution;
+        self
+    }
+
+    /// Give the poller a blocking pool of its own, running at most
+    /// `threads` threads, for [`spawn_blocking`](super::spawn_blocking)
+    /// calls from the futures it drives. By default those share the pool of
+    /// the whole process.
+    #[cfg(feature = "blocking")]
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = Some(threads);
+        self
+    }
+
+    /// Cut every `every`-th wait short, and poll every task as if something
+    /// it waits on became ready when nothing did. This is for testing that
+    /// futures cope with spurious wakeups; it's off by default.
+    pub fn spurious_wakeup_injection(mut self, every: u32) -> Self {
+        self.spurious_wakeups = Some(every);
+        self
+    }
+
+    /// Set how many turns in a row a task may be polled before the tasks
+    /// which have news but weren't polled as often go first, so a task whose
+    /// socket always has data can't hold the others up. Timers which became
+    /// due in the meantime fire before the busy tasks are polled too.
+    /// Defaults to `16`.
+    pub fn poll_budget(mut self, turns: u32) -> Self {
+        self.poll_budget = turns;
+        self
+    }
+
+    /// Have the futures the poller drives take their read buffers from
+    /// `pool`, through [`Handle::buffer_pool`](super::Handle::buffer_pool),
+    /// which pollers can share by passing clones of the same pool. By
+    /// default each poller has a pool of its own, of 8KiB buffers, keeping
+    /// up to 64 of them.
+    pub fn buffer_pool(mut self, pool: BufferPool) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Call `hook` right before every wait for events, as in
+    /// [`Poller::wait`], for maintenance work which can happen whenever the
+    /// poller has nothing else to do. Hooks added before it go first.
+    ///
+    /// Returning a [`MaxParkTime`] cuts the wait short after that long, so
+    /// the hook gets called again by then even if no event comes in. The
+    /// shortest one any hook returned applies.
+    pub fn on_park<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Option<MaxParkTime> + Send + Sync + 'static,
+    {
+        self.hooks.park.push(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` right after every wait for events returns, including
+    /// when it failed. Hooks added before it go first.
+    pub fn on_unpark<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.hooks.unpark.push(Arc::new(hook));
+        self
+    }
+
+    /// Open the poller.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] for an event capacity, a
+    /// number of blocking threads, a spurious wakeup interval, or a poll
+    /// budget of zero.
+    pub fn build(self) -> io::Result<Poller> {
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        if self.event_capacity == 0 {
+            return invalid("the event capacity must be at least 1");
+        }
+        #[cfg(feature = "blocking")]
+        if self.blocking_threads == Some(0) {
+            return invalid("the blocking pool needs at least one thread");
+        }
+        if self.spurious_wakeups == Some(0) {
+            return invalid("spurious wakeups need an interval of at least 1");
+        }
+        if self.poll_budget == 0 {
+            return invalid("the poll budget must be at least 1");
+        }
+        Poller::with_builder(self)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
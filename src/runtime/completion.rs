@@ -0,0 +1,73 @@
+//! Things completing on other threads, by id.
+//!
+//! A future waiting for one yields `Waitable::Completion(id)`. The poller
+//! subscribes to the id when it sees that, and whoever completes it then
+//! wakes every subscribed poller up through its [`Notify`].
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use super::Notify;
+
+/// Whether a completion happened yet, and who to tell once it does.
+/// Completions without an entry are no longer of interest to anyone.
+enum State {
+    Pending(Vec<Notify>),
+    Complete,
+}
+
+static COMPLETIONS: Mutex<BTreeMap<u64, State>> = Mutex::new(BTreeMap::new());
+
+fn completions() -> MutexGuard<'static, BTreeMap<u64, State>> {
+    COMPLETIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Make up an id for a new, pending completion.
+pub(crate) fn register() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    completions().insert(id, State::Pending(Vec::new()));
+    id
+}
+
+/// Have the poller behind `notify` told once `id` completes, or right away
+/// if it already has.
+pub(crate) fn subscribe(id: u64, notify: &Notify) {
+    match completions().get_mut(&id) {
+        Some(State::Pending(waiters)) => waiters.push(notify.clone()),
+        Some(State::Complete) => notify.complete(id),
+        None => {}
+    }
+}
+
+/// Mark `id` as completed, telling every poller subscribed to it.
+pub(crate) fn complete(id: u64) {
+    if let Some(state) = completions().get_mut(&id) {
+        if let State::Pending(waiters) = std::mem::replace(state, State::Complete) {
+            for notify in waiters {
+                notify.complete(id);
+            }
+        }
+    }
+}
+
+/// Whether `id` completed.
+pub(crate) fn is_complete(id: u64) -> bool {
+    matches!(completions().get(&id), Some(State::Complete))
+}
+
+/// Mark `id` as pending again, for completions which can happen more than
+/// once.
+pub(crate) fn reset(id: u64) {
+    if let Some(state) = completions().get_mut(&id) {
+        if let State::Complete = state {
+            *state = State::Pending(Vec::new());
+        }
+    }
+}
+
+/// Forget about `id`.
+pub(crate) fn remove(id: u64) {
+    completions().remove(&id);
+}
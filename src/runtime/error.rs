@@ -0,0 +1,117 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::fd::RawFd;
+
+use crate::error::Error as IoError;
+use crate::future::Interest;
+
+/// An error from running a [`Poller`](super::Poller).
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// Talking to the OS failed, for instance registering an fd something
+    /// waits on.
+    Io(IoError),
+    /// A future broke the polling protocol, for instance by waiting on
+    /// nothing without having any output to take either.
+    FutureMisbehaved(&'static str),
+    /// The queue reported an event the poller never registers for, with a
+    /// description of the event.
+    UnknownEvent(String),
+    /// A poller was asked to `block_on` or `run` from a future which a
+    /// poller on the same thread was already driving.
+    AlreadyRunning,
+    /// The poller was about to wait without a timeout while nothing could
+    /// ever wake it up, so it would have hung forever.
+    Deadlock,
+    /// A future waited on an fd which another poller has registered, such as
+    /// a stream moved to another thread while a future on the first one
+    /// still waited on it. Both pollers would get its events, and each
+    /// future would miss some. The pollers go by [`Poller::id`].
+    ///
+    /// [`Poller::id`]: super::Poller::id
+    WrongRuntime {
+        fd: RawFd,
+        registered_with: u64,
+        polled_by: u64,
+    },
+    /// Two spawned tasks waited on the same fd for the same thing, so
+    /// whichever is done first would deregister it from under the other.
+    /// The tasks go by the ids of [`TaskDump::id`], the one which waited
+    /// first first.
+    ///
+    /// Debug builds panic instead, where it happens.
+    ///
+    /// [`TaskDump::id`]: super::TaskDump::id
+    DuplicateRegistration {
+        fd: RawFd,
+        interest: Interest,
+        tasks: (u64, u64),
+    },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Io(err) => write!(f, "{err}"),
+            RuntimeError::FutureMisbehaved(msg) => write!(f, "a future misbehaved: {msg}"),
+            RuntimeError::UnknownEvent(event) => write!(f, "unexpected event: {event}"),
+            RuntimeError::AlreadyRunning => {
+                write!(
+                    f,
+                    "cannot block_on inside a running runtime; use spawn or await instead"
+                )
+            }
+            RuntimeError::Deadlock => {
+                write!(f, "waiting for events with nothing registered to wait on")
+            }
+            RuntimeError::WrongRuntime {
+                fd,
+                registered_with,
+                polled_by,
+            } => write!(
+                f,
+                "fd {fd} is registered with poller {registered_with}, but was waited on \
+                 through poller {polled_by}"
+            ),
+            RuntimeError::DuplicateRegistration {
+                fd,
+                interest,
+                tasks: (first, second),
+            } => write!(
+                f,
+                "tasks {first} and {second} both wait on fd {fd} for {interest:?}"
+            ),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RuntimeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(err: io::Error) -> Self {
+        RuntimeError::Io(err.into())
+    }
+}
+
+impl From<IoError> for RuntimeError {
+    fn from(err: IoError) -> Self {
+        RuntimeError::Io(err)
+    }
+}
+
+impl From<RuntimeError> for io::Error {
+    fn from(err: RuntimeError) -> Self {
+        match err {
+            RuntimeError::Io(err) => err.into(),
+            err => io::Error::other(err),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Numbers about what a poller has been up to.
+
+use std::time::Duration;
+
+/// A snapshot of a poller's metrics, taken with
+/// [`Poller::metrics`](super::Poller::metrics).
+///
+/// The counters and durations only ever grow over the poller's lifetime, so
+/// subtracting an earlier snapshot gives what happened in between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuntimeMetrics {
+    /// Spawned tasks which didn't finish yet.
+    pub live_tasks: usize,
+    /// Tasks which joined the poller's task table.
+    pub tasks_spawned: u64,
+    /// Spawned tasks which finished, including by panicking.
+    pub tasks_completed: u64,
+    /// Spawned tasks which were aborted, or dropped before they finished.
+    pub tasks_aborted: u64,
+    /// Turns of the loop, that is rounds of polling.
+    pub turns: u64,
+    /// Events taken from the queue, and timers which fired.
+    pub events: u64,
+    /// Times a task or a future passed to `block_on` was polled. Divided by
+    /// `turns`, that's how many got polled per turn.
+    pub polls: u64,
+    /// Time spent waiting for events.
+    pub parked: Duration,
+    /// Time spent polling futures.
+    pub polling: Duration,
+    /// Jobs waiting for a thread on the blocking pool the poller uses.
+    /// Always zero without the `blocking` feature.
+    pub blocking_queue_depth: usize,
+}
+
+/// The upper bounds of [`LatencyHistogram`]'s buckets but the last, which
+/// takes everything slower.
+#[cfg(feature = "metrics")]
+const BUCKET_BOUNDS: [Duration; 6] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// How long tasks waited to be polled once the poller woke up, taken with
+/// [`Poller::latency_histogram`](super::Poller::latency_histogram).
+///
+/// Every poll counts once, in the first bucket whose bound it's within:
+/// 10µs, 100µs, 1ms, and so on up to 1s, with a last bucket for anything
+/// slower. Polls before the first wakeup count from when the poller was
+/// opened. Like [`RuntimeMetrics`], the counts only ever grow.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKET_BOUNDS.len() + 1],
+}
+
+#[cfg(feature = "metrics")]
+impl LatencyHistogram {
+    /// Each bucket's upper bound, or `None` for the last one, along with
+    /// how many polls fell into it.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        let bounds = BUCKET_BOUNDS.iter().copied().map(Some).chain([None]);
+        bounds.zip(self.counts.iter().copied())
+    }
+
+    /// How many polls there were in all.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let bucket = BUCKET_BOUNDS.partition_point(|&bound| bound < latency);
+        self.counts[bucket] += 1;
+    }
+}
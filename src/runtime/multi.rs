@@ -0,0 +1,302 @@
+//! Running tasks on several threads, each with a poller of its own.
+//!
+//! Every worker drives its own inbox: a future waiting on a completion,
+//! which whoever hands the worker a job completes. Tasks never move between
+//! workers once they're there.
+
+use std::io;
+#[cfg(feature = "net")]
+use std::iter;
+use std::mem;
+#[cfg(feature = "net")]
+use std::net::SocketAddr;
+#[cfg(feature = "net")]
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use super::task::Spawned;
+use super::{completion, Handle, JoinHandle, Poller, RuntimeError};
+#[cfg(feature = "net")]
+use crate::future::Interest;
+use crate::future::{Future, IntoFuture, Waitable};
+#[cfg(feature = "net")]
+use crate::io::Step;
+#[cfg(feature = "net")]
+use crate::tcp::{AcceptError, AsyncTcpListener, AsyncTcpStream};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A runtime running tasks on a fixed number of worker threads, created with
+/// [`MultiThread::new`].
+///
+/// Each worker runs a [`Poller`] of its own, so the tasks on a worker only
+/// run on its thread. Tasks spawned from outside are handed to the workers
+/// in turn, and stay where they were put: there's no work stealing. Tasks
+/// spawned from tasks, through [`spawn`](super::spawn), stay on the worker
+/// they were spawned from.
+///
+/// Dropping the runtime stops the workers, dropping the tasks they still
+/// run; see [`MultiThread::shutdown`].
+#[derive(Debug)]
+pub struct MultiThread {
+    workers: Vec<Arc<Worker>>,
+    threads: Vec<thread::JoinHandle<Result<(), RuntimeError>>>,
+    /// Which worker gets the next task.
+    next: AtomicUsize,
+}
+
+/// What a worker shares with the runtime.
+struct Worker {
+    /// The completion the worker's inbox waits on.
+    id: u64,
+    jobs: Mutex<Vec<Job>>,
+    closed: AtomicBool,
+    /// How many tasks the worker took on.
+    tasks: AtomicU64,
+}
+
+impl std::fmt::Debug for Worker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Worker")
+            .field("id", &self.id)
+            .field("closed", &self.closed)
+            .field("tasks", &self.tasks)
+            .finish()
+    }
+}
+
+impl Worker {
+    /// Hand the worker a job, waking it up.
+    fn push(&self, job: Job) {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(job);
+        completion::complete(self.id);
+    }
+}
+
+impl MultiThread {
+    /// Start `workers` worker threads, each opening a poller of its own.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] for zero workers, and with
+    /// the error of the first poller which failed to open.
+    pub fn new(workers: usize) -> io::Result<Self> {
+        if workers == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a runtime needs at least one worker",
+            ));
+        }
+        let mut runtime = Self {
+            workers: Vec::with_capacity(workers),
+            threads: Vec::with_capacity(workers),
+            next: AtomicUsize::new(0),
+        };
+        for n in 0..workers {
+            let worker = Arc::new(Worker {
+                id: completion::register(),
+                jobs: Mutex::new(Vec::new()),
+                closed: AtomicBool::new(false),
+                tasks: AtomicU64::new(0),
+            });
+            let (opened, open) = mpsc::channel();
+            let inbox = Inbox(worker.clone());
+            let thread = thread::Builder::new()
+                .name(format!("worker-{n}"))
+                .spawn(move || {
+                    let mut poller = match Poller::open() {
+                        Ok(poller) => poller,
+                        Err(err) => {
+                            let _ = opened.send(Err(err));
+                            return Ok(());
+                        }
+                    };
+                    let _ = opened.send(Ok(()));
+                    poller.run_until(inbox)
+                })?;
+            runtime.workers.push(worker);
+            runtime.threads.push(thread);
+            // Dropping the runtime stops the workers which did start.
+            open.recv()
+                .map_err(|_| io::Error::other("a worker exited early"))??;
+        }
+        Ok(runtime)
+    }
+
+    /// Spawn `future` onto the next worker.
+    ///
+    /// The future is created on this thread and moves to the worker's, so
+    /// it needs to be `Send`, as does its output.
+    pub fn spawn<Fut>(&self, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send,
+    {
+        let n = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        let worker = &self.workers[n];
+        let (task, handle) = Spawned::new(future.into_future());
+        let counted = worker.clone();
+        worker.push(Box::new(move || {
+            counted.tasks.fetch_add(1, Ordering::Relaxed);
+            Handle::current().spawn_task(task);
+        }));
+        handle
+    }
+
+    /// Accept connections on `addr` on every worker, spawning
+    /// `handler(stream)` as a task on the worker which accepted it.
+    ///
+    /// Every worker gets a listener of its own, bound with `SO_REUSEPORT`.
+    /// Linux spreads the connections over them; the BSDs, macOS included,
+    /// tend to hand them all to one. Returns the address the
+    /// listeners are bound to, which is where to connect to if `addr` has
+    /// port 0. The listeners keep accepting until the runtime stops.
+    #[cfg(feature = "net")]
+    pub fn serve<H, Fut>(&self, addr: SocketAddr, handler: H) -> io::Result<SocketAddr>
+    where
+        H: Fn(AsyncTcpStream) -> Fut + Send + Sync + 'static,
+        Fut: IntoFuture<Output = ()>,
+        Fut::IntoFuture: 'static,
+    {
+        let bind = |addr| AsyncTcpListener::builder(addr).reuse_port(true).build();
+        let first = bind(addr)?;
+        // Port 0 picks a port for the first listener; the others join it.
+        let addr = first.local_addr()?;
+        let mut listeners = vec![first];
+        for _ in 1..self.workers.len() {
+            listeners.push(bind(addr)?);
+        }
+
+        let handler = Arc::new(handler);
+        for (worker, listener) in self.workers.iter().zip(listeners) {
+            let accept = Accept {
+                listener,
+                handler: handler.clone(),
+                worker: worker.clone(),
+            };
+            worker.push(Box::new(move || {
+                Handle::current().spawn(accept);
+            }));
+        }
+        Ok(addr)
+    }
+
+    /// How many tasks each worker took on so far, counting the ones spawned
+    /// onto it and the connections it accepted for [`MultiThread::serve`].
+    pub fn worker_tasks(&self) -> Vec<u64> {
+        self.workers
+            .iter()
+            .map(|worker| worker.tasks.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Stop the workers and wait for their threads to exit. The tasks they
+    /// still run are dropped, and their [`JoinHandle`]s resolve with
+    /// [`JoinError::Aborted`](super::JoinError::Aborted).
+    ///
+    /// Fails with the error of the first worker which failed while running.
+    pub fn shutdown(mut self) -> Result<(), RuntimeError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), RuntimeError> {
+        for worker in &self.workers {
+            worker.closed.store(true, Ordering::Release);
+            completion::complete(worker.id);
+        }
+        let mut result = Ok(());
+        for thread in mem::take(&mut self.threads) {
+            let stopped = match thread.join() {
+                Ok(stopped) => stopped,
+                Err(payload) => std::panic::resume_unwind(payload),
+            };
+            if result.is_ok() {
+                result = stopped;
+            }
+        }
+        for worker in mem::take(&mut self.workers) {
+            completion::remove(worker.id);
+        }
+        result
+    }
+}
+
+impl Drop for MultiThread {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The future each worker runs, taking on the jobs handed to it until the
+/// runtime stops.
+struct Inbox(Arc<Worker>);
+
+impl Future for Inbox {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        // Reset before taking the jobs, so one pushed in between still
+        // wakes us up again.
+        completion::reset(self.0.id);
+        let jobs = mem::take(&mut *self.0.jobs.lock().unwrap_or_else(|e| e.into_inner()));
+        for job in jobs {
+            job();
+        }
+        match self.0.closed.load(Ordering::Acquire) {
+            true => None,
+            false => Some(Waitable::Completion(self.0.id)),
+        }
+        .into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.closed.load(Ordering::Acquire).then_some(())
+    }
+}
+
+/// The accept loop of one worker in [`MultiThread::serve`].
+#[cfg(feature = "net")]
+struct Accept<H> {
+    listener: AsyncTcpListener,
+    handler: Arc<H>,
+    worker: Arc<Worker>,
+}
+
+#[cfg(feature = "net")]
+impl<H, Fut> Future for Accept<H>
+where
+    H: Fn(AsyncTcpStream) -> Fut,
+    Fut: IntoFuture<Output = ()>,
+    Fut::IntoFuture: 'static,
+{
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        loop {
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => return waitables,
+                Step::Done((stream, _)) => {
+                    self.worker.tasks.fetch_add(1, Ordering::Relaxed);
+                    Handle::current().spawn((self.handler)(stream));
+                }
+                // The listener waits out its backoff on the next accept.
+                Step::Error(e) if AcceptError::is_exhausted(&e) => {}
+                // Other errors are about the connection which failed, not
+                // the listener, so keep accepting, but give the other tasks
+                // a turn first in case they keep coming.
+                Step::Error(_) => {
+                    let fd = self.listener.as_raw_fd();
+                    return iter::once(Waitable::Fd(fd, Interest::Read)).chain(None);
+                }
+            }
+        }
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
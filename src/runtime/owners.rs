@@ -0,0 +1,74 @@
+//! Which poller each fd is registered with, across the process.
+//!
+//! An fd registered with two pollers at once has its events go to either,
+//! so whichever future waits on it through the other one may never hear of
+//! them. Pollers claim an fd here when they first register it and let go
+//! of it once they deregister it, so a second one can tell and refuse.
+
+use std::collections::BTreeMap;
+use std::mem::MaybeUninit;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+struct Owner {
+    poller: u64,
+    /// The device and inode of the file behind the fd when it was claimed.
+    /// An fd which was closed without being deregistered can come back as
+    /// another file, which the poller holding on to it has no claim to.
+    file: Option<(u64, u64)>,
+}
+
+static OWNERS: Mutex<BTreeMap<RawFd, Owner>> = Mutex::new(BTreeMap::new());
+
+fn owners() -> MutexGuard<'static, BTreeMap<RawFd, Owner>> {
+    OWNERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Make up an id for a new poller.
+pub(super) fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Claim `fd` for `poller`, failing with the id of the poller which has it
+/// already.
+pub(super) fn claim(fd: RawFd, poller: u64) -> Result<(), u64> {
+    let mut owners = owners();
+    if owners.get(&fd).is_some_and(|owner| owner.poller == poller) {
+        return Ok(());
+    }
+    let file = file(fd);
+    match owners.get(&fd) {
+        Some(owner) if owner.file == file => Err(owner.poller),
+        _ => {
+            owners.insert(fd, Owner { poller, file });
+            Ok(())
+        }
+    }
+}
+
+/// Let go of `fd`, if `poller` has it.
+pub(super) fn release(fd: RawFd, poller: u64) {
+    let mut owners = owners();
+    if owners.get(&fd).is_some_and(|owner| owner.poller == poller) {
+        owners.remove(&fd);
+    }
+}
+
+/// The device and inode of the file behind `fd`, which sockets and pipes
+/// have too.
+// Their types differ between platforms, and are `u64` on some.
+#[allow(clippy::unnecessary_cast)]
+fn file(fd: RawFd) -> Option<(u64, u64)> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: `fstat` fills in `stat` when it succeeds, and only then is it
+    // read.
+    match unsafe { libc::fstat(fd, stat.as_mut_ptr()) } {
+        -1 => None,
+        _ => {
+            let stat = unsafe { stat.assume_init() };
+            Some((stat.st_dev as u64, stat.st_ino as u64))
+        }
+    }
+}
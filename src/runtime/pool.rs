@@ -0,0 +1,231 @@
+//! Buffers reused from one read to the next, so connections don't each
+//! hold on to one while they're idle, and reads don't each allocate one.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+/// How big the buffers of a pool are, by default.
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// How many free buffers a pool keeps around, by default.
+const MAX_POOLED: usize = 64;
+
+/// A pool of equally sized buffers to read into.
+///
+/// [`BufferPool::acquire`] hands out a free buffer, or allocates one if
+/// there is none, and the buffer goes back to the pool once it's dropped.
+/// The pool keeps up to a maximum number of free buffers and frees the rest,
+/// so a burst of reads doesn't pin its memory down for good.
+///
+/// Every poller has one, which its futures reach through
+/// [`Handle::buffer_pool`](super::Handle::buffer_pool); see
+/// [`Builder::buffer_pool`](super::Builder::buffer_pool). Clones refer to
+/// the same pool, and can be used from any thread.
+#[derive(Clone)]
+pub struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    buffer_size: usize,
+    max_pooled: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    free: Vec<Box<[u8]>>,
+    stats: PoolStats,
+}
+
+/// Counters of a [`BufferPool`], from [`BufferPool::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Buffers handed out which were free in the pool.
+    pub hits: u64,
+    /// Buffers handed out which had to be allocated, because none were
+    /// free.
+    pub misses: u64,
+    /// Buffers handed out and not dropped yet.
+    pub live: usize,
+    /// Free buffers in the pool.
+    pub pooled: usize,
+}
+
+impl BufferPool {
+    /// A pool of `buffer_size` byte buffers, keeping up to `max_pooled` of
+    /// them once they're free.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer_size` is zero.
+    pub fn new(buffer_size: usize, max_pooled: usize) -> Self {
+        assert!(buffer_size > 0, "buffers must hold at least one byte");
+        Self {
+            shared: Arc::new(Shared {
+                buffer_size,
+                max_pooled,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Take a free buffer, or allocate one. It goes back to the pool once
+    /// it's dropped.
+    ///
+    /// A buffer which comes from the pool still holds whatever was last
+    /// written to it.
+    pub fn acquire(&self) -> PoolBuf {
+        let mut state = self.shared.lock();
+        let buffer = match state.free.pop() {
+            Some(buffer) => {
+                state.stats.hits += 1;
+                buffer
+            }
+            None => {
+                state.stats.misses += 1;
+                vec![0; self.shared.buffer_size].into_boxed_slice()
+            }
+        };
+        state.stats.live += 1;
+        PoolBuf {
+            len: buffer.len(),
+            buffer,
+            pool: Arc::downgrade(&self.shared),
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.shared.buffer_size
+    }
+
+    pub fn max_pooled(&self) -> usize {
+        self.shared.max_pooled
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.shared.lock();
+        PoolStats {
+            pooled: state.free.len(),
+            ..state.stats
+        }
+    }
+}
+
+/// 8KiB buffers, keeping up to 64 of them.
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(BUFFER_SIZE, MAX_POOLED)
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("buffer_size", &self.shared.buffer_size)
+            .field("max_pooled", &self.shared.max_pooled)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A buffer from a [`BufferPool`], which goes back to it once dropped.
+///
+/// It derefs to the first [`PoolBuf::len`] bytes of the buffer, which is
+/// all of it until [`PoolBuf::truncate`] cuts it short, say to the part a
+/// read filled in before writing it out again.
+pub struct PoolBuf {
+    buffer: Box<[u8]>,
+    len: usize,
+    /// Gone along with the pool, in which case the buffer is freed.
+    pool: Weak<Shared>,
+}
+
+impl PoolBuf {
+    /// A buffer which isn't part of any pool, and is freed once dropped.
+    pub(crate) fn unpooled(size: usize) -> Self {
+        Self {
+            buffer: vec![0; size].into_boxed_slice(),
+            len: size,
+            pool: Weak::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many bytes the buffer holds, regardless of its length.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Shorten the buffer to `len` bytes. Longer lengths do nothing.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// Lengthen the buffer back to its capacity.
+    pub fn reset(&mut self) {
+        self.len = self.buffer.len();
+    }
+}
+
+impl Deref for PoolBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl DerefMut for PoolBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for PoolBuf {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for PoolBuf {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PoolBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolBuf")
+            .field("len", &self.len)
+            .field("capacity", &self.buffer.len())
+            .finish()
+    }
+}
+
+impl Drop for PoolBuf {
+    fn drop(&mut self) {
+        let Some(shared) = self.pool.upgrade() else {
+            return;
+        };
+        let mut state = shared.lock();
+        state.stats.live -= 1;
+        if state.free.len() < shared.max_pooled {
+            state.free.push(std::mem::take(&mut self.buffer));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Values handed to the poller by other threads, in order.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::completion;
+use crate::future::{Future, Waitable};
+use crate::stream::Stream;
+
+/// A queue of values other threads complete, such as the results of
+/// operations running on an FFI library's callback thread.
+///
+/// Those threads push values through [`CompletionHandle`]s, which wake up
+/// whichever poller waits on the queue. The queue yields the values in the
+/// order they were pushed, through [`CompletionQueue::next`] or
+/// [`CompletionQueue::stream`], and ends once every handle is gone and
+/// every value was taken.
+pub struct CompletionQueue<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    /// Complete while there are values, or no handles.
+    id: u64,
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    values: VecDeque<T>,
+    handles: usize,
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        completion::remove(self.id);
+    }
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// What came of taking a value from a [`CompletionQueue`].
+pub(crate) enum Pop<T> {
+    Value(T),
+    /// There are no values, and no handles to push more.
+    Closed,
+    /// There are no values yet; this becomes ready once there are.
+    Empty(Waitable),
+}
+
+impl<T> CompletionQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                id: completion::register(),
+                state: Mutex::new(State {
+                    values: VecDeque::new(),
+                    handles: 0,
+                }),
+            }),
+        }
+    }
+
+    /// A handle for pushing values from other threads.
+    pub fn handle(&self) -> CompletionHandle<T> {
+        self.shared.lock().handles += 1;
+        CompletionHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Take the next value, resolving with `None` once the queue ended.
+    // Like `StreamExt::next`, this is a future rather than an iterator.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> NextCompletion<'_, T> {
+        NextCompletion {
+            queue: self,
+            output: None,
+        }
+    }
+
+    /// The values as a stream, ending along with the queue.
+    pub fn stream(&mut self) -> Completions<'_, T> {
+        Completions {
+            queue: self,
+            item: None,
+            done: false,
+        }
+    }
+
+    /// How many values were pushed and not taken yet.
+    pub fn len(&self) -> usize {
+        self.shared.lock().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn pop(&self) -> Pop<T> {
+        let mut state = self.shared.lock();
+        match state.values.pop_front() {
+            Some(value) => Pop::Value(value),
+            None if state.handles == 0 => Pop::Closed,
+            None => {
+                // Handles complete it again once they push, which they can
+                // only do after we let go of the lock.
+                completion::reset(self.shared.id);
+                Pop::Empty(Waitable::Completion(self.shared.id))
+            }
+        }
+    }
+}
+
+impl<T> Default for CompletionQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for CompletionQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.lock();
+        f.debug_struct("CompletionQueue")
+            .field("values", &state.values.len())
+            .field("handles", &state.handles)
+            .finish()
+    }
+}
+
+/// Pushes values onto a [`CompletionQueue`], from any thread.
+pub struct CompletionHandle<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> CompletionHandle<T> {
+    /// Push `value`, waking up the poller waiting on the queue. Values
+    /// pushed after the queue was dropped are dropped too.
+    pub fn push(&self, value: T) {
+        self.shared.lock().values.push_back(value);
+        completion::complete(self.shared.id);
+    }
+}
+
+impl<T> Clone for CompletionHandle<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().handles += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for CompletionHandle<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.handles -= 1;
+        if state.handles == 0 {
+            // Tell the queue it ended.
+            completion::complete(self.shared.id);
+        }
+    }
+}
+
+impl<T> fmt::Debug for CompletionHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompletionHandle").finish_non_exhaustive()
+    }
+}
+
+/// Future for [`CompletionQueue::next`].
+pub struct NextCompletion<'a, T> {
+    queue: &'a mut CompletionQueue<T>,
+    output: Option<Option<T>>,
+}
+
+impl<T> Future for NextCompletion<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.queue.pop() {
+                Pop::Value(value) => self.output = Some(Some(value)),
+                Pop::Closed => self.output = Some(None),
+                Pop::Empty(waitable) => pending = Some(waitable),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`CompletionQueue::stream`].
+pub struct Completions<'a, T> {
+    queue: &'a mut CompletionQueue<T>,
+    item: Option<T>,
+    done: bool,
+}
+
+impl<'a, T> Stream for Completions<'a, T> {
+    type Item = T;
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<'a, T> {
+        let mut pending = None;
+        if self.item.is_none() && !self.done {
+            match self.queue.pop() {
+                Pop::Value(value) => self.item = Some(value),
+                Pop::Closed => self.done = true,
+                Pop::Empty(waitable) => pending = Some(waitable),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
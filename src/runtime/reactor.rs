@@ -0,0 +1,42 @@
+//! What the poller waits for events with.
+//!
+//! That's kqueue on macOS. Everywhere else a last-resort backend stands in
+//! for it, with threads blocking in `poll(2)`; see [`threads`]. The
+//! `threads-backend` feature picks that one on macOS too. Both have the same
+//! interface, and report what they saw as [`Event`]s.
+
+use std::os::fd::RawFd;
+
+#[cfg(all(target_os = "macos", not(feature = "threads-backend")))]
+mod kqueue;
+#[cfg(any(not(target_os = "macos"), feature = "threads-backend"))]
+mod threads;
+
+#[cfg(all(target_os = "macos", not(feature = "threads-backend")))]
+pub(super) use self::kqueue::{Reactor, Wakeup};
+#[cfg(any(not(target_os = "macos"), feature = "threads-backend"))]
+pub(super) use threads::{Reactor, Wakeup};
+
+/// Something a wait of the [`Reactor`] came back with. Only kqueue reports
+/// exits, file changes and signals.
+#[cfg_attr(
+    any(not(target_os = "macos"), feature = "threads-backend"),
+    allow(dead_code)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Event {
+    /// The fd is readable, and if `hangup`, the other end is gone.
+    Read { fd: RawFd, hangup: bool },
+    /// The fd is writable.
+    Write(RawFd),
+    /// An exceptional condition came up on the fd, such as urgent data.
+    Priority(RawFd),
+    /// A process the reaper watches exited.
+    Exit(u32),
+    /// The file behind the fd changed, in the ways the bits say.
+    Vnode(RawFd, u32),
+    /// Another thread woke the poller up.
+    Notify,
+    /// A signal was delivered `times` times.
+    Signal { signum: i32, times: usize },
+}
@@ -0,0 +1,228 @@
+//! The kqueue backend.
+
+use rustix::event::kqueue;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Event;
+use crate::runtime::RuntimeError;
+
+/// The ident of the user event other threads trigger to wake the poller.
+const NOTIFY_IDENT: isize = 0;
+
+pub(crate) struct Reactor {
+    queue: Arc<OwnedFd>,
+    events: Vec<kqueue::Event>,
+}
+
+impl Reactor {
+    /// Open a queue taking up to `capacity` events per wait.
+    pub(crate) fn new(capacity: usize) -> io::Result<Self> {
+        let mut reactor = Self {
+            queue: Arc::new(kqueue::kqueue()?),
+            events: Vec::with_capacity(capacity),
+        };
+        reactor.change(
+            notify_filter(kqueue::UserFlags::NOINPUT),
+            kqueue::EventFlags::ADD | kqueue::EventFlags::CLEAR,
+        )?;
+        Ok(reactor)
+    }
+
+    /// The kqueue, which is readable while events are waiting in it.
+    pub(crate) fn fd(&self) -> Arc<OwnedFd> {
+        self.queue.clone()
+    }
+
+    /// The fd the reaper subscribes to exits with.
+    #[cfg_attr(not(feature = "process"), allow(dead_code))]
+    pub(crate) fn as_fd(&self) -> BorrowedFd<'_> {
+        self.queue.as_fd()
+    }
+
+    /// A handle for waking the reactor up from other threads.
+    pub(crate) fn wakeup(&self) -> Wakeup {
+        Wakeup(self.queue.clone())
+    }
+
+    pub(crate) fn register_read(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(kqueue::EventFilter::Read(fd), kqueue::EventFlags::ADD)
+    }
+
+    pub(crate) fn register_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(kqueue::EventFilter::Write(fd), kqueue::EventFlags::ADD)
+    }
+
+    pub(crate) fn unregister_read(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(kqueue::EventFilter::Read(fd), kqueue::EventFlags::DELETE)
+    }
+
+    pub(crate) fn unregister_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(kqueue::EventFilter::Write(fd), kqueue::EventFlags::DELETE)
+    }
+
+    // That's `EVFILT_EXCEPT` with `NOTE_OOB`, which rustix can't express, so
+    // it goes through libc instead. Its events come back as rustix's unknown
+    // filter, with the fd as their user data.
+    pub(crate) fn register_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change_except(fd, libc::EV_ADD)
+    }
+
+    pub(crate) fn unregister_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change_except(fd, libc::EV_DELETE)
+    }
+
+    // Registering it again replaces the kinds of change.
+    pub(crate) fn register_vnode(&mut self, fd: RawFd, kinds: u32) -> io::Result<usize> {
+        let filter = kqueue::EventFilter::Vnode {
+            vnode: fd,
+            flags: kqueue::VnodeEvents::from_bits_retain(kinds),
+        };
+        self.change(filter, kqueue::EventFlags::ADD | kqueue::EventFlags::CLEAR)
+    }
+
+    pub(crate) fn unregister_vnode(&mut self, fd: RawFd) -> io::Result<usize> {
+        let filter = kqueue::EventFilter::Vnode {
+            vnode: fd,
+            flags: kqueue::VnodeEvents::empty(),
+        };
+        self.change(filter, kqueue::EventFlags::DELETE)
+    }
+
+    // Registering it again does nothing.
+    pub(crate) fn register_signal(&mut self, signum: i32) -> io::Result<usize> {
+        self.change(signal_filter(signum)?, kqueue::EventFlags::ADD)
+    }
+
+    pub(crate) fn unregister_signal(&mut self, signum: i32) -> io::Result<usize> {
+        self.change(signal_filter(signum)?, kqueue::EventFlags::DELETE)
+    }
+
+    // Wait for events, or for the timeout to pass.
+    pub(crate) fn wait(&mut self, timeout: Option<Duration>) -> io::Result<usize> {
+        // safety: we are not modifying the list, just polling
+        Ok(unsafe { kqueue::kevent(self.queue.as_fd(), &[], &mut self.events, timeout)? })
+    }
+
+    // The events the last wait took.
+    pub(crate) fn events(&self) -> impl Iterator<Item = Result<Event, RuntimeError>> + '_ {
+        self.events.iter().map(|event| match event.filter() {
+            kqueue::EventFilter::Read(fd) => Ok(Event::Read {
+                fd,
+                hangup: event.flags().contains(kqueue::EventFlags::EOF),
+            }),
+            kqueue::EventFilter::Write(fd) => Ok(Event::Write(fd)),
+            kqueue::EventFilter::Proc { pid, .. } => {
+                Ok(Event::Exit(pid.as_raw_nonzero().get() as u32))
+            }
+            kqueue::EventFilter::Vnode { vnode, flags } => Ok(Event::Vnode(vnode, flags.bits())),
+            kqueue::EventFilter::User { .. } => Ok(Event::Notify),
+            // The only filter we add which rustix doesn't know is the one for
+            // priority events.
+            kqueue::EventFilter::Unknown => Ok(Event::Priority(event.udata() as RawFd)),
+            kqueue::EventFilter::Signal { signal, times } => Ok(Event::Signal {
+                signum: signal as i32,
+                times,
+            }),
+            kqueue::EventFilter::Timer { ident, .. } => {
+                Err(RuntimeError::UnknownEvent(format!("timer {ident}")))
+            }
+            _ => Err(RuntimeError::UnknownEvent("unknown filter".into())),
+        })
+    }
+
+    pub(crate) fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    // Apply a single change to the queue without waiting for events to come in.
+    fn change(
+        &mut self,
+        filter: kqueue::EventFilter,
+        flags: kqueue::EventFlags,
+    ) -> io::Result<usize> {
+        let udata = 7;
+        let event = kqueue::Event::new(filter, flags, udata);
+
+        // Without room for events, errors come back from the call itself,
+        // and the empty list doesn't allocate.
+        let mut event_list = Vec::new();
+        let timeout = Some(Duration::ZERO);
+        Ok(unsafe { kqueue::kevent(&*self.queue, &[event], &mut event_list, timeout)? })
+    }
+
+    // Apply a change to the `EVFILT_EXCEPT` filter of an fd, like `change`.
+    fn change_except(&mut self, fd: RawFd, flags: u16) -> io::Result<usize> {
+        let change = libc::kevent {
+            ident: fd as libc::uintptr_t,
+            filter: libc::EVFILT_EXCEPT,
+            flags,
+            fflags: libc::NOTE_OOB,
+            data: 0,
+            udata: fd as isize as *mut libc::c_void,
+        };
+        let timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: there's one change to read, and no room for events to
+        // write. As with `change`, the fd is deregistered before it closes.
+        let n = unsafe {
+            libc::kevent(
+                self.queue.as_raw_fd(),
+                &change,
+                1,
+                ptr::null_mut(),
+                0,
+                &timeout,
+            )
+        };
+        match n {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n as usize),
+        }
+    }
+}
+
+/// Wakes a [`Reactor`] up by triggering its user event.
+#[derive(Debug, Clone)]
+pub(crate) struct Wakeup(Arc<OwnedFd>);
+
+impl Wakeup {
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        let event = kqueue::Event::new(
+            notify_filter(kqueue::UserFlags::TRIGGER),
+            kqueue::EventFlags::empty(),
+            0,
+        );
+        let mut event_list = Vec::new();
+        // SAFETY: a user event doesn't refer to any fd which could close.
+        unsafe { kqueue::kevent(&*self.0, &[event], &mut event_list, Some(Duration::ZERO))? };
+        Ok(())
+    }
+}
+
+fn notify_filter(flags: kqueue::UserFlags) -> kqueue::EventFilter {
+    kqueue::EventFilter::User {
+        ident: NOTIFY_IDENT,
+        flags,
+        user_flags: kqueue::UserDefinedFlags::new(0),
+    }
+}
+
+fn signal_filter(signum: i32) -> io::Result<kqueue::EventFilter> {
+    let signal = rustix::process::Signal::from_raw(signum).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{signum} is not a signal number"),
+        )
+    })?;
+    Ok(kqueue::EventFilter::Signal { signal, times: 0 })
+}
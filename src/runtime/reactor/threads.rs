@@ -0,0 +1,470 @@
+//! The last-resort backend, for platforms without kqueue, or with the
+//! `threads-backend` feature.
+//!
+//! Every reactor gets a watcher thread, which blocks in `poll(2)` on all the
+//! registered fds and hands what became ready back through a shared queue,
+//! writing to a wakeup pipe so the poller's own wait ends. Readiness is
+//! level-triggered, like kqueue's: an fd the watcher reported is left alone
+//! until the poller waits again, and is reported again then if it's still
+//! ready. Waits hold off until the watcher checked every fd since what it
+//! watches last changed, so an fd which is ready when it's registered shows
+//! up in the next wait, as it would with kqueue.
+//!
+//! This is slow, with a thread hop for every event, but correct for sockets,
+//! pipes, and anything else `poll(2)` supports. It can't watch files or
+//! signals; registering those fails with [`io::ErrorKind::Unsupported`].
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use super::Event;
+use crate::pipe;
+use crate::runtime::RuntimeError;
+
+/// What `POLLRDHUP` is where there's one: the other end shut down writing.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RDHUP: libc::c_short = libc::POLLRDHUP;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const RDHUP: libc::c_short = 0;
+
+pub(crate) struct Reactor {
+    shared: Arc<Shared>,
+    /// The read end of the wakeup pipe, which is readable while events are
+    /// waiting to be taken.
+    wakeups: Arc<OwnedFd>,
+    events: Vec<Event>,
+    capacity: usize,
+    watcher: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when the watcher checked every fd it watches.
+    scanned: Condvar,
+    /// The write end of the wakeup pipe.
+    wake: OwnedFd,
+    /// The write end of the pipe which cuts the watcher's `poll(2)` short
+    /// when what it should watch changed.
+    interrupt: OwnedFd,
+}
+
+#[derive(Default)]
+struct State {
+    /// What each fd is registered for.
+    interests: HashMap<RawFd, Interests>,
+    /// What the watcher reported for each fd since the poller last waited.
+    reported: HashMap<RawFd, Interests>,
+    /// Events the watcher found which the poller didn't take yet.
+    ready: VecDeque<Event>,
+    /// Whether another thread woke the poller up since it last waited.
+    notified: bool,
+    /// Bumped whenever what the watcher should watch changes.
+    generation: u64,
+    /// The generation the watcher last checked every fd for.
+    scanned: u64,
+    closed: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Interests {
+    read: bool,
+    write: bool,
+    priority: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wake(&self) {
+        signal(&self.wake);
+    }
+
+    fn interrupt(&self) {
+        signal(&self.interrupt);
+    }
+}
+
+impl Reactor {
+    /// Start a reactor taking up to `capacity` events per wait.
+    pub(crate) fn new(capacity: usize) -> io::Result<Self> {
+        let (wakeups, wake) = pipe::pipe()?;
+        let (interrupts, interrupt) = pipe::pipe()?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            scanned: Condvar::new(),
+            wake: wake.into(),
+            interrupt: interrupt.into(),
+        });
+        let watched = shared.clone();
+        let interrupts = OwnedFd::from(interrupts);
+        let watcher = thread::Builder::new()
+            .name("reactor".into())
+            .spawn(move || watch(&watched, &interrupts))?;
+        Ok(Self {
+            shared,
+            wakeups: Arc::new(wakeups.into()),
+            events: Vec::with_capacity(capacity),
+            capacity,
+            watcher: Some(watcher),
+        })
+    }
+
+    /// The wakeup pipe, which is readable while events are waiting.
+    pub(crate) fn fd(&self) -> Arc<OwnedFd> {
+        self.wakeups.clone()
+    }
+
+    /// The fd the reaper subscribes to exits with.
+    #[cfg_attr(not(feature = "process"), allow(dead_code))]
+    pub(crate) fn as_fd(&self) -> BorrowedFd<'_> {
+        self.wakeups.as_fd()
+    }
+
+    /// A handle for waking the reactor up from other threads.
+    pub(crate) fn wakeup(&self) -> Wakeup {
+        Wakeup(self.shared.clone())
+    }
+
+    pub(crate) fn register_read(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(fd, |interests| interests.read = true);
+        Ok(0)
+    }
+
+    pub(crate) fn register_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(fd, |interests| interests.write = true);
+        Ok(0)
+    }
+
+    pub(crate) fn unregister_read(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.unregister(
+            fd,
+            |interests| &mut interests.read,
+            |event| match event {
+                Event::Read { fd: read, .. } => *read == fd,
+                _ => false,
+            },
+        )
+    }
+
+    pub(crate) fn unregister_write(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.unregister(
+            fd,
+            |interests| &mut interests.write,
+            |event| *event == Event::Write(fd),
+        )
+    }
+
+    // That's `POLLPRI`.
+    pub(crate) fn register_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.change(fd, |interests| interests.priority = true);
+        Ok(0)
+    }
+
+    pub(crate) fn unregister_priority(&mut self, fd: RawFd) -> io::Result<usize> {
+        self.unregister(
+            fd,
+            |interests| &mut interests.priority,
+            |event| *event == Event::Priority(fd),
+        )
+    }
+
+    pub(crate) fn register_vnode(&mut self, _fd: RawFd, _kinds: u32) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "watching files needs kqueue",
+        ))
+    }
+
+    // Nothing could have been registered.
+    pub(crate) fn unregister_vnode(&mut self, _fd: RawFd) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    pub(crate) fn register_signal(&mut self, _signum: i32) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "waiting for signals needs kqueue",
+        ))
+    }
+
+    pub(crate) fn unregister_signal(&mut self, _signum: i32) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    // Wait for the watcher to find events, or for the timeout to pass.
+    pub(crate) fn wait(&mut self, timeout: Option<Duration>) -> io::Result<usize> {
+        let idle = {
+            let mut state = self.shared.lock();
+            // The poller acted on what was reported, so watch it again.
+            if !state.reported.is_empty() {
+                state.reported.clear();
+                state.generation += 1;
+                self.shared.interrupt();
+            }
+            while state.scanned < state.generation {
+                state = self
+                    .shared
+                    .scanned
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            state.ready.is_empty() && !state.notified
+        };
+        if idle {
+            let mut fds = [pollfd(self.wakeups.as_raw_fd(), libc::POLLIN)];
+            poll(&mut fds, timeout)?;
+        }
+        drain(&self.wakeups);
+
+        let mut state = self.shared.lock();
+        self.events.clear();
+        if std::mem::take(&mut state.notified) {
+            self.events.push(Event::Notify);
+        }
+        while self.events.len() < self.capacity {
+            match state.ready.pop_front() {
+                Some(event) => self.events.push(event),
+                None => break,
+            }
+        }
+        // Keep the pipe readable for whatever is left.
+        if !state.ready.is_empty() {
+            self.shared.wake();
+        }
+        Ok(self.events.len())
+    }
+
+    // The events the last wait took.
+    pub(crate) fn events(&self) -> impl Iterator<Item = Result<Event, RuntimeError>> + '_ {
+        self.events.iter().copied().map(Ok)
+    }
+
+    pub(crate) fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    fn change(&mut self, fd: RawFd, f: impl FnOnce(&mut Interests)) {
+        let mut state = self.shared.lock();
+        f(state.interests.entry(fd).or_default());
+        state.generation += 1;
+        drop(state);
+        self.shared.interrupt();
+    }
+
+    // Stop watching `fd` for one interest, dropping the events for it which
+    // weren't taken yet, as deleting a kqueue filter does. Fails with
+    // `NotFound` if it wasn't registered.
+    fn unregister(
+        &mut self,
+        fd: RawFd,
+        interest: impl Fn(&mut Interests) -> &mut bool,
+        is_for: impl Fn(&Event) -> bool,
+    ) -> io::Result<usize> {
+        let mut state = self.shared.lock();
+        let State {
+            interests,
+            reported,
+            ready,
+            generation,
+            ..
+        } = &mut *state;
+        let not_found = || Err(io::ErrorKind::NotFound.into());
+        let Some(registered) = interests.get_mut(&fd) else {
+            return not_found();
+        };
+        if !std::mem::take(interest(registered)) {
+            return not_found();
+        }
+        if !registered.read && !registered.write && !registered.priority {
+            interests.remove(&fd);
+        }
+        if let Some(reported) = reported.get_mut(&fd) {
+            *interest(reported) = false;
+        }
+        ready.retain(|event| !is_for(event));
+        *generation += 1;
+        drop(state);
+        self.shared.interrupt();
+        Ok(0)
+    }
+}
+
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.interrupt();
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.join();
+        }
+    }
+}
+
+/// Wakes a [`Reactor`] up from another thread.
+#[derive(Clone)]
+pub(crate) struct Wakeup(Arc<Shared>);
+
+impl std::fmt::Debug for Wakeup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wakeup").finish_non_exhaustive()
+    }
+}
+
+impl Wakeup {
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        self.0.lock().notified = true;
+        self.0.wake();
+        Ok(())
+    }
+}
+
+/// The watcher thread: wait for any registered fd which wasn't reported yet
+/// to become ready, and queue up an event for it.
+fn watch(shared: &Shared, interrupts: &OwnedFd) {
+    let mut fds = Vec::new();
+    loop {
+        fds.clear();
+        fds.push(pollfd(interrupts.as_raw_fd(), libc::POLLIN));
+        let generation = {
+            let state = shared.lock();
+            if state.closed {
+                // Nobody waits for a scan anymore.
+                return;
+            }
+            for (&fd, interests) in &state.interests {
+                let reported = state.reported.get(&fd).copied().unwrap_or_default();
+                let mut events = 0;
+                if interests.read && !reported.read {
+                    events |= libc::POLLIN | RDHUP;
+                }
+                if interests.write && !reported.write {
+                    events |= libc::POLLOUT;
+                }
+                if interests.priority && !reported.priority {
+                    events |= libc::POLLPRI;
+                }
+                if events != 0 {
+                    fds.push(pollfd(fd, events));
+                }
+            }
+            state.generation
+        };
+        // Check without waiting first, so whoever waits for the scan knows
+        // what's ready right now.
+        let mut polled = poll(&mut fds, Some(Duration::ZERO));
+        if polled.is_ok() && fds.iter().all(|fd| fd.revents == 0) {
+            scanned(shared, &mut shared.lock(), generation);
+            polled = poll(&mut fds, None);
+        }
+        if polled.is_err() {
+            // Only running out of memory fails like this; don't spin on it.
+            thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        if fds[0].revents != 0 {
+            drain(interrupts);
+        }
+
+        let mut state = shared.lock();
+        let State {
+            interests,
+            reported,
+            ready,
+            ..
+        } = &mut *state;
+        let mut found = false;
+        for polled in &fds[1..] {
+            let (fd, revents) = (polled.fd, polled.revents);
+            let Some(wanted) = interests.get(&fd).copied().filter(|_| revents != 0) else {
+                continue;
+            };
+            if revents & libc::POLLNVAL != 0 {
+                // The fd was closed without being deregistered, which drops
+                // its kqueue filters too.
+                interests.remove(&fd);
+                reported.remove(&fd);
+                continue;
+            }
+            let reported = reported.entry(fd).or_default();
+            // Errors and hangups are news for whoever waits either way.
+            let failed = revents & (libc::POLLERR | libc::POLLHUP) != 0;
+            if wanted.read && !reported.read && (revents & (libc::POLLIN | RDHUP) != 0 || failed) {
+                reported.read = true;
+                let hangup = revents & (libc::POLLHUP | RDHUP) != 0;
+                ready.push_back(Event::Read { fd, hangup });
+                found = true;
+            }
+            if wanted.write && !reported.write && (revents & libc::POLLOUT != 0 || failed) {
+                reported.write = true;
+                ready.push_back(Event::Write(fd));
+                found = true;
+            }
+            let exceptional = revents & libc::POLLPRI != 0;
+            if wanted.priority && !reported.priority && (exceptional || failed) {
+                reported.priority = true;
+                ready.push_back(Event::Priority(fd));
+                found = true;
+            }
+        }
+        scanned(shared, &mut state, generation);
+        drop(state);
+        if found {
+            shared.wake();
+        }
+    }
+}
+
+/// Record that the watcher checked every fd as of `generation`.
+fn scanned(shared: &Shared, state: &mut State, generation: u64) {
+    if state.scanned < generation {
+        state.scanned = generation;
+        shared.scanned.notify_all();
+    }
+}
+
+fn pollfd(fd: RawFd, events: libc::c_short) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    }
+}
+
+/// `poll(2)` on `fds`, treating an interrupted wait as a spurious wakeup.
+fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<()> {
+    let timeout = timeout.map_or(-1, |timeout| {
+        let millis = timeout.as_nanos().div_ceil(1_000_000);
+        libc::c_int::try_from(millis).unwrap_or(libc::c_int::MAX)
+    });
+    // SAFETY: `fds` is a valid array of `pollfd`s. Fds which were closed
+    // in the meantime are reported with `POLLNVAL` rather than touched.
+    let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+    match n {
+        -1 => match io::Error::last_os_error() {
+            e if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            e => Err(e),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Write a byte to a pipe. A full pipe already has a wakeup pending, so
+/// failing is fine.
+fn signal(fd: &OwnedFd) {
+    let _ = rustix::io::write(fd, &[0]);
+}
+
+/// Empty a pipe.
+fn drain(fd: &OwnedFd) {
+    let mut buf = [0; 64];
+    while let Ok(1..) = rustix::io::read(fd, &mut buf) {}
+}
@@ -0,0 +1,299 @@
+//! Child exit notification.
+//!
+//! kqueue can watch any number of pids directly with `EVFILT_PROC`, so there
+//! the reaper only keeps track of who is waiting. Pollers without an
+//! equivalent, such as epoll, get a process-wide `SIGCHLD` handler instead,
+//! which writes to a self-pipe; when that wakes the poller, every pid being
+//! waited on is checked with a nonblocking `waitid`.
+//!
+//! Either way the reaper never reaps anything itself: `std::process::Child`
+//! owns the exit status, and `AsyncChild` collects it with `try_wait` once it
+//! has been told the process exited.
+
+use std::collections::HashSet;
+use std::io;
+use std::os::fd::{BorrowedFd, RawFd};
+
+use crate::future::{Interest, Waitable};
+
+/// Tracks which child processes are being waited on, and which of them have
+/// exited.
+#[derive(Debug)]
+pub(super) struct ChildReaper {
+    /// Pids with a wait pending on them.
+    waiting: HashSet<u32>,
+    /// Pids which exited, but which haven't been dispatched yet.
+    exited: Vec<u32>,
+    #[cfg(any(not(target_os = "macos"), feature = "threads-backend"))]
+    signals: &'static signal::SignalPipe,
+}
+
+impl ChildReaper {
+    /// Whether exits are ready to dispatch without waiting on the poller.
+    pub(super) fn has_exited(&self) -> bool {
+        !self.exited.is_empty()
+    }
+
+    /// Report every exited process as ready, all at once.
+    pub(super) fn dispatch(&mut self, ready: &mut Vec<Waitable>) {
+        self.scan(ready);
+        for pid in self.exited.drain(..) {
+            // An exit for a pid nobody waits on anymore, because its wait
+            // was cancelled after the event was queued, wakes nobody up.
+            if self.waiting.remove(&pid) {
+                ready.push(Waitable::Process(pid, Interest::Read));
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "macos", not(feature = "threads-backend")))]
+impl ChildReaper {
+    pub(super) fn new() -> io::Result<Self> {
+        Ok(Self {
+            waiting: HashSet::new(),
+            exited: Vec::new(),
+        })
+    }
+
+    /// Start watching for the exit of `pid`.
+    pub(super) fn subscribe(&mut self, queue: BorrowedFd<'_>, pid: u32) -> io::Result<()> {
+        use rustix::event::kqueue::EventFlags;
+
+        if !self.waiting.insert(pid) {
+            return Ok(());
+        }
+        match change(queue, pid, EventFlags::ADD | EventFlags::ONESHOT) {
+            Ok(()) => Ok(()),
+            // It exited before we could register it, so there's nothing to
+            // wait for.
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {
+                self.exited.push(pid);
+                Ok(())
+            }
+            Err(e) => {
+                self.waiting.remove(&pid);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop watching for the exit of `pid`. The kernel drops the
+    /// registration by itself once the process has exited, so that's fine
+    /// too.
+    pub(super) fn unsubscribe(&mut self, queue: BorrowedFd<'_>, pid: u32) -> io::Result<()> {
+        use rustix::event::kqueue::EventFlags;
+
+        if !self.waiting.remove(&pid) {
+            return Ok(());
+        }
+        match change(queue, pid, EventFlags::DELETE) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record an exit event the poller received for `pid`.
+    pub(super) fn notify(&mut self, pid: u32) {
+        self.exited.push(pid);
+    }
+
+    /// The fd the poller has to watch for reads on the reaper's behalf.
+    pub(super) fn wakeup_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Exit events arrive through the poller itself, so there's nothing to
+    /// look for.
+    fn scan(&mut self, _ready: &mut Vec<Waitable>) {}
+}
+
+// Apply a single change to the `EVFILT_PROC` filter for `pid`.
+#[cfg(all(target_os = "macos", not(feature = "threads-backend")))]
+fn change(
+    queue: BorrowedFd<'_>,
+    pid: u32,
+    flags: rustix::event::kqueue::EventFlags,
+) -> io::Result<()> {
+    use rustix::event::kqueue;
+    use rustix::process::Pid;
+    use std::time::Duration;
+
+    let pid = Pid::from_raw(pid as i32).expect("pid is nonzero");
+    let filter = kqueue::EventFilter::Proc {
+        pid,
+        flags: kqueue::ProcessEvents::EXIT,
+    };
+    let event = kqueue::Event::new(filter, flags, 0);
+    let mut event_list = vec![];
+    // SAFETY: a process filter doesn't refer to any fd which could close.
+    unsafe { kqueue::kevent(queue, &[event], &mut event_list, Some(Duration::ZERO))? };
+    Ok(())
+}
+
+#[cfg(any(not(target_os = "macos"), feature = "threads-backend"))]
+impl ChildReaper {
+    pub(super) fn new() -> io::Result<Self> {
+        Ok(Self {
+            waiting: HashSet::new(),
+            exited: Vec::new(),
+            signals: signal::SignalPipe::install()?,
+        })
+    }
+
+    /// Start watching for the exit of `pid`.
+    pub(super) fn subscribe(&mut self, _queue: BorrowedFd<'_>, pid: u32) -> io::Result<()> {
+        // A process which exited before the handler was installed, or whose
+        // signal was already drained, won't signal again, so check once now.
+        if self.waiting.insert(pid) && signal::has_exited(pid)? {
+            self.exited.push(pid);
+        }
+        Ok(())
+    }
+
+    /// Stop watching for the exit of `pid`.
+    pub(super) fn unsubscribe(&mut self, _queue: BorrowedFd<'_>, pid: u32) -> io::Result<()> {
+        self.waiting.remove(&pid);
+        Ok(())
+    }
+
+    /// Exit events only ever arrive through the self-pipe.
+    pub(super) fn notify(&mut self, _pid: u32) {}
+
+    /// The fd the poller has to watch for reads on the reaper's behalf.
+    pub(super) fn wakeup_fd(&self) -> Option<RawFd> {
+        Some(self.signals.read_fd())
+    }
+
+    /// If `SIGCHLD` fired, check every pid being waited on. Several children
+    /// exiting at once may only leave a single byte in the pipe, so this
+    /// can't stop at the first exit it finds.
+    fn scan(&mut self, ready: &mut Vec<Waitable>) {
+        let fd = self.signals.read_fd();
+        let len = ready.len();
+        ready.retain(|waitable| *waitable != Waitable::Fd(fd, Interest::Read));
+        if ready.len() == len || !self.signals.drain() {
+            return;
+        }
+        for &pid in &self.waiting {
+            // On error the process is reported as exited, so that `try_wait`
+            // gets to surface the error.
+            if signal::has_exited(pid).unwrap_or(true) {
+                self.exited.push(pid);
+            }
+        }
+    }
+}
+
+#[cfg(any(not(target_os = "macos"), feature = "threads-backend"))]
+mod signal {
+    use std::io;
+    use std::os::fd::{AsFd, AsRawFd, RawFd};
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::{Mutex, OnceLock};
+
+    use crate::pipe::{self, PipeReader, PipeWriter};
+
+    /// The write end of the self-pipe, for the signal handler.
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    /// The self-pipe `SIGCHLD` writes to.
+    ///
+    /// Signal handlers are process-wide, so there's one of these per
+    /// process. It's shared by every runtime, which means two runtimes
+    /// waiting on children at the same time can steal each other's wakeups.
+    #[derive(Debug)]
+    pub(super) struct SignalPipe {
+        reader: PipeReader,
+        // Kept open for as long as the handler may write to it, which is
+        // forever.
+        _writer: PipeWriter,
+    }
+
+    impl SignalPipe {
+        /// Install the `SIGCHLD` handler, unless that already happened.
+        ///
+        /// This replaces any handler which was installed before.
+        pub(super) fn install() -> io::Result<&'static Self> {
+            static PIPE: OnceLock<SignalPipe> = OnceLock::new();
+            static INSTALL: Mutex<()> = Mutex::new(());
+
+            if let Some(pipe) = PIPE.get() {
+                return Ok(pipe);
+            }
+            let _guard = INSTALL.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(pipe) = PIPE.get() {
+                return Ok(pipe);
+            }
+            let (reader, writer) = pipe::pipe()?;
+            WRITE_FD.store(writer.as_raw_fd(), Ordering::Relaxed);
+            // SAFETY: the handler only makes async-signal-safe calls, and
+            // `action` is fully initialized before use.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_sigchld as *const () as usize;
+                action.sa_flags = libc::SA_RESTART | libc::SA_NOCLDSTOP;
+                libc::sigemptyset(&mut action.sa_mask);
+                if libc::sigaction(libc::SIGCHLD, &action, std::ptr::null_mut()) == -1 {
+                    WRITE_FD.store(-1, Ordering::Relaxed);
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(PIPE.get_or_init(|| SignalPipe {
+                reader,
+                _writer: writer,
+            }))
+        }
+
+        pub(super) fn read_fd(&self) -> RawFd {
+            self.reader.as_raw_fd()
+        }
+
+        /// Empty the pipe, returning whether there was anything in it.
+        pub(super) fn drain(&self) -> bool {
+            let mut buf = [0; 64];
+            let mut drained = false;
+            while let Ok(1..) = rustix::io::read(self.reader.as_fd(), &mut buf) {
+                drained = true;
+            }
+            drained
+        }
+    }
+
+    extern "C" fn on_sigchld(_signal: libc::c_int) {
+        // SAFETY: `write` is async-signal-safe, and `errno` is saved and
+        // restored so the interrupted code doesn't see ours.
+        unsafe {
+            let errno = *errno_location();
+            let fd = WRITE_FD.load(Ordering::Relaxed);
+            // A full pipe already has a wakeup pending, so failing is fine.
+            libc::write(fd, [0u8].as_ptr().cast(), 1);
+            *errno_location() = errno;
+        }
+    }
+
+    /// Where the calling thread's `errno` lives, under whichever name the
+    /// platform gives it.
+    unsafe fn errno_location() -> *mut libc::c_int {
+        #[cfg(target_vendor = "apple")]
+        return libc::__error();
+        #[cfg(not(target_vendor = "apple"))]
+        return libc::__errno_location();
+    }
+
+    /// Whether `pid` exited, leaving it for `try_wait` to reap.
+    pub(super) fn has_exited(pid: u32) -> io::Result<bool> {
+        // SAFETY: `info` is zeroed, so `si_pid` reads as 0 unless `waitid`
+        // found the process exited.
+        unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+            if libc::waitid(libc::P_PID, pid, &mut info, options) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(info.si_pid() != 0)
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Tasks which may borrow from the stack, spawned through
+//! [`Poller::scope`](super::Poller::scope).
+//!
+//! The scope's tasks don't go into the poller's task list, which only
+//! holds `'static` ones. They're driven by a future of their own instead,
+//! which `scope` blocks on until every one of them is done, so nothing they
+//! borrow can go away while they still run.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::completion;
+use super::task::{finish, Entry, Progress, Task};
+use super::JoinError;
+use crate::future::{Future, Interest, IntoFuture, Waitable};
+
+/// A scope to spawn tasks in, which may borrow anything outliving it; see
+/// [`Poller::scope`](super::Poller::scope).
+pub struct Scope<'scope, 'env: 'scope> {
+    /// Tasks spawned since the scope's driver last took them over. Tasks
+    /// borrowing the scope itself can't be dropped along with it, so
+    /// `Poller::scope` empties it by hand instead.
+    spawned: RefCell<ManuallyDrop<Vec<Box<dyn ScopedTask + 'scope>>>>,
+    /// Both lifetimes are invariant, as with `std::thread::Scope`.
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    pub(super) fn new() -> Self {
+        Self {
+            spawned: RefCell::new(ManuallyDrop::new(Vec::new())),
+            scope: PhantomData,
+            env: PhantomData,
+        }
+    }
+
+    /// Spawn `future` as a task of the scope, which may borrow anything
+    /// outliving the scope.
+    ///
+    /// The task first runs once the closure passed to
+    /// [`Poller::scope`](super::Poller::scope) returned, alongside the
+    /// scope's other tasks. It can spawn more tasks if it was handed the
+    /// scope.
+    pub fn spawn<Fut>(&'scope self, future: Fut) -> ScopedJoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: 'scope,
+        Fut::Output: 'scope,
+    {
+        let id = completion::register();
+        let slot = Rc::new(RefCell::new(Slot {
+            output: None,
+            panic: None,
+            aborted: false,
+        }));
+        let task = Scoped {
+            future: future.into_future(),
+            id,
+            slot: slot.clone(),
+        };
+        self.spawned.borrow_mut().push(Box::new(task));
+        ScopedJoinHandle { id, slot }
+    }
+
+    /// Drop the tasks the driver never took over, because the poller
+    /// failed before it got to them.
+    pub(super) fn clear(&self) {
+        drop(mem::take(&mut **self.spawned.borrow_mut()));
+    }
+}
+
+/// What a scoped task and its [`ScopedJoinHandle`] share.
+struct Slot<T> {
+    output: Option<T>,
+    /// The payload of the panic the task finished with instead, until the
+    /// handle takes it.
+    panic: Option<Box<dyn Any + Send>>,
+    aborted: bool,
+}
+
+/// A task of a [`Scope`].
+trait ScopedTask: Task {
+    /// The payload of the panic the task finished with, unless its handle
+    /// took it, so the scope resumes it instead.
+    fn unjoined_panic(&mut self) -> Option<Box<dyn Any + Send>>;
+}
+
+/// A future passed to [`Scope::spawn`].
+struct Scoped<F: Future> {
+    future: F,
+    id: u64,
+    slot: Rc<RefCell<Slot<F::Output>>>,
+}
+
+/// A panic is caught and handed over to the join handle, like it is for a
+/// spawned task.
+impl<F: Future> Task for Scoped<F> {
+    fn poll_task(&mut self, ready: &[Waitable], waiting_on: &mut Vec<Waitable>) -> Progress {
+        let polled = panic::catch_unwind(AssertUnwindSafe(|| {
+            waiting_on.extend(self.future.poll(ready));
+            match waiting_on.is_empty() {
+                true => Some(finish(&mut self.future)),
+                false => None,
+            }
+        }));
+        let progress = match polled {
+            Ok(None) => return Progress::Pending,
+            Ok(Some(output)) => {
+                self.slot.borrow_mut().output = Some(output);
+                Progress::Finished
+            }
+            Err(payload) => {
+                waiting_on.clear();
+                self.slot.borrow_mut().panic = Some(payload);
+                Progress::Panicked
+            }
+        };
+        completion::complete(self.id);
+        progress
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.slot.borrow().aborted
+    }
+}
+
+impl<F: Future> ScopedTask for Scoped<F> {
+    fn unjoined_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.slot.borrow_mut().panic.take()
+    }
+}
+
+impl<F: Future> Drop for Scoped<F> {
+    fn drop(&mut self) {
+        // The empty slot tells the handle the task was cancelled.
+        completion::complete(self.id);
+    }
+}
+
+/// Future for a task spawned with [`Scope::spawn`], resolving with the
+/// task's output.
+///
+/// Dropping the handle detaches the task: the scope still waits for it, but
+/// its output is discarded. The handle resolves with [`JoinError::Aborted`]
+/// if the task was aborted, and with [`JoinError::Panicked`] if it
+/// panicked, in which case the scope doesn't resume the panic.
+///
+/// The handle doesn't borrow the scope, so the closure passed to
+/// [`Poller::scope`](super::Poller::scope) can return it, to take the
+/// task's output without waiting once the scope is done.
+pub struct ScopedJoinHandle<T> {
+    id: u64,
+    slot: Rc<RefCell<Slot<T>>>,
+}
+
+impl<T> ScopedJoinHandle<T> {
+    /// Abort the task. The scope drops it the next time it polls its tasks,
+    /// deregistering whatever the task was waiting on, and the handle
+    /// resolves with [`JoinError::Aborted`] right away.
+    ///
+    /// Aborting a task which already finished does nothing: the handle
+    /// still resolves with its output.
+    pub fn abort(&self) {
+        let mut slot = self.slot.borrow_mut();
+        if slot.output.is_none() && slot.panic.is_none() {
+            slot.aborted = true;
+        }
+    }
+
+    /// Whether the task finished, was aborted, or was dropped, so the handle
+    /// resolves without waiting.
+    pub fn is_finished(&self) -> bool {
+        let slot = self.slot.borrow();
+        slot.output.is_some() || slot.aborted || completion::is_complete(self.id)
+    }
+}
+
+impl<T> Future for ScopedJoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        match self.is_finished() {
+            true => None,
+            false => Some(Waitable::Completion(self.id)),
+        }
+        .into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        if !self.is_finished() {
+            return None;
+        }
+        completion::remove(self.id);
+        let mut slot = self.slot.borrow_mut();
+        match (slot.output.take(), slot.panic.take()) {
+            (Some(output), _) => Some(Ok(output)),
+            (None, Some(payload)) => Some(Err(JoinError::Panicked(payload))),
+            (None, None) => Some(Err(JoinError::Aborted)),
+        }
+    }
+}
+
+impl<T> Drop for ScopedJoinHandle<T> {
+    fn drop(&mut self) {
+        completion::remove(self.id);
+    }
+}
+
+/// Drives the tasks of a scope, resolving once they're all done with the
+/// first panic nobody joined, if any.
+pub(super) struct Drive<'a, 'scope, 'env> {
+    scope: &'a Scope<'scope, 'env>,
+    tasks: Vec<Entry<Box<dyn ScopedTask + 'scope>>>,
+    /// Tasks which panicked, until the scope ends and checks whether their
+    /// handles took the panic.
+    panicked: Vec<Box<dyn ScopedTask + 'scope>>,
+    done: bool,
+}
+
+impl<'a, 'scope, 'env> Drive<'a, 'scope, 'env> {
+    pub(super) fn new(scope: &'a Scope<'scope, 'env>) -> Self {
+        Self {
+            scope,
+            tasks: Vec::new(),
+            panicked: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl Future for Drive<'_, '_, '_> {
+    type Output = Option<Box<dyn Any + Send>>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut yielded = Vec::new();
+        let mut repoll = false;
+        let mut ready = ready;
+        // Tasks spawned while polling get their first poll right away.
+        loop {
+            let spawned = mem::take(&mut **self.scope.spawned.borrow_mut());
+            let mut rest = Vec::new();
+            self.tasks.extend(spawned.into_iter().map(Entry::new));
+            for mut entry in self.tasks.drain(..) {
+                if entry.task.is_aborted() {
+                    yielded.extend(entry.waiting_on.drain(..).filter_map(Waitable::cancel));
+                    continue;
+                }
+                let relevant: Vec<_> = ready
+                    .iter()
+                    .copied()
+                    .filter(|r| entry.waiting_on.iter().any(|w| r.wakes(*w)))
+                    .collect();
+                if relevant.is_empty() && !entry.repoll {
+                    yielded.extend(entry.waiting_on.iter().copied().filter(|w| waits(*w)));
+                    rest.push(entry);
+                    continue;
+                }
+                let previous = mem::take(&mut entry.waiting_on);
+                match entry.task.poll_task(&relevant, &mut entry.waiting_on) {
+                    Progress::Pending => {
+                        entry.repoll = !entry.waiting_on.iter().any(|w| waits(*w));
+                        repoll |= entry.repoll;
+                        yielded.extend_from_slice(&entry.waiting_on);
+                        rest.push(entry);
+                    }
+                    Progress::Finished => {}
+                    Progress::Panicked => {
+                        // Whatever the task waited on before, it won't anymore.
+                        yielded.extend(previous.into_iter().filter_map(Waitable::cancel));
+                        self.panicked.push(entry.task);
+                    }
+                }
+            }
+            self.tasks = rest;
+            if self.scope.spawned.borrow().is_empty() {
+                break;
+            }
+            ready = &[];
+        }
+        if repoll {
+            // A task only deregistered things, so it has more work to do
+            // right away.
+            yielded.push(Waitable::Timer(Instant::now()));
+        }
+        self.done = self.tasks.is_empty();
+        yielded.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        if !self.done {
+            return None;
+        }
+        let mut panics = self
+            .panicked
+            .iter_mut()
+            .filter_map(|task| task.unjoined_panic());
+        Some(panics.next())
+    }
+}
+
+/// Whether a task yielding `waitable` waits for it, rather than having the
+/// poller stop waiting for it.
+fn waits(waitable: Waitable) -> bool {
+    match waitable {
+        Waitable::Fd(_, Interest::Read | Interest::Write | Interest::Hangup) => true,
+        Waitable::Fd(_, Interest::Priority) => true,
+        Waitable::FdUntil(_, Interest::Read | Interest::Write | Interest::Hangup, _) => true,
+        Waitable::FdUntil(_, Interest::Priority, _) => true,
+        Waitable::Process(_, Interest::Read | Interest::Write) => true,
+        Waitable::Fd(..) | Waitable::FdUntil(..) | Waitable::Process(..) => false,
+        Waitable::Vnode(_, kinds) => kinds != 0,
+        Waitable::Timer(_) | Waitable::Completion(_) | Waitable::Signal(_) => true,
+    }
+}
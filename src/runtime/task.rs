@@ -0,0 +1,565 @@
+//! Futures spawned onto a poller, and the handles for joining them.
+//!
+//! Every task is keyed by the id of the completion its [`JoinHandle`]
+//! waits on. The poller keeps what each task waited on as of its last poll,
+//! and only polls it again once one of those became ready.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use super::{completion, BufferPool, RuntimeError};
+#[cfg(feature = "blocking")]
+use crate::blocking::Pool;
+use crate::future::{Future, Interest, IntoFuture, Waitable};
+
+thread_local! {
+    /// The handle of the poller running on this thread, if any.
+    static CURRENT: RefCell<Option<Handle>> = const { RefCell::new(None) };
+}
+
+/// What came of polling a task.
+pub(super) enum Progress {
+    /// The task waits on what it added to `waiting_on`.
+    Pending,
+    /// The task finished, and its output was handed over.
+    Finished,
+    /// The task panicked, and the payload was handed over instead.
+    Panicked,
+}
+
+/// A future the poller drives, with its output going somewhere else.
+pub(super) trait Task {
+    /// Poll the future, adding what it waits on to `waiting_on`.
+    fn poll_task(&mut self, ready: &[Waitable], waiting_on: &mut Vec<Waitable>) -> Progress;
+
+    /// Whether the task was aborted, so the poller should drop it.
+    fn is_aborted(&self) -> bool {
+        false
+    }
+
+    /// The id of a spawned task, for tracing.
+    #[cfg(feature = "tracing")]
+    fn task_id(&self) -> Option<u64> {
+        None
+    }
+
+    /// The name a task was spawned with, for [`TaskDump`]s.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// A task, and what it waited on as of its last poll.
+pub(super) struct Entry<T: ?Sized> {
+    pub(super) waiting_on: Vec<Waitable>,
+    /// Whether to poll the task next turn even if nothing it waits on became
+    /// ready, because it's new or only deregistered things last time.
+    pub(super) repoll: bool,
+    /// How many turns in a row the task was polled.
+    pub(super) streak: u32,
+    /// When the task was spawned, or last polled.
+    pub(super) progressed: Instant,
+    pub(super) task: T,
+}
+
+impl<T> Entry<T> {
+    pub(super) fn new(task: T) -> Self {
+        Self {
+            waiting_on: Vec::new(),
+            repoll: true,
+            streak: 0,
+            progressed: Instant::now(),
+            task,
+        }
+    }
+}
+
+/// Take the output of a future which has nothing left to wait on.
+pub(super) fn finish<F: Future>(future: &mut F) -> F::Output {
+    match future.take() {
+        Some(output) => output,
+        None => panic!("No more events to wait on and no data present"),
+    }
+}
+
+/// The future passed to [`Poller::block_on`](super::Poller::block_on).
+pub(super) struct BlockOn<F: Future> {
+    pub(super) future: F,
+    pub(super) output: Option<F::Output>,
+}
+
+impl<F: Future> BlockOn<F> {
+    /// Take the output once the poller says the future finished.
+    pub(super) fn take_output(&mut self) -> Result<F::Output, RuntimeError> {
+        self.output.take().ok_or(RuntimeError::FutureMisbehaved(
+            "nothing to wait on, but no output to take either",
+        ))
+    }
+}
+
+/// Panics aren't caught: they unwind out of `block_on`.
+impl<F: Future> Task for BlockOn<F> {
+    fn poll_task(&mut self, ready: &[Waitable], waiting_on: &mut Vec<Waitable>) -> Progress {
+        waiting_on.extend(self.future.poll(ready));
+        match waiting_on.is_empty() {
+            true => {
+                // No output shows up as `FutureMisbehaved` in `take_output`.
+                self.output = self.future.take();
+                Progress::Finished
+            }
+            false => Progress::Pending,
+        }
+    }
+}
+
+/// What a task and its [`JoinHandle`] share.
+#[derive(Debug)]
+struct Slot<T> {
+    output: Option<T>,
+    /// The payload of the panic the task finished with instead.
+    panic: Option<Box<dyn Any + Send>>,
+    aborted: bool,
+    /// The task was spawned while its poller shut down, so it never ran.
+    refused: bool,
+}
+
+fn lock<T>(slot: &Mutex<Slot<T>>) -> MutexGuard<'_, Slot<T>> {
+    slot.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A future passed to [`Poller::spawn`](super::Poller::spawn).
+pub(super) struct Spawned<F: Future> {
+    future: F,
+    id: u64,
+    name: Option<String>,
+    slot: Arc<Mutex<Slot<F::Output>>>,
+}
+
+impl<F: Future> Spawned<F> {
+    /// Wrap `future` into a task, along with the handle for joining it.
+    pub(super) fn new(future: F) -> (Self, JoinHandle<F::Output>) {
+        let id = completion::register();
+        let slot = Arc::new(Mutex::new(Slot {
+            output: None,
+            panic: None,
+            aborted: false,
+            refused: false,
+        }));
+        let handle = JoinHandle {
+            id,
+            slot: slot.clone(),
+        };
+        let task = Self {
+            future,
+            id,
+            name: None,
+            slot,
+        };
+        (task, handle)
+    }
+
+    pub(super) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Drop the task without running it, because its poller is shutting
+    /// down.
+    fn refuse(self) {
+        lock(&self.slot).refused = true;
+    }
+}
+
+/// A panic is caught and handed over to the join handle, so it only takes
+/// down the task which panicked.
+impl<F: Future> Task for Spawned<F> {
+    fn poll_task(&mut self, ready: &[Waitable], waiting_on: &mut Vec<Waitable>) -> Progress {
+        let polled = panic::catch_unwind(AssertUnwindSafe(|| {
+            waiting_on.extend(self.future.poll(ready));
+            match waiting_on.is_empty() {
+                true => Some(finish(&mut self.future)),
+                false => None,
+            }
+        }));
+        let progress = match polled {
+            Ok(None) => return Progress::Pending,
+            Ok(Some(output)) => {
+                lock(&self.slot).output = Some(output);
+                Progress::Finished
+            }
+            Err(payload) => {
+                waiting_on.clear();
+                lock(&self.slot).panic = Some(payload);
+                Progress::Panicked
+            }
+        };
+        completion::complete(self.id);
+        progress
+    }
+
+    fn is_aborted(&self) -> bool {
+        lock(&self.slot).aborted
+    }
+
+    #[cfg(feature = "tracing")]
+    fn task_id(&self) -> Option<u64> {
+        Some(self.id)
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl<F: Future> Drop for Spawned<F> {
+    fn drop(&mut self) {
+        // Wake up whoever joins the task if it's dropped before finishing;
+        // the empty slot tells them it was cancelled. For a finished task
+        // this does nothing.
+        completion::complete(self.id);
+    }
+}
+
+/// Future for a task spawned with [`Poller::spawn`](super::Poller::spawn),
+/// resolving with the task's output.
+///
+/// The task only makes progress while its poller runs. Dropping the handle
+/// detaches the task: it keeps running, but its output is discarded. The
+/// handle resolves with [`JoinError::Aborted`] instead if the task was
+/// aborted, or dropped by its poller before it finished, as
+/// [`Poller::run_until`](super::Poller::run_until) does, with
+/// [`JoinError::Panicked`] if it panicked, and with [`JoinError::ShutDown`]
+/// if it was spawned while the poller shut down.
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    id: u64,
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Abort the task. Its poller drops it the next time it takes a turn,
+    /// deregistering whatever the task was waiting on, and the handle
+    /// resolves with [`JoinError::Aborted`] right away.
+    ///
+    /// Aborting a task which already finished does nothing: the handle
+    /// still resolves with its output.
+    pub fn abort(&self) {
+        let mut slot = lock(&self.slot);
+        if slot.output.is_none() {
+            slot.aborted = true;
+        }
+    }
+
+    /// Whether the task finished, was aborted, or was dropped, so the handle
+    /// resolves without waiting.
+    pub fn is_finished(&self) -> bool {
+        let slot = lock(&self.slot);
+        slot.output.is_some() || slot.aborted || completion::is_complete(self.id)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        match self.is_finished() {
+            true => None,
+            false => Some(Waitable::Completion(self.id)),
+        }
+        .into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        if !self.is_finished() {
+            return None;
+        }
+        completion::remove(self.id);
+        let mut slot = lock(&self.slot);
+        match (slot.output.take(), slot.panic.take()) {
+            (Some(output), _) => Some(Ok(output)),
+            (None, Some(payload)) => Some(Err(JoinError::Panicked(payload))),
+            (None, None) if slot.refused => Some(Err(JoinError::ShutDown)),
+            (None, None) => Some(Err(JoinError::Aborted)),
+        }
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        completion::remove(self.id);
+    }
+}
+
+/// Why a [`JoinHandle`] resolved without the task's output.
+pub enum JoinError {
+    /// The task was aborted through [`JoinHandle::abort`], or dropped by
+    /// its poller before it finished.
+    Aborted,
+    /// The task panicked, with this payload. Pass it to
+    /// [`std::panic::resume_unwind`] to carry on panicking.
+    Panicked(Box<dyn Any + Send>),
+    /// The task was spawned while its poller was shutting down, through
+    /// [`Poller::shutdown`](super::Poller::shutdown), so it never ran.
+    ShutDown,
+}
+
+impl JoinError {
+    /// The panic's message, for the usual payloads of `&str` or `String`.
+    fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+        match payload.downcast_ref::<&str>() {
+            Some(msg) => Some(msg),
+            None => payload.downcast_ref::<String>().map(String::as_str),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Aborted => f.write_str("Aborted"),
+            JoinError::ShutDown => f.write_str("ShutDown"),
+            JoinError::Panicked(payload) => f
+                .debug_tuple("Panicked")
+                .field(&Self::panic_message(&**payload).unwrap_or("<non-string payload>"))
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Aborted => write!(f, "the task was aborted"),
+            JoinError::ShutDown => write!(f, "the runtime was shutting down"),
+            JoinError::Panicked(payload) => match Self::panic_message(&**payload) {
+                Some(msg) => write!(f, "the task panicked: {msg}"),
+                None => write!(f, "the task panicked"),
+            },
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+impl From<JoinError> for io::Error {
+    fn from(err: JoinError) -> Self {
+        io::Error::other(err.to_string())
+    }
+}
+
+/// A spawned task, waiting for its poller to pick it up.
+pub(super) type Pending = (u64, Box<Entry<dyn Task>>);
+
+/// A handle for spawning tasks onto a [`Poller`](super::Poller), including
+/// from tasks running on it.
+///
+/// Tasks spawned through a handle join the poller's task table on its next
+/// turn; once the poller is gone, they never run. Clones refer to the same
+/// poller. Handles don't leave the thread, so spawned futures don't need to
+/// be `Send`.
+#[derive(Clone, Default)]
+pub struct Handle {
+    pending: Rc<RefCell<Vec<Pending>>>,
+    /// Whether the poller shuts down, and so refuses new tasks.
+    closed: Rc<Cell<bool>>,
+    /// The poller's own blocking pool, if it has one.
+    #[cfg(feature = "blocking")]
+    pool: Option<Arc<Pool>>,
+    buffers: BufferPool,
+}
+
+impl std::fmt::Debug for Handle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle")
+            .field("pending", &self.pending.borrow().len())
+            .field("closed", &self.closed.get())
+            .finish()
+    }
+}
+
+impl Handle {
+    pub(super) fn new(
+        #[cfg(feature = "blocking")] pool: Option<Arc<Pool>>,
+        buffers: BufferPool,
+    ) -> Self {
+        Self {
+            #[cfg(feature = "blocking")]
+            pool,
+            buffers,
+            ..Self::default()
+        }
+    }
+
+    /// The handle of the poller running on this thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no poller is running on this thread, that is: this isn't
+    /// called from a future driven by `block_on` or `run`.
+    pub fn current() -> Self {
+        match Self::try_current() {
+            Some(handle) => handle,
+            None => panic!("no runtime running on this thread"),
+        }
+    }
+
+    /// The handle of the poller running on this thread, if any.
+    pub fn try_current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Spawn `future` onto the poller, like
+    /// [`Poller::spawn`](super::Poller::spawn).
+    pub fn spawn<Fut>(&self, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: 'static,
+    {
+        let (task, handle) = Spawned::new(future.into_future());
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Spawn `future` onto the poller under `name`, like
+    /// [`Poller::spawn_named`](super::Poller::spawn_named).
+    pub fn spawn_named<Fut>(&self, name: impl Into<String>, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: 'static,
+    {
+        let (mut task, handle) = Spawned::new(future.into_future());
+        task.name = Some(name.into());
+        self.spawn_task(task);
+        handle
+    }
+
+    /// Hand a task created elsewhere to the poller.
+    pub(super) fn spawn_task<F: Future + 'static>(&self, task: Spawned<F>) {
+        if self.closed.get() {
+            return task.refuse();
+        }
+        let pending: Pending = (task.id(), Box::new(Entry::new(task)));
+        self.pending.borrow_mut().push(pending);
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(super) fn pool(&self) -> Option<Arc<Pool>> {
+        self.pool.clone()
+    }
+
+    /// The poller's pool of read buffers; see
+    /// [`Builder::buffer_pool`](super::Builder::buffer_pool).
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.buffers
+    }
+
+    /// Refuse the tasks spawned from now on.
+    pub(super) fn close(&self) {
+        self.closed.set(true);
+    }
+
+    /// Take the tasks which were spawned since last time.
+    pub(super) fn take_pending(&self) -> Vec<Pending> {
+        std::mem::take(&mut *self.pending.borrow_mut())
+    }
+
+    /// Make this the current handle until the guard is dropped.
+    pub(super) fn enter(&self) -> EnterGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        EnterGuard { previous }
+    }
+}
+
+/// Restores the previous current handle on drop.
+pub(super) struct EnterGuard {
+    previous: Option<Handle>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// A spawned task as of [`Poller::dump_tasks`](super::Poller::dump_tasks),
+/// for finding out what's stuck.
+///
+/// Its `Debug` output reads like
+/// `task 3 "conn-9" waiting on fd 12 READ since 4.2s`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TaskDump {
+    /// The id the task is keyed by. Ids go up with every spawn, so they tell
+    /// unnamed tasks apart.
+    pub id: u64,
+    /// The name it was spawned with, if any.
+    pub name: Option<String>,
+    /// What the task waited on as of its last poll. Empty for a task which
+    /// wasn't polled yet, or will be polled again next turn.
+    pub waiting_on: Vec<Waitable>,
+    /// When the task was spawned, or last polled.
+    pub progressed: Instant,
+}
+
+impl fmt::Debug for TaskDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}", self.id)?;
+        if let Some(name) = &self.name {
+            write!(f, " {name:?}")?;
+        }
+        match self.waiting_on.as_slice() {
+            [] => f.write_str(" runnable")?,
+            [first, rest @ ..] => {
+                f.write_str(" waiting on ")?;
+                fmt_waitable(first, f)?;
+                for waitable in rest {
+                    f.write_str(", ")?;
+                    fmt_waitable(waitable, f)?;
+                }
+            }
+        }
+        write!(f, " since {:.1?}", self.progressed.elapsed())
+    }
+}
+
+fn fmt_waitable(waitable: &Waitable, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn interest(interest: Interest) -> &'static str {
+        match interest {
+            Interest::Read => "READ",
+            Interest::Write => "WRITE",
+            Interest::CloseRead => "CLOSE_READ",
+            Interest::CloseWrite => "CLOSE_WRITE",
+            Interest::Close => "CLOSE",
+            Interest::Hangup => "HANGUP",
+            Interest::Priority => "PRIORITY",
+            Interest::ClosePriority => "CLOSE_PRIORITY",
+        }
+    }
+    match *waitable {
+        Waitable::Fd(fd, i) => write!(f, "fd {fd} {}", interest(i)),
+        Waitable::FdUntil(fd, i, deadline) => {
+            let now = Instant::now();
+            match deadline.checked_duration_since(now) {
+                Some(left) => write!(f, "fd {fd} {} until {left:.1?} from now", interest(i)),
+                None => write!(f, "fd {fd} {} timed out", interest(i)),
+            }
+        }
+        Waitable::Timer(deadline) => {
+            let now = Instant::now();
+            match deadline.checked_duration_since(now) {
+                Some(left) => write!(f, "timer in {left:.1?}"),
+                None => write!(f, "timer {:.1?} ago", now - deadline),
+            }
+        }
+        Waitable::Process(pid, i) => write!(f, "process {pid} {}", interest(i)),
+        Waitable::Vnode(fd, kinds) => write!(f, "vnode {fd} {kinds:#x}"),
+        Waitable::Completion(id) => write!(f, "completion {id}"),
+        Waitable::Signal(signum) => write!(f, "signal {signum}"),
+    }
+}
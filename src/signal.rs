@@ -0,0 +1,235 @@
+//! Unix signals.
+//!
+//! A [`Signal`] is a [`Stream`] yielding an item for every delivery of one
+//! signal. kqueue reports each signal once per queue however many `Signal`s
+//! are listening for it, so deliveries are counted in a process-wide table,
+//! and every `Signal` yields one item for each delivery it hasn't seen yet.
+//!
+//! While any `Signal` for a signal number exists, the signal is ignored and
+//! blocked, so it doesn't run its default action (which for most signals is
+//! to terminate the process); kqueue still records it. Once the last one is
+//! dropped, the previous action and mask are restored.
+//!
+//! kqueue only records signals for a queue once the signal is registered
+//! with it, which happens the first time a `Signal` is polled. Deliveries
+//! from before then are missed. Since the table is shared, only one runtime
+//! per process should be listening for a given signal.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::future::{Future, Waitable};
+use crate::stream::Stream;
+
+/// Bookkeeping for a signal with at least one [`Signal`] listening.
+struct Registration {
+    /// How many `Signal`s exist for this signal number.
+    listeners: usize,
+    /// How many times the signal was delivered since the first of them was
+    /// created.
+    deliveries: u64,
+    /// The action to restore once the last listener is gone.
+    previous_action: libc::sigaction,
+    /// Whether the signal was blocked before we blocked it.
+    was_blocked: bool,
+}
+
+static REGISTRATIONS: Mutex<BTreeMap<i32, Registration>> = Mutex::new(BTreeMap::new());
+
+fn registrations() -> MutexGuard<'static, BTreeMap<i32, Registration>> {
+    REGISTRATIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record that the runtime saw `signum` delivered `times` times. Returns
+/// whether anyone is still listening for it.
+pub(crate) fn deliver(signum: i32, times: usize) -> bool {
+    match registrations().get_mut(&signum) {
+        Some(registration) => {
+            registration.deliveries += times as u64;
+            true
+        }
+        None => false,
+    }
+}
+
+/// A stream of the deliveries of one signal.
+///
+/// Every `Signal` yields every delivery, even when there are several for the
+/// same signal number. Deliveries which arrive faster than the stream is
+/// polled are queued up rather than merged, as far as kqueue counts them.
+#[derive(Debug)]
+pub struct Signal {
+    signum: i32,
+    /// How many deliveries this stream has yielded, or skipped because they
+    /// arrived before it was created.
+    seen: u64,
+    item: Option<()>,
+}
+
+impl Signal {
+    /// Start listening for the signal with number `signum`, such as
+    /// `libc::SIGHUP`.
+    ///
+    /// Fails with `InvalidInput` for signals which can't be caught, such as
+    /// `SIGKILL`.
+    pub fn new(signum: i32) -> io::Result<Self> {
+        if rustix::process::Signal::from_raw(signum).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{signum} is not a signal number"),
+            ));
+        }
+        let mut registrations = registrations();
+        let seen = match registrations.get_mut(&signum) {
+            Some(registration) => {
+                registration.listeners += 1;
+                registration.deliveries
+            }
+            None => {
+                registrations.insert(signum, subscribe(signum)?);
+                0
+            }
+        };
+        Ok(Self {
+            signum,
+            seen,
+            item: None,
+        })
+    }
+
+    /// The number of the signal this stream yields deliveries of.
+    pub fn signum(&self) -> i32 {
+        self.signum
+    }
+}
+
+impl Stream for Signal {
+    type Item = ();
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<> {
+        let mut pending = None;
+        if self.item.is_none() {
+            let deliveries = registrations()
+                .get(&self.signum)
+                .map_or(0, |registration| registration.deliveries);
+            match deliveries > self.seen {
+                true => {
+                    self.seen += 1;
+                    self.item = Some(());
+                }
+                false => pending = Some(Waitable::Signal(self.signum)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    /// The stream never ends, so this only returns `None` if no delivery
+    /// was made ready.
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
+
+impl Drop for Signal {
+    fn drop(&mut self) {
+        let mut registrations = registrations();
+        let Some(registration) = registrations.get_mut(&self.signum) else {
+            return;
+        };
+        registration.listeners -= 1;
+        if registration.listeners == 0 {
+            let registration = registrations.remove(&self.signum).unwrap();
+            unsubscribe(self.signum, &registration);
+        }
+    }
+}
+
+/// Ignore and block `signum`, remembering how to undo that.
+fn subscribe(signum: i32) -> io::Result<Registration> {
+    // SAFETY: both structs are fully initialized before use, and ignoring a
+    // signal doesn't run any code of ours in signal context.
+    unsafe {
+        let mut ignore: libc::sigaction = std::mem::zeroed();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        libc::sigemptyset(&mut ignore.sa_mask);
+        let mut previous_action = std::mem::zeroed();
+        if libc::sigaction(signum, &ignore, &mut previous_action) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The mask is per thread, so this only covers this thread and the
+        // threads it spawns from now on. Ignoring the signal covers the rest.
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signum);
+        let mut previous_set = std::mem::zeroed();
+        let result = libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut previous_set);
+        if result != 0 {
+            libc::sigaction(signum, &previous_action, std::ptr::null_mut());
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        Ok(Registration {
+            listeners: 1,
+            deliveries: 0,
+            previous_action,
+            was_blocked: libc::sigismember(&previous_set, signum) == 1,
+        })
+    }
+}
+
+/// Undo [`subscribe`]. Failing to do so leaves the signal ignored, which
+/// there's nothing to be done about from a destructor.
+fn unsubscribe(signum: i32, registration: &Registration) {
+    // SAFETY: as in `subscribe`. The signal is still ignored while it's
+    // unblocked, so any instance which is pending gets discarded rather than
+    // running the previous action.
+    unsafe {
+        if !registration.was_blocked {
+            let mut set = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, signum);
+            libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+        }
+        libc::sigaction(signum, &registration.previous_action, std::ptr::null_mut());
+    }
+}
+
+/// Wait for the next `SIGINT`, as sent by ctrl-c in a terminal.
+///
+/// Until the future is dropped, ctrl-c no longer terminates the process.
+pub fn ctrl_c() -> CtrlC {
+    let (signal, output) = match Signal::new(libc::SIGINT) {
+        Ok(signal) => (Some(signal), None),
+        Err(e) => (None, Some(Err(e))),
+    };
+    CtrlC { signal, output }
+}
+
+/// Future for [`ctrl_c`].
+#[derive(Debug)]
+pub struct CtrlC {
+    /// Only `None` if listening failed.
+    signal: Option<Signal>,
+    output: Option<io::Result<()>>,
+}
+
+impl Future for CtrlC {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let (Some(signal), None) = (&mut self.signal, &self.output) {
+            let waitables = signal.poll_next(ready);
+            match signal.take_next() {
+                Some(()) => self.output = Some(Ok(())),
+                None => pending = Some(waitables),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,218 @@
+//! Asynchronous sequences of values.
+
+#[cfg(feature = "time")]
+use std::time::{Duration, Instant};
+
+use crate::future::{Future, Waitable};
+#[cfg(feature = "time")]
+use crate::time::Elapsed;
+
+/// An asynchronous sequence of values, in the same poll style as [`Future`].
+pub trait Stream {
+    type Item;
+
+    /// Make progress towards the next item, yielding the waitables the
+    /// stream is blocked on.
+    ///
+    /// Once this yields nothing, either an item is ready or the stream has
+    /// ended, and [`Stream::take_next`] says which. Polling again before
+    /// taking the item must not produce another one. The returned waitables
+    /// may not borrow from `self`, so callers are free to poll in a loop.
+    fn poll_next(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<Self>;
+
+    /// Take the item produced by the last call to [`Stream::poll_next`], or
+    /// `None` if the stream has ended.
+    fn take_next(&mut self) -> Option<Self::Item>;
+}
+
+/// Convenience futures for everything implementing [`Stream`].
+pub trait StreamExt: Stream {
+    /// Resolve with the next item in the stream, or `None` once it has ended.
+    fn next(&mut self) -> Next<'_, Self> {
+        Next {
+            stream: self,
+            ready: false,
+        }
+    }
+
+    /// Call `f` for every item in the stream, resolving once it has ended.
+    fn for_each<F>(self, f: F) -> ForEach<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item),
+    {
+        ForEach {
+            stream: self,
+            f,
+            done: false,
+            output: None,
+        }
+    }
+
+    /// Fail with [`Elapsed`] whenever the stream goes `per_item` without
+    /// producing an item, counting from the first poll and then from each
+    /// item.
+    ///
+    /// The stream keeps going after a timeout, with the clock started over.
+    /// The read and write registrations the stream was waiting on are
+    /// dropped when the timeout hits, so giving up on the stream then leaves
+    /// nothing behind in the poller.
+    #[cfg(feature = "time")]
+    fn timeout(self, per_item: Duration) -> Timeout<Self>
+    where
+        Self: Sized,
+    {
+        Timeout {
+            stream: self,
+            per_item,
+            deadline: None,
+            elapsed: false,
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+/// Future for [`StreamExt::next`].
+pub struct Next<'a, S: ?Sized> {
+    stream: &'a mut S,
+    ready: bool,
+}
+
+impl<'a, S: Stream + ?Sized> Future for Next<'a, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waitables = self.stream.poll_next(ready).peekable();
+        self.ready = waitables.peek().is_none();
+        waitables
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.ready {
+            true => {
+                self.ready = false;
+                Some(self.stream.take_next())
+            }
+            false => None,
+        }
+    }
+}
+
+/// Future for [`StreamExt::for_each`].
+pub struct ForEach<S, F> {
+    stream: S,
+    f: F,
+    done: bool,
+    output: Option<()>,
+}
+
+impl<S, F> Future for ForEach<S, F>
+where
+    S: Stream,
+    F: FnMut(S::Item),
+{
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        while !self.done {
+            let mut waitables = self.stream.poll_next(ready).peekable();
+            if waitables.peek().is_some() {
+                return Some(waitables).into_iter().flatten();
+            }
+            match self.stream.take_next() {
+                Some(item) => (self.f)(item),
+                None => {
+                    self.done = true;
+                    self.output = Some(());
+                }
+            }
+        }
+        None.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`StreamExt::timeout`].
+#[cfg(feature = "time")]
+pub struct Timeout<S> {
+    stream: S,
+    per_item: Duration,
+    /// When the next item is due, once the stream has been polled.
+    deadline: Option<Instant>,
+    /// Whether the deadline passed, and the error is next.
+    elapsed: bool,
+    /// Whether the stream ended.
+    done: bool,
+}
+
+#[cfg(feature = "time")]
+impl<S> Timeout<S> {
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(feature = "time")]
+impl<S: Stream> Stream for Timeout<S> {
+    type Item = Result<S::Item, Elapsed>;
+
+    fn poll_next(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<S> {
+        let (mut waitables, mut cancelled, mut timer) = (None, Vec::new(), None);
+        if !self.done && !self.elapsed {
+            let mut pending = self.stream.poll_next(ready).peekable();
+            // Only a stream which is still waiting needs the timer; one with
+            // an item ready, or which ended, gets none.
+            if pending.peek().is_some() {
+                let now = Instant::now();
+                let deadline = *self.deadline.get_or_insert(now + self.per_item);
+                match now >= deadline {
+                    true => {
+                        self.elapsed = true;
+                        cancelled.extend(pending.filter_map(Waitable::cancel));
+                    }
+                    false => {
+                        waitables = Some(pending);
+                        timer = Some(Waitable::Timer(deadline));
+                    }
+                }
+            }
+        }
+        let waitables = waitables.into_iter().flatten();
+        waitables.chain(cancelled).chain(timer)
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.elapsed {
+            self.elapsed = false;
+            self.deadline = None;
+            return Some(Err(Elapsed::new()));
+        }
+        match self.stream.take_next() {
+            Some(item) => {
+                self.deadline = None;
+                Some(Ok(item))
+            }
+            None => {
+                self.done = true;
+                self.deadline = None;
+                None
+            }
+        }
+    }
+}
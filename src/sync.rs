@@ -0,0 +1,100 @@
+//! Synchronization between threads.
+
+use std::sync::Arc;
+
+use crate::future::{Future, Waitable};
+use crate::runtime::completion;
+
+/// An event which is either set or not, like a gate.
+///
+/// Waiting on a set event resolves right away; waiting on an unset one
+/// resolves once somebody sets it, from any thread. A single
+/// [`Event::set`] releases every waiter, on any number of runtimes. The
+/// event stays set until [`Event::reset`], so it suits one-off conditions
+/// such as "the configuration has loaded".
+///
+/// Clones refer to the same event.
+#[derive(Debug, Clone)]
+pub struct Event {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// The event's completion, which is complete while the event is set.
+    id: u64,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        completion::remove(self.id);
+    }
+}
+
+impl Event {
+    /// Create an event which isn't set.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                id: completion::register(),
+            }),
+        }
+    }
+
+    /// Set the event, releasing everyone waiting on it. Setting an event
+    /// which is already set does nothing.
+    pub fn set(&self) {
+        completion::complete(self.inner.id);
+    }
+
+    /// Unset the event, so later waits wait again.
+    ///
+    /// Waiters which haven't noticed the event was set by now may keep
+    /// waiting.
+    pub fn reset(&self) {
+        completion::reset(self.inner.id);
+    }
+
+    pub fn is_set(&self) -> bool {
+        completion::is_complete(self.inner.id)
+    }
+
+    /// Wait for the event to be set.
+    pub fn wait(&self) -> EventWait<'_> {
+        EventWait {
+            event: self,
+            output: None,
+        }
+    }
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future for [`Event::wait`].
+pub struct EventWait<'a> {
+    event: &'a Event,
+    output: Option<()>,
+}
+
+impl<'a> Future for EventWait<'a> {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.event.is_set() {
+                true => self.output = Some(()),
+                false => pending = Some(Waitable::Completion(self.event.inner.id)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -1,10 +1,28 @@
-use std::io::{self, Read};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::os::fd::{AsRawFd, RawFd};
+use rustix::io::FdFlags;
+use rustix::net::{sockopt, AddressFamily, SocketType};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::option;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::error::{self, Operation, WithContext};
 use crate::future::{Future, Interest, Waitable};
+use crate::io::{
+    read_buf, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadBufFuture,
+    ReadFuture, ReadStep, Step, WriteFuture, WriteStep,
+};
+use crate::net::{self, ResolveFuture};
+use crate::stream::Stream;
+use crate::time::{self, FdTimeout};
 
-pub struct AsyncTcpStream(pub TcpStream);
+pub struct AsyncTcpStream(TcpStream);
 impl AsRawFd for AsyncTcpStream {
     fn as_raw_fd(&self) -> RawFd {
         self.0.as_raw_fd()
@@ -18,28 +36,393 @@ impl AsyncTcpStream {
         Ok(Self(client))
     }
 
-    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> ReadFuture<'_, 'a> {
-        ReadFuture {
-            client: self,
+    /// Create a pair of connected streams over loopback.
+    ///
+    /// The listener behind it binds an ephemeral port, so any number of
+    /// pairs can exist at once. Handy for tests.
+    pub fn pair() -> io::Result<(AsyncTcpStream, AsyncTcpStream)> {
+        let listener = TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))?;
+        let a = TcpStream::connect(listener.local_addr()?)?;
+        let (b, _) = listener.accept()?;
+        a.set_nonblocking(true)?;
+        b.set_nonblocking(true)?;
+        Ok((Self(a), Self(b)))
+    }
+
+    /// Connect to `addr` without blocking, resolving once the connection is
+    /// established.
+    ///
+    /// Unlike [`AsyncTcpStream::connect`] this takes a resolved address, as
+    /// resolving a name can block.
+    pub fn connect_async(addr: SocketAddr) -> ConnectFuture {
+        ConnectFuture {
+            state: ConnectState::Start(addr),
+            fallbacks: Vec::new().into_iter(),
+            output: None,
+        }
+    }
+
+    /// Resolve `host` with [`net::resolve`] and connect to it without
+    /// blocking, trying each address in turn until one of them accepts.
+    ///
+    /// If none of them do, the future fails with the error of the last one.
+    pub fn connect_host(host: &str, port: u16) -> ConnectFuture {
+        ConnectFuture {
+            state: ConnectState::Resolving(net::resolve(host, port)),
+            fallbacks: Vec::new().into_iter(),
+            output: None,
+        }
+    }
+
+    /// Adopt a connected std stream, switching it to nonblocking mode.
+    ///
+    /// Fails with `InvalidInput` if the fd isn't a stream socket, which can
+    /// happen when the `TcpStream` was built from a raw fd.
+    pub fn from_std(stream: TcpStream) -> io::Result<Self> {
+        check_stream_socket(&stream)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self(stream))
+    }
+
+    /// Turn the stream back into a blocking std stream.
+    ///
+    /// This is a future rather than a plain conversion so the stream can be
+    /// deregistered from the poller first. Otherwise the poller would keep
+    /// reporting events for an fd it no longer owns.
+    pub fn into_std(self) -> IntoStdFuture<TcpStream> {
+        IntoStdFuture::new(self.0)
+    }
+
+    /// Borrow the underlying std stream.
+    ///
+    /// The stream is in nonblocking mode, and has to stay that way for the
+    /// futures on `AsyncTcpStream` to work.
+    pub fn as_std(&self) -> &TcpStream {
+        &self.0
+    }
+
+    /// Take the pending socket error from `SO_ERROR`, clearing it.
+    ///
+    /// Some failures only show up this way, like a refused connection
+    /// attempt or an ICMP error which arrived between two operations.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        self.0.take_error()
+    }
+
+    /// Set `TCP_NODELAY`, disabling Nagle's algorithm so small writes are sent
+    /// right away instead of being coalesced.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.0.set_nodelay(nodelay)
+    }
+
+    /// Whether `TCP_NODELAY` is set.
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.0.nodelay()
+    }
+
+    /// Set `IP_TTL`, the time-to-live of outgoing packets.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.0.set_ttl(ttl)
+    }
+
+    /// The `IP_TTL` of outgoing packets.
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.0.ttl()
+    }
+
+    /// Set `SO_LINGER`, controlling what happens to unsent data on close.
+    ///
+    /// With `None`, the default, closing returns immediately and the kernel
+    /// keeps sending queued data in the background. With a timeout, closing
+    /// blocks until the data is sent or the timeout expires. A zero timeout
+    /// discards the data and resets the connection; see
+    /// [`AsyncTcpStream::abort`].
+    pub fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
+        Ok(sockopt::set_socket_linger(&self.0, linger)?)
+    }
+
+    /// The `SO_LINGER` timeout, or `None` if closing doesn't wait for unsent
+    /// data. See [`AsyncTcpStream::set_linger`].
+    pub fn linger(&self) -> io::Result<Option<Duration>> {
+        Ok(sockopt::get_socket_linger(&self.0)?)
+    }
+
+    /// Close the stream right away, discarding unsent data and sending the
+    /// peer an RST instead of a FIN.
+    ///
+    /// The peer sees `ECONNRESET` on its next read or write. Closing the fd
+    /// also drops any registrations the poller still has for it, so unlike
+    /// [`AsyncTcpStream::disconnect`] this doesn't have to go through the
+    /// poller.
+    pub fn abort(self) -> io::Result<()> {
+        self.set_linger(Some(Duration::ZERO))?;
+        close(self.0)
+    }
+
+    /// Enable TCP keepalive probes with the given settings, or disable them
+    /// with `None`.
+    pub fn set_keepalive(&self, keepalive: Option<KeepaliveConfig>) -> io::Result<()> {
+        let Some(config) = keepalive else {
+            return Ok(sockopt::set_socket_keepalive(&self.0, false)?);
+        };
+        sockopt::set_tcp_keepidle(&self.0, config.idle)?;
+        sockopt::set_tcp_keepintvl(&self.0, config.interval)?;
+        sockopt::set_tcp_keepcnt(&self.0, config.count)?;
+        Ok(sockopt::set_socket_keepalive(&self.0, true)?)
+    }
+
+    /// The current keepalive settings, or `None` if keepalive is disabled.
+    pub fn keepalive(&self) -> io::Result<Option<KeepaliveConfig>> {
+        if !sockopt::get_socket_keepalive(&self.0)? {
+            return Ok(None);
+        }
+        Ok(Some(KeepaliveConfig {
+            idle: sockopt::get_tcp_keepidle(&self.0)?,
+            interval: sockopt::get_tcp_keepintvl(&self.0)?,
+            count: sockopt::get_tcp_keepcnt(&self.0)?,
+        }))
+    }
+
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+
+    /// Read data into `buf`, failing with `TimedOut` if nothing arrives
+    /// within `dur`.
+    ///
+    /// The deadline goes along with the stream's read registration, so data
+    /// arriving right as it passes is either read or left for the next read,
+    /// and never lost. When the timeout wins, the registration is dropped.
+    pub fn read_timeout<'a>(
+        &mut self,
+        buf: &'a mut [u8],
+        dur: Duration,
+    ) -> WithContext<ReadTimeout<'_, 'a>> {
+        let fd = self.as_raw_fd();
+        let read = ReadTimeout(time::fd_timeout(dur, AsyncReadExt::read(self, buf)));
+        WithContext::new(read, Operation::Read, fd)
+    }
+
+    /// Write data from `buf`, failing with `TimedOut` if the stream doesn't
+    /// accept any within `dur`.
+    ///
+    /// The deadline goes along with the stream's write registration, like
+    /// for [`AsyncTcpStream::read_timeout`]. When the timeout wins, the
+    /// registration is dropped.
+    pub fn write_timeout<'a>(
+        &mut self,
+        buf: &'a [u8],
+        dur: Duration,
+    ) -> WithContext<WriteTimeout<'_, 'a>> {
+        let fd = self.as_raw_fd();
+        let write = WriteTimeout(time::fd_timeout(dur, AsyncWriteExt::write(self, buf)));
+        WithContext::new(write, Operation::Write, fd)
+    }
+
+    /// Read data into the unfilled part of `buf`, which doesn't need to be
+    /// initialized.
+    pub fn read_buf<'a, 'b>(
+        &mut self,
+        buf: &'a mut ReadBuf<'b>,
+    ) -> WithContext<ReadBufFuture<'_, 'a, 'b, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read_buf(self, buf), Operation::Read, fd)
+    }
+
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+
+    /// Read data into `data` without removing it from the socket's receive
+    /// queue, so a subsequent [`AsyncTcpStream::read`] returns the same bytes.
+    pub fn peek<'a>(&mut self, data: &'a mut [u8]) -> WithContext<PeekFuture<'_, 'a>> {
+        let peek = PeekFuture {
+            stream: &self.0,
             buffer: data,
             output: None,
+        };
+        WithContext::new(peek, Operation::Read, self.0.as_raw_fd())
+    }
+
+    /// Read the urgent data the peer sent with `MSG_OOB` into `data`,
+    /// waiting for some to arrive.
+    ///
+    /// TCP keeps a single byte of urgent data at a time, outside the
+    /// ordinary stream, so reads don't see it and newer urgent data replaces
+    /// it. That the byte arrived is a priority event rather than a read
+    /// event. With `SO_OOBINLINE` set, urgent data stays in the ordinary
+    /// stream instead, and this never resolves.
+    pub fn read_oob<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadOobFuture<'_, 'a>> {
+        let read = ReadOobFuture {
+            stream: &self.0,
+            buffer: data,
+            output: None,
+        };
+        WithContext::new(read, Operation::Read, self.0.as_raw_fd())
+    }
+
+    /// Wait until the stream is readable.
+    ///
+    /// Readiness may be spurious: a subsequent [`AsyncTcpStream::try_read`]
+    /// can still fail with `WouldBlock`, in which case wait again.
+    pub fn readable(&mut self) -> Readable<'_> {
+        Readable {
+            stream: &self.0,
+            output: None,
         }
     }
 
+    /// Wait until the stream is writable.
+    ///
+    /// Readiness may be spurious: a subsequent [`AsyncTcpStream::try_write`]
+    /// can still fail with `WouldBlock`, in which case wait again.
+    pub fn writable(&mut self) -> Writable<'_> {
+        Writable {
+            stream: &self.0,
+            output: None,
+        }
+    }
+
+    /// Try to read data from the stream without waiting, returning a
+    /// `WouldBlock` error if no data is available.
+    pub fn try_read(&mut self, data: &mut [u8]) -> io::Result<usize> {
+        self.0.read(data)
+    }
+
+    /// Try to write data to the stream without waiting, returning a
+    /// `WouldBlock` error if the send buffer is full.
+    pub fn try_write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    /// Read data into `buf`, handing the buffer back along with the result.
+    ///
+    /// Unlike [`AsyncTcpStream::read`] the future borrows nothing, so it is
+    /// `'static` and can be moved into a spawned task. Like the slice-based
+    /// read, this reads into `buf[..buf.len()]`, not its spare capacity. Wrap
+    /// an owned stream in an `Arc` to call this.
+    ///
+    /// Besides a `Vec<u8>`, `buf` can be a [`PoolBuf`] from the runtime's
+    /// [`BufferPool`], so connections which read one message after another
+    /// recycle buffers rather than allocate them.
+    ///
+    /// [`PoolBuf`]: crate::runtime::PoolBuf
+    /// [`BufferPool`]: crate::runtime::BufferPool
+    pub fn read_owned<B: AsMut<[u8]>>(self: Arc<Self>, buf: B) -> ReadOwnedFuture<B> {
+        ReadOwnedFuture {
+            stream: self,
+            buffer: Some(buf),
+            output: None,
+        }
+    }
+
+    /// Write data from `buf`, handing the buffer back along with the result.
+    ///
+    /// This is the owned counterpart of [`AsyncTcpStream::write`]; see
+    /// [`AsyncTcpStream::read_owned`].
+    pub fn write_owned<B: AsRef<[u8]>>(self: Arc<Self>, buf: B) -> WriteOwnedFuture<B> {
+        WriteOwnedFuture {
+            stream: self,
+            buffer: Some(buf),
+            output: None,
+        }
+    }
+
+    /// Split the stream into a read half and a write half which can be used
+    /// concurrently, for example from two futures under a single `join`.
+    ///
+    /// The halves register separate read and write interest for the same fd,
+    /// so they don't interfere with each other's registrations. Use
+    /// [`OwnedReadHalf::reunite`] to get the stream back.
+    pub fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        let stream = Arc::new(self.0);
+        (OwnedReadHalf(stream.clone()), OwnedWriteHalf(stream))
+    }
+
+    /// Borrow the stream as a read half and a write half.
+    ///
+    /// This is cheaper than [`AsyncTcpStream::into_split`] when both halves
+    /// live inside the same `join`: `Read` and `Write` are implemented for
+    /// `&TcpStream`, so both halves can share a plain reference.
+    pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
+        (ReadHalf(&self.0), WriteHalf(&self.0))
+    }
+
+    /// Shut down the read side, the write side, or both sides of the stream.
+    ///
+    /// Unlike [`AsyncTcpStream::disconnect`] this doesn't tear down the
+    /// stream: after shutting down the write side the peer sees EOF, but
+    /// reads keep working until the peer closes its end too.
+    pub fn shutdown(&mut self, how: Shutdown) -> ShutdownFuture<'_> {
+        ShutdownFuture::new(self.0.as_fd(), how)
+    }
+
+    /// How many of the bytes written to the stream the peer has yet to
+    /// acknowledge, from `SIOCOUTQ`, or `SO_NWRITE` on macOS. That includes
+    /// the ones the kernel hasn't sent yet.
+    pub fn bytes_unsent(&self) -> io::Result<usize> {
+        bytes_unsent(&self.0)
+    }
+
+    /// Wait for the peer to acknowledge everything written to the stream,
+    /// then shut down the write side, for a graceful shutdown which knows
+    /// the data arrived. The stream itself doesn't buffer, so there's nothing
+    /// to flush before; flush writers wrapping it first.
+    ///
+    /// Nothing tells the poller once the peer acknowledged the data, so this
+    /// checks [`AsyncTcpStream::bytes_unsent`] on a timer, backing off from
+    /// 1ms to 50ms. If the peer doesn't acknowledge everything within
+    /// `timeout`, say because it stopped reading, the future fails with
+    /// `TimedOut` and leaves the stream as it is.
+    pub fn flush_and_shutdown(&mut self, timeout: Duration) -> FlushAndShutdownFuture<'_> {
+        FlushAndShutdownFuture {
+            stream: self,
+            deadline: Instant::now() + timeout,
+            interval: Duration::from_millis(1),
+            output: None,
+        }
+    }
+
+    /// Send `len` bytes of `file` starting at `offset`, resolving with the
+    /// number of bytes sent.
+    ///
+    /// The data is handed to the socket by the kernel through `sendfile(2)`,
+    /// without copying it through userspace. Where that isn't supported for
+    /// this file, it falls back to reading and writing in chunks. The file's
+    /// own offset is left untouched. If the file is shorter than
+    /// `offset + len` this sends what there is.
+    pub fn send_file<'a>(
+        &'a mut self,
+        file: &'a File,
+        offset: u64,
+        len: u64,
+    ) -> WithContext<SendFileFuture<'a>> {
+        let send = SendFileFuture {
+            stream: &self.0,
+            file,
+            offset,
+            remaining: len,
+            sent: 0,
+            fallback: None,
+            output: None,
+        };
+        WithContext::new(send, Operation::Write, self.0.as_raw_fd())
+    }
+
+    /// Deregister the stream from the poller and close it.
+    ///
+    /// To only signal EOF to the peer while continuing to read from the stream,
+    /// use [`AsyncTcpStream::shutdown`] instead.
     pub fn disconnect(self) -> CloseFuture {
         CloseFuture {
-            client: self,
-            state: CloseFutureState::Pending,
+            state: CloseFutureState::Pending(self),
+            output: None,
         }
     }
 }
 
-pub struct ReadFuture<'a, 'b> {
-    client: &'a mut AsyncTcpStream,
-    buffer: &'b mut [u8],
-    output: Option<io::Result<usize>>,
-}
-
 enum Once<T> {
     Empty,
     Once(Option<T>),
@@ -56,18 +439,28 @@ impl<T> Iterator for Once<T> {
     }
 }
 
-impl<'a, 'b> Future for ReadFuture<'a, 'b> {
+pub struct PeekFuture<'a, 'b> {
+    stream: &'a TcpStream,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for PeekFuture<'a, 'b> {
     type Output = io::Result<usize>;
 
     fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
-        // NOTE: this would be significantly nicer to write as `gen fn poll`
-        match self.client.0.read(&mut self.buffer) {
+        if self.output.is_some() {
+            return Once::Empty;
+        }
+        // `MSG_PEEK` leaves the data queued, so the fd stays readable after
+        // this completes.
+        match self.stream.peek(self.buffer) {
             Ok(n) => {
                 self.output = Some(Ok(n));
                 Once::Empty
             }
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                Once::Once(Some(Waitable::Fd(self.client.as_raw_fd(), Interest::Read)))
+                Once::Once(Some(Waitable::Fd(self.stream.as_raw_fd(), Interest::Read)))
             }
             Err(e) => {
                 self.output = Some(Err(e));
@@ -81,37 +474,1339 @@ impl<'a, 'b> Future for ReadFuture<'a, 'b> {
     }
 }
 
+/// Future for [`AsyncTcpStream::read_oob`].
+pub struct ReadOobFuture<'a, 'b> {
+    stream: &'a TcpStream,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for ReadOobFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_some() {
+            return Once::Empty;
+        }
+        let flags = rustix::net::RecvFlags::OOB;
+        match rustix::net::recv(self.stream, self.buffer, flags) {
+            Ok(n) => {
+                self.output = Some(Ok(n));
+                Once::Empty
+            }
+            // Without urgent data queued that's `EINVAL`, and `EAGAIN` while
+            // the byte it points to is still on its way.
+            Err(rustix::io::Errno::INVAL | rustix::io::Errno::AGAIN | rustix::io::Errno::INTR) => {
+                Once::Once(Some(Waitable::Fd(
+                    self.stream.as_raw_fd(),
+                    Interest::Priority,
+                )))
+            }
+            Err(e) => {
+                self.output = Some(Err(e.into()));
+                Once::Empty
+            }
+        }
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncTcpStream::into_std`] and [`AsyncTcpListener::into_std`].
+pub struct IntoStdFuture<T> {
+    io: Option<T>,
+    deregistered: bool,
+}
+
+impl<T> IntoStdFuture<T> {
+    fn new(io: T) -> Self {
+        Self {
+            io: Some(io),
+            deregistered: false,
+        }
+    }
+}
+
+impl<T: AsFd + AsRawFd> Future for IntoStdFuture<T> {
+    type Output = io::Result<T>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        match (&self.io, self.deregistered) {
+            (Some(io), false) => {
+                self.deregistered = true;
+                Once::Once(Some(Waitable::Fd(io.as_raw_fd(), Interest::Close)))
+            }
+            _ => Once::Empty,
+        }
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        if !self.deregistered {
+            return None;
+        }
+        let io = self.io.take()?;
+        Some(
+            rustix::io::ioctl_fionbio(&io, false)
+                .map(|()| io)
+                .map_err(Into::into),
+        )
+    }
+}
+
+// Sockets adopted from elsewhere may be anything; we only support TCP.
+fn check_stream_socket(socket: impl AsFd) -> io::Result<()> {
+    match sockopt::get_socket_type(socket)? {
+        SocketType::STREAM => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected a stream socket",
+        )),
+    }
+}
+
+/// TCP keepalive settings, for [`AsyncTcpStream::set_keepalive`].
+///
+/// The kernel works in whole seconds, so durations are rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// How long the connection has to be idle before the first probe.
+    pub idle: Duration,
+    /// How long to wait between unanswered probes.
+    pub interval: Duration,
+    /// How many unanswered probes it takes to drop the connection.
+    pub count: u32,
+}
+
+/// Write `buf` to `stream`.
+///
+/// Errors which only say that the connection is gone are replaced by the
+/// pending socket error if there is one, since that says why: a
+/// `ConnectionRefused` is more useful than the `BrokenPipe` it leads to.
+fn write(mut stream: &TcpStream, buf: &[u8]) -> WriteStep<iter::Once<Waitable>> {
+    let result = stream.write(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected => {
+            stream.take_error().ok().flatten().unwrap_or(e)
+        }
+        _ => e,
+    });
+    Step::from_syscall(result, stream.as_raw_fd(), Interest::Write)
+}
+
+impl AsyncRead for AsyncTcpStream {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, buf)
+    }
+}
+
+impl AsyncWrite for AsyncTcpStream {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        write(&self.0, buf)
+    }
+}
+
+/// Future for [`AsyncTcpStream::read_timeout`].
+pub struct ReadTimeout<'a, 'b>(FdTimeout<ReadFuture<'a, 'b, AsyncTcpStream>>);
+
+impl Future for ReadTimeout<'_, '_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.0.poll(ready)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0
+            .take()
+            .map(|output| output.unwrap_or_else(|e| Err(e.into())))
+    }
+}
+
+/// Future for [`AsyncTcpStream::write_timeout`].
+pub struct WriteTimeout<'a, 'b>(FdTimeout<WriteFuture<'a, 'b, AsyncTcpStream>>);
+
+impl Future for WriteTimeout<'_, '_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.0.poll(ready)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0
+            .take()
+            .map(|output| output.unwrap_or_else(|e| Err(e.into())))
+    }
+}
+
+/// Future for [`AsyncTcpStream::read_owned`].
+pub struct ReadOwnedFuture<B = Vec<u8>> {
+    stream: Arc<AsyncTcpStream>,
+    /// Only `None` once the output took it.
+    buffer: Option<B>,
+    output: Option<(B, Result<usize, error::Error>)>,
+}
+
+impl<B: AsMut<[u8]>> Future for ReadOwnedFuture<B> {
+    type Output = (B, Result<usize, error::Error>);
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let Some(buffer) = &mut self.buffer {
+            let mut stream: &TcpStream = &self.stream.0;
+            let result = stream.read(buffer.as_mut());
+            match Step::from_syscall(result, stream.as_raw_fd(), Interest::Read) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = self.buffer.take().map(|buffer| (buffer, Ok(n))),
+                Step::Error(e) => {
+                    let e = error::Error::new(Operation::Read, e).with_fd(self.stream.as_raw_fd());
+                    self.output = self.buffer.take().map(|buffer| (buffer, Err(e)));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncTcpStream::write_owned`].
+pub struct WriteOwnedFuture<B = Vec<u8>> {
+    stream: Arc<AsyncTcpStream>,
+    /// Only `None` once the output took it.
+    buffer: Option<B>,
+    output: Option<(B, Result<usize, error::Error>)>,
+}
+
+impl<B: AsRef<[u8]>> Future for WriteOwnedFuture<B> {
+    type Output = (B, Result<usize, error::Error>);
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let Some(buffer) = &self.buffer {
+            match write(&self.stream.0, buffer.as_ref()) {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = self.buffer.take().map(|buffer| (buffer, Ok(n))),
+                Step::Error(e) => {
+                    let e = error::Error::new(Operation::Write, e).with_fd(self.stream.as_raw_fd());
+                    self.output = self.buffer.take().map(|buffer| (buffer, Err(e)));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncTcpStream::send_file`].
+pub struct SendFileFuture<'a> {
+    stream: &'a TcpStream,
+    file: &'a File,
+    /// Where in the file to read next.
+    offset: u64,
+    /// How many bytes are left to read from the file.
+    remaining: u64,
+    sent: u64,
+    /// Set once `sendfile` turned out to be unsupported.
+    fallback: Option<FallbackBuffer>,
+    output: Option<io::Result<u64>>,
+}
+
+/// Data read from the file but not yet written to the socket.
+struct FallbackBuffer {
+    buffer: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+}
+
+impl SendFileFuture<'_> {
+    fn poll_send(&mut self) -> Step<iter::Once<Waitable>, u64> {
+        loop {
+            if let Some(fallback) = &mut self.fallback {
+                if fallback.pos < fallback.cap {
+                    match write(self.stream, &fallback.buffer[fallback.pos..fallback.cap]) {
+                        Step::Pending(waitables) => return Step::Pending(waitables),
+                        Step::Done(n) => {
+                            fallback.pos += n;
+                            self.sent += n as u64;
+                        }
+                        Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Step::Error(e) => return Step::Error(e),
+                    }
+                    continue;
+                }
+                if self.remaining == 0 {
+                    return Step::Done(self.sent);
+                }
+                let len = fallback.buffer.len().min(self.remaining as usize);
+                match self.file.read_at(&mut fallback.buffer[..len], self.offset) {
+                    Ok(0) => return Step::Done(self.sent),
+                    Ok(n) => {
+                        self.offset += n as u64;
+                        self.remaining -= n as u64;
+                        fallback.pos = 0;
+                        fallback.cap = n;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Step::Error(e),
+                }
+                continue;
+            }
+
+            if self.remaining == 0 {
+                return Step::Done(self.sent);
+            }
+            let result = sendfile(self.file, self.stream, self.offset, self.remaining);
+            match Step::from_syscall(result, self.stream.as_raw_fd(), Interest::Write) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(0) => return Step::Done(self.sent),
+                Step::Done(n) => {
+                    self.offset += n;
+                    self.remaining -= n;
+                    self.sent += n;
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) if is_unsupported(&e) => {
+                    self.fallback = Some(FallbackBuffer {
+                        buffer: vec![0; 64 * 1024].into_boxed_slice(),
+                        pos: 0,
+                        cap: 0,
+                    });
+                }
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+    }
+}
+
+impl Future for SendFileFuture<'_> {
+    type Output = io::Result<u64>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.poll_send() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+// `sendfile` reports files it can't send with `ENOTSUP`, or `EINVAL` on
+// Linux, in which case we copy by hand instead.
+fn is_unsupported(e: &io::Error) -> bool {
+    let unsupported = [libc::ENOTSUP, libc::EOPNOTSUPP];
+    let invalid = cfg!(target_os = "linux") && e.raw_os_error() == Some(libc::EINVAL);
+    invalid
+        || e.raw_os_error()
+            .is_some_and(|code| unsupported.contains(&code))
+}
+
+#[cfg(target_os = "macos")]
+fn bytes_unsent(socket: &TcpStream) -> io::Result<usize> {
+    let mut unsent: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `unsent` and `len` describe a buffer of the right size.
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_NWRITE,
+            &mut unsent as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(unsent as usize),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bytes_unsent(socket: &TcpStream) -> io::Result<usize> {
+    let mut unsent: libc::c_int = 0;
+    // SAFETY: `SIOCOUTQ`, which is `TIOCOUTQ` for sockets, writes an int.
+    match unsafe { libc::ioctl(socket.as_raw_fd(), libc::TIOCOUTQ, &mut unsent) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(unsent as usize),
+    }
+}
+
+/// Send up to `len` bytes of `file` starting at `offset` to `socket`,
+/// returning how many were sent.
+#[cfg(target_os = "macos")]
+fn sendfile(file: &File, socket: &TcpStream, offset: u64, len: u64) -> io::Result<u64> {
+    // On input `sent` is how much to send, with zero meaning "until EOF". On
+    // output it is how much was sent, which may be nonzero even if the call
+    // failed with `EAGAIN`.
+    let mut sent = len.min(i64::MAX as u64) as libc::off_t;
+    let ret = unsafe {
+        libc::sendfile(
+            file.as_raw_fd(),
+            socket.as_raw_fd(),
+            offset as libc::off_t,
+            &mut sent,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    match ret {
+        -1 => {
+            let e = io::Error::last_os_error();
+            match (e.kind(), sent) {
+                (io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted, 1..) => Ok(sent as u64),
+                _ => Err(e),
+            }
+        }
+        _ => Ok(sent as u64),
+    }
+}
+
+/// Send up to `len` bytes of `file` starting at `offset` to `socket`,
+/// returning how many were sent.
+#[cfg(target_os = "linux")]
+fn sendfile(file: &File, socket: &TcpStream, offset: u64, len: u64) -> io::Result<u64> {
+    let mut offset = offset as libc::off_t;
+    let len = len.min(0x7fff_f000) as usize;
+    let ret = unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut offset, len) };
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n as u64),
+    }
+}
+
+pub struct Readable<'a> {
+    stream: &'a TcpStream,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a> Future for Readable<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_some() {
+            return Once::Empty;
+        }
+        let waitable = Waitable::Fd(self.stream.as_raw_fd(), Interest::Read);
+        if ready.contains(&waitable) {
+            self.output = Some(Ok(()));
+            // The registration would outlive the future otherwise, waking
+            // the poller for as long as the stream stays ready.
+            return Once::Once(waitable.cancel());
+        }
+        Once::Once(Some(waitable))
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+pub struct Writable<'a> {
+    stream: &'a TcpStream,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a> Future for Writable<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_some() {
+            return Once::Empty;
+        }
+        let waitable = Waitable::Fd(self.stream.as_raw_fd(), Interest::Write);
+        if ready.contains(&waitable) {
+            self.output = Some(Ok(()));
+            // The registration would outlive the future otherwise, waking
+            // the poller for as long as the stream stays ready.
+            return Once::Once(waitable.cancel());
+        }
+        Once::Once(Some(waitable))
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+pub struct ShutdownFuture<'a> {
+    stream: BorrowedFd<'a>,
+    how: Shutdown,
+    output: Option<io::Result<()>>,
+}
+
+impl<'a> ShutdownFuture<'a> {
+    pub(crate) fn new(stream: BorrowedFd<'a>, how: Shutdown) -> Self {
+        Self {
+            stream,
+            how,
+            output: None,
+        }
+    }
+}
+
+impl<'a> Future for ShutdownFuture<'a> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.output.is_some() {
+            return Once::Empty;
+        }
+        // `shutdown(2)` never blocks, but a shut down side stays permanently
+        // ready. Drop our interest in it so we don't keep getting woken up.
+        let (how, interest) = match self.how {
+            Shutdown::Read => (rustix::net::Shutdown::Read, Interest::CloseRead),
+            Shutdown::Write => (rustix::net::Shutdown::Write, Interest::CloseWrite),
+            Shutdown::Both => (rustix::net::Shutdown::ReadWrite, Interest::Close),
+        };
+        self.output = Some(rustix::net::shutdown(self.stream, how).map_err(Into::into));
+        Once::Once(Some(Waitable::Fd(self.stream.as_raw_fd(), interest)))
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncTcpStream::flush_and_shutdown`].
+pub struct FlushAndShutdownFuture<'a> {
+    stream: &'a mut AsyncTcpStream,
+    deadline: Instant,
+    /// How long to wait before checking on the peer again.
+    interval: Duration,
+    output: Option<io::Result<()>>,
+}
+
+impl Future for FlushAndShutdownFuture<'_> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_some() {
+            return pending.into_iter();
+        }
+        match self.stream.bytes_unsent() {
+            Ok(0) => {
+                let shutdown = self.stream.0.shutdown(Shutdown::Write);
+                self.output = Some(shutdown);
+                // A shut down side stays permanently writable.
+                let fd = self.stream.as_raw_fd();
+                pending = Some(Waitable::Fd(fd, Interest::CloseWrite));
+            }
+            Ok(_) if Instant::now() >= self.deadline => {
+                let e = io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the peer didn't acknowledge the data in time",
+                );
+                self.output = Some(Err(e));
+            }
+            Ok(_) => {
+                let next = (Instant::now() + self.interval).min(self.deadline);
+                self.interval = (self.interval * 2).min(Duration::from_millis(50));
+                pending = Some(Waitable::Timer(next));
+            }
+            Err(e) => self.output = Some(Err(e)),
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
 enum CloseFutureState {
-    Pending,
+    /// Waiting to ask the poller to deregister the stream.
+    Pending(AsyncTcpStream),
+    /// The poller has deregistered the stream, so it can be closed.
+    Deregistered(AsyncTcpStream),
     Closed,
-    Completed,
 }
 
+/// Future for [`AsyncTcpStream::disconnect`].
+///
+/// This is a graceful close: the stream is deregistered from the poller and
+/// then closed, all before the future resolves. The kernel keeps sending
+/// whatever data is still queued and then sends a FIN, unless
+/// [`AsyncTcpStream::set_linger`] says otherwise. For an abortive close, use
+/// [`AsyncTcpStream::abort`].
 pub struct CloseFuture {
-    client: AsyncTcpStream,
     state: CloseFutureState,
+    output: Option<io::Result<()>>,
 }
 
 impl Future for CloseFuture {
-    type Output = ();
+    type Output = io::Result<()>;
 
     fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
-        match self.state {
-            CloseFutureState::Pending => {
-                self.state = CloseFutureState::Closed;
-                Once::Once(Some(Waitable::Fd(self.client.as_raw_fd(), Interest::Close)))
+        match std::mem::replace(&mut self.state, CloseFutureState::Closed) {
+            CloseFutureState::Pending(client) => {
+                let waitable = Waitable::Fd(client.as_raw_fd(), Interest::Close);
+                self.state = CloseFutureState::Deregistered(client);
+                Once::Once(Some(waitable))
             }
-            CloseFutureState::Closed | CloseFutureState::Completed => Once::Empty,
+            CloseFutureState::Deregistered(client) => {
+                self.output = Some(close(client.0));
+                Once::Empty
+            }
+            CloseFutureState::Closed => Once::Empty,
         }
     }
 
     fn take(&mut self) -> Option<Self::Output> {
-        match self.state {
-            CloseFutureState::Closed => {
-                self.state = CloseFutureState::Completed;
-                Some(())
+        self.output.take()
+    }
+}
+
+/// Close `stream`, reporting errors which dropping it would ignore.
+fn close(stream: TcpStream) -> io::Result<()> {
+    let fd = stream.into_raw_fd();
+    // SAFETY: we own the fd, and nothing refers to it after this.
+    match unsafe { libc::close(fd) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// The borrowed read half of an [`AsyncTcpStream`], created by [`AsyncTcpStream::split`].
+#[derive(Debug)]
+pub struct ReadHalf<'a>(&'a TcpStream);
+impl AsRawFd for ReadHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl ReadHalf<'_> {
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+}
+
+impl<'a> AsyncRead for ReadHalf<'a> {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a>> {
+        read_buf(self.0, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a>> {
+        read_buf(self.0, buf)
+    }
+}
+
+/// The borrowed write half of an [`AsyncTcpStream`], created by [`AsyncTcpStream::split`].
+#[derive(Debug)]
+pub struct WriteHalf<'a>(&'a TcpStream);
+impl AsRawFd for WriteHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl WriteHalf<'_> {
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+
+    /// Shut down the write side of the stream, signalling EOF to the peer.
+    /// The read half keeps working.
+    pub fn shutdown(&mut self) -> ShutdownFuture<'_> {
+        ShutdownFuture::new(self.0.as_fd(), Shutdown::Write)
+    }
+}
+
+impl<'a> AsyncWrite for WriteHalf<'a> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<'a>> {
+        write(self.0, buf)
+    }
+}
+
+/// The read half of an [`AsyncTcpStream`], created by [`AsyncTcpStream::into_split`].
+#[derive(Debug)]
+pub struct OwnedReadHalf(Arc<TcpStream>);
+impl AsRawFd for OwnedReadHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl OwnedReadHalf {
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+
+    /// Join the two halves back into an `AsyncTcpStream`.
+    ///
+    /// Fails if the halves were split off from different streams.
+    pub fn reunite(self, other: OwnedWriteHalf) -> Result<AsyncTcpStream, ReuniteError> {
+        if !Arc::ptr_eq(&self.0, &other.0) {
+            return Err(ReuniteError(self, other));
+        }
+        drop(other);
+        let stream = Arc::try_unwrap(self.0).expect("only the two halves hold the stream");
+        Ok(AsyncTcpStream(stream))
+    }
+}
+
+impl AsyncRead for OwnedReadHalf {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, buf)
+    }
+}
+
+/// The write half of an [`AsyncTcpStream`], created by [`AsyncTcpStream::into_split`].
+#[derive(Debug)]
+pub struct OwnedWriteHalf(Arc<TcpStream>);
+impl AsRawFd for OwnedWriteHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl OwnedWriteHalf {
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+
+    /// Shut down the write side of the stream, signalling EOF to the peer.
+    /// The read half keeps working.
+    pub fn shutdown(&mut self) -> ShutdownFuture<'_> {
+        ShutdownFuture::new(self.0.as_fd(), Shutdown::Write)
+    }
+
+    pub fn reunite(self, other: OwnedReadHalf) -> Result<AsyncTcpStream, ReuniteError> {
+        other.reunite(self)
+    }
+}
+
+impl AsyncWrite for OwnedWriteHalf {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        write(&self.0, buf)
+    }
+}
+
+/// Error returned by [`OwnedReadHalf::reunite`] when the halves belong to
+/// different streams. Both halves are handed back.
+#[derive(Debug)]
+pub struct ReuniteError(pub OwnedReadHalf, pub OwnedWriteHalf);
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tried to reunite halves that are not from the same socket"
+        )
+    }
+}
+
+impl Error for ReuniteError {}
+
+pub struct AsyncTcpListener {
+    listener: TcpListener,
+    /// How long to wait before accepting again after running out of fds or
+    /// memory.
+    backoff: Duration,
+    /// Until when accepts wait out the backoff, and whether the listener
+    /// was deregistered for it yet.
+    retry_at: Option<(Instant, bool)>,
+    /// Whether to hold on to `spare`, reopening it after it was used up.
+    reserve_fd: bool,
+    /// An fd to close when out of fds, making room to accept and shed a
+    /// pending connection.
+    spare: Option<File>,
+}
+
+impl AsRawFd for AsyncTcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl AsyncTcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(listener))
+    }
+
+    /// Adopt a listening std listener, switching it to nonblocking mode.
+    ///
+    /// Fails with `InvalidInput` if the fd isn't a stream socket.
+    pub fn from_std(listener: TcpListener) -> io::Result<Self> {
+        check_stream_socket(&listener)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(listener))
+    }
+
+    fn new(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            backoff: DEFAULT_BACKOFF,
+            retry_at: None,
+            reserve_fd: false,
+            spare: None,
+        }
+    }
+
+    /// Turn the listener back into a blocking std listener, deregistering it
+    /// from the poller first. See [`AsyncTcpStream::into_std`].
+    pub fn into_std(self) -> IntoStdFuture<TcpListener> {
+        IntoStdFuture::new(self.listener)
+    }
+
+    /// Configure the socket options of a listener before it is bound.
+    pub fn builder(addr: SocketAddr) -> TcpListenerBuilder {
+        TcpListenerBuilder {
+            addr,
+            reuse_address: true,
+            reuse_port: false,
+            backlog: 128,
+            only_v6: None,
+            backoff: DEFAULT_BACKOFF,
+            reserve_fd: false,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn accept(&mut self) -> AcceptFuture<'_> {
+        AcceptFuture {
+            listener: self,
+            output: None,
+        }
+    }
+
+    /// A stream of incoming connections, which never ends.
+    ///
+    /// Errors from individual accepts are yielded as items, and the stream
+    /// keeps accepting afterwards, though there's little point in going on
+    /// after an [`AcceptError::Fatal`]. After an
+    /// [`AcceptError::ResourceExhausted`] the stream waits out the
+    /// listener's backoff before accepting again, rather than retrying in a
+    /// busy loop; see [`TcpListenerBuilder::exhausted_backoff`].
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+            item: None,
+        }
+    }
+
+    /// Accept a connection, waiting out the backoff first if the last
+    /// attempt ran out of resources.
+    pub(crate) fn poll_accept(&mut self) -> Step<AcceptWait, (AsyncTcpStream, SocketAddr)> {
+        if let Some((retry_at, deregistered)) = &mut self.retry_at {
+            if Instant::now() < *retry_at {
+                // Pending connections keep the listener readable, so stop
+                // waiting for it until then.
+                let fd = self.listener.as_raw_fd();
+                let close = (!*deregistered).then_some(Waitable::Fd(fd, Interest::CloseRead));
+                *deregistered = true;
+                return Step::Pending(iter::once(Waitable::Timer(*retry_at)).chain(close));
+            }
+            self.retry_at = None;
+        }
+        let fd = self.listener.as_raw_fd();
+        match Step::from_syscall(self.listener.accept(), fd, Interest::Read) {
+            // Accepted sockets don't reliably inherit `O_NONBLOCK` from the
+            // listener, so set it explicitly.
+            Step::Done((stream, addr)) => {
+                if self.reserve_fd && self.spare.is_none() {
+                    self.spare = File::open("/dev/null").ok();
+                }
+                match stream.set_nonblocking(true) {
+                    Ok(()) => Step::Done((AsyncTcpStream(stream), addr)),
+                    Err(e) => Step::Error(e),
+                }
+            }
+            Step::Pending(waitables) => Step::Pending(waitables.chain(None)),
+            Step::Error(e) if AcceptError::is_exhausted(&e) => {
+                if matches!(e.raw_os_error(), Some(libc::EMFILE | libc::ENFILE)) {
+                    self.shed();
+                }
+                if !self.backoff.is_zero() {
+                    self.retry_at = Some((Instant::now() + self.backoff, false));
+                }
+                Step::Error(e)
+            }
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+
+    /// Use the spare fd to accept a pending connection and close it right
+    /// away, so the client hears about it rather than waiting in the
+    /// backlog for as long as we're out of fds.
+    fn shed(&mut self) {
+        if self.spare.take().is_some() {
+            drop(self.listener.accept());
+            self.spare = File::open("/dev/null").ok();
+        }
+    }
+}
+
+/// How long [`AsyncTcpListener`]s back off after running out of resources,
+/// unless configured otherwise.
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What [`AsyncTcpListener::poll_accept`] waits on: the listener, or the
+/// backoff timer along with deregistering the listener.
+pub(crate) type AcceptWait = iter::Chain<iter::Once<Waitable>, option::IntoIter<Waitable>>;
+
+/// Why accepting a connection failed, as yielded by
+/// [`AsyncTcpListener::incoming`].
+#[derive(Debug)]
+pub enum AcceptError {
+    /// Accepting the connection failed, but the listener is fine: the
+    /// connection was aborted before it was accepted, say. Accepting again
+    /// right away is fine.
+    Transient(io::Error),
+    /// The process or the system ran out of fds or memory. The listener
+    /// waits for its backoff before accepting again.
+    ResourceExhausted(io::Error),
+    /// The listener itself is broken, or was shut down.
+    Fatal(io::Error),
+}
+
+impl AcceptError {
+    /// Classify an error `accept` failed with.
+    pub fn new(error: io::Error) -> Self {
+        if Self::is_exhausted(&error) {
+            AcceptError::ResourceExhausted(error)
+        } else if Self::is_transient(&error) {
+            AcceptError::Transient(error)
+        } else {
+            AcceptError::Fatal(error)
+        }
+    }
+
+    pub(crate) fn is_exhausted(error: &io::Error) -> bool {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+        )
+    }
+
+    /// Whether the error is about the connection being accepted. Linux
+    /// passes errors pending on the new socket on to `accept`, which are
+    /// these besides the aborted connections.
+    fn is_transient(error: &io::Error) -> bool {
+        let kind = matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::Interrupted
+        );
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let errno = matches!(error.raw_os_error(), Some(libc::ENONET));
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let errno = false;
+        let errno = errno
+            || matches!(
+                error.raw_os_error(),
+                Some(
+                    libc::EPROTO
+                        | libc::EPERM
+                        | libc::ENETDOWN
+                        | libc::ENOPROTOOPT
+                        | libc::EHOSTDOWN
+                        | libc::EHOSTUNREACH
+                        | libc::EOPNOTSUPP
+                        | libc::ENETUNREACH
+                )
+            );
+        kind || errno
+    }
+
+    pub fn get_ref(&self) -> &io::Error {
+        match self {
+            AcceptError::Transient(e)
+            | AcceptError::ResourceExhausted(e)
+            | AcceptError::Fatal(e) => e,
+        }
+    }
+
+    pub fn into_inner(self) -> io::Error {
+        match self {
+            AcceptError::Transient(e)
+            | AcceptError::ResourceExhausted(e)
+            | AcceptError::Fatal(e) => e,
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        self.get_ref().kind()
+    }
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            AcceptError::Transient(_) => "accepting a connection failed",
+            AcceptError::ResourceExhausted(_) => "out of resources to accept connections",
+            AcceptError::Fatal(_) => "the listener failed",
+        };
+        write!(f, "{what}: {}", self.get_ref())
+    }
+}
+
+impl Error for AcceptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.get_ref())
+    }
+}
+
+impl From<io::Error> for AcceptError {
+    fn from(error: io::Error) -> Self {
+        AcceptError::new(error)
+    }
+}
+
+impl From<AcceptError> for io::Error {
+    fn from(error: AcceptError) -> Self {
+        error.into_inner()
+    }
+}
+
+/// Builder for an [`AsyncTcpListener`], created with [`AsyncTcpListener::builder`].
+///
+/// The socket is constructed by hand rather than through `std::net::TcpListener`,
+/// since std only lets us touch socket options after `bind` and `listen` have
+/// already happened.
+#[derive(Debug)]
+pub struct TcpListenerBuilder {
+    addr: SocketAddr,
+    reuse_address: bool,
+    reuse_port: bool,
+    backlog: u32,
+    only_v6: Option<bool>,
+    backoff: Duration,
+    reserve_fd: bool,
+}
+
+impl TcpListenerBuilder {
+    /// Set `SO_REUSEADDR`. Defaults to `true`, matching std.
+    pub fn reuse_address(mut self, reuse: bool) -> Self {
+        self.reuse_address = reuse;
+        self
+    }
+
+    /// Set `SO_REUSEPORT`, allowing several listeners to bind the same
+    /// address. Defaults to `false`.
+    pub fn reuse_port(mut self, reuse: bool) -> Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// Set the maximum length of the pending connection queue. Defaults to
+    /// `128`; the kernel may silently clamp larger values.
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Set `IPV6_V6ONLY`. Ignored for IPv4 addresses.
+    pub fn only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// Set how long to wait before accepting again after running out of fds
+    /// or memory, which accepting again right away would only run into
+    /// again. Defaults to 100ms; zero retries right away.
+    pub fn exhausted_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Hold on to a spare fd which is closed when the process runs out of
+    /// fds, to accept the next pending connection and close it right away,
+    /// then reopened. Clients then see their connection closed, rather than
+    /// waiting in the backlog until fds free up. Defaults to `false`.
+    pub fn reserve_fd(mut self, reserve: bool) -> Self {
+        self.reserve_fd = reserve;
+        self
+    }
+
+    pub fn build(self) -> io::Result<AsyncTcpListener> {
+        let socket = nonblocking_socket(&self.addr)?;
+
+        sockopt::set_socket_reuseaddr(&socket, self.reuse_address)?;
+        if self.reuse_port {
+            sockopt::set_socket_reuseport(&socket, true)?;
+        }
+        if let (SocketAddr::V6(_), Some(only_v6)) = (self.addr, self.only_v6) {
+            sockopt::set_ipv6_v6only(&socket, only_v6)?;
+        }
+
+        rustix::net::bind(&socket, &self.addr)?;
+        let backlog = i32::try_from(self.backlog).unwrap_or(i32::MAX);
+        rustix::net::listen(&socket, backlog)?;
+        let mut listener = AsyncTcpListener::new(TcpListener::from(socket));
+        listener.backoff = self.backoff;
+        listener.reserve_fd = self.reserve_fd;
+        if self.reserve_fd {
+            listener.spare = Some(File::open("/dev/null")?);
+        }
+        Ok(listener)
+    }
+}
+
+/// Create a nonblocking TCP socket for the address family of `addr`.
+///
+/// Apple platforms don't support `SOCK_NONBLOCK` and `SOCK_CLOEXEC`, so the
+/// flags are set after the fact.
+fn nonblocking_socket(addr: &SocketAddr) -> io::Result<OwnedFd> {
+    let family = match addr {
+        SocketAddr::V4(_) => AddressFamily::INET,
+        SocketAddr::V6(_) => AddressFamily::INET6,
+    };
+    let socket = rustix::net::socket(family, SocketType::STREAM, None)?;
+    rustix::io::fcntl_setfd(&socket, FdFlags::CLOEXEC)?;
+    rustix::io::ioctl_fionbio(&socket, true)?;
+    Ok(socket)
+}
+
+/// Future for [`AsyncTcpStream::connect_async`] and
+/// [`AsyncTcpStream::connect_host`].
+pub struct ConnectFuture {
+    state: ConnectState,
+    /// The addresses to try if the current one fails.
+    fallbacks: std::vec::IntoIter<SocketAddr>,
+    output: Option<Result<AsyncTcpStream, error::Error>>,
+}
+
+enum ConnectState {
+    Resolving(ResolveFuture),
+    Start(SocketAddr),
+    /// Waiting for the socket to connect, and whether it's registered for
+    /// the write event which says it's done.
+    Connecting(TcpStream, SocketAddr, bool),
+    /// Done connecting, with the error if that failed, while the poller
+    /// drops the write registration. The socket stays open meanwhile, so
+    /// its fd isn't reused under the registration.
+    Connected(TcpStream, SocketAddr, Option<io::Error>),
+    Done,
+}
+
+impl ConnectFuture {
+    /// Attempt to connect. Failing to connect to the last address fails
+    /// with an `io::Error` carrying an [`error::Error`], which says which
+    /// address it was.
+    pub(crate) fn poll_connect(&mut self) -> Step<iter::Once<Waitable>, AsyncTcpStream> {
+        loop {
+            let (error, addr) = match std::mem::replace(&mut self.state, ConnectState::Done) {
+                ConnectState::Resolving(mut resolve) => {
+                    // Resolving waits on at most its own completion, which
+                    // doesn't need to be told what's ready.
+                    let waitable = resolve.poll(&[]).next();
+                    match resolve.take() {
+                        Some(Ok(addrs)) => {
+                            self.fallbacks = addrs.into_iter();
+                            match self.fallbacks.next() {
+                                Some(addr) => self.state = ConnectState::Start(addr),
+                                None => {
+                                    return Step::Error(io::Error::new(
+                                        io::ErrorKind::NotFound,
+                                        "host did not resolve to any address",
+                                    ))
+                                }
+                            }
+                        }
+                        Some(Err(e)) => return Step::Error(e),
+                        None => {
+                            self.state = ConnectState::Resolving(resolve);
+                            let waitable = waitable.expect("unresolved without a waitable");
+                            return Step::Pending(iter::once(waitable));
+                        }
+                    }
+                    continue;
+                }
+                ConnectState::Start(addr) => match nonblocking_socket(&addr) {
+                    Ok(socket) => match rustix::net::connect(&socket, &addr) {
+                        Ok(()) => return Step::Done(AsyncTcpStream(socket.into())),
+                        Err(rustix::io::Errno::INPROGRESS) => {
+                            self.state = ConnectState::Connecting(socket.into(), addr, false);
+                            continue;
+                        }
+                        Err(e) => (e.into(), addr),
+                    },
+                    Err(e) => (e, addr),
+                },
+                ConnectState::Connecting(stream, addr, registered) => {
+                    // The socket becomes writable once connecting finished,
+                    // either way. `SO_ERROR` says whether it failed, and
+                    // having a peer says whether it's done at all.
+                    let error = match stream.take_error() {
+                        Ok(None) => match stream.peer_addr() {
+                            Ok(_) => None,
+                            Err(e) if e.kind() == io::ErrorKind::NotConnected => {
+                                let waitable = Waitable::Fd(stream.as_raw_fd(), Interest::Write);
+                                self.state = ConnectState::Connecting(stream, addr, true);
+                                return Step::Pending(iter::once(waitable));
+                            }
+                            Err(e) => Some(e),
+                        },
+                        Ok(Some(e)) | Err(e) => Some(e),
+                    };
+                    if registered {
+                        let waitable = Waitable::Fd(stream.as_raw_fd(), Interest::CloseWrite);
+                        self.state = ConnectState::Connected(stream, addr, error);
+                        return Step::Pending(iter::once(waitable));
+                    }
+                    match error {
+                        None => return Step::Done(AsyncTcpStream(stream)),
+                        Some(e) => (e, addr),
+                    }
+                }
+                ConnectState::Connected(stream, addr, error) => match error {
+                    None => return Step::Done(AsyncTcpStream(stream)),
+                    Some(e) => (e, addr),
+                },
+                ConnectState::Done => unreachable!("connect future polled after completion"),
+            };
+            // Move on to the next address, if there is one.
+            match self.fallbacks.next() {
+                Some(addr) => self.state = ConnectState::Start(addr),
+                None => {
+                    let error = error::Error::new(Operation::Connect, error).with_peer(addr);
+                    return Step::Error(error.into());
+                }
+            }
+        }
+    }
+}
+
+impl Future for ConnectFuture {
+    type Output = Result<AsyncTcpStream, error::Error>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if !matches!(self.state, ConnectState::Done) {
+            match self.poll_connect() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(stream) => self.output = Some(Ok(stream)),
+                Step::Error(e) => self.output = Some(Err(e.into())),
             }
-            CloseFutureState::Pending | CloseFutureState::Completed => None,
         }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+pub struct AcceptFuture<'a> {
+    listener: &'a mut AsyncTcpListener,
+    output: Option<Result<(AsyncTcpStream, SocketAddr), error::Error>>,
+}
+
+impl<'a> Future for AcceptFuture<'a> {
+    type Output = Result<(AsyncTcpStream, SocketAddr), error::Error>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(accepted) => self.output = Some(Ok(accepted)),
+                Step::Error(e) => {
+                    let e = error::Error::new(Operation::Accept, e);
+                    self.output = Some(Err(e.with_fd(self.listener.as_raw_fd())));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`AsyncTcpListener::incoming`].
+pub struct Incoming<'a> {
+    listener: &'a mut AsyncTcpListener,
+    item: Option<Result<AsyncTcpStream, AcceptError>>,
+}
+
+impl<'a> Stream for Incoming<'a> {
+    type Item = Result<AsyncTcpStream, AcceptError>;
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<'a> {
+        let mut pending = None;
+        if self.item.is_none() {
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done((stream, _)) => self.item = Some(Ok(stream)),
+                Step::Error(e) => self.item = Some(Err(AcceptError::new(e))),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
     }
 }
@@ -0,0 +1,230 @@
+//! Waiting for time to pass.
+//!
+//! Futures wait for a point in time by yielding [`Waitable::Timer`]. The
+//! poller wakes up once the earliest of them has passed, and reports it as
+//! ready. A deadline for an fd to become ready can go along with its
+//! registration instead, as [`Waitable::FdUntil`].
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::future::{Future, Interest, IntoFuture, Waitable};
+
+/// Wait until `duration` has passed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Wait until `deadline` has passed.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        output: None,
+    }
+}
+
+/// Run `future`, failing with [`Elapsed`] if it doesn't complete within
+/// `duration`.
+///
+/// When the deadline wins, the poller is asked to drop the read and write
+/// registrations the future was waiting on.
+pub fn timeout<F: IntoFuture>(duration: Duration, future: F) -> Timeout<F::IntoFuture> {
+    Timeout {
+        future: future.into_future(),
+        deadline: Instant::now() + duration,
+        waiting_on: Vec::new(),
+        output: None,
+    }
+}
+
+/// Future for [`sleep`] and [`sleep_until`].
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    output: Option<()>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match Instant::now() >= self.deadline {
+                true => self.output = Some(()),
+                false => pending = Some(Waitable::Timer(self.deadline)),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`timeout`].
+pub struct Timeout<F: Future> {
+    future: F,
+    deadline: Instant,
+    /// What the future was blocked on as of the last poll.
+    waiting_on: Vec<Waitable>,
+    output: Option<Result<F::Output, Elapsed>>,
+}
+
+impl<F: Future> Timeout<F> {
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut timer = None;
+        self.waiting_on.clear();
+        if self.output.is_none() {
+            self.waiting_on.extend(self.future.poll(ready));
+            if self.waiting_on.is_empty() {
+                self.output = self.future.take().map(Ok);
+            }
+        }
+        if self.output.is_none() {
+            match Instant::now() >= self.deadline {
+                true => {
+                    self.output = Some(Err(Elapsed(())));
+                    let waiting_on = std::mem::take(&mut self.waiting_on);
+                    self.waiting_on = waiting_on
+                        .into_iter()
+                        .filter_map(Waitable::cancel)
+                        .collect();
+                }
+                false => timer = Some(Waitable::Timer(self.deadline)),
+            }
+        }
+        self.waiting_on.iter().copied().chain(timer)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Run `future`, which waits on fds, failing with [`Elapsed`] if it doesn't
+/// complete within `duration`.
+///
+/// Rather than a timer of its own, the deadline goes along with the fd
+/// registrations, as [`Waitable::FdUntil`]. The poller then reports each one
+/// either as ready or as timed out, so a read which lands right at the
+/// deadline either completes or times out, and never races the timer.
+/// Anything else the future waits on goes without the deadline, so a
+/// future waiting on no fds never times out.
+pub fn fd_timeout<F: IntoFuture>(duration: Duration, future: F) -> FdTimeout<F::IntoFuture> {
+    FdTimeout {
+        future: future.into_future(),
+        deadline: Instant::now() + duration,
+        waiting_on: Vec::new(),
+        output: None,
+    }
+}
+
+/// Future for [`fd_timeout`].
+pub struct FdTimeout<F: Future> {
+    future: F,
+    deadline: Instant,
+    /// What the future was blocked on as of the last poll, with the
+    /// deadline.
+    waiting_on: Vec<Waitable>,
+    output: Option<Result<F::Output, Elapsed>>,
+}
+
+impl<F: Future> FdTimeout<F> {
+    pub fn get_ref(&self) -> &F {
+        &self.future
+    }
+
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.future
+    }
+}
+
+impl<F: Future> Future for FdTimeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let timed_out = ready.iter().any(|r| {
+            matches!(r, Waitable::FdUntil(..)) && self.waiting_on.iter().any(|w| r.wakes(*w))
+        });
+        if self.output.is_none() && timed_out {
+            self.output = Some(Err(Elapsed(())));
+            let waiting_on = std::mem::take(&mut self.waiting_on);
+            self.waiting_on = waiting_on
+                .into_iter()
+                .filter_map(Waitable::cancel)
+                .collect();
+        } else {
+            self.waiting_on.clear();
+        }
+        if self.output.is_none() {
+            let deadline = self.deadline;
+            let waiting_on = self.future.poll(ready).map(|waitable| match waitable {
+                Waitable::Fd(
+                    fd,
+                    interest @ (Interest::Read
+                    | Interest::Write
+                    | Interest::Hangup
+                    | Interest::Priority),
+                ) => Waitable::FdUntil(fd, interest, deadline),
+                waitable => waitable,
+            });
+            self.waiting_on.extend(waiting_on);
+            if self.waiting_on.is_empty() {
+                self.output = self.future.take().map(Ok);
+            }
+        }
+        self.waiting_on.iter().copied()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Error returned by [`timeout`] and
+/// [`Poller::block_on_timeout`](crate::runtime::Poller::block_on_timeout)
+/// when the deadline passes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl Elapsed {
+    pub(crate) fn new() -> Self {
+        Elapsed(())
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+impl From<Elapsed> for io::Error {
+    fn from(elapsed: Elapsed) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, elapsed)
+    }
+}
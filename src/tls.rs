@@ -0,0 +1,344 @@
+//! TLS over [`AsyncTcpStream`].
+//!
+//! TLS libraries like rustls are sans-io: they hold a state machine which
+//! consumes and produces TLS records through plain `Read` and `Write`, and
+//! say whether they want more input or have output to send. That slots into
+//! the readiness model directly, so this module only has to pump a
+//! [`Session`] against the socket's readiness.
+//!
+//! [`Session`] mirrors the API of rustls, and is implemented for its client
+//! and server connections. [`TlsConnector`] sets up the client side. Another
+//! TLS library only needs a [`Session`] impl to be used with [`connect`].
+//! Which crypto provider rustls uses is up to the caller, through the
+//! `ClientConfig` they pass in.
+
+use std::io::{self, Read, Write};
+use std::iter;
+use std::net::TcpStream;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, ServerConnection};
+
+use crate::future::{Future, Interest, Waitable};
+use crate::io::{AsyncRead, AsyncWrite, ReadStep, Step, WriteStep};
+use crate::tcp::AsyncTcpStream;
+
+/// A sans-io TLS connection.
+pub trait Session {
+    /// Whether the session wants to read TLS records from the peer.
+    fn wants_read(&self) -> bool;
+
+    /// Whether the session has TLS records to send to the peer.
+    fn wants_write(&self) -> bool;
+
+    fn is_handshaking(&self) -> bool;
+
+    /// Read TLS records from `rd`, which may be at EOF.
+    fn read_tls(&mut self, rd: &mut dyn Read) -> io::Result<usize>;
+
+    /// Write pending TLS records to `wr`.
+    fn write_tls(&mut self, wr: &mut dyn Write) -> io::Result<usize>;
+
+    /// Process the records read so far, failing if the peer misbehaved.
+    fn process_new_packets(&mut self) -> io::Result<()>;
+
+    /// Read decrypted application data, failing with `WouldBlock` if there
+    /// is none yet. Returns `Ok(0)` once the peer closed the connection.
+    fn read_plaintext(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Queue application data to be encrypted and sent.
+    fn write_plaintext(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Queue a `close_notify` alert, telling the peer we're done writing.
+    fn send_close_notify(&mut self);
+}
+
+macro_rules! rustls_session {
+    ($($connection:ty),*) => {$(
+        // The methods are those of the `ConnectionCommon` the connection
+        // derefs to, which `Session` methods of the same name would shadow.
+        impl Session for $connection {
+            fn wants_read(&self) -> bool {
+                (**self).wants_read()
+            }
+
+            fn wants_write(&self) -> bool {
+                (**self).wants_write()
+            }
+
+            fn is_handshaking(&self) -> bool {
+                (**self).is_handshaking()
+            }
+
+            fn read_tls(&mut self, rd: &mut dyn Read) -> io::Result<usize> {
+                (**self).read_tls(rd)
+            }
+
+            fn write_tls(&mut self, wr: &mut dyn Write) -> io::Result<usize> {
+                (**self).write_tls(wr)
+            }
+
+            fn process_new_packets(&mut self) -> io::Result<()> {
+                match (**self).process_new_packets() {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                }
+            }
+
+            fn read_plaintext(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                (**self).reader().read(buf)
+            }
+
+            fn write_plaintext(&mut self, buf: &[u8]) -> io::Result<usize> {
+                (**self).writer().write(buf)
+            }
+
+            fn send_close_notify(&mut self) {
+                (**self).send_close_notify();
+            }
+        }
+    )*};
+}
+
+rustls_session!(ClientConnection, ServerConnection);
+
+/// Connects to servers over TLS with rustls.
+#[derive(Debug, Clone)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    pub fn new(config: Arc<ClientConfig>) -> Self {
+        Self { config }
+    }
+
+    /// Start a TLS handshake with `domain` over `stream`, resolving with the
+    /// established [`TlsStream`].
+    ///
+    /// The server's certificate has to be valid for `domain`, which may also
+    /// be an IP address. If it's neither, or rustls rejects the config, the
+    /// future fails right away.
+    pub fn connect(
+        &self,
+        domain: &str,
+        stream: AsyncTcpStream,
+    ) -> HandshakeFuture<ClientConnection> {
+        let session = ServerName::try_from(domain.to_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            .and_then(|name| {
+                ClientConnection::new(self.config.clone(), name)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            });
+        match session {
+            Ok(session) => connect(session, stream),
+            Err(e) => HandshakeFuture {
+                inner: None,
+                output: Some(Err(e)),
+            },
+        }
+    }
+}
+
+/// Start a TLS handshake with `session` over `stream`, resolving with the
+/// established [`TlsStream`].
+///
+/// The handshake runs the same way on either side, so `session` may be a
+/// server connection for a stream which was accepted.
+pub fn connect<S: Session>(session: S, stream: AsyncTcpStream) -> HandshakeFuture<S> {
+    HandshakeFuture {
+        inner: Some(TlsStream {
+            stream,
+            session,
+            eof: false,
+        }),
+        output: None,
+    }
+}
+
+/// A TLS session over an [`AsyncTcpStream`].
+pub struct TlsStream<S> {
+    stream: AsyncTcpStream,
+    session: S,
+    /// Whether the socket reached EOF.
+    eof: bool,
+}
+
+impl<S: Session> TlsStream<S> {
+    pub fn get_ref(&self) -> (&AsyncTcpStream, &S) {
+        (&self.stream, &self.session)
+    }
+
+    /// Reading from or writing to the stream directly corrupts the session.
+    pub fn get_mut(&mut self) -> (&mut AsyncTcpStream, &mut S) {
+        (&mut self.stream, &mut self.session)
+    }
+
+    pub fn into_inner(self) -> (AsyncTcpStream, S) {
+        (self.stream, self.session)
+    }
+
+    /// Queue a `close_notify` alert. It goes out with the next write; writing
+    /// an empty buffer sends just the alert.
+    pub fn send_close_notify(&mut self) {
+        self.session.send_close_notify();
+    }
+
+    /// Send everything the session has queued.
+    fn poll_write_tls(&mut self) -> Step<iter::Once<Waitable>, ()> {
+        let mut socket: &TcpStream = self.stream.as_std();
+        while self.session.wants_write() {
+            let result = self.session.write_tls(&mut socket);
+            match Step::from_syscall(result, socket.as_raw_fd(), Interest::Write) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(_) => {}
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+        Step::Done(())
+    }
+
+    /// Read and process one batch of records from the socket.
+    fn poll_read_tls(&mut self) -> Step<iter::Once<Waitable>, ()> {
+        let mut socket: &TcpStream = self.stream.as_std();
+        loop {
+            let result = self.session.read_tls(&mut socket);
+            match Step::from_syscall(result, socket.as_raw_fd(), Interest::Read) {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(0) => self.eof = true,
+                Step::Done(_) => {}
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Step::Error(e) => return Step::Error(e),
+            }
+            break;
+        }
+        match self.session.process_new_packets() {
+            Ok(()) => Step::Done(()),
+            Err(e) => {
+                // Try to tell the peer what went wrong, but don't wait for it.
+                let _ = self.poll_write_tls();
+                Step::Error(e)
+            }
+        }
+    }
+
+    fn poll_handshake(&mut self) -> Step<iter::Once<Waitable>, ()> {
+        loop {
+            match self.poll_write_tls() {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(()) => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+            if !self.session.is_handshaking() {
+                return Step::Done(());
+            }
+            if self.eof {
+                let e = io::Error::new(io::ErrorKind::UnexpectedEof, "tls handshake eof");
+                return Step::Error(e);
+            }
+            match self.poll_read_tls() {
+                Step::Pending(waitables) => return Step::Pending(waitables),
+                Step::Done(()) => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+    }
+}
+
+impl<S: Session> AsyncRead for TlsStream<S> {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<S>> {
+        // The session may want to write while we only asked to read, for
+        // example to answer a key update. Send that along the way, and wait
+        // for the socket to become writable too if it can't go out yet.
+        let mut write_pending = None;
+        loop {
+            match self.session.read_plaintext(buf) {
+                Ok(n) => return Step::Done(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Step::Error(e),
+            }
+            if self.eof {
+                return Step::Done(0);
+            }
+            match self.poll_write_tls() {
+                Step::Pending(waitables) => write_pending = Some(waitables),
+                Step::Done(()) => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+            match self.poll_read_tls() {
+                Step::Pending(waitables) => {
+                    let write_pending = write_pending.into_iter().flatten();
+                    return Step::Pending(waitables.chain(write_pending));
+                }
+                Step::Done(()) => {}
+                Step::Error(e) => return Step::Error(e),
+            }
+        }
+    }
+}
+
+impl<S: Session> AsyncWrite for TlsStream<S> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<S>> {
+        let n = match self.session.write_plaintext(buf) {
+            Ok(n) => n,
+            Err(e) => return Step::Error(e),
+        };
+        match self.poll_write_tls() {
+            // Once the session took the data it's as good as written; it goes
+            // out with the next write.
+            Step::Pending(_) if n > 0 => Step::Done(n),
+            Step::Pending(waitables) => Step::Pending(waitables),
+            Step::Done(()) => Step::Done(n),
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+
+    /// Send everything the session has queued, including data
+    /// [`AsyncWrite::poll_write`] took without sending it yet.
+    fn poll_flush(
+        &mut self,
+        _ready: &[Waitable],
+    ) -> Step<impl Iterator<Item = Waitable> + use<S>, ()> {
+        self.poll_write_tls()
+    }
+}
+
+/// Future for [`connect`].
+pub struct HandshakeFuture<S> {
+    inner: Option<TlsStream<S>>,
+    output: Option<io::Result<TlsStream<S>>>,
+}
+
+impl<S: Session> Future for HandshakeFuture<S> {
+    type Output = io::Result<TlsStream<S>>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if let Some(stream) = &mut self.inner {
+            match stream.poll_handshake() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(()) => self.output = self.inner.take().map(Ok),
+                Step::Error(e) => {
+                    self.inner = None;
+                    self.output = Some(Err(e));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,639 @@
+use rustix::net::{sockopt, RecvAncillaryBuffer, RecvFlags};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IoSliceMut};
+use std::iter;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::fd::{AsRawFd, RawFd};
+
+use crate::future::{Future, Interest, Waitable};
+use crate::io::Step;
+use crate::stream::Stream;
+
+mod batch;
+
+/// How much [`AsyncUdpSocket::next_packet_len`] peeks at where the kernel
+/// can't report a datagram's length: more than any datagram but an IPv6
+/// jumbogram carries.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const PROBE_SIZE: usize = 64 * 1024;
+
+pub struct AsyncUdpSocket(UdpSocket);
+impl AsRawFd for AsyncUdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsyncUdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self(socket))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    /// Borrow the underlying std socket.
+    ///
+    /// The socket is in nonblocking mode, and has to stay that way for the
+    /// futures on `AsyncUdpSocket` to work.
+    pub fn as_std(&self) -> &UdpSocket {
+        &self.0
+    }
+
+    /// Set the default destination for [`AsyncUdpSocket::send`], and only
+    /// receive datagrams from `addr` from now on.
+    ///
+    /// Connecting a datagram socket only records the address, so this doesn't
+    /// block.
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        self.0.connect(addr)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// Join the IPv4 multicast `group` on the interface with address
+    /// `interface`. [`Ipv4Addr::UNSPECIFIED`] lets the system pick one.
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> io::Result<()> {
+        Ok(sockopt::set_ip_add_membership(&self.0, &group, &interface)?)
+    }
+
+    pub fn leave_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> io::Result<()> {
+        Ok(sockopt::set_ip_drop_membership(
+            &self.0, &group, &interface,
+        )?)
+    }
+
+    /// Join the IPv6 multicast `group` on the interface with index
+    /// `interface`. Zero lets the system pick one.
+    pub fn join_multicast_v6(&self, group: Ipv6Addr, interface: u32) -> io::Result<()> {
+        Ok(sockopt::set_ipv6_add_membership(
+            &self.0, &group, interface,
+        )?)
+    }
+
+    pub fn leave_multicast_v6(&self, group: Ipv6Addr, interface: u32) -> io::Result<()> {
+        Ok(sockopt::set_ipv6_drop_membership(
+            &self.0, &group, interface,
+        )?)
+    }
+
+    /// Set `IP_MULTICAST_TTL`, how many hops outgoing multicast datagrams
+    /// may take. The default of 1 keeps them on the local network.
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        Ok(sockopt::set_ip_multicast_ttl(&self.0, ttl)?)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        Ok(sockopt::get_ip_multicast_ttl(&self.0)?)
+    }
+
+    /// Set `IP_MULTICAST_LOOP`, whether multicast datagrams we send are
+    /// delivered back to sockets on this host which joined the group.
+    pub fn set_multicast_loop_v4(&self, on: bool) -> io::Result<()> {
+        Ok(sockopt::set_ip_multicast_loop(&self.0, on)?)
+    }
+
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        Ok(sockopt::get_ip_multicast_loop(&self.0)?)
+    }
+
+    /// Set `IPV6_MULTICAST_LOOP`; see [`AsyncUdpSocket::set_multicast_loop_v4`].
+    pub fn set_multicast_loop_v6(&self, on: bool) -> io::Result<()> {
+        Ok(sockopt::set_ipv6_multicast_loop(&self.0, on)?)
+    }
+
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        Ok(sockopt::get_ipv6_multicast_loop(&self.0)?)
+    }
+
+    /// Receive a single datagram, resolving with its length and sender.
+    ///
+    /// If the datagram doesn't fit in `buf` the rest of it is discarded.
+    pub fn recv_from<'a>(&mut self, buf: &'a mut [u8]) -> RecvFromFuture<'_, 'a> {
+        RecvFromFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Look at the next datagram without taking it off the queue, resolving
+    /// with its length and sender like [`AsyncUdpSocket::recv_from`].
+    ///
+    /// The next receive gets the same datagram again, in full, even if it
+    /// was cut short here.
+    pub fn peek_from<'a>(&mut self, buf: &'a mut [u8]) -> PeekFromFuture<'_, 'a> {
+        PeekFromFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Wait for the next datagram and resolve with its length, leaving it
+    /// queued, so a buffer of the right size can be set aside to receive it
+    /// into.
+    ///
+    /// On Linux the kernel reports the length without copying anything.
+    /// Elsewhere the datagram is peeked at in a 64KiB buffer, which no
+    /// datagram but an IPv6 jumbogram outgrows.
+    pub fn next_packet_len(&mut self) -> NextPacketLenFuture<'_> {
+        NextPacketLenFuture {
+            socket: self,
+            probe: Vec::new(),
+            output: None,
+        }
+    }
+
+    /// Send `buf` as a single datagram to `target`, resolving with the number
+    /// of bytes sent.
+    pub fn send_to<'a>(&mut self, buf: &'a [u8], target: SocketAddr) -> SendToFuture<'_, 'a> {
+        SendToFuture {
+            socket: self,
+            buffer: buf,
+            target,
+            output: None,
+        }
+    }
+
+    /// Send `buf` as a single datagram to the connected peer, resolving with
+    /// the number of bytes sent.
+    pub fn send<'a>(&mut self, buf: &'a [u8]) -> SendFuture<'_, 'a> {
+        SendFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Receive a single datagram from the connected peer, resolving with its
+    /// length.
+    ///
+    /// A datagram that doesn't fit in `buf` fails the future with
+    /// [`DatagramTruncated`] rather than quietly cutting it short. Whatever
+    /// did fit is in `buf` regardless, and the rest of the datagram is gone.
+    pub fn recv<'a>(&mut self, buf: &'a mut [u8]) -> RecvFuture<'_, 'a> {
+        RecvFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// A stream of datagrams from the connected peer, each received into a
+    /// fresh buffer of `buf_size` bytes. It never ends.
+    ///
+    /// Datagrams larger than `buf_size` are yielded as [`DatagramTruncated`]
+    /// errors, and the stream keeps receiving afterwards.
+    pub fn recv_stream(&mut self, buf_size: usize) -> RecvStream<'_> {
+        RecvStream {
+            socket: self,
+            buf_size,
+            item: None,
+        }
+    }
+
+    /// Send several datagrams at once, each to its own target, resolving
+    /// with how many were sent.
+    ///
+    /// That may be fewer than all of them, in which case the caller should
+    /// send the rest with another batch. The future only waits if the socket
+    /// can't take a single datagram.
+    pub fn send_batch<'a>(
+        &mut self,
+        packets: &'a [(&'a [u8], SocketAddr)],
+    ) -> SendBatchFuture<'_, 'a> {
+        SendBatchFuture {
+            socket: self,
+            packets,
+            output: None,
+        }
+    }
+
+    /// Receive several datagrams at once, resolving with how many were
+    /// received.
+    ///
+    /// Datagram `i` is stored in `bufs[i]`, and its length and sender in
+    /// `meta[i]`. Like [`AsyncUdpSocket::recv_from`], datagrams too large for
+    /// their buffer are cut short. The future resolves as soon as at least
+    /// one datagram arrived, and at most `min(bufs.len(), meta.len())` are
+    /// received.
+    pub fn recv_batch<'a, 'b>(
+        &mut self,
+        bufs: &'a mut [&'b mut [u8]],
+        meta: &'a mut [RecvMeta],
+    ) -> RecvBatchFuture<'_, 'a, 'b> {
+        RecvBatchFuture {
+            socket: self,
+            bufs,
+            meta,
+            output: None,
+        }
+    }
+
+    fn poll_recv(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>> {
+        // `recvmsg` rather than `recv`, since only it reports truncation
+        // everywhere.
+        let result = rustix::net::recvmsg(
+            &self.0,
+            &mut [IoSliceMut::new(buf)],
+            &mut RecvAncillaryBuffer::default(),
+            RecvFlags::empty(),
+        );
+        let result = result.map_err(io::Error::from).and_then(|received| {
+            if received.flags.contains(RecvFlags::TRUNC) {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    DatagramTruncated,
+                ))
+            } else {
+                Ok(received.bytes)
+            }
+        });
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Read)
+    }
+
+    fn poll_send(&self, buf: &[u8]) -> Step<iter::Once<Waitable>> {
+        Step::from_syscall(self.0.send(buf), self.as_raw_fd(), Interest::Write)
+    }
+
+    fn poll_recv_from(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>, (usize, SocketAddr)> {
+        Step::from_syscall(self.0.recv_from(buf), self.as_raw_fd(), Interest::Read)
+    }
+
+    fn poll_peek_from(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>, (usize, SocketAddr)> {
+        Step::from_syscall(self.0.peek_from(buf), self.as_raw_fd(), Interest::Read)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn poll_next_packet_len(&self, _probe: &mut Vec<u8>) -> Step<iter::Once<Waitable>> {
+        // With `MSG_TRUNC`, Linux returns the length of the whole datagram
+        // rather than of what fit, which is nothing.
+        // SAFETY: a zero-length buffer is never written to.
+        let n = unsafe {
+            libc::recv(
+                self.as_raw_fd(),
+                std::ptr::null_mut(),
+                0,
+                libc::MSG_PEEK | libc::MSG_TRUNC,
+            )
+        };
+        let result = match n {
+            -1 => Err(io::Error::last_os_error()),
+            n => Ok(n as usize),
+        };
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Read)
+    }
+
+    // Elsewhere `MSG_TRUNC` only says the datagram was cut short, so peek at
+    // it in a buffer large enough for the whole of it.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn poll_next_packet_len(&self, probe: &mut Vec<u8>) -> Step<iter::Once<Waitable>> {
+        probe.resize(PROBE_SIZE, 0);
+        let result = self.0.peek_from(probe).map(|(n, _)| n);
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Read)
+    }
+
+    fn poll_send_to(&self, buf: &[u8], target: SocketAddr) -> Step<iter::Once<Waitable>> {
+        // A full send buffer is the only reason a datagram socket blocks on
+        // writing.
+        Step::from_syscall(
+            self.0.send_to(buf, target),
+            self.as_raw_fd(),
+            Interest::Write,
+        )
+    }
+}
+
+/// The length and sender of a datagram received by
+/// [`AsyncUdpSocket::recv_batch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvMeta {
+    pub len: usize,
+    pub addr: SocketAddr,
+}
+
+impl Default for RecvMeta {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            addr: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        }
+    }
+}
+
+/// Future for [`AsyncUdpSocket::send_batch`].
+pub struct SendBatchFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    packets: &'b [(&'b [u8], SocketAddr)],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendBatchFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            if self.packets.is_empty() {
+                self.output = Some(Ok(0));
+                break;
+            }
+            let result = batch::send_batch(&self.socket.0, self.packets);
+            match Step::from_syscall(result, self.socket.as_raw_fd(), Interest::Write) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::recv_batch`].
+pub struct RecvBatchFuture<'a, 'b, 'c> {
+    socket: &'a mut AsyncUdpSocket,
+    bufs: &'b mut [&'c mut [u8]],
+    meta: &'b mut [RecvMeta],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b, 'c> Future for RecvBatchFuture<'a, 'b, 'c> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            if self.bufs.is_empty() || self.meta.is_empty() {
+                self.output = Some(Ok(0));
+                break;
+            }
+            let result = batch::recv_batch(&self.socket.0, self.bufs, self.meta);
+            match Step::from_syscall(result, self.socket.as_raw_fd(), Interest::Read) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::recv_from`].
+pub struct RecvFromFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<(usize, SocketAddr)>>,
+}
+
+impl<'a, 'b> Future for RecvFromFuture<'a, 'b> {
+    type Output = io::Result<(usize, SocketAddr)>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_recv_from(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(received) => self.output = Some(Ok(received)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::peek_from`].
+pub struct PeekFromFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<(usize, SocketAddr)>>,
+}
+
+impl<'a, 'b> Future for PeekFromFuture<'a, 'b> {
+    type Output = io::Result<(usize, SocketAddr)>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_peek_from(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(peeked) => self.output = Some(Ok(peeked)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::next_packet_len`].
+pub struct NextPacketLenFuture<'a> {
+    socket: &'a mut AsyncUdpSocket,
+    /// What the datagram is peeked at in, where the kernel can't tell its
+    /// length otherwise.
+    probe: Vec<u8>,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a> Future for NextPacketLenFuture<'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_next_packet_len(&mut self.probe) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::send_to`].
+pub struct SendToFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    buffer: &'b [u8],
+    target: SocketAddr,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendToFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_send_to(self.buffer, self.target) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::send`].
+pub struct SendFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    buffer: &'b [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_send(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUdpSocket::recv`].
+pub struct RecvFuture<'a, 'b> {
+    socket: &'a mut AsyncUdpSocket,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for RecvFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_recv(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`AsyncUdpSocket::recv_stream`].
+pub struct RecvStream<'a> {
+    socket: &'a mut AsyncUdpSocket,
+    buf_size: usize,
+    item: Option<io::Result<Vec<u8>>>,
+}
+
+impl<'a> Stream for RecvStream<'a> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<'a> {
+        let mut pending = None;
+        while self.item.is_none() {
+            let mut buf = vec![0; self.buf_size];
+            match self.socket.poll_recv(&mut buf) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => {
+                    buf.truncate(n);
+                    self.item = Some(Ok(buf));
+                }
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.item = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
+
+/// Error for a datagram larger than the buffer it was received into, wrapped
+/// in an `InvalidData` [`io::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramTruncated;
+
+impl fmt::Display for DatagramTruncated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("datagram was larger than the buffer and got truncated")
+    }
+}
+
+impl Error for DatagramTruncated {}
@@ -0,0 +1,207 @@
+//! Sending and receiving many datagrams per syscall.
+//!
+//! Linux has `sendmmsg` and `recvmmsg` for this. Elsewhere, namely macOS, we
+//! fall back to a loop of `sendto` and `recvfrom`, which at least saves
+//! round trips through the poller.
+//!
+//! Both report partial progress the way `sendmmsg` does: if some datagrams
+//! were transferred before an error, including `WouldBlock`, the call
+//! succeeds with that many, and the error shows up again on the next call.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use super::RecvMeta;
+
+/// How many datagrams to hand to the kernel at once. Linux caps a single
+/// `sendmmsg` or `recvmmsg` at 1024.
+#[cfg(target_os = "linux")]
+const MAX_BATCH: usize = 1024;
+
+#[cfg(target_os = "linux")]
+pub(super) fn send_batch(socket: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+    use std::os::fd::AsRawFd;
+
+    let packets = &packets[..packets.len().min(MAX_BATCH)];
+    let mut addrs: Vec<_> = packets
+        .iter()
+        .map(|(_, addr)| raw::from_addr(*addr))
+        .collect();
+    let mut iovs: Vec<_> = packets
+        .iter()
+        .map(|(buf, _)| libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut msgs: Vec<_> = iovs
+        .iter_mut()
+        .zip(&mut addrs)
+        .map(|(iov, (addr, addr_len))| raw::mmsghdr(iov, addr, *addr_len))
+        .collect();
+    // SAFETY: every header points at an address and a buffer which outlive
+    // the call, and the kernel only reads from them.
+    let ret = unsafe {
+        libc::sendmmsg(
+            socket.as_raw_fd(),
+            msgs.as_mut_ptr(),
+            msgs.len() as libc::c_uint,
+            0,
+        )
+    };
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n as usize),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn recv_batch(
+    socket: &UdpSocket,
+    bufs: &mut [&mut [u8]],
+    meta: &mut [RecvMeta],
+) -> io::Result<usize> {
+    use std::os::fd::AsRawFd;
+
+    let count = bufs.len().min(meta.len()).min(MAX_BATCH);
+    let addr_len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // SAFETY: all-zero bytes are a valid `sockaddr_storage`.
+    let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { std::mem::zeroed() }; count];
+    let mut iovs: Vec<_> = bufs[..count]
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut msgs: Vec<_> = iovs
+        .iter_mut()
+        .zip(&mut addrs)
+        .map(|(iov, addr)| raw::mmsghdr(iov, addr, addr_len))
+        .collect();
+    // SAFETY: every header points at an address and a buffer which outlive
+    // the call and which the kernel may write to. Without a timeout the call
+    // returns whatever is queued right away, as the socket is nonblocking.
+    let ret = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            msgs.as_mut_ptr(),
+            msgs.len() as libc::c_uint,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    let received = match ret {
+        -1 => return Err(io::Error::last_os_error()),
+        n => n as usize,
+    };
+    for ((msg, addr), meta) in msgs.iter().zip(&addrs).zip(meta).take(received) {
+        meta.len = msg.msg_len as usize;
+        meta.addr = raw::to_addr(addr)?;
+    }
+    Ok(received)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn send_batch(socket: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+    let mut sent = 0;
+    for (buf, target) in packets {
+        match socket.send_to(buf, target) {
+            Ok(_) => sent += 1,
+            Err(_) if sent > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn recv_batch(
+    socket: &UdpSocket,
+    bufs: &mut [&mut [u8]],
+    meta: &mut [RecvMeta],
+) -> io::Result<usize> {
+    let mut received = 0;
+    for (buf, meta) in bufs.iter_mut().zip(meta) {
+        match socket.recv_from(buf) {
+            Ok((len, addr)) => {
+                *meta = RecvMeta { len, addr };
+                received += 1;
+            }
+            Err(_) if received > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(received)
+}
+
+/// Conversions between std and libc types for the `mmsg` calls.
+#[cfg(target_os = "linux")]
+mod raw {
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+    pub(super) fn mmsghdr(
+        iov: &mut libc::iovec,
+        addr: &mut libc::sockaddr_storage,
+        addr_len: libc::socklen_t,
+    ) -> libc::mmsghdr {
+        // SAFETY: all-zero bytes are a valid `msghdr`, with no control data.
+        let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+        hdr.msg_name = addr as *mut libc::sockaddr_storage as *mut libc::c_void;
+        hdr.msg_namelen = addr_len;
+        hdr.msg_iov = iov;
+        hdr.msg_iovlen = 1;
+        libc::mmsghdr {
+            msg_hdr: hdr,
+            msg_len: 0,
+        }
+    }
+
+    pub(super) fn from_addr(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        // SAFETY: all-zero bytes are a valid `sockaddr_storage`, and both
+        // `sockaddr_in` and `sockaddr_in6` fit inside it.
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                raw.sin_family = libc::AF_INET as libc::sa_family_t;
+                raw.sin_port = addr.port().to_be();
+                raw.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+                std::mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+                raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                raw.sin6_port = addr.port().to_be();
+                raw.sin6_flowinfo = addr.flowinfo();
+                raw.sin6_addr.s6_addr = addr.ip().octets();
+                raw.sin6_scope_id = addr.scope_id();
+                std::mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    pub(super) fn to_addr(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // SAFETY: the family says the kernel wrote a `sockaddr_in`.
+                let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                let ip = Ipv4Addr::from(raw.sin_addr.s_addr.to_ne_bytes());
+                Ok(SocketAddrV4::new(ip, u16::from_be(raw.sin_port)).into())
+            }
+            libc::AF_INET6 => {
+                // SAFETY: the family says the kernel wrote a `sockaddr_in6`.
+                let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                let ip = Ipv6Addr::from(raw.sin6_addr.s6_addr);
+                let port = u16::from_be(raw.sin6_port);
+                Ok(SocketAddrV6::new(ip, port, raw.sin6_flowinfo, raw.sin6_scope_id).into())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "received a datagram from an unknown address family",
+            )),
+        }
+    }
+}
@@ -0,0 +1,861 @@
+//! Unix domain sockets, with the same shape as the TCP types.
+
+use rustix::io::FdFlags;
+use rustix::net::{
+    RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SendAncillaryBuffer,
+    SendAncillaryMessage, SendFlags,
+};
+use std::fs;
+use std::io::{self, IoSlice, IoSliceMut, Write};
+use std::iter;
+use std::net::Shutdown;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use crate::error::{self, Operation, WithContext};
+use crate::future::{Future, Interest, Waitable};
+use crate::io::{
+    read_buf, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadFuture, ReadStep,
+    Step, WriteFuture, WriteStep,
+};
+use crate::stream::Stream;
+use crate::tcp::ShutdownFuture;
+
+pub struct AsyncUnixStream(UnixStream);
+impl AsRawFd for AsyncUnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsyncUnixStream {
+    /// Connect to the socket at `path`.
+    ///
+    /// Connecting to a Unix socket doesn't wait on the network, so this
+    /// doesn't return a future: it fails right away if nothing listens at
+    /// `path`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self(stream))
+    }
+
+    /// Create a pair of connected streams.
+    pub fn pair() -> io::Result<(AsyncUnixStream, AsyncUnixStream)> {
+        let (a, b) = UnixStream::pair()?;
+        a.set_nonblocking(true)?;
+        b.set_nonblocking(true)?;
+        Ok((Self(a), Self(b)))
+    }
+
+    /// Borrow the underlying std stream.
+    ///
+    /// The stream is in nonblocking mode, and has to stay that way for the
+    /// futures on `AsyncUnixStream` to work.
+    pub fn as_std(&self) -> &UnixStream {
+        &self.0
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// The credentials of the process on the other end, as they were when
+    /// the connection was made.
+    pub fn peer_cred(&self) -> io::Result<UCred> {
+        peer_cred(&self.0)
+    }
+
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+
+    /// Borrow the stream as a read half and a write half, which can be used
+    /// concurrently. See [`AsyncTcpStream::split`](crate::tcp::AsyncTcpStream::split).
+    pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
+        (ReadHalf(&self.0), WriteHalf(&self.0))
+    }
+
+    /// Shut down the read side, the write side, or both sides of the stream.
+    pub fn shutdown(&mut self, how: Shutdown) -> ShutdownFuture<'_> {
+        ShutdownFuture::new(self.0.as_fd(), how)
+    }
+
+    /// Send `data` along with copies of `fds`, resolving with the number of
+    /// bytes of `data` sent.
+    ///
+    /// The fds travel with the first byte, so `data` must not be empty. The
+    /// peer receives them with [`AsyncUnixStream::recv_with_fds`].
+    pub fn send_with_fds<'a>(
+        &mut self,
+        data: &'a [u8],
+        fds: &'a [BorrowedFd<'a>],
+    ) -> SendFdsFuture<'_, 'a> {
+        SendFdsFuture {
+            stream: self,
+            data,
+            fds,
+            output: None,
+        }
+    }
+
+    /// Receive data into `buf` along with up to `max_fds` fds sent by
+    /// [`AsyncUnixStream::send_with_fds`], resolving with the number of bytes
+    /// read and the fds.
+    ///
+    /// If the peer sent more than `max_fds` fds the kernel discards the rest,
+    /// and the future fails with `InvalidData`. The fds which did arrive are
+    /// closed so none of them leak, and the bytes read alongside them are
+    /// lost, so treat this as fatal for the connection.
+    pub fn recv_with_fds<'a>(
+        &mut self,
+        buf: &'a mut [u8],
+        max_fds: usize,
+    ) -> RecvFdsFuture<'_, 'a> {
+        RecvFdsFuture {
+            stream: self,
+            buffer: buf,
+            max_fds,
+            output: None,
+        }
+    }
+
+    fn poll_send_with_fds(
+        &self,
+        data: &[u8],
+        fds: &[BorrowedFd<'_>],
+    ) -> WriteStep<iter::Once<Waitable>> {
+        if data.is_empty() {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "can't send fds without data");
+            return Step::Error(e);
+        }
+        let mut space = vec![0; rustix::cmsg_space!(ScmRights(fds.len()))];
+        let mut control = SendAncillaryBuffer::new(&mut space);
+        if !control.push(SendAncillaryMessage::ScmRights(fds)) {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "too many fds to send");
+            return Step::Error(e);
+        }
+        let result = rustix::net::sendmsg(
+            &self.0,
+            &[IoSlice::new(data)],
+            &mut control,
+            SendFlags::empty(),
+        );
+        Step::from_syscall(
+            result.map_err(Into::into),
+            self.as_raw_fd(),
+            Interest::Write,
+        )
+    }
+
+    fn poll_recv_with_fds(
+        &self,
+        buf: &mut [u8],
+        max_fds: usize,
+    ) -> Step<iter::Once<Waitable>, (usize, Vec<OwnedFd>)> {
+        let mut space = vec![0; rustix::cmsg_space!(ScmRights(max_fds))];
+        let mut control = RecvAncillaryBuffer::new(&mut space);
+        let result = rustix::net::recvmsg(
+            &self.0,
+            &mut [IoSliceMut::new(buf)],
+            &mut control,
+            RecvFlags::empty(),
+        );
+        let result = result.map_err(io::Error::from).and_then(|received| {
+            let mut fds = Vec::new();
+            for message in control.drain() {
+                if let RecvAncillaryMessage::ScmRights(received) = message {
+                    fds.extend(received);
+                }
+            }
+            // The flag isn't named in `RecvFlags`, but the kernel sets it.
+            // The buffer is padded, so room for a few more than `max_fds`
+            // doesn't mean they were meant to arrive.
+            let truncated = RecvFlags::from_bits_retain(libc::MSG_CTRUNC as u32);
+            if received.flags.contains(truncated) || fds.len() > max_fds {
+                let msg = "more fds were sent than `max_fds`";
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            // Not every platform can set `CLOEXEC` as part of `recvmsg`.
+            for fd in &fds {
+                rustix::io::fcntl_setfd(fd, FdFlags::CLOEXEC)?;
+            }
+            Ok((received.bytes, fds))
+        });
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Read)
+    }
+}
+
+/// The credentials of a peer process, from [`AsyncUnixStream::peer_cred`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UCred {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    /// The peer's process id, if the platform reports it.
+    pub pid: Option<libc::pid_t>,
+}
+
+#[cfg(target_os = "macos")]
+fn peer_cred(stream: &UnixStream) -> io::Result<UCred> {
+    let fd = stream.as_raw_fd();
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `getpeereid` only writes the two ids.
+    if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // `LOCAL_PEERPID` is newer than `getpeereid`, so treat failure as not
+    // knowing the pid rather than as an error.
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    // SAFETY: `pid` and `len` describe a buffer of the right size.
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_LOCAL,
+            libc::LOCAL_PEERPID,
+            &mut pid as *mut libc::pid_t as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    let pid = (ret == 0).then_some(pid);
+    Ok(UCred { uid, gid, pid })
+}
+
+#[cfg(target_os = "linux")]
+fn peer_cred(stream: &UnixStream) -> io::Result<UCred> {
+    let cred = rustix::net::sockopt::get_socket_peercred(stream)?;
+    Ok(UCred {
+        uid: cred.uid.as_raw(),
+        gid: cred.gid.as_raw(),
+        pid: Some(cred.pid.as_raw_nonzero().get()),
+    })
+}
+
+fn write(mut stream: &UnixStream, buf: &[u8]) -> WriteStep<iter::Once<Waitable>> {
+    Step::from_syscall(stream.write(buf), stream.as_raw_fd(), Interest::Write)
+}
+
+impl AsyncRead for AsyncUnixStream {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<>> {
+        read_buf(&self.0, buf)
+    }
+}
+
+impl AsyncWrite for AsyncUnixStream {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<>> {
+        write(&self.0, buf)
+    }
+}
+
+/// Future for [`AsyncUnixStream::send_with_fds`].
+pub struct SendFdsFuture<'a, 'b> {
+    stream: &'a mut AsyncUnixStream,
+    data: &'b [u8],
+    fds: &'b [BorrowedFd<'b>],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendFdsFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.stream.poll_send_with_fds(self.data, self.fds) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.output = Some(Ok(n)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUnixStream::recv_with_fds`].
+pub struct RecvFdsFuture<'a, 'b> {
+    stream: &'a mut AsyncUnixStream,
+    buffer: &'b mut [u8],
+    max_fds: usize,
+    output: Option<io::Result<(usize, Vec<OwnedFd>)>>,
+}
+
+impl<'a, 'b> Future for RecvFdsFuture<'a, 'b> {
+    type Output = io::Result<(usize, Vec<OwnedFd>)>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.stream.poll_recv_with_fds(self.buffer, self.max_fds) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(received) => self.output = Some(Ok(received)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// The borrowed read half of an [`AsyncUnixStream`], created by [`AsyncUnixStream::split`].
+#[derive(Debug)]
+pub struct ReadHalf<'a>(&'a UnixStream);
+impl AsRawFd for ReadHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl ReadHalf<'_> {
+    pub fn read<'a>(&mut self, data: &'a mut [u8]) -> WithContext<ReadFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncReadExt::read(self, data), Operation::Read, fd)
+    }
+}
+
+impl<'a> AsyncRead for ReadHalf<'a> {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a>> {
+        read_buf(self.0, &mut ReadBuf::new(buf))
+    }
+
+    fn poll_read_buf(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut ReadBuf<'_>,
+    ) -> ReadStep<impl Iterator<Item = Waitable> + use<'a>> {
+        read_buf(self.0, buf)
+    }
+}
+
+/// The borrowed write half of an [`AsyncUnixStream`], created by [`AsyncUnixStream::split`].
+#[derive(Debug)]
+pub struct WriteHalf<'a>(&'a UnixStream);
+impl AsRawFd for WriteHalf<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl WriteHalf<'_> {
+    pub fn write<'a>(&mut self, data: &'a [u8]) -> WithContext<WriteFuture<'_, 'a, Self>> {
+        let fd = self.as_raw_fd();
+        WithContext::new(AsyncWriteExt::write(self, data), Operation::Write, fd)
+    }
+
+    /// Shut down the write side of the stream, signalling EOF to the peer.
+    /// The read half keeps working.
+    pub fn shutdown(&mut self) -> ShutdownFuture<'_> {
+        ShutdownFuture::new(self.0.as_fd(), Shutdown::Write)
+    }
+}
+
+impl<'a> AsyncWrite for WriteHalf<'a> {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> WriteStep<impl Iterator<Item = Waitable> + use<'a>> {
+        write(self.0, buf)
+    }
+}
+
+/// A listener bound to a path, which removes its socket file when dropped.
+///
+/// Only the socket the listener bound is removed. If something else took
+/// its place at the path since, such as the socket of a listener which
+/// replaced this one, it's left alone.
+pub struct AsyncUnixListener {
+    listener: UnixListener,
+    path: PathBuf,
+    /// The device and inode of the socket file, `None` if we couldn't tell.
+    socket_file: Option<(u64, u64)>,
+}
+
+impl AsRawFd for AsyncUnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl AsyncUnixListener {
+    /// Bind a listener to `path`.
+    ///
+    /// Fails with `AddrInUse` if a file exists at `path`, which is usually a
+    /// socket left behind by a process which didn't clean up. Use
+    /// [`AsyncUnixListener::builder`] to replace it instead.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::builder(path).build()
+    }
+
+    /// Configure how the listener is bound.
+    pub fn builder<P: AsRef<Path>>(path: P) -> UnixListenerBuilder {
+        UnixListenerBuilder {
+            path: path.as_ref().to_path_buf(),
+            unlink_on_bind: false,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn accept(&mut self) -> AcceptFuture<'_> {
+        AcceptFuture {
+            listener: self,
+            output: None,
+        }
+    }
+
+    /// A stream of incoming connections, which never ends.
+    ///
+    /// Errors from individual accepts are yielded as items, and the stream
+    /// keeps accepting afterwards.
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+            item: None,
+        }
+    }
+
+    fn poll_accept(&mut self) -> Step<iter::Once<Waitable>, (AsyncUnixStream, SocketAddr)> {
+        match Step::from_syscall(self.listener.accept(), self.as_raw_fd(), Interest::Read) {
+            Step::Done((stream, addr)) => match stream.set_nonblocking(true) {
+                Ok(()) => Step::Done((AsyncUnixStream(stream), addr)),
+                Err(e) => Step::Error(e),
+            },
+            Step::Pending(waitables) => Step::Pending(waitables),
+            Step::Error(e) => Step::Error(e),
+        }
+    }
+}
+
+impl Drop for AsyncUnixListener {
+    fn drop(&mut self) {
+        if self.socket_file.is_some() && file_id(&self.path) == self.socket_file {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The device and inode of the file at `path`, without following symlinks.
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Builder for an [`AsyncUnixListener`], created with [`AsyncUnixListener::builder`].
+#[derive(Debug)]
+pub struct UnixListenerBuilder {
+    path: PathBuf,
+    unlink_on_bind: bool,
+}
+
+impl UnixListenerBuilder {
+    /// Remove a socket file already at the path before binding. Defaults to
+    /// `false`.
+    ///
+    /// Only sockets are removed; any other kind of file still fails the bind.
+    /// This doesn't check whether another listener still uses the socket.
+    pub fn unlink_on_bind(mut self, unlink: bool) -> Self {
+        self.unlink_on_bind = unlink;
+        self
+    }
+
+    pub fn build(self) -> io::Result<AsyncUnixListener> {
+        match fs::symlink_metadata(&self.path) {
+            Ok(meta) if self.unlink_on_bind && meta.file_type().is_socket() => {
+                fs::remove_file(&self.path)?;
+            }
+            Ok(_) => {
+                let msg = format!("{} already exists", self.path.display());
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, msg));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(&self.path)?;
+        listener.set_nonblocking(true)?;
+        Ok(AsyncUnixListener {
+            listener,
+            socket_file: file_id(&self.path),
+            path: self.path,
+        })
+    }
+}
+
+pub struct AcceptFuture<'a> {
+    listener: &'a mut AsyncUnixListener,
+    output: Option<Result<(AsyncUnixStream, SocketAddr), error::Error>>,
+}
+
+impl<'a> Future for AcceptFuture<'a> {
+    type Output = Result<(AsyncUnixStream, SocketAddr), error::Error>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        if self.output.is_none() {
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done(accepted) => self.output = Some(Ok(accepted)),
+                Step::Error(e) => {
+                    let e = error::Error::new(Operation::Accept, e);
+                    self.output = Some(Err(e.with_fd(self.listener.as_raw_fd())));
+                }
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Stream for [`AsyncUnixListener::incoming`].
+pub struct Incoming<'a> {
+    listener: &'a mut AsyncUnixListener,
+    item: Option<io::Result<AsyncUnixStream>>,
+}
+
+impl<'a> Stream for Incoming<'a> {
+    type Item = io::Result<AsyncUnixStream>;
+
+    fn poll_next(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> + use<'a> {
+        let mut pending = None;
+        if self.item.is_none() {
+            match self.listener.poll_accept() {
+                Step::Pending(waitables) => pending = Some(waitables),
+                Step::Done((stream, _)) => self.item = Some(Ok(stream)),
+                Step::Error(e) => self.item = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take_next(&mut self) -> Option<Self::Item> {
+        self.item.take()
+    }
+}
+
+/// A Unix datagram socket.
+///
+/// A socket created with [`AsyncUnixDatagram::bind`] removes its socket file
+/// when dropped, like [`AsyncUnixListener`].
+pub struct AsyncUnixDatagram {
+    socket: UnixDatagram,
+    /// The socket file to remove on drop, if we created one.
+    path: Option<PathBuf>,
+}
+
+impl AsRawFd for AsyncUnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl AsyncUnixDatagram {
+    /// Bind a socket to `path`.
+    ///
+    /// Like [`AsyncUnixListener::bind`], this fails with `AddrInUse` if a
+    /// file exists at `path`.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let socket = UnixDatagram::bind(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AddrInUse => {
+                let msg = format!("{} already exists", path.display());
+                io::Error::new(io::ErrorKind::AddrInUse, msg)
+            }
+            _ => e,
+        })?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Create a socket which isn't bound to a path. It can send, but only
+    /// receive replies once connected.
+    pub fn unbound() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, path: None })
+    }
+
+    /// Create a pair of sockets connected to each other, with no socket
+    /// files involved.
+    pub fn pair() -> io::Result<(AsyncUnixDatagram, AsyncUnixDatagram)> {
+        let (a, b) = UnixDatagram::pair()?;
+        a.set_nonblocking(true)?;
+        b.set_nonblocking(true)?;
+        let a = Self {
+            socket: a,
+            path: None,
+        };
+        let b = Self {
+            socket: b,
+            path: None,
+        };
+        Ok((a, b))
+    }
+
+    /// Set the default destination for [`AsyncUnixDatagram::send`], and only
+    /// receive datagrams from `path` from now on.
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.socket.connect(path)
+    }
+
+    /// Borrow the underlying std socket.
+    ///
+    /// The socket is in nonblocking mode, and has to stay that way for the
+    /// futures on `AsyncUnixDatagram` to work.
+    pub fn as_std(&self) -> &UnixDatagram {
+        &self.socket
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Send `buf` as a single datagram to the socket at `target`, resolving
+    /// with the number of bytes sent.
+    pub fn send_to<'a>(&mut self, buf: &'a [u8], target: &'a Path) -> SendToFuture<'_, 'a> {
+        SendToFuture {
+            socket: self,
+            buffer: buf,
+            target,
+            output: None,
+        }
+    }
+
+    /// Receive a single datagram, resolving with its length and sender.
+    ///
+    /// If the datagram doesn't fit in `buf` the rest of it is discarded.
+    pub fn recv_from<'a>(&mut self, buf: &'a mut [u8]) -> RecvFromFuture<'_, 'a> {
+        RecvFromFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Send `buf` as a single datagram to the connected peer.
+    pub fn send<'a>(&mut self, buf: &'a [u8]) -> SendFuture<'_, 'a> {
+        SendFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    /// Receive a single datagram from the connected peer.
+    ///
+    /// If the datagram doesn't fit in `buf` the rest of it is discarded.
+    pub fn recv<'a>(&mut self, buf: &'a mut [u8]) -> RecvFuture<'_, 'a> {
+        RecvFuture {
+            socket: self,
+            buffer: buf,
+            output: None,
+        }
+    }
+
+    fn poll_send_to(&self, buf: &[u8], target: &Path) -> Step<iter::Once<Waitable>> {
+        let result = self.socket.send_to(buf, target);
+        Step::from_syscall(result, self.as_raw_fd(), Interest::Write)
+    }
+
+    fn poll_recv_from(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>, (usize, SocketAddr)> {
+        Step::from_syscall(self.socket.recv_from(buf), self.as_raw_fd(), Interest::Read)
+    }
+
+    fn poll_send(&self, buf: &[u8]) -> Step<iter::Once<Waitable>> {
+        Step::from_syscall(self.socket.send(buf), self.as_raw_fd(), Interest::Write)
+    }
+
+    fn poll_recv(&self, buf: &mut [u8]) -> Step<iter::Once<Waitable>> {
+        Step::from_syscall(self.socket.recv(buf), self.as_raw_fd(), Interest::Read)
+    }
+}
+
+impl Drop for AsyncUnixDatagram {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Future for [`AsyncUnixDatagram::send_to`].
+pub struct SendToFuture<'a, 'b> {
+    socket: &'a mut AsyncUnixDatagram,
+    buffer: &'b [u8],
+    target: &'b Path,
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendToFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_send_to(self.buffer, self.target) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(done) => self.output = Some(Ok(done)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUnixDatagram::recv_from`].
+pub struct RecvFromFuture<'a, 'b> {
+    socket: &'a mut AsyncUnixDatagram,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<(usize, SocketAddr)>>,
+}
+
+impl<'a, 'b> Future for RecvFromFuture<'a, 'b> {
+    type Output = io::Result<(usize, SocketAddr)>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_recv_from(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(done) => self.output = Some(Ok(done)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUnixDatagram::send`].
+pub struct SendFuture<'a, 'b> {
+    socket: &'a mut AsyncUnixDatagram,
+    buffer: &'b [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for SendFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_send(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(done) => self.output = Some(Ok(done)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+/// Future for [`AsyncUnixDatagram::recv`].
+pub struct RecvFuture<'a, 'b> {
+    socket: &'a mut AsyncUnixDatagram,
+    buffer: &'b mut [u8],
+    output: Option<io::Result<usize>>,
+}
+
+impl<'a, 'b> Future for RecvFuture<'a, 'b> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while self.output.is_none() {
+            match self.socket.poll_recv(self.buffer) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(done) => self.output = Some(Ok(done)),
+                Step::Error(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Step::Error(e) => self.output = Some(Err(e)),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(all(unix, feature = "net"))]
+
+//! Counts every allocation in the process, so it runs on its own: other
+//! tests in the same binary would allocate concurrently.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::io::{AsyncRead, AsyncWrite, Step};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Echoes whatever it reads back, until the peer hangs up.
+struct Echo {
+    stream: AsyncTcpStream,
+    buf: [u8; 64],
+    /// The bytes read which weren't written back yet.
+    unwritten: std::ops::Range<usize>,
+    done: bool,
+}
+
+impl Future for Echo {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let (mut reading, mut writing) = (None, None);
+        while !self.done && reading.is_none() && writing.is_none() {
+            if self.unwritten.is_empty() {
+                match self.stream.poll_read(ready, &mut self.buf) {
+                    Step::Pending(waitables) => reading = Some(waitables),
+                    Step::Done(0) | Step::Error(_) => self.done = true,
+                    Step::Done(n) => self.unwritten = 0..n,
+                }
+            } else {
+                match self
+                    .stream
+                    .poll_write(ready, &self.buf[self.unwritten.clone()])
+                {
+                    Step::Pending(waitables) => writing = Some(waitables),
+                    Step::Done(n) => self.unwritten.start += n,
+                    Step::Error(_) => self.done = true,
+                }
+            }
+        }
+        let reading = reading.into_iter().flatten();
+        reading.chain(writing.into_iter().flatten())
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.done.then_some(())
+    }
+}
+
+#[test]
+fn steady_state_turns_do_not_allocate() -> io::Result<()> {
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let mut poller = Poller::open()?;
+    let echo = poller.spawn(Echo {
+        stream: server,
+        buf: [0; 64],
+        unwritten: 0..0,
+        done: false,
+    });
+
+    let mut buf = [0; 4];
+    let mut roundtrip = |poller: &mut Poller| -> io::Result<()> {
+        poller.block_on(client.write(b"ping"))??;
+        let n = poller.block_on(client.read(&mut buf))??;
+        assert_eq!(&buf[..n], &b"ping"[..n]);
+        Ok(())
+    };
+    // Let every buffer grow to what an echo needs first.
+    for _ in 0..100 {
+        roundtrip(&mut poller)?;
+    }
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..1000 {
+        roundtrip(&mut poller)?;
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert_eq!(allocations, 0);
+
+    drop(client);
+    poller.block_on(echo)??;
+    Ok(())
+}
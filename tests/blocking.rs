@@ -0,0 +1,35 @@
+#![cfg(all(unix, feature = "blocking", feature = "time"))]
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use playground_future_2_0::runtime::{spawn_blocking, Poller};
+use playground_future_2_0::time;
+
+#[test]
+fn resolves_with_the_result() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let task = spawn_blocking(|| thread::current().name().map(str::to_owned));
+    assert_eq!(poller.block_on(task)?.as_deref(), Some("blocking"));
+    Ok(())
+}
+
+#[test]
+fn does_not_block_the_poller() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let start = Instant::now();
+    let task = spawn_blocking(|| thread::sleep(Duration::from_millis(200)));
+    let result = poller.block_on(time::timeout(Duration::from_millis(20), task))?;
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_millis(200));
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "oh no")]
+fn resumes_panics() {
+    let mut poller = Poller::open().unwrap();
+    let task = spawn_blocking(|| panic!("oh no"));
+    let _ = poller.block_on(task);
+}
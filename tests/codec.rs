@@ -0,0 +1,148 @@
+#![cfg(unix)]
+
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use playground_future_2_0::codec::{
+    Encoder, Framed, LengthDelimitedCodec, LineTooLong, LinesCodec,
+};
+use playground_future_2_0::io::mem::{duplex, DuplexStream};
+use playground_future_2_0::io::AsyncWriteExt;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::StreamExt;
+
+/// Write `pieces` to `writer` from another thread, a little apart so each
+/// arrives on its own, then hang up.
+fn trickle(mut writer: DuplexStream, pieces: Vec<Vec<u8>>) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut poller = Poller::open()?;
+        for piece in pieces {
+            thread::sleep(Duration::from_millis(10));
+            poller.block_on(writer.write_all(&piece))??;
+        }
+        Ok(())
+    })
+}
+
+/// A frame of `len` bytes which differ from one to the next.
+fn frame(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn length_delimited_frames_across_reads() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let frames = [
+        b"hello".to_vec(),
+        frame(100_000),
+        Vec::new(),
+        b"end".to_vec(),
+    ];
+    let mut encoded = Vec::new();
+    for frame in &frames {
+        LengthDelimitedCodec::new().encode(&frame[..], &mut encoded)?;
+    }
+    // The first prefix and payload are split, and the large frame takes
+    // many reads through the small duplex buffer.
+    let rest = encoded.split_off(6);
+    let pieces = vec![encoded[..2].to_vec(), encoded[2..].to_vec(), rest];
+
+    let (a, b) = duplex(64);
+    let writing = trickle(a, pieces);
+    let mut framed = Framed::new(b, LengthDelimitedCodec::new());
+    let mut received = Vec::new();
+    while let Some(frame) = poller.block_on(framed.next())? {
+        received.push(frame?);
+    }
+    writing.join().unwrap()?;
+    assert_eq!(received, frames);
+    Ok(())
+}
+
+#[test]
+fn length_delimited_prefix_size_and_order() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let codec = || {
+        LengthDelimitedCodec::new()
+            .length_field_length(2)
+            .little_endian()
+    };
+    let mut encoded = Vec::new();
+    codec().encode(&b"abc"[..], &mut encoded)?;
+    assert_eq!(encoded, b"\x03\x00abc");
+
+    // Room for a whole frame, as each is sent before it's received.
+    let (a, b) = duplex(1024);
+    let mut sender = Framed::new(a, codec());
+    let mut receiver = Framed::new(b, codec());
+    for frame in [frame(300), frame(1)] {
+        poller.block_on(sender.send(&frame[..]))??;
+        assert_eq!(poller.block_on(receiver.next())?.unwrap()?, frame);
+    }
+    // No more than the two bytes can count.
+    let e = poller
+        .block_on(sender.send(&frame(70_000)[..]))?
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn oversized_frames_are_rejected() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let codec = || LengthDelimitedCodec::new().max_frame_length(16);
+    let (a, b) = duplex(64);
+    let mut sender = Framed::new(a, codec());
+    let e = poller.block_on(sender.send(&frame(17)[..]))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // Only the prefix has to arrive for the receiver to give up, after
+    // which the stream ends.
+    let mut writer = sender.into_inner();
+    poller.block_on(writer.write_all(&17u32.to_be_bytes()))??;
+    let mut receiver = Framed::new(b, codec());
+    let e = poller.block_on(receiver.next())?.unwrap().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert!(poller.block_on(receiver.next())?.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn pipelined_lines_are_separate_frames() -> io::Result<()> {
+    use playground_future_2_0::tcp::AsyncTcpStream;
+
+    let mut poller = Poller::open()?;
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    // All three in a single write, so they share a segment.
+    poller.block_on(client.write_all(b"one\ntwo\r\nthree\n"))??;
+    poller.block_on(client.disconnect())??;
+    let mut lines = Framed::new(server, LinesCodec::new());
+    let mut received = Vec::new();
+    while let Some(line) = poller.block_on(lines.next())? {
+        received.push(line?);
+    }
+    assert_eq!(received, ["one", "two", "three"]);
+    Ok(())
+}
+
+#[test]
+fn a_flood_without_newlines_is_too_long() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, b) = duplex(64 * 1024);
+    let flood = thread::spawn(move || -> io::Result<()> {
+        Poller::open()?.block_on(a.write_all(&vec![b'x'; 10 * 1024 * 1024]))?
+    });
+    let mut lines = Framed::new(b, LinesCodec::new().max_length(1024));
+    let e = poller.block_on(lines.next())?.unwrap().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let too_long = e.get_ref().and_then(|e| e.downcast_ref::<LineTooLong>());
+    assert_eq!(too_long.map(|e| e.0), Some(1024));
+
+    // Giving up long before the end, the writer is left with nowhere to go.
+    drop(lines);
+    let e = flood.join().unwrap().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    Ok(())
+}
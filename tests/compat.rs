@@ -0,0 +1,95 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::future::Future as _;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::executor::block_on;
+use playground_future_2_0::compat::{compat, StdCompat};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+use playground_future_2_0::time::sleep;
+
+#[test]
+fn sleep_on_another_executor() -> io::Result<()> {
+    let start = Instant::now();
+    block_on(compat(sleep(Duration::from_millis(20))))?;
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    Ok(())
+}
+
+#[test]
+fn read_on_another_executor() -> io::Result<()> {
+    let (client, server) = AsyncTcpStream::pair()?;
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        client.as_std().write_all(b"hello")
+    });
+    let (buf, n) = block_on(compat(Arc::new(server).read_owned(vec![0; 5])))?;
+    assert_eq!(&buf[..n?], b"hello");
+    writer.join().unwrap()
+}
+
+#[test]
+fn dropping_a_pending_future() -> io::Result<()> {
+    let (_client, server) = AsyncTcpStream::pair()?;
+    let mut future = compat(Arc::new(server).read_owned(vec![0; 5]));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+    drop(future);
+    Ok(())
+}
+
+/// A oneshot channel whose sender wakes the receiver from another thread.
+#[derive(Default)]
+struct Oneshot {
+    value: Option<u32>,
+    waker: Option<Waker>,
+}
+
+struct Recv(Arc<Mutex<Oneshot>>);
+
+impl std::future::Future for Recv {
+    type Output = u32;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+        let mut oneshot = self.0.lock().unwrap();
+        match oneshot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                oneshot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[test]
+fn std_futures_woken_from_another_thread() -> io::Result<()> {
+    let oneshot = Arc::new(Mutex::new(Oneshot::default()));
+    let sender = oneshot.clone();
+    let thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        let mut oneshot = sender.lock().unwrap();
+        oneshot.value = Some(42);
+        if let Some(waker) = oneshot.waker.take() {
+            waker.wake();
+        }
+    });
+
+    let value = Poller::open()?.block_on(StdCompat::new(Recv(oneshot)))?;
+    assert_eq!(value, 42);
+    thread.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn async_blocks_run_on_the_poller() -> io::Result<()> {
+    let value = Poller::open()?.block_on(StdCompat::new(async { 1 + 1 }))?;
+    assert_eq!(value, 2);
+    Ok(())
+}
@@ -0,0 +1,85 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io;
+use std::time::Duration;
+
+use playground_future_2_0::codec::{Framed, LinesCodec};
+use playground_future_2_0::examples_support::chat::Room;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::StreamExt;
+use playground_future_2_0::tcp::AsyncTcpStream;
+
+#[test]
+fn chat_clients_hear_each_other_in_order() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let room = Room::new();
+    let mut clients = Vec::new();
+    for id in 1..=3 {
+        let (client, server) = AsyncTcpStream::pair()?;
+        let connection = room.connection(server);
+        assert_eq!(connection.id(), id);
+        poller.spawn(connection);
+        clients.push(Framed::new(client, LinesCodec::new()));
+    }
+    assert_eq!(room.len(), 3);
+
+    // Taking turns, everyone hears each line as soon as it's sent.
+    for round in 0..3 {
+        for sender in 0..3 {
+            let line = format!("round {round}");
+            poller.block_on(clients[sender].send(line.as_str()))??;
+            for receiver in (0..3).filter(|&receiver| receiver != sender) {
+                let heard = poller.block_on(clients[receiver].next())?.unwrap()?;
+                assert_eq!(heard, format!("{}: {line}", sender + 1));
+            }
+        }
+    }
+
+    // All talking at once, everyone hears each sender's lines in the order
+    // they were sent.
+    for client in &mut clients {
+        for n in 0..5 {
+            poller.block_on(client.send(format!("burst {n}")))??;
+        }
+    }
+    for (receiver, client) in clients.iter_mut().enumerate() {
+        let mut heard = Vec::new();
+        for _ in 0..10 {
+            heard.push(poller.block_on(client.next())?.unwrap()?);
+        }
+        for sender in (1..=3).filter(|&sender| sender != receiver + 1) {
+            let from: Vec<_> = heard
+                .iter()
+                .filter(|line| line.starts_with(&format!("{sender}: ")))
+                .cloned()
+                .collect();
+            let expected: Vec<_> = (0..5).map(|n| format!("{sender}: burst {n}")).collect();
+            assert_eq!(from, expected);
+        }
+    }
+
+    // Shutting down closes every connection, without anyone having heard
+    // their own lines back.
+    room.shutdown();
+    for client in &mut clients {
+        assert!(poller.block_on(client.next())?.is_none());
+    }
+    poller.run()?;
+    assert!(room.is_empty());
+    Ok(())
+}
+
+#[test]
+fn chat_drops_clients_which_go_quiet() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let room = Room::new().idle_timeout(Duration::from_millis(50));
+    let (client, server) = AsyncTcpStream::pair()?;
+    let connection = poller.spawn(room.connection(server));
+    let mut client = Framed::new(client, LinesCodec::new());
+
+    let e = poller.block_on(connection)?.unwrap().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert!(poller.block_on(client.next())?.is_none());
+    assert!(room.is_empty());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+#![cfg(all(target_os = "macos", not(feature = "threads-backend"), feature = "fs"))]
+
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs, io, process, thread};
+
+use playground_future_2_0::fs::{watch, File, WatchEvent, WatchKinds};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::StreamExt;
+
+/// A path in the temp dir which no other test or test run uses.
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("playground-future-{}-{name}", process::id()))
+}
+
+#[test]
+fn write_and_read_back() -> io::Result<()> {
+    let path = temp_path("write-and-read-back");
+    let data: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut poller = Poller::open()?;
+
+    let file = poller.block_on(File::create(&path))??;
+    assert_eq!(poller.block_on(file.write_at(&data, 0))??, data.len());
+    poller.block_on(file.sync_all())??;
+
+    let file = poller.block_on(File::open(&path))??;
+    let mut buf = [0; 16];
+    assert_eq!(poller.block_on(file.read_at(&mut buf, 1000))??, 16);
+    assert_eq!(buf, data[1000..1016]);
+    assert_eq!(poller.block_on(file.read_to_end())??, data);
+
+    fs::remove_file(&path)
+}
+
+#[test]
+fn watch_directory() -> io::Result<()> {
+    let dir = temp_path("watch-directory");
+    fs::create_dir(&dir)?;
+    let mut poller = Poller::open()?;
+    let mut watcher = watch(&dir, WatchKinds::WRITE | WatchKinds::DELETE)?;
+
+    let entry = dir.join("entry");
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        fs::write(&entry, b"hello").unwrap();
+    });
+    let event = poller.block_on(watcher.next())?;
+    assert_eq!(event.transpose()?, Some(WatchEvent::Write));
+    writer.join().unwrap();
+
+    fs::remove_dir_all(&dir)?;
+    let mut events = Vec::new();
+    while let Some(event) = poller.block_on(watcher.next())? {
+        events.push(event?);
+    }
+    assert_eq!(events.last(), Some(&WatchEvent::Delete));
+    Ok(())
+}
+
+/// Watch a fresh file for `kinds`, have `change` act on it from another
+/// thread, and collect the events up to the end of the stream.
+fn watch_file(
+    name: &str,
+    kinds: WatchKinds,
+    change: impl FnOnce(PathBuf) -> io::Result<()> + Send + 'static,
+) -> io::Result<Vec<WatchEvent>> {
+    let path = temp_path(name);
+    fs::write(&path, b"watched")?;
+    let mut poller = Poller::open()?;
+    let mut watcher = watch(&path, kinds)?;
+    let changing = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        change(path)
+    });
+    let mut events = Vec::new();
+    while let Some(event) = poller.block_on(watcher.next())? {
+        events.push(event?);
+    }
+    changing.join().unwrap()?;
+    Ok(events)
+}
+
+#[test]
+fn watch_file_until_deleted() -> io::Result<()> {
+    let events = watch_file("watch-file-until-deleted", WatchKinds::DELETE, |path| {
+        fs::remove_file(path)
+    })?;
+    assert_eq!(events, [WatchEvent::Delete]);
+    Ok(())
+}
+
+#[test]
+fn watch_file_until_renamed() -> io::Result<()> {
+    let renamed = temp_path("watch-file-until-renamed-after");
+    let to = renamed.clone();
+    let events = watch_file("watch-file-until-renamed", WatchKinds::RENAME, |path| {
+        fs::rename(path, to)
+    })?;
+    assert_eq!(events, [WatchEvent::Rename]);
+    fs::remove_file(renamed)
+}
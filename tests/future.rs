@@ -0,0 +1,106 @@
+#![cfg(all(unix, feature = "time"))]
+
+use std::cell::Cell;
+use std::io;
+use std::time::{Duration, Instant};
+
+use playground_future_2_0::future::{retry, Future, RetryPolicy, Waitable};
+use playground_future_2_0::runtime::Poller;
+
+/// An attempt which resolves right away, failing until `fails` attempts
+/// failed before it.
+struct Attempt(Option<io::Result<u32>>);
+
+impl Attempt {
+    fn new(attempts: &Cell<u32>, fails: u32, kind: io::ErrorKind) -> Self {
+        attempts.set(attempts.get() + 1);
+        match attempts.get() > fails {
+            true => Attempt(Some(Ok(attempts.get()))),
+            false => Attempt(Some(Err(kind.into()))),
+        }
+    }
+}
+
+impl Future for Attempt {
+    type Output = io::Result<u32>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        std::iter::empty()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.take()
+    }
+}
+
+#[test]
+fn retry_until_it_succeeds() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let attempts = Cell::new(0);
+    let policy = RetryPolicy::fixed(Duration::from_millis(50));
+    let start = Instant::now();
+    let make = || Attempt::new(&attempts, 2, io::ErrorKind::ConnectionRefused);
+    let output = poller.block_on(retry(policy, make))??;
+    assert_eq!(output, 3);
+    assert_eq!(attempts.get(), 3);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    Ok(())
+}
+
+#[test]
+fn exponential_backoff_doubles() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let attempts = Cell::new(0);
+    let policy = RetryPolicy::exponential(Duration::from_millis(40)).max_attempts(4);
+    let start = Instant::now();
+    let make = || Attempt::new(&attempts, 2, io::ErrorKind::ConnectionRefused);
+    poller.block_on(retry(policy, make))??;
+    assert_eq!(attempts.get(), 3);
+    // 40ms, then 80ms.
+    assert!(start.elapsed() >= Duration::from_millis(120));
+    Ok(())
+}
+
+#[test]
+fn jitter_waits_at_least_half() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let attempts = Cell::new(0);
+    let policy = RetryPolicy::exponential(Duration::from_millis(100))
+        .max_delay(Duration::from_millis(100))
+        .jitter(true);
+    let start = Instant::now();
+    let make = || Attempt::new(&attempts, 2, io::ErrorKind::ConnectionRefused);
+    poller.block_on(retry(policy, make))??;
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_millis(1000));
+    Ok(())
+}
+
+#[test]
+fn retry_gives_up_with_the_last_error() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let attempts = Cell::new(0);
+    let policy = RetryPolicy::fixed(Duration::from_millis(1)).max_attempts(2);
+    let make = || Attempt::new(&attempts, 5, io::ErrorKind::ConnectionRefused);
+    let e = poller.block_on(retry(policy, make))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(attempts.get(), 2);
+    Ok(())
+}
+
+#[test]
+fn other_errors_fail_fast() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let attempts = Cell::new(0);
+    let policy = RetryPolicy::fixed(Duration::from_secs(10));
+    let start = Instant::now();
+    let make = || Attempt::new(&attempts, 2, io::ErrorKind::PermissionDenied);
+    let retry =
+        retry(policy, make).retry_if(|e: &io::Error| e.kind() == io::ErrorKind::ConnectionRefused);
+    let e = poller.block_on(retry)?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(attempts.get(), 1);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    Ok(())
+}
@@ -0,0 +1,57 @@
+#![cfg(all(unix, feature = "futures-io", feature = "net"))]
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use futures::executor::block_on;
+use futures::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Cursor};
+use playground_future_2_0::compat::FuturesIoCompat;
+use playground_future_2_0::io::{AsyncReadExt, AsyncWriteExt as _};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+
+#[test]
+fn tcp_stream_roundtrips_through_a_line_codec() -> io::Result<()> {
+    let (client, server) = AsyncTcpStream::pair()?;
+    let echo = thread::spawn(move || {
+        let mut server = BufReader::new(FuturesIoCompat::new(server));
+        block_on(async {
+            let mut line = String::new();
+            while server.read_line(&mut line).await? > 0 {
+                server.get_mut().write_all(line.as_bytes()).await?;
+                line.clear();
+            }
+            io::Result::Ok(())
+        })
+    });
+
+    let mut client = BufReader::new(FuturesIoCompat::new(client));
+    block_on(async {
+        for message in ["hello\n", "world\n"] {
+            // Give the server time to wait for the line.
+            thread::sleep(Duration::from_millis(10));
+            client.get_mut().write_all(message.as_bytes()).await?;
+            let mut line = String::new();
+            client.read_line(&mut line).await?;
+            assert_eq!(line, message);
+        }
+        io::Result::Ok(())
+    })?;
+    drop(client);
+    echo.join().unwrap()
+}
+
+#[test]
+fn futures_io_objects_run_on_a_poller() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = FuturesIoCompat::new(Cursor::new(b"hello".to_vec()));
+    let mut buf = Vec::new();
+    let n = poller.block_on(reader.read_to_end(&mut buf))??;
+    assert_eq!(&buf[..n], b"hello");
+
+    let mut writer = FuturesIoCompat::new(Cursor::new(Vec::new()));
+    poller.block_on(writer.write_all(b"world"))??;
+    assert_eq!(writer.get_ref().get_ref(), b"world");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io;
+use std::net::SocketAddr;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use playground_future_2_0::http::{self, Response};
+use playground_future_2_0::io::{AsyncWriteExt, BufReader};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpListener;
+use playground_future_2_0::time;
+
+/// Answer the first request to the returned address with `response`, then
+/// hang up. The handle resolves with the request's head.
+fn respond(response: &'static [u8]) -> io::Result<(SocketAddr, JoinHandle<io::Result<String>>)> {
+    let mut listener = AsyncTcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let handle = thread::spawn(move || {
+        let mut poller = Poller::open()?;
+        let (stream, _) = poller.block_on(listener.accept())??;
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            if poller.block_on(reader.read_line(&mut head))?? == 0 {
+                break;
+            }
+        }
+        let mut stream = reader.into_inner();
+        poller.block_on(stream.write_all(response))??;
+        poller.block_on(stream.disconnect())??;
+        Ok(head)
+    });
+    Ok((addr, handle))
+}
+
+/// Get `path` from a responder answering with `response`, giving up after a
+/// second rather than hanging.
+fn get(path: &str, response: &'static [u8]) -> io::Result<(io::Result<Response>, String)> {
+    let (addr, responding) = respond(response)?;
+    let mut poller = Poller::open()?;
+    let get = http::get(&format!("http://{addr}{path}"));
+    let result = poller.block_on(time::timeout(Duration::from_secs(1), get))??;
+    Ok((result, responding.join().unwrap()?))
+}
+
+#[test]
+fn get_reads_a_content_length_body() -> io::Result<()> {
+    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Answer: 42\r\n\r\nhello";
+    let (result, request) = get("/index.html", response)?;
+    let response = result?;
+    assert_eq!((response.status, response.reason.as_str()), (200, "OK"));
+    assert_eq!(response.header("x-answer"), Some("42"));
+    assert_eq!(response.body, b"hello");
+
+    assert!(
+        request.starts_with("GET /index.html HTTP/1.1\r\n"),
+        "{request}"
+    );
+    assert!(request.contains("\r\nConnection: close\r\n"), "{request}");
+    Ok(())
+}
+
+#[test]
+fn get_reads_a_body_until_the_connection_closes() -> io::Result<()> {
+    let response = b"HTTP/1.1 404 Not Found\r\nServer: test\r\n\r\nnothing here\n";
+    let (result, request) = get("", response)?;
+    let response = result?;
+    assert_eq!(
+        (response.status, response.reason.as_str()),
+        (404, "Not Found")
+    );
+    assert_eq!(response.headers, [("Server".to_owned(), "test".to_owned())]);
+    assert_eq!(response.body, b"nothing here\n");
+    assert!(request.starts_with("GET / HTTP/1.1\r\n"), "{request}");
+    Ok(())
+}
+
+#[test]
+fn malformed_responses_fail_cleanly() -> io::Result<()> {
+    let cases: [(&[u8], io::ErrorKind); 5] = [
+        (b"HTTP/1.1 abc OK\r\n\r\n", io::ErrorKind::InvalidData),
+        (b"garbage\r\n\r\n", io::ErrorKind::InvalidData),
+        (
+            b"HTTP/1.1 200 OK\r\nno colon\r\n\r\n",
+            io::ErrorKind::InvalidData,
+        ),
+        // The connection closes before the head, or the body, is complete.
+        (b"HTTP/1.1 200", io::ErrorKind::UnexpectedEof),
+        (
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort",
+            io::ErrorKind::UnexpectedEof,
+        ),
+    ];
+    for (response, kind) in cases {
+        let (result, _) = get("/", response)?;
+        let shown = String::from_utf8_lossy(response);
+        match result {
+            Ok(_) => panic!("{shown:?} parsed"),
+            Err(e) => assert_eq!(e.kind(), kind, "{shown:?}: {e}"),
+        }
+    }
+    Ok(())
+}
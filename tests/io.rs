@@ -0,0 +1,992 @@
+#![cfg(unix)]
+
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, fmt, iter, thread};
+
+use playground_future_2_0::codec::{Framed, LinesCodec};
+use playground_future_2_0::future::Future;
+use playground_future_2_0::future::{Interest, Waitable};
+use playground_future_2_0::io::mem::{duplex, DuplexStream};
+use playground_future_2_0::io::{
+    copy, AsyncFd, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+    Direction, ReadBuf, ReadFuture, Step, Tee, Throttle, WriteFmtError, WriteFuture,
+};
+use playground_future_2_0::pipe::{pipe, PipeReader};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::StreamExt;
+
+#[test]
+fn async_fd_reads_and_writes() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (a, mut b) = UnixStream::pair()?;
+    a.set_nonblocking(true)?;
+    let mut fd = AsyncFd::new(a);
+
+    let mut buf = [0; 5];
+    b.write_all(b"hello")?;
+    poller.block_on(fd.readable())?;
+    let n = poller.block_on(fd.try_io(Interest::Read, |a| a.read(&mut buf)))??;
+    assert_eq!(&buf[..n], b"hello");
+
+    poller.block_on(fd.try_io(Interest::Write, |a| a.write(b"world")))??;
+    b.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"world");
+
+    let a = poller.block_on(fd.deregister())?;
+    assert!(!poller.is_registered(a.as_raw_fd()));
+    Ok(())
+}
+
+/// A reader with a tiny buffer over a pipe which holds `data`, and then
+/// ends.
+fn buffered(poller: &mut Poller, data: &[u8]) -> io::Result<BufReader<PipeReader>> {
+    let (reader, mut writer) = pipe()?;
+    poller.block_on(writer.write_all(data))??;
+    Ok(BufReader::with_capacity(4, reader))
+}
+
+#[test]
+fn read_until_across_refills() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // Delimiters at, after, and well past the end of the first fill.
+    let mut reader = buffered(&mut poller, b"abc\0defghij\0kl\0\0tail")?;
+    let mut records = Vec::new();
+    loop {
+        let mut record = Vec::new();
+        match poller.block_on(reader.read_until(0, &mut record))?? {
+            0 => break,
+            n => assert_eq!(n, record.len()),
+        }
+        records.push(record);
+    }
+    let expected: [&[u8]; 5] = [b"abc\0", b"defghij\0", b"kl\0", b"\0", b"tail"];
+    assert_eq!(records, expected);
+    Ok(())
+}
+
+#[test]
+fn read_until_appends_after_buffered_reads() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = buffered(&mut poller, b"xyz;rest;")?;
+    let mut first = [0; 2];
+    poller.block_on(reader.read_exact(&mut first))??;
+    assert_eq!(&first, b"xy");
+    let mut buf = b"> ".to_vec();
+    assert_eq!(poller.block_on(reader.read_until(b';', &mut buf))??, 2);
+    assert_eq!(buf, b"> z;");
+    Ok(())
+}
+
+#[test]
+fn skip_more_than_the_buffer_holds() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = buffered(&mut poller, b"0123456789abcdef")?;
+    let mut first = [0; 1];
+    poller.block_on(reader.read_exact(&mut first))??;
+    // Three bytes are buffered by now; skip those and ten more.
+    poller.block_on(reader.skip(13))??;
+    let mut rest = Vec::new();
+    poller.block_on(reader.read_until(b'!', &mut rest))??;
+    assert_eq!(rest, b"ef");
+    Ok(())
+}
+
+#[test]
+fn skip_past_the_end_fails() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = buffered(&mut poller, b"short")?;
+    let e = poller.block_on(reader.skip(6))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    poller.block_on(reader.skip(0))??;
+    Ok(())
+}
+
+/// A pipe over which `pieces` arrive one by one, a little apart, before it
+/// ends.
+fn trickle(
+    pieces: &'static [&'static [u8]],
+) -> io::Result<(PipeReader, thread::JoinHandle<io::Result<()>>)> {
+    let (reader, mut writer) = pipe()?;
+    let writing = thread::spawn(move || {
+        let mut poller = Poller::open()?;
+        for piece in pieces {
+            thread::sleep(Duration::from_millis(10));
+            poller.block_on(writer.write_all(piece))??;
+        }
+        Ok(())
+    });
+    Ok((reader, writing))
+}
+
+#[test]
+fn read_line_across_packet_boundaries() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // Buffers smaller than a line, and big enough for all of them.
+    for capacity in [4, 8 * 1024] {
+        let pieces: &[&[u8]] = &[b"first li", b"ne\nsecond", b" line\nthi", b"rd line", b"\n"];
+        let (reader, writing) = trickle(pieces)?;
+        let mut reader = BufReader::with_capacity(capacity, reader);
+        let mut line = String::new();
+        for expected in ["first line\n", "second line\n", "third line\n"] {
+            line.clear();
+            let n = poller.block_on(reader.read_line(&mut line))??;
+            assert_eq!((n, line.as_str()), (expected.len(), expected));
+        }
+        assert_eq!(poller.block_on(reader.read_line(&mut line))??, 0);
+        writing.join().unwrap()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn read_line_at_eof_returns_the_partial_line() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = buffered(&mut poller, b"done\nunfinished")?;
+    let mut line = String::new();
+    assert_eq!(poller.block_on(reader.read_line(&mut line))??, 5);
+    line.clear();
+    assert_eq!(poller.block_on(reader.read_line(&mut line))??, 10);
+    assert_eq!(line, "unfinished");
+    assert_eq!(poller.block_on(reader.read_line(&mut line))??, 0);
+    assert_eq!(line, "unfinished");
+    Ok(())
+}
+
+#[test]
+fn read_line_rejects_invalid_utf8_and_carries_on() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut reader = buffered(&mut poller, b"ok\nbad \xff\xfe\nfine\n")?;
+    let mut line = String::new();
+    poller.block_on(reader.read_line(&mut line))??;
+    // The bad line fails without touching what was read before it.
+    let e = poller.block_on(reader.read_line(&mut line))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(line, "ok\n");
+    poller.block_on(reader.read_line(&mut line))??;
+    assert_eq!(line, "ok\nfine\n");
+    Ok(())
+}
+
+/// Every line `reader` yields, until it ends.
+fn collect_lines(poller: &mut Poller, reader: BufReader<PipeReader>) -> io::Result<Vec<String>> {
+    let mut lines = reader.lines();
+    let mut collected = Vec::new();
+    while let Some(line) = poller.block_on(lines.next())? {
+        collected.push(line?);
+    }
+    Ok(collected)
+}
+
+#[test]
+fn lines_strip_newlines_and_outgrow_the_buffer() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // The buffer holds four bytes, so the second line spans many refills.
+    let reader = buffered(
+        &mut poller,
+        b"one\r\na line far longer than four bytes\n\nlast",
+    )?;
+    let lines = collect_lines(&mut poller, reader)?;
+    assert_eq!(
+        lines,
+        ["one", "a line far longer than four bytes", "", "last"]
+    );
+    Ok(())
+}
+
+#[test]
+fn lines_of_an_empty_stream_end_at_once() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let reader = buffered(&mut poller, b"")?;
+    assert!(collect_lines(&mut poller, reader)?.is_empty());
+    Ok(())
+}
+
+/// What an attempt at reading or writing through a [`Script`] may do.
+#[derive(Clone, Copy)]
+enum Turn {
+    Pending,
+    /// Transfer at most this many bytes.
+    Upto(usize),
+    Fail(io::ErrorKind),
+}
+
+/// Reads from `data` and writes into `written`, taking turns as scripted,
+/// then reaching EOF.
+struct Script {
+    turns: VecDeque<Turn>,
+    data: &'static [u8],
+    written: Vec<u8>,
+}
+
+impl Script {
+    fn new() -> Self {
+        use Turn::*;
+        Script {
+            turns: [
+                Upto(3),
+                Pending,
+                Upto(100),
+                Fail(io::ErrorKind::Interrupted),
+                Upto(1),
+            ]
+            .into(),
+            data: b"hello, tee!",
+            written: Vec::new(),
+        }
+    }
+
+    fn turn<W>(&mut self, pending: W, len: usize) -> Step<W> {
+        match self.turns.pop_front().unwrap_or(Turn::Upto(usize::MAX)) {
+            Turn::Pending => Step::Pending(pending),
+            Turn::Upto(most) => Step::Done(len.min(most)),
+            Turn::Fail(kind) => Step::Error(kind.into()),
+        }
+    }
+}
+
+impl AsyncRead for Script {
+    fn poll_read(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &mut [u8],
+    ) -> Step<impl Iterator<Item = Waitable> + use<>> {
+        let pending = iter::once(Waitable::Fd(7, Interest::Read));
+        let step = self.turn(pending, buf.len().min(self.data.len()));
+        if let Step::Done(n) = step {
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+        }
+        step
+    }
+}
+
+impl AsyncWrite for Script {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> Step<impl Iterator<Item = Waitable> + use<>> {
+        let step = self.turn(iter::once(Waitable::Fd(7, Interest::Write)), buf.len());
+        if let Step::Done(n) = step {
+            self.written.extend_from_slice(&buf[..n]);
+        }
+        step
+    }
+}
+
+/// What an attempt did, in a form which compares.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pending(Vec<Waitable>),
+    Done(Vec<u8>),
+    Error(io::ErrorKind),
+}
+
+fn outcome<W: Iterator<Item = Waitable>>(step: Step<W>, buf: &[u8]) -> Outcome {
+    match step {
+        Step::Pending(waitables) => Outcome::Pending(waitables.collect()),
+        Step::Done(n) => Outcome::Done(buf[..n].to_vec()),
+        Step::Error(e) => Outcome::Error(e.kind()),
+    }
+}
+
+/// Read until EOF in small pieces, some through `poll_read_buf`.
+fn read_all(reader: &mut impl AsyncRead) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for attempt in 0.. {
+        let mut buf = [0; 4];
+        let outcome = match attempt % 2 {
+            0 => outcome(reader.poll_read(&[], &mut buf), &buf),
+            _ => {
+                let mut read_buf = ReadBuf::new(&mut buf);
+                let step = reader.poll_read_buf(&[], &mut read_buf);
+                let filled = read_buf.filled().to_vec();
+                outcome(step, &filled)
+            }
+        };
+        let eof = outcome == Outcome::Done(Vec::new());
+        outcomes.push(outcome);
+        if eof {
+            return outcomes;
+        }
+    }
+    unreachable!()
+}
+
+/// Write all of `data`, however the writer takes it.
+fn write_all(writer: &mut impl AsyncWrite, mut data: &[u8]) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    while !data.is_empty() {
+        let outcome = outcome(writer.poll_write(&[], data), data);
+        if let Outcome::Done(written) = &outcome {
+            data = &data[written.len()..];
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+#[test]
+fn tee_forwards_reads_and_writes_unchanged() {
+    let mut plain = Script::new();
+    let expected = (read_all(&mut plain), write_all(&mut plain, b"0123456789"));
+
+    let mut seen = Vec::new();
+    let mut tee = Tee::new(Script::new(), |direction, _at, bytes: &[u8]| {
+        seen.push((direction, bytes.to_vec()));
+    });
+    let outcomes = (read_all(&mut tee), write_all(&mut tee, b"0123456789"));
+    assert_eq!(outcomes, expected);
+    assert_eq!(tee.get_ref().written, plain.written);
+
+    // The callback saw exactly what completed, EOF included.
+    let completed = |outcomes: Vec<Outcome>, direction| {
+        outcomes
+            .into_iter()
+            .filter_map(move |outcome| match outcome {
+                Outcome::Done(bytes) => Some((direction, bytes)),
+                _ => None,
+            })
+    };
+    let mut transferred: Vec<_> = completed(expected.0, Direction::Read).collect();
+    transferred.extend(completed(expected.1, Direction::Write));
+    drop(tee);
+    assert_eq!(seen, transferred);
+}
+
+#[test]
+fn tee_hexdumps() {
+    let mut dump = Vec::new();
+    let mut tee = Tee::hexdump(Script::new(), &mut dump);
+    read_all(&mut tee);
+    write_all(&mut tee, b"0123456789abcdefXYZ");
+    drop(tee);
+    let dump = String::from_utf8(dump).unwrap();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert!(lines[0].ends_with("] read 3 bytes"), "{dump}");
+    assert_eq!(
+        lines[1],
+        format!("00000000  68 65 6c{}  |hel|", "   ".repeat(13))
+    );
+    assert!(dump.contains("] read EOF\n"), "{dump}");
+    assert!(dump.contains("00000010  58 59 5a"), "{dump}");
+}
+
+#[test]
+fn slices_cursors_and_vecs_are_always_ready() -> io::Result<()> {
+    // `std::io::Read` and `Write` are in scope too, hence the paths.
+    let mut poller = Poller::open()?;
+    let mut read = Vec::new();
+    poller.block_on(AsyncReadExt::read_to_end(&mut &b"slice"[..], &mut read))??;
+    let mut cursor = Cursor::new(b"cursor".to_vec());
+    poller.block_on(AsyncReadExt::read_to_end(&mut cursor, &mut read))??;
+    assert_eq!(read, b"slicecursor");
+
+    let mut vec = Vec::new();
+    poller.block_on(AsyncWriteExt::write_all(&mut vec, b"vec"))??;
+    assert_eq!(vec, b"vec");
+    let mut array = [0; 4];
+    let mut cursor = Cursor::new(&mut array[..]);
+    assert_eq!(cursor.poll_write(&[], b"cursor").count_done(), 4);
+    assert_eq!(cursor.poll_write(&[], b"more").count_done(), 0);
+    assert_eq!(&array, b"curs");
+    Ok(())
+}
+
+trait CountDone {
+    fn count_done(self) -> usize;
+}
+
+impl<W: Iterator<Item = Waitable>> CountDone for Step<W> {
+    /// How many bytes a step which must not block transferred.
+    fn count_done(self) -> usize {
+        match self {
+            Step::Done(n) => n,
+            Step::Pending(waitables) => panic!("pending on {:?}", waitables.collect::<Vec<_>>()),
+            Step::Error(e) => panic!("{e}"),
+        }
+    }
+}
+
+/// Writes `data` to `stream`, then drops it.
+struct WriteAndClose {
+    stream: Option<DuplexStream>,
+    data: Vec<u8>,
+    written: usize,
+}
+
+impl Future for WriteAndClose {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        while let Some(stream) = &mut self.stream {
+            if self.written == self.data.len() {
+                self.stream = None;
+                break;
+            }
+            match stream.poll_write(ready, &self.data[self.written..]) {
+                Step::Pending(waitables) => {
+                    pending = Some(waitables);
+                    break;
+                }
+                Step::Done(n) => self.written += n,
+                Step::Error(e) => panic!("{e}"),
+            }
+        }
+        pending.into_iter().flatten()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.stream.is_none().then_some(())
+    }
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn duplex_copies_through_a_small_buffer() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (a, mut b) = duplex(16);
+    let writer = poller.spawn(WriteAndClose {
+        stream: Some(a),
+        data: data(64 * 1024),
+        written: 0,
+    });
+    let mut copied = Vec::new();
+    let n = poller.block_on(copy(&mut b, &mut copied))??;
+    assert_eq!(n, 64 * 1024);
+    assert!(copied == data(64 * 1024));
+    poller.block_on(writer)??;
+    Ok(())
+}
+
+#[test]
+fn duplex_works_across_threads() -> io::Result<()> {
+    let (mut a, mut b) = duplex(7);
+    let writer = thread::spawn(move || -> io::Result<()> {
+        Poller::open()?.block_on(a.write_all(&data(10_000)))??;
+        Ok(())
+    });
+    let mut poller = Poller::open()?;
+    let mut reader = BufReader::with_capacity(5, &mut b);
+    let mut read = Vec::new();
+    poller.block_on(reader.read_until(250, &mut read))??;
+    assert_eq!(read, data(251));
+    poller.block_on(reader.skip(9_000))??;
+    read.clear();
+    poller.block_on(reader.read_to_end(&mut read))??;
+    assert_eq!(read, data(10_000)[9_251..]);
+    writer.join().unwrap()
+}
+
+#[test]
+fn duplex_ends_when_either_side_drops() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, b) = duplex(4);
+    drop(b);
+    let e = poller.block_on(a.write_all(b"anyone?"))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(poller.block_on(a.read(&mut [0; 4]))??, 0);
+    Ok(())
+}
+
+#[test]
+fn duplex_carries_frames() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (a, b) = duplex(8);
+    let mut sender = Framed::new(a, LinesCodec::new());
+    let mut receiver = Framed::new(b, LinesCodec::new());
+    let lines = ["a line longer than the buffer", "", "short"];
+    let receiving = thread::spawn(move || -> io::Result<Vec<String>> {
+        let mut poller = Poller::open()?;
+        let mut received = Vec::new();
+        while let Some(line) = poller.block_on(receiver.next())? {
+            received.push(line?);
+        }
+        Ok(received)
+    });
+    for line in lines {
+        poller.block_on(sender.send(line))??;
+    }
+    drop(sender);
+    assert_eq!(receiving.join().unwrap()?, lines);
+    Ok(())
+}
+
+#[test]
+fn copy_flushes_through_nested_buffers() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let data = b"small enough to sit in either buffer";
+    let mut writer = BufWriter::new(BufWriter::new(Vec::new()));
+    let n = poller.block_on(copy(&mut &data[..], &mut writer))??;
+    assert_eq!(n, data.len() as u64);
+    assert!(writer.buffer().is_empty());
+    assert_eq!(writer.get_ref().get_ref(), data);
+
+    // Flushing through the trait reaches the inner buffer too.
+    poller.block_on(AsyncWriteExt::write_all(&mut writer, b"!"))??;
+    assert_eq!(writer.get_ref().get_ref().len(), data.len());
+    poller.block_on(AsyncWriteExt::flush(&mut writer))??;
+    assert_eq!(writer.get_ref().get_ref().last(), Some(&b'!'));
+    Ok(())
+}
+
+/// Accepts whatever it's given, counting the writes.
+#[derive(Default)]
+struct Counting {
+    writes: usize,
+    written: Vec<u8>,
+}
+
+impl AsyncWrite for Counting {
+    fn poll_write(
+        &mut self,
+        _ready: &[Waitable],
+        buf: &[u8],
+    ) -> Step<impl Iterator<Item = Waitable> + use<>> {
+        self.writes += 1;
+        self.written.extend_from_slice(buf);
+        Step::<iter::Empty<Waitable>>::Done(buf.len())
+    }
+}
+
+#[test]
+fn small_writes_wait_for_flush() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let messages: Vec<[u8; 10]> = (0..100).map(|i| [i as u8; 10]).collect();
+    let sent = messages.concat();
+
+    // The peer sees nothing until the flush, and then everything.
+    let (a, mut b) = duplex(4096);
+    let mut writer = BufWriter::new(a);
+    for message in &messages {
+        poller.block_on(writer.write_all(message))??;
+    }
+    let mut buf = [0; 1];
+    assert!(matches!(
+        outcome(b.poll_read(&[], &mut buf), &buf),
+        Outcome::Pending(_)
+    ));
+    poller.block_on(writer.flush())??;
+    let mut received = vec![0; sent.len()];
+    poller.block_on(b.read_exact(&mut received))??;
+    assert_eq!(received, sent);
+
+    // A write per buffer full rather than one per message: 64 bytes hold
+    // six of them.
+    for (capacity, most) in [(8 * 1024, 1), (64, messages.len().div_ceil(6))] {
+        let mut writer = BufWriter::with_capacity(capacity, Counting::default());
+        for message in &messages {
+            poller.block_on(writer.write_all(message))??;
+        }
+        poller.block_on(writer.flush())??;
+        let counting = writer.get_ref();
+        assert_eq!(counting.written, sent);
+        assert!(counting.writes <= most, "{} writes", counting.writes);
+    }
+    Ok(())
+}
+
+/// Assert that `elapsed` is within a tenth of a second below and a quarter of
+/// one above `expected`, as timers may fire late on a busy machine.
+fn assert_took(elapsed: Duration, expected: Duration) {
+    let range = expected - Duration::from_millis(100)..expected + Duration::from_millis(250);
+    assert!(
+        range.contains(&elapsed),
+        "took {elapsed:?}, not {expected:?}"
+    );
+}
+
+#[test]
+fn throttle_caps_throughput() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // 100KB at 50KB/s, from an empty bucket.
+    let mut writer = Throttle::new(Vec::new(), 50_000);
+    let start = Instant::now();
+    poller.block_on(writer.write_all(&data(100_000)))??;
+    assert_took(start.elapsed(), Duration::from_secs(2));
+    assert_eq!(writer.get_ref(), &data(100_000));
+
+    let source = data(25_000);
+    let mut reader = Throttle::new(&source[..], 50_000);
+    let mut read = Vec::new();
+    let start = Instant::now();
+    poller.block_on(reader.read_to_end(&mut read))??;
+    assert_took(start.elapsed(), Duration::from_millis(500));
+    assert_eq!(read, source);
+    Ok(())
+}
+
+/// Writes part of its output, then fails.
+struct FailsHalfway;
+
+impl fmt::Display for FailsHalfway {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("half")?;
+        Err(fmt::Error)
+    }
+}
+
+#[test]
+fn write_fmt_writes_what_format_would() -> io::Result<()> {
+    let lines = [
+        (200, "OK"),
+        (404, "Not Found"),
+        (503, "Service Unavailable"),
+    ];
+    let mut poller = Poller::open()?;
+    let mut expected = Vec::new();
+    for (status, reason) in lines {
+        let line = format!("HTTP/1.1 {status} {reason}\r\n");
+        poller.block_on(AsyncWriteExt::write_all(&mut expected, line.as_bytes()))??;
+    }
+
+    // Through a duplex too small to take a line at once.
+    let (mut a, mut b) = duplex(7);
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let mut poller = Poller::open()?;
+        for (status, reason) in lines {
+            poller.block_on(write!(a, "HTTP/1.1 {status} {reason}\r\n"))??;
+        }
+        Ok(())
+    });
+    let mut written = Vec::new();
+    poller.block_on(b.read_to_end(&mut written))??;
+    writer.join().unwrap()?;
+    assert_eq!(written, expected);
+
+    // Into the buffer of a `BufWriter`, past its capacity.
+    let mut buffered = BufWriter::with_capacity(16, Vec::new());
+    for (status, reason) in lines {
+        poller.block_on(write!(buffered, "HTTP/1.1 {status} {reason}\r\n"))??;
+    }
+    poller.block_on(buffered.write_str("done\r\n"))??;
+    expected.extend_from_slice(b"done\r\n");
+    poller.block_on(buffered.flush())??;
+    assert_eq!(buffered.get_ref(), &expected);
+    assert_eq!(buffered.capacity(), 16);
+    Ok(())
+}
+
+#[test]
+fn write_fmt_tells_formatting_errors_apart() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut raw = Vec::new();
+    // `Vec` is an `io::Write` too, which `write!` can't tell apart.
+    let args = format_args!("a{FailsHalfway}");
+    let err = poller
+        .block_on(AsyncWriteExt::write_fmt(&mut raw, args))?
+        .unwrap_err();
+    assert!(matches!(err, WriteFmtError::Format));
+    assert!(raw.is_empty());
+
+    let mut buffered = BufWriter::new(Vec::new());
+    poller.block_on(write!(buffered, "kept"))??;
+    let err = poller
+        .block_on(writeln!(buffered, "{FailsHalfway}"))?
+        .unwrap_err();
+    assert!(matches!(err, WriteFmtError::Format));
+    assert_eq!(buffered.buffer(), b"kept");
+
+    let (mut a, b) = duplex(4);
+    drop(b);
+    let err = poller.block_on(write!(a, "{}", 1))?.unwrap_err();
+    assert!(matches!(&err, WriteFmtError::Io(e) if e.kind() == io::ErrorKind::BrokenPipe));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::BrokenPipe);
+    Ok(())
+}
+
+/// Pass `data` from `writer` to `reader` with nothing but the generic read
+/// and write futures.
+fn pass<W, R>(
+    poller: &mut Poller,
+    writer: &mut W,
+    reader: &mut R,
+    data: &[u8],
+) -> io::Result<Vec<u8>>
+where
+    W: AsyncWrite,
+    R: AsyncRead,
+{
+    let mut written = 0;
+    while written < data.len() {
+        written += poller.block_on(WriteFuture::new(writer, &data[written..]))??;
+    }
+    let mut read = vec![0; data.len()];
+    let mut filled = 0;
+    while filled < read.len() {
+        match poller.block_on(ReadFuture::new(reader, &mut read[filled..]))?? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    Ok(read)
+}
+
+#[test]
+fn read_and_write_futures_take_any_implementor() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut reader, mut writer) = pipe()?;
+    let read = pass(&mut poller, &mut writer, &mut reader, b"through a pipe")?;
+    assert_eq!(read, b"through a pipe");
+
+    #[cfg(feature = "net")]
+    {
+        use playground_future_2_0::tcp::AsyncTcpStream;
+
+        let (mut a, mut b) = AsyncTcpStream::pair()?;
+        assert_eq!(pass(&mut poller, &mut a, &mut b, b"a to b")?, b"a to b");
+        let (mut a_read, mut a_write) = a.into_split();
+        let (mut b_read, mut b_write) = b.into_split();
+        let read = pass(&mut poller, &mut b_write, &mut a_read, b"owned halves")?;
+        assert_eq!(read, b"owned halves");
+        let read = pass(&mut poller, &mut a_write, &mut b_read, b"and back")?;
+        assert_eq!(read, b"and back");
+        let mut a = a_read.reunite(a_write).unwrap();
+        let mut b = b_read.reunite(b_write).unwrap();
+        let ((mut a_read, _), (_, mut b_write)) = (a.split(), b.split());
+        let read = pass(&mut poller, &mut b_write, &mut a_read, b"borrowed halves")?;
+        assert_eq!(read, b"borrowed halves");
+    }
+    Ok(())
+}
+
+#[test]
+fn read_future_ignores_unrelated_wakeups() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut reader, mut writer) = pipe()?;
+    let mut buf = [0; 8];
+    let mut read = ReadFuture::new(&mut reader, &mut buf);
+    let blocked_on: Vec<_> = read.poll(&[]).collect();
+    assert_eq!(read.attempts(), 1);
+
+    // Another fd being ready, or nothing at all, doesn't warrant a read.
+    let unrelated = [Waitable::Fd(writer.as_raw_fd(), Interest::Write)];
+    for ready in [&unrelated[..], &[], &unrelated] {
+        assert_eq!(read.poll(ready).collect::<Vec<_>>(), blocked_on);
+    }
+    assert_eq!(read.attempts(), 1);
+
+    poller.block_on(writer.write_all(b"hello"))??;
+    assert_eq!(read.poll(&blocked_on).count(), 0);
+    assert_eq!(read.attempts(), 2);
+    assert_eq!(read.take().unwrap()?, 5);
+    Ok(())
+}
+
+#[test]
+fn pipe_reads_end_once_the_writer_is_dropped() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut reader, mut writer) = pipe()?;
+    poller.block_on(writer.write_all(b"last words"))??;
+    drop(writer);
+    let mut buf = [0; 32];
+    let n = poller.block_on(reader.read(&mut buf))??;
+    assert_eq!(&buf[..n], b"last words");
+    assert_eq!(poller.block_on(reader.read(&mut buf))??, 0);
+    assert_eq!(poller.block_on(reader.read(&mut buf))??, 0);
+    Ok(())
+}
+
+#[test]
+fn pipe_writes_fail_once_the_reader_is_dropped() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (reader, mut writer) = pipe()?;
+    drop(reader);
+    // `SIGPIPE` is ignored, so this is an error rather than the end of the
+    // test process.
+    let e = poller
+        .block_on(writer.write(b"nobody listens"))?
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    let e = poller.block_on(writer.write_all(b"still"))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    Ok(())
+}
+
+#[test]
+fn pipe_carries_a_mebibyte_intact() -> io::Result<()> {
+    let (mut reader, mut writer) = pipe()?;
+    let sent: Vec<u8> = (0..1 << 20).map(|i: u32| (i % 251) as u8).collect();
+    let expected = sent.clone();
+    // Far more than the pipe buffer holds, so both ends block repeatedly.
+    let writing = thread::spawn(move || -> io::Result<()> {
+        Poller::open()?.block_on(writer.write_all(&sent))??;
+        Ok(())
+    });
+    let mut poller = Poller::open()?;
+    let mut received = Vec::new();
+    poller.block_on(reader.read_to_end(&mut received))??;
+    writing.join().unwrap()?;
+    assert!(
+        received == expected,
+        "{} bytes came through",
+        received.len()
+    );
+    Ok(())
+}
+
+/// Talk both ways between `a` and `b` through the extension traits, then
+/// have `close` close `a`, and read to EOF on `b`.
+fn converse<T, C>(poller: &mut Poller, mut a: T, mut b: T, close: C) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite,
+    C: FnOnce(&mut Poller, T) -> io::Result<()>,
+{
+    poller.block_on(a.write_all(b"hello"))??;
+    poller.block_on(a.flush())??;
+    let mut buf = [0; 5];
+    poller.block_on(b.read_exact(&mut buf))??;
+    assert_eq!(&buf, b"hello");
+
+    assert_eq!(poller.block_on(b.write(b"!"))??, 1);
+    assert_eq!(poller.block_on(a.read(&mut buf))??, 1);
+    assert_eq!(buf[0], b'!');
+
+    poller.block_on(a.write_all(b"bye"))??;
+    close(poller, a)?;
+    let mut rest = Vec::new();
+    assert_eq!(poller.block_on(b.read_to_end(&mut rest))??, 3);
+    assert_eq!(rest, b"bye");
+    Ok(())
+}
+
+#[test]
+fn extension_futures_work_on_any_implementor() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (a, b) = duplex(64);
+    converse(&mut poller, a, b, |_, a| {
+        drop(a);
+        Ok(())
+    })?;
+
+    #[cfg(feature = "net")]
+    {
+        use playground_future_2_0::tcp::AsyncTcpStream;
+
+        let (a, b) = AsyncTcpStream::pair()?;
+        converse(&mut poller, a, b, |poller, a| {
+            poller.block_on(a.disconnect())??;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// `len` bytes of xorshift noise, from a nonzero `seed`.
+#[cfg(feature = "net")]
+fn noise(len: usize, mut seed: u64) -> Vec<u8> {
+    let mut noise = Vec::with_capacity(len + 8);
+    while noise.len() < len {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        noise.extend_from_slice(&seed.to_le_bytes());
+    }
+    noise.truncate(len);
+    noise
+}
+
+/// FNV-1a, to compare a lot of data by.
+#[cfg(feature = "net")]
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn copy_moves_a_megabyte_of_noise_intact() -> io::Result<()> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use playground_future_2_0::unix::AsyncUnixStream;
+
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let seed = since_epoch.as_nanos() as u64 | 1;
+    let sent = noise(1 << 20, seed);
+    // The ends the threads use block, and the copy goes between the others.
+    let blocking = |stream: AsyncUnixStream| -> io::Result<UnixStream> {
+        let stream = stream.as_std().try_clone()?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    };
+    let (source, mut from) = AsyncUnixStream::pair()?;
+    let (mut to, sink) = AsyncUnixStream::pair()?;
+    let (mut source, mut sink) = (blocking(source)?, blocking(sink)?);
+    let writing = thread::spawn({
+        let sent = sent.clone();
+        move || source.write_all(&sent)
+    });
+    let reading = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut received = Vec::new();
+        sink.read_to_end(&mut received)?;
+        Ok(received)
+    });
+
+    let mut poller = Poller::open()?;
+    let copied = poller.block_on(copy(&mut from, &mut to))??;
+    drop(to);
+    writing.join().unwrap()?;
+    let received = reading.join().unwrap()?;
+    assert_eq!(copied, sent.len() as u64, "seed {seed}");
+    assert_eq!(received.len(), sent.len(), "seed {seed}");
+    assert_eq!(checksum(&received), checksum(&sent), "seed {seed}");
+    Ok(())
+}
+
+#[test]
+fn stdin_reads_a_pipe_as_it_fills() -> io::Result<()> {
+    // The child's stdin is a pipe dup'd onto fd 0, which this process's
+    // stdin can't be made into without upsetting the harness.
+    const CHILD: &str = "PLAYGROUND_STDIN_CHILD";
+    if env::var_os(CHILD).is_none() {
+        let mut child = Command::new(env::current_exe()?)
+            .args(["stdin_reads_a_pipe_as_it_fills", "--exact"])
+            .env(CHILD, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut writer = child.stdin.take().unwrap();
+        for piece in ["hello", " from", " the parent\n"] {
+            thread::sleep(Duration::from_millis(20));
+            writer.write_all(piece.as_bytes())?;
+        }
+        drop(writer);
+        let output = child.wait_with_output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"), "{stdout}");
+        return Ok(());
+    }
+
+    let mut poller = Poller::open()?;
+    let mut stdin = playground_future_2_0::io::stdin()?;
+    let mut received = Vec::new();
+    let mut reads = 0;
+    let mut buf = [0; 64];
+    loop {
+        match poller.block_on(stdin.read(&mut buf))?? {
+            0 => break,
+            n => received.extend_from_slice(&buf[..n]),
+        }
+        reads += 1;
+    }
+    assert_eq!(received, b"hello from the parent\n");
+    // Each piece was read as it arrived rather than all at the end.
+    assert!(reads > 1, "{reads} reads");
+
+    // The pipe is blocking again once the handle is gone.
+    drop(stdin);
+    let flags = unsafe { libc::fcntl(0, libc::F_GETFL) };
+    assert_eq!(flags & libc::O_NONBLOCK, 0);
+    Ok(())
+}
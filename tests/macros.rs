@@ -0,0 +1,32 @@
+#![cfg(all(unix, feature = "macros"))]
+
+use std::time::Duration;
+
+use playground_future_2_0::future::IntoFuture;
+use playground_future_2_0::time::{sleep, timeout, Elapsed, Sleep};
+
+#[playground_future_2_0::test]
+fn tests_run_the_future_they_return() -> impl IntoFuture<Output = ()> {
+    sleep(Duration::from_millis(10))
+}
+
+#[playground_future_2_0::test]
+fn tests_return_its_output() -> impl IntoFuture<Output = Result<(), Elapsed>> {
+    timeout(Duration::from_secs(1), sleep(Duration::from_millis(10)))
+}
+
+#[playground_future_2_0::test]
+fn tests_return_futures_by_name() -> Sleep {
+    sleep(Duration::from_millis(10))
+}
+
+#[playground_future_2_0::test(timeout_secs = 1)]
+#[should_panic = "the test timed out after 1s"]
+fn hung_tests_time_out() -> impl IntoFuture<Output = ()> {
+    sleep(Duration::from_secs(3600))
+}
+
+#[test]
+fn wrong_signatures_fail_to_compile() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
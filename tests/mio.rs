@@ -0,0 +1,36 @@
+#![cfg(all(unix, feature = "mio-compat"))]
+
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use playground_future_2_0::future::Interest;
+use playground_future_2_0::io::AsyncFd;
+use playground_future_2_0::runtime::Poller;
+
+#[test]
+fn mio_streams_wait_on_the_poller() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let stream = mio::net::TcpStream::connect(listener.local_addr()?)?;
+    let (mut peer, _) = listener.accept()?;
+    let mut fd = AsyncFd::from_mio(stream);
+
+    let writer = thread::spawn(move || {
+        // Give the poller time to wait for the stream first.
+        thread::sleep(Duration::from_millis(10));
+        peer.write_all(b"hello")?;
+        io::Result::Ok(peer)
+    });
+    let ready = poller.block_on(fd.ready(mio::Interest::READABLE))?;
+    assert!(ready.is_readable());
+    let mut buf = [0; 5];
+    let n = poller.block_on(fd.try_io(Interest::Read, |stream| stream.read(&mut buf)))??;
+    assert_eq!(&buf[..n], b"hello");
+
+    let ready = poller.block_on(fd.ready(mio::Interest::READABLE | mio::Interest::WRITABLE))?;
+    assert!(ready.is_writable());
+    writer.join().unwrap()?;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::runtime::{MultiThread, Poller, Runtime};
+use playground_future_2_0::tcp::{AsyncTcpStream, ReadOwnedFuture, WriteOwnedFuture};
+use playground_future_2_0::time::sleep;
+
+/// Echoes one read back to the peer.
+enum Echo {
+    Reading(Arc<AsyncTcpStream>, ReadOwnedFuture),
+    Writing(WriteOwnedFuture),
+    Done,
+}
+
+fn echo(stream: AsyncTcpStream) -> Echo {
+    let stream = Arc::new(stream);
+    Echo::Reading(stream.clone(), stream.read_owned(vec![0; 64]))
+}
+
+impl Future for Echo {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut pending = None;
+        loop {
+            match self {
+                Echo::Reading(stream, read) => {
+                    let waitable = read.poll(ready).next();
+                    match read.take() {
+                        Some((mut buf, Ok(n))) => {
+                            buf.truncate(n);
+                            let write = stream.clone().write_owned(buf);
+                            *self = Echo::Writing(write);
+                        }
+                        Some((_, Err(_))) => *self = Echo::Done,
+                        None => pending = waitable,
+                    }
+                }
+                Echo::Writing(write) => {
+                    let waitable = write.poll(ready).next();
+                    match write.take() {
+                        Some(_) => *self = Echo::Done,
+                        None => pending = waitable,
+                    }
+                }
+                Echo::Done => {}
+            }
+            if pending.is_some() || matches!(self, Echo::Done) {
+                break;
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        matches!(self, Echo::Done).then_some(())
+    }
+}
+
+#[test]
+fn spawns_go_round_robin() -> io::Result<()> {
+    let runtime = MultiThread::new(4)?;
+    let handles = (0..8)
+        .map(|_| runtime.spawn(sleep(Duration::from_millis(10))))
+        .collect::<Vec<_>>();
+    let mut poller = Poller::open()?;
+    for handle in handles {
+        poller.block_on(handle)??;
+    }
+    assert_eq!(runtime.worker_tasks(), vec![2; 4]);
+    runtime.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn serve_echoes_on_every_worker() -> io::Result<()> {
+    let runtime = Runtime::new_multi_thread(4)?;
+    let addr = runtime.serve(SocketAddr::from(([127, 0, 0, 1], 0)), echo)?;
+
+    let clients = (0..200)
+        .map(|n| {
+            thread::spawn(move || -> io::Result<()> {
+                let mut stream = TcpStream::connect(addr)?;
+                let message = format!("hello {n}");
+                stream.write_all(message.as_bytes())?;
+                let mut buf = vec![0; message.len()];
+                stream.read_exact(&mut buf)?;
+                assert_eq!(buf, message.as_bytes());
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for client in clients {
+        client.join().unwrap()?;
+    }
+
+    let tasks = runtime.worker_tasks();
+    assert_eq!(tasks.len(), 4);
+    assert_eq!(tasks.iter().sum::<u64>(), 200, "{tasks:?}");
+    runtime.shutdown()?;
+    Ok(())
+}
+
+#[test]
+fn zero_workers_is_an_error() {
+    let result = MultiThread::new(0);
+    assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::InvalidInput));
+}
@@ -0,0 +1,252 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use playground_future_2_0::blocking::Pool;
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Step};
+use playground_future_2_0::net::{
+    checksum, echo, resolve, serve, AsyncIcmpSocket, EchoPacket, IdleTimeout,
+};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::sync::Event;
+use playground_future_2_0::tcp::{AsyncTcpListener, AsyncTcpStream};
+
+#[test]
+fn literals_skip_the_pool() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let spawned = Pool::shared().spawned();
+
+    let addrs = poller.block_on(resolve("127.0.0.1", 80))??;
+    assert_eq!(addrs.len(), 1);
+    assert_eq!(addrs[0].ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    let addrs = poller.block_on(resolve("[::1]", 443))??;
+    assert_eq!(addrs[0].ip(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+    assert_eq!(addrs[0].port(), 443);
+
+    assert_eq!(Pool::shared().spawned(), spawned);
+    Ok(())
+}
+
+#[test]
+fn resolve_localhost() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let spawned = Pool::shared().spawned();
+
+    let addrs = poller.block_on(resolve("localhost", 8080))??;
+    assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    assert!(addrs.iter().all(|addr| addr.port() == 8080));
+
+    assert!(Pool::shared().spawned() > spawned);
+    Ok(())
+}
+
+#[test]
+fn echo_packet_roundtrip() -> io::Result<()> {
+    let packet = EchoPacket::request(0x1234, 7, b"ping");
+    let encoded = packet.encode();
+    assert_eq!(encoded[0], 8);
+    assert_eq!(checksum(&encoded), 0);
+    assert_eq!(EchoPacket::decode(&encoded)?, packet);
+
+    // Raw sockets hand over the IPv4 header too.
+    let mut with_header = vec![0x45; 1];
+    with_header.resize(20, 0);
+    with_header.extend_from_slice(&encoded);
+    assert_eq!(EchoPacket::decode(&with_header)?, packet);
+
+    let mut corrupted = encoded;
+    corrupted[9] ^= 1;
+    let err = EchoPacket::decode(&corrupted).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs unprivileged ICMP sockets"]
+fn ping_localhost() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let socket = AsyncIcmpSocket::new()?;
+    let rtt = poller.block_on(socket.ping(Ipv4Addr::LOCALHOST, 1, Duration::from_secs(2)))?;
+    assert!(rtt.expect("ping timed out")? < Duration::from_secs(2));
+    Ok(())
+}
+
+/// Does `roundtrips` round trips of a message of its own, then sets `done`
+/// if it's the last of the clients still going.
+struct Client {
+    stream: AsyncTcpStream,
+    message: Vec<u8>,
+    /// How much of the message was written, then read back, this round trip.
+    written: usize,
+    read: Vec<u8>,
+    roundtrips: usize,
+    remaining: Arc<AtomicUsize>,
+    done: Event,
+    output: Option<io::Result<()>>,
+}
+
+impl Future for Client {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let (mut reading, mut writing) = (None, None);
+        while self.output.is_none() && reading.is_none() && writing.is_none() {
+            if self.roundtrips == 0 {
+                if self.remaining.fetch_sub(1, Ordering::Relaxed) == 1 {
+                    self.done.set();
+                }
+                self.output = Some(Ok(()));
+            } else if self.written < self.message.len() {
+                match self.stream.poll_write(ready, &self.message[self.written..]) {
+                    Step::Pending(waitables) => writing = Some(waitables),
+                    Step::Done(n) => self.written += n,
+                    Step::Error(e) => self.output = Some(Err(e)),
+                }
+            } else if self.read.len() < self.message.len() {
+                let mut buf = [0; 64];
+                let want = self.message.len() - self.read.len();
+                match self.stream.poll_read(ready, &mut buf[..want]) {
+                    Step::Pending(waitables) => reading = Some(waitables),
+                    Step::Done(0) => self.output = Some(Err(io::ErrorKind::UnexpectedEof.into())),
+                    Step::Done(n) => self.read.extend_from_slice(&buf[..n]),
+                    Step::Error(e) => self.output = Some(Err(e)),
+                }
+            } else {
+                assert_eq!(self.read, self.message);
+                self.read.clear();
+                self.written = 0;
+                self.roundtrips -= 1;
+            }
+        }
+        let reading = reading.into_iter().flatten();
+        reading.chain(writing.into_iter().flatten())
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+#[test]
+fn serve_echoes_to_concurrent_clients() -> io::Result<()> {
+    const CLIENTS: usize = 50;
+    let mut poller = Poller::open()?;
+    let listener = AsyncTcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let done = Event::new();
+    let server = serve(listener, echo)
+        .max_connections(16)
+        .idle_timeout(Duration::from_secs(10))
+        .shutdown_on(done.clone());
+    let server = poller.spawn(server);
+
+    let remaining = Arc::new(AtomicUsize::new(CLIENTS));
+    let mut clients = Vec::new();
+    for client in 0..CLIENTS {
+        clients.push(poller.spawn(Client {
+            stream: AsyncTcpStream::connect(addr)?,
+            message: format!("hello from client {client}").into_bytes(),
+            written: 0,
+            read: Vec::new(),
+            roundtrips: 10,
+            remaining: remaining.clone(),
+            done: done.clone(),
+            output: None,
+        }));
+    }
+    poller.run()?;
+
+    for client in clients {
+        poller.block_on(client)???;
+    }
+    poller.block_on(server)???;
+    Ok(())
+}
+
+#[test]
+fn idle_timeout_closes_connections_which_go_silent() -> io::Result<()> {
+    const IDLE: Duration = Duration::from_millis(300);
+    let mut poller = Poller::open()?;
+    let (active, active_server) = AsyncTcpStream::pair()?;
+    let (silent, silent_server) = AsyncTcpStream::pair()?;
+    let mut active = poller.block_on(active.into_std())??;
+    let mut silent = poller.block_on(silent.into_std())??;
+    // Both peers send a byte every 100ms; the silent one stops after five,
+    // but keeps the connection open for well past the idle window.
+    let peers = [
+        thread::spawn(move || -> io::Result<()> {
+            for _ in 0..15 {
+                active.write_all(b"x")?;
+                thread::sleep(Duration::from_millis(100));
+            }
+            Ok(())
+        }),
+        thread::spawn(move || -> io::Result<()> {
+            for _ in 0..5 {
+                silent.write_all(b"x")?;
+                thread::sleep(Duration::from_millis(100));
+            }
+            thread::sleep(Duration::from_millis(1500));
+            Ok(())
+        }),
+    ];
+
+    let mut active_server = IdleTimeout::new(active_server, IDLE);
+    let mut silent_server = IdleTimeout::new(silent_server, IDLE);
+    let (mut active_read, mut silent_read) = (Vec::new(), Vec::new());
+    let start = Instant::now();
+    let (active_done, silent_done) = poller.scope(|s| {
+        let active = s.spawn(AsyncReadExt::read_to_end(
+            &mut active_server,
+            &mut active_read,
+        ));
+        let silent = s.spawn(Timed {
+            future: AsyncReadExt::read_to_end(&mut silent_server, &mut silent_read),
+            start,
+        });
+        (active, silent)
+    })?;
+    assert_eq!(poller.block_on(active_done)???, 15);
+    assert!(!active_server.is_closed());
+
+    let (read, failed_at) = poller.block_on(silent_done)??;
+    assert_eq!(read.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    // The last byte arrives at about 400ms, so the read fails at about
+    // 700ms, long before the peer hangs up.
+    assert!(failed_at > Duration::from_millis(600), "{failed_at:?}");
+    assert!(failed_at < Duration::from_millis(2000), "{failed_at:?}");
+    assert_eq!(silent_read, b"xxxxx");
+    assert!(silent_server.is_closed());
+    // Everything after fails too, writes included.
+    let write = poller.block_on(AsyncWriteExt::write_all(&mut silent_server, b"late"))?;
+    assert_eq!(write.unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+    for peer in peers {
+        peer.join().unwrap()?;
+    }
+    Ok(())
+}
+
+/// Resolves with the output of `future`, and how long after `start` it did.
+struct Timed<F> {
+    future: F,
+    start: Instant,
+}
+
+impl<F: Future> Future for Timed<F> {
+    type Output = (F::Output, Duration);
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.future.poll(ready)
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        Some((self.future.take()?, self.start.elapsed()))
+    }
+}
@@ -0,0 +1,144 @@
+#![cfg(all(unix, feature = "process", feature = "time"))]
+
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::io::AsyncReadExt;
+use playground_future_2_0::process::Command;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::time;
+
+/// Wait for all of `futures` at once.
+struct JoinAll<F: Future> {
+    futures: Vec<F>,
+    outputs: Vec<Option<F::Output>>,
+}
+
+fn join_all<F: Future>(futures: Vec<F>) -> JoinAll<F> {
+    let outputs = futures.iter().map(|_| None).collect();
+    JoinAll { futures, outputs }
+}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waiting = Vec::new();
+        for (future, output) in self.futures.iter_mut().zip(&mut self.outputs) {
+            if output.is_none() {
+                let len = waiting.len();
+                waiting.extend(future.poll(ready));
+                if waiting.len() == len {
+                    *output = future.take();
+                }
+            }
+        }
+        waiting.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match self.outputs.iter().all(Option::is_some) {
+            true => Some(self.outputs.iter_mut().map(|o| o.take().unwrap()).collect()),
+            false => None,
+        }
+    }
+}
+
+#[test]
+fn wait_for_many_children() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut children = (0..50)
+        .map(|i| {
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("exit {}", i % 3))
+                .spawn()
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let waits = children.iter_mut().map(|child| child.wait()).collect();
+    let statuses = poller.block_on(join_all(waits))?;
+    for (i, status) in statuses.into_iter().enumerate() {
+        assert_eq!(status?.code(), Some(i as i32 % 3));
+    }
+    Ok(())
+}
+
+#[test]
+fn output_collects_stdout_and_stderr() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let output = poller.block_on(
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo out; echo err >&2; exit 3")
+            .output(),
+    )??;
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"out\n");
+    assert_eq!(output.stderr, b"err\n");
+    Ok(())
+}
+
+#[test]
+fn output_drains_both_pipes_at_once() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // Each burst is well beyond a pipe buffer, so the child blocks on
+    // whichever pipe isn't drained, in turn.
+    let output = poller.block_on(
+        Command::new("sh")
+            .arg("-c")
+            .arg(
+                "spew() { head -c 200000 /dev/zero | tr '\\0' \"$1\"; }
+                 spew e >&2; spew o; spew e >&2",
+            )
+            .output(),
+    )??;
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), 200_000);
+    assert!(output.stdout.iter().all(|&b| b == b'o'));
+    assert_eq!(output.stderr.len(), 400_000);
+    assert!(output.stderr.iter().all(|&b| b == b'e'));
+    Ok(())
+}
+
+#[test]
+fn read_to_end_after_kill() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg("echo started; exec sleep 30")
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+
+    let mut line = [0; 8];
+    poller.block_on(stdout.read_exact(&mut line))??;
+    assert_eq!(&line, b"started\n");
+
+    child.kill()?;
+    poller.block_on(stdout.hangup())?;
+    assert!(stdout.is_closed());
+
+    let mut rest = Vec::new();
+    let read = time::timeout(Duration::from_secs(5), stdout.read_to_end(&mut rest));
+    assert_eq!(poller.block_on(read)?.expect("read_to_end hung")?, 0);
+    assert!(!poller.block_on(child.wait())??.success());
+    Ok(())
+}
+
+#[test]
+fn a_timed_out_wait_with_kill_on_drop_leaves_nothing_behind() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut child = Command::new("sleep").arg("10").kill_on_drop(true).spawn()?;
+    let pid = child.id() as libc::pid_t;
+    let wait = time::timeout(Duration::from_millis(50), child.wait());
+    assert!(poller.block_on(wait)?.is_err(), "sleep 10 exited early");
+
+    // Killed and reaped, so not even a zombie is left with the pid.
+    drop(child);
+    assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+    assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ESRCH));
+    Ok(())
+}
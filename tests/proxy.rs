@@ -0,0 +1,111 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io;
+use std::net::SocketAddr;
+use std::thread::{self, JoinHandle};
+
+use playground_future_2_0::io::{AsyncReadExt, AsyncWriteExt};
+use playground_future_2_0::proxy::socks5_connect;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::{AsyncTcpListener, AsyncTcpStream};
+
+/// The CONNECT request a SOCKS5 proxy received: the address type, the raw
+/// address, and the port.
+type Request = (u8, Vec<u8>, u16);
+
+/// Read exactly `len` bytes from `stream`.
+fn read_vec(poller: &mut Poller, stream: &mut AsyncTcpStream, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    poller.block_on(stream.read_exact(&mut buf))??;
+    Ok(buf)
+}
+
+/// A SOCKS5 proxy for one client, which answers its CONNECT request with
+/// `reply`. If that reports success it then echoes everything back, as if
+/// the target were an echo server. The handle resolves with the request.
+fn proxy(reply: &'static [u8]) -> io::Result<(SocketAddr, JoinHandle<io::Result<Request>>)> {
+    let mut listener = AsyncTcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let handle = thread::spawn(move || {
+        let mut poller = Poller::open()?;
+        let (mut stream, _) = poller.block_on(listener.accept())??;
+        let greeting = read_vec(&mut poller, &mut stream, 2)?;
+        let methods = read_vec(&mut poller, &mut stream, greeting[1] as usize)?;
+        assert_eq!((greeting[0], methods), (5, vec![0]));
+        poller.block_on(stream.write_all(&[5, 0]))??;
+
+        let head = read_vec(&mut poller, &mut stream, 4)?;
+        assert_eq!(head[..3], [5, 1, 0]);
+        let len = match head[3] {
+            1 => 4,
+            4 => 16,
+            _ => read_vec(&mut poller, &mut stream, 1)?[0] as usize,
+        };
+        let address = read_vec(&mut poller, &mut stream, len)?;
+        let port = read_vec(&mut poller, &mut stream, 2)?;
+        poller.block_on(stream.write_all(reply))??;
+
+        if reply[1] == 0 {
+            let mut buf = [0; 1024];
+            loop {
+                match poller.block_on(stream.read(&mut buf))?? {
+                    0 => break,
+                    n => poller.block_on(stream.write_all(&buf[..n]))??,
+                }
+            }
+        }
+        poller.block_on(stream.disconnect())??;
+        Ok((head[3], address, u16::from_be_bytes([port[0], port[1]])))
+    });
+    Ok((addr, handle))
+}
+
+#[test]
+fn socks5_tunnels_to_the_target() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    // The proxy may report the address it bound in any of the three forms.
+    let replies: [&[u8]; 3] = [
+        &[5, 0, 0, 1, 10, 0, 0, 1, 0x1f, 0x90],
+        &[5, 0, 0, 3, 4, b'h', b'o', b's', b't', 0, 80],
+        &[
+            5, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 80,
+        ],
+    ];
+    for reply in replies {
+        let (addr, proxying) = proxy(reply)?;
+        let future = socks5_connect(addr, "example.test", 443);
+        let Ok(mut stream) = poller.block_on(future)? else {
+            panic!("the proxy's reply {reply:?} was rejected");
+        };
+        // Nothing of the reply is left over to be read as tunneled data.
+        poller.block_on(stream.write_all(b"ping"))??;
+        let mut buf = [0; 4];
+        poller.block_on(stream.read_exact(&mut buf))??;
+        assert_eq!(&buf, b"ping");
+        poller.block_on(stream.disconnect())??;
+        let request = proxying.join().unwrap()?;
+        assert_eq!(request, (3, b"example.test".to_vec(), 443));
+    }
+
+    // Addresses go to the proxy as such, not as names.
+    let (addr, proxying) = proxy(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    let Ok(stream) = poller.block_on(socks5_connect(addr, "192.0.2.7", 8080))? else {
+        panic!("connecting to an address failed");
+    };
+    poller.block_on(stream.disconnect())??;
+    assert_eq!(proxying.join().unwrap()?, (1, vec![192, 0, 2, 7], 8080));
+    Ok(())
+}
+
+#[test]
+fn socks5_reports_a_refused_connection() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (addr, proxying) = proxy(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    let Err(e) = poller.block_on(socks5_connect(addr, "example.test", 443))? else {
+        panic!("the proxy's refusal was ignored");
+    };
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(e.to_string(), "connection refused by the target");
+    proxying.join().unwrap()?;
+    Ok(())
+}
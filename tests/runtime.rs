@@ -0,0 +1,951 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use playground_future_2_0::blocking::BlockingTask;
+use playground_future_2_0::future::{Future, Interest, Waitable};
+use playground_future_2_0::io::{copy_pooled, AsyncReadExt, AsyncWriteExt};
+use playground_future_2_0::runtime::{
+    self, BufferPool, CompletionQueue, Handle, JoinError, JoinHandle, MaxParkTime, Poller,
+};
+use playground_future_2_0::stream::StreamExt;
+use playground_future_2_0::sync::Event;
+use playground_future_2_0::tcp::AsyncTcpStream;
+use playground_future_2_0::time::{self, sleep};
+use playground_future_2_0::unix::AsyncUnixDatagram;
+use playground_future_2_0::RuntimeError;
+
+/// Panics once it's polled after `deadline`.
+struct PanicsAt(Instant);
+
+impl Future for PanicsAt {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if Instant::now() >= self.0 {
+            panic!("oh no");
+        }
+        Some(Waitable::Timer(self.0)).into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Waits for an event it owns, so it can be spawned.
+struct WaitsFor(Event);
+
+impl Future for WaitsFor {
+    type Output = ();
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.0.wait().poll(ready).collect::<Vec<_>>().into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.is_set().then_some(())
+    }
+}
+
+/// Spawns a sleep the first time it's polled, and resolves once that's done.
+#[derive(Default)]
+struct SpawnsSleep {
+    handle: Option<JoinHandle<()>>,
+    output: Option<Result<(), JoinError>>,
+}
+
+impl Future for SpawnsSleep {
+    type Output = Result<(), JoinError>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let handle = self
+            .handle
+            .get_or_insert_with(|| runtime::spawn(sleep(Duration::from_millis(10))));
+        let waitable = handle.poll(ready).next();
+        if waitable.is_none() {
+            self.output = handle.take();
+        }
+        waitable.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+#[test]
+fn run_finishes_every_task() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let start = Instant::now();
+    for ms in [30, 10, 20] {
+        poller.spawn(sleep(Duration::from_millis(ms)));
+    }
+    assert_eq!(poller.task_count(), 3);
+    poller.run()?;
+    assert_eq!(poller.task_count(), 0);
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    Ok(())
+}
+
+#[test]
+fn run_without_tasks_returns() -> io::Result<()> {
+    Poller::open()?.run()?;
+    Ok(())
+}
+
+#[test]
+fn background_tasks_outlived_by_foreground() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let handle = poller.spawn(sleep(Duration::from_millis(10)));
+    poller.run_until(sleep(Duration::from_millis(50)))?;
+    assert_eq!(poller.task_count(), 0);
+    poller.block_on(handle)??;
+    Ok(())
+}
+
+#[test]
+fn background_tasks_outliving_foreground_are_dropped() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    poller.spawn(sleep(Duration::from_secs(10)));
+    let start = Instant::now();
+    poller.run_until(sleep(Duration::from_millis(10)))?;
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(poller.task_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn joining_a_dropped_task_reports_aborted() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let handle = poller.spawn(sleep(Duration::from_secs(10)));
+    poller.run_until(sleep(Duration::ZERO))?;
+    let result = poller.block_on(handle)?;
+    assert!(matches!(result, Err(JoinError::Aborted)));
+    Ok(())
+}
+
+#[test]
+fn detached_tasks_keep_running() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    poller.set_detach(true);
+    let handle = poller.spawn(sleep(Duration::from_millis(50)));
+    poller.run_until(sleep(Duration::from_millis(10)))?;
+    assert_eq!(poller.task_count(), 1);
+    poller.block_on(handle)??;
+    assert_eq!(poller.task_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn tasks_spawn_tasks() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let handle = poller.spawn(SpawnsSleep::default());
+    poller.run()?;
+    assert_eq!(poller.task_count(), 0);
+    poller.block_on(handle)???;
+    Ok(())
+}
+
+#[test]
+fn free_block_on() -> io::Result<()> {
+    assert!(Handle::try_current().is_none());
+    runtime::block_on(SpawnsSleep::default())??;
+    assert!(Handle::try_current().is_none());
+    Ok(())
+}
+
+#[test]
+fn spawn_through_a_handle() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let handle = poller.handle().spawn(sleep(Duration::from_millis(10)));
+    assert_eq!(poller.task_count(), 1);
+    poller.block_on(handle)??;
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "no runtime running on this thread")]
+fn spawn_outside_a_runtime_panics() {
+    runtime::spawn(sleep(Duration::from_millis(10)));
+}
+
+#[test]
+fn abort_a_blocked_task() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, _server) = AsyncTcpStream::pair()?;
+    let handle = poller.spawn(Arc::new(client).read_owned(vec![0; 8]));
+    poller.block_on(sleep(Duration::from_millis(10)))?;
+    assert_eq!(poller.registration_count(), 1);
+
+    handle.abort();
+    let result = poller.block_on(handle)?;
+    assert!(matches!(result, Err(JoinError::Aborted)));
+    assert_eq!(poller.task_count(), 0);
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn dumps_show_what_named_tasks_wait_on() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, _server) = AsyncTcpStream::pair()?;
+    let fd = client.as_raw_fd();
+    let handle = poller.spawn_named("conn-9", Arc::new(client).read_owned(vec![0; 8]));
+    poller.spawn(sleep(Duration::from_secs(60)));
+    poller.poll_once(Duration::ZERO)?;
+
+    let dump = poller.dump_tasks();
+    assert_eq!(dump.len(), 2);
+    let stuck = &dump[0];
+    assert_eq!(stuck.name.as_deref(), Some("conn-9"));
+    assert_eq!(stuck.waiting_on, [Waitable::Fd(fd, Interest::Read)]);
+    let printed = format!("{stuck:?}");
+    let expected = format!(
+        "task {} \"conn-9\" waiting on fd {fd} READ since ",
+        stuck.id
+    );
+    assert!(printed.starts_with(&expected), "{printed}");
+    assert_eq!(dump[1].name, None);
+    assert!(dump[1].id > stuck.id);
+
+    handle.abort();
+    poller.block_on(handle)?.unwrap_err();
+    Ok(())
+}
+
+#[test]
+fn abort_after_finishing_keeps_the_output() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let handle = poller.spawn(sleep(Duration::ZERO));
+    poller.run()?;
+    handle.abort();
+    poller.block_on(handle)??;
+    Ok(())
+}
+
+#[test]
+fn panics_stay_in_their_task() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let before = poller.spawn(sleep(Duration::from_millis(5)));
+    let panics = poller.spawn(PanicsAt(Instant::now() + Duration::from_millis(10)));
+    let after = poller.spawn(sleep(Duration::from_millis(20)));
+    poller.run()?;
+
+    poller.block_on(before)??;
+    poller.block_on(after)??;
+    match poller.block_on(panics)? {
+        Err(err @ JoinError::Panicked(_)) => {
+            assert_eq!(err.to_string(), "the task panicked: oh no")
+        }
+        result => panic!("expected a panic, got {result:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "oh no")]
+fn block_on_resumes_panics() {
+    let mut poller = Poller::open().unwrap();
+    let _ = poller.block_on(PanicsAt(Instant::now()));
+}
+
+/// Has nothing to wait on, but no output either.
+struct NeverDone;
+
+impl Future for NeverDone {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        None.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+#[test]
+fn misbehaving_futures_are_an_error() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let err = poller.block_on(NeverDone).unwrap_err();
+    assert!(matches!(err, RuntimeError::FutureMisbehaved(_)));
+
+    // The poller is still usable afterwards.
+    poller.block_on(sleep(Duration::from_millis(1)))?;
+    Ok(())
+}
+
+/// Calls `runtime::block_on` from inside a running runtime.
+struct Nested(Option<Result<(), RuntimeError>>);
+
+impl Future for Nested {
+    type Output = Result<(), RuntimeError>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.0 = Some(runtime::block_on(sleep(Duration::ZERO)));
+        None.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.take()
+    }
+}
+
+#[test]
+fn nested_block_on_is_an_error() -> io::Result<()> {
+    let result = Poller::open()?.block_on(Nested(None))?;
+    assert!(matches!(result, Err(RuntimeError::AlreadyRunning)));
+    Ok(())
+}
+
+/// Waits on a completion which doesn't exist, so nothing ever wakes it.
+struct WaitsOnNothing;
+
+impl Future for WaitsOnNothing {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        Some(Waitable::Completion(u64::MAX)).into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+#[test]
+fn waiting_on_nothing_is_a_deadlock() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let err = poller.block_on(WaitsOnNothing).unwrap_err();
+    assert!(matches!(err, RuntimeError::Deadlock));
+
+    // A spawned task alone can deadlock `run` the same way.
+    let mut poller = Poller::open()?;
+    let _task = poller.spawn(WaitsOnNothing);
+    assert!(matches!(poller.run(), Err(RuntimeError::Deadlock)));
+    Ok(())
+}
+
+/// Calls `Poller::block_on` on a poller of its own from inside a running
+/// runtime.
+struct NestedPoller(Option<Result<(), RuntimeError>>);
+
+impl Future for NestedPoller {
+    type Output = Result<(), RuntimeError>;
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.0 = Some(match Poller::open() {
+            Ok(mut poller) => poller.block_on(sleep(Duration::ZERO)),
+            Err(err) => Err(err.into()),
+        });
+        None.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.take()
+    }
+}
+
+#[test]
+fn nested_pollers_are_an_error() -> io::Result<()> {
+    let result = Poller::open()?.block_on(NestedPoller(None))?;
+    let err = result.unwrap_err();
+    assert!(matches!(err, RuntimeError::AlreadyRunning));
+    assert!(err.to_string().contains("use spawn or await instead"));
+    Ok(())
+}
+
+/// Waits on the same thing forever.
+struct WaitsOn(Waitable);
+
+impl Future for WaitsOn {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        Some(self.0).into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+#[test]
+fn waiting_through_another_poller_is_an_error() -> io::Result<()> {
+    let (mut client, mut server) = AsyncUnixDatagram::pair()?;
+    let fd = server.as_raw_fd();
+    let mut first = Poller::open()?;
+    let mut second = Poller::open()?;
+    let _task = first.spawn(WaitsOn(Waitable::Fd(fd, Interest::Read)));
+    first.block_on(sleep(Duration::from_millis(1)))?;
+
+    let mut buf = [0; 4];
+    let err = second.block_on(server.recv(&mut buf)).unwrap_err();
+    let (first_id, second_id) = (first.id(), second.id());
+    assert!(matches!(
+        err,
+        RuntimeError::WrongRuntime { fd: f, registered_with, polled_by }
+            if f == fd && registered_with == first_id && polled_by == second_id
+    ));
+
+    // Once the first poller is gone, the second one can have it.
+    drop(_task);
+    drop(first);
+    second.block_on(client.send(b"ping"))??;
+    assert_eq!(second.block_on(server.recv(&mut buf))??, 4);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "both wait on fd"))]
+fn two_tasks_waiting_on_the_same_fd_is_an_error() {
+    let (_client, server) = AsyncUnixDatagram::pair().unwrap();
+    let fd = server.as_raw_fd();
+    let mut poller = Poller::open().unwrap();
+    // Waiting on different things is fine.
+    let _writes = poller.spawn(WaitsOn(Waitable::Fd(fd, Interest::Write)));
+    let _reads = poller.spawn(WaitsOn(Waitable::Fd(fd, Interest::Read)));
+    let _more_reads = poller.spawn(WaitsOn(Waitable::Fd(fd, Interest::Read)));
+    let err = poller
+        .block_on(sleep(Duration::from_millis(1)))
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        RuntimeError::DuplicateRegistration { fd: f, interest: Interest::Read, .. } if f == fd
+    ));
+}
+
+#[test]
+fn block_on_timeout_bounds_a_stuck_read() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, _server) = AsyncTcpStream::pair()?;
+    let start = Instant::now();
+    let result = poller.block_on_timeout(
+        Arc::new(client).read_owned(vec![0; 8]),
+        Duration::from_millis(100),
+    )?;
+    let elapsed = start.elapsed();
+    assert!(result.is_err());
+    assert!(elapsed >= Duration::from_millis(100), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+    assert_eq!(poller.registration_count(), 0);
+
+    // Futures which finish in time resolve as usual.
+    let result = poller.block_on_timeout(sleep(Duration::ZERO), Duration::from_secs(1))?;
+    assert!(result.is_ok());
+    Ok(())
+}
+
+#[test]
+fn shutdown_drains_then_aborts() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let quick = poller.spawn(sleep(Duration::from_millis(10)));
+    let slower = poller.spawn(sleep(Duration::from_millis(50)));
+    let stuck = poller.spawn(sleep(Duration::from_secs(10)));
+
+    let start = Instant::now();
+    let summary = poller.shutdown(Duration::from_millis(100))?;
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(summary.completed, 2);
+    assert_eq!(summary.aborted, 1);
+    assert_eq!(poller.task_count(), 0);
+
+    assert!(poller.block_on(quick)?.is_ok());
+    assert!(poller.block_on(slower)?.is_ok());
+    assert!(matches!(poller.block_on(stuck)?, Err(JoinError::Aborted)));
+
+    // Tasks spawned afterwards never run.
+    let late = poller.spawn(sleep(Duration::ZERO));
+    assert!(matches!(poller.block_on(late)?, Err(JoinError::ShutDown)));
+    Ok(())
+}
+
+#[test]
+fn builder_rejects_zeroes() {
+    let builders = [
+        runtime::Builder::new().event_capacity(0),
+        runtime::Builder::new().blocking_threads(0),
+        runtime::Builder::new().spurious_wakeup_injection(0),
+        runtime::Builder::new().poll_budget(0),
+    ];
+    for builder in builders {
+        let result = builder.build();
+        assert!(matches!(result, Err(e) if e.kind() == io::ErrorKind::InvalidInput));
+    }
+}
+
+#[test]
+fn runtime_is_the_poller_by_another_name() -> io::Result<()> {
+    let mut built: runtime::Runtime = runtime::Builder::new().build()?;
+    let mut runtime = runtime::Runtime::new()?;
+    let handle = built.spawn(sleep(Duration::from_millis(1)));
+    built.block_on(handle)??;
+    runtime.block_on(sleep(Duration::from_millis(1)))?;
+    Ok(())
+}
+
+#[test]
+fn event_capacity_bounds_events_per_wakeup() -> io::Result<()> {
+    let mut poller = runtime::Builder::new().event_capacity(1).build()?;
+    let (mut a_tx, a_rx) = UnixStream::pair()?;
+    let (mut b_tx, b_rx) = UnixStream::pair()?;
+    a_tx.write_all(b"a")?;
+    b_tx.write_all(b"b")?;
+    poller.register_read(a_rx.as_raw_fd())?;
+    poller.register_read(b_rx.as_raw_fd())?;
+    assert_eq!(poller.wait_timeout(Some(Duration::ZERO))?, 1);
+    Ok(())
+}
+
+#[test]
+fn timer_resolution_rounds_sleeps_up() -> io::Result<()> {
+    let mut poller = runtime::Builder::new()
+        .timer_resolution(Duration::from_millis(100))
+        .build()?;
+    let start = Instant::now();
+    poller.block_on(sleep(Duration::from_millis(1)))?;
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    Ok(())
+}
+
+/// Runs two blocking jobs at once, resolving with the threads they ran on.
+struct TwoJobs(Vec<(BlockingTask<ThreadId>, Option<ThreadId>)>);
+
+impl Future for TwoJobs {
+    type Output = Vec<ThreadId>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        if self.0.is_empty() {
+            for _ in 0..2 {
+                let task = runtime::spawn_blocking(|| {
+                    thread::sleep(Duration::from_millis(20));
+                    thread::current().id()
+                });
+                self.0.push((task, None));
+            }
+        }
+        let mut pending = Vec::new();
+        for (task, output) in self.0.iter_mut().filter(|(_, output)| output.is_none()) {
+            let waitable = task.poll(ready).next();
+            match task.take() {
+                Some(id) => *output = Some(id),
+                None => pending.extend(waitable),
+            }
+        }
+        pending.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.iter().map(|(_, output)| *output).collect()
+    }
+}
+
+#[test]
+fn blocking_threads_gives_the_poller_its_own_pool() -> io::Result<()> {
+    let mut poller = runtime::Builder::new().blocking_threads(1).build()?;
+    let threads = poller.block_on(TwoJobs(Vec::new()))?;
+    assert_eq!(threads[0], threads[1]);
+
+    // The shared pool runs the second job on another thread instead.
+    let threads = Poller::open()?.block_on(TwoJobs(Vec::new()))?;
+    assert_ne!(threads[0], threads[1]);
+    Ok(())
+}
+
+/// Counts how often the inner future is polled.
+struct CountPolls<F>(F, usize);
+
+impl<F: Future> Future for CountPolls<F> {
+    type Output = usize;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.1 += 1;
+        self.0.poll(ready).collect::<Vec<_>>().into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.take().map(|_| self.1)
+    }
+}
+
+#[test]
+fn spurious_wakeups_repoll_futures() -> io::Result<()> {
+    let polls = Poller::open()?.block_on(CountPolls(sleep(Duration::from_millis(50)), 0))?;
+    assert_eq!(polls, 2);
+
+    let mut poller = runtime::Builder::new()
+        .spurious_wakeup_injection(1)
+        .build()?;
+    let polls = poller.block_on(CountPolls(sleep(Duration::from_millis(50)), 0))?;
+    assert!(polls > 2, "{polls}");
+    Ok(())
+}
+
+#[test]
+fn write_events_leave_readers_of_the_same_fd_alone() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (a, _b) = UnixStream::pair()?;
+    let fd = a.as_raw_fd();
+    // Writable all along, so it's ready on every turn.
+    let writer = WaitsOn(Waitable::Fd(fd, Interest::Write));
+    let writing = poller.spawn(time::timeout(Duration::from_millis(100), writer));
+    let reader = WaitsOn(Waitable::Fd(fd, Interest::Read));
+    let reading = time::timeout(Duration::from_millis(100), reader);
+    // Once to start waiting, once to time out, and once more after dropping
+    // the registration.
+    assert_eq!(poller.block_on(CountPolls(reading, 0))?, 3);
+    assert!(matches!(poller.block_on(writing)?, Ok(Err(_))));
+    Ok(())
+}
+
+#[test]
+fn metrics_add_up() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let before = poller.metrics();
+    for _ in 0..3 {
+        poller.spawn(sleep(Duration::from_millis(20)));
+    }
+    assert_eq!(poller.metrics().live_tasks, 3);
+    poller.run()?;
+
+    let after = poller.metrics();
+    assert_eq!(after.live_tasks, 0);
+    assert_eq!(after.tasks_spawned - before.tasks_spawned, 3);
+    assert_eq!(after.tasks_completed - before.tasks_completed, 3);
+    assert_eq!(after.tasks_aborted, 0);
+    assert!(after.turns > before.turns);
+    assert!(after.polls >= 6, "{after:?}");
+    assert!(after.parked >= Duration::from_millis(15), "{after:?}");
+
+    let handle = poller.spawn(sleep(Duration::from_secs(10)));
+    handle.abort();
+    assert!(poller.block_on(handle)?.is_err());
+    assert_eq!(poller.metrics().tasks_aborted, 1);
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn latency_histogram_counts_every_poll() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    for ms in [5, 10, 15, 20] {
+        poller.spawn(sleep(Duration::from_millis(ms)));
+    }
+    let (mut a, mut b) = AsyncTcpStream::pair()?;
+    poller.spawn(SpawnsSleep::default());
+    poller.run()?;
+    poller.block_on(a.write(b"ping"))??;
+    poller.block_on(b.read(&mut [0; 4]))??;
+
+    let histogram = poller.latency_histogram();
+    assert_eq!(histogram.total(), poller.metrics().polls);
+    let buckets: Vec<_> = histogram.buckets().collect();
+    assert_eq!(buckets.len(), 7);
+    assert_eq!(buckets[0].0, Some(Duration::from_micros(10)));
+    assert_eq!(buckets[6].0, None);
+    Ok(())
+}
+
+/// Reads a byte off a socket which always has more, taking a while to
+/// process each one.
+struct Firehose(UnixStream);
+
+impl Future for Firehose {
+    type Output = ();
+
+    fn poll(&mut self, _ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let _ = self.0.read(&mut [0]);
+        thread::sleep(Duration::from_millis(1));
+        Some(Waitable::Fd(self.0.as_raw_fd(), Interest::Read)).into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        None
+    }
+}
+
+#[test]
+fn a_busy_task_doesnt_hold_up_timers() -> io::Result<()> {
+    let mut poller = runtime::Builder::new().poll_budget(2).build()?;
+    let (mut tx, rx) = UnixStream::pair()?;
+    rx.set_nonblocking(true)?;
+    tx.write_all(&[0; 4096])?;
+    poller.spawn(Firehose(rx));
+    poller.block_on(sleep(Duration::from_millis(10)))?;
+
+    let start = Instant::now();
+    let sleeping = poller.spawn(sleep(Duration::from_millis(10)));
+    poller.block_on(sleeping)??;
+    assert!(
+        start.elapsed() < Duration::from_millis(20),
+        "{:?}",
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[test]
+fn poll_once_drives_a_roundtrip() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, server) = AsyncTcpStream::pair()?;
+    let mut read = poller.spawn(Arc::new(server).read_owned(vec![0; 5]));
+    let mut write = poller.spawn(Arc::new(client).write_owned(b"hello".to_vec()));
+
+    let mut turns = 0;
+    while !(read.is_finished() && write.is_finished()) {
+        assert!(turns < 100, "the roundtrip never finished");
+        poller.poll_once(Duration::from_millis(10))?;
+        turns += 1;
+    }
+    let turn = poller.poll_once(Duration::from_millis(10))?;
+    assert_eq!(turn.live_tasks, 0);
+    assert_eq!(turn.next_timer, None);
+
+    let (_, written) = write.take().unwrap()?;
+    assert_eq!(written?, 5);
+    let (buf, n) = read.take().unwrap()?;
+    assert_eq!(&buf[..n?], b"hello");
+
+    // Timers tell the caller when to come back.
+    let _sleep = poller.spawn(sleep(Duration::from_secs(1)));
+    let turn = poller.poll_once(Duration::ZERO)?;
+    assert_eq!(turn.live_tasks, 1);
+    assert!(turn.next_timer.is_some());
+    Ok(())
+}
+
+#[test]
+fn scoped_tasks_read_into_the_stack() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut connections = Vec::new();
+    for _ in 0..4 {
+        connections.push(AsyncTcpStream::pair()?);
+    }
+    let messages = [b"zero!", b"one!!", b"two!!", b"three"];
+    let mut bufs = [[0; 5]; 4];
+    poller.scope(|s| {
+        let tasks = connections.iter_mut().zip(&mut bufs).zip(&messages);
+        for (((client, server), buf), message) in tasks {
+            s.spawn(AsyncReadExt::read_exact(server, buf));
+            s.spawn(AsyncWriteExt::write_all(client, *message));
+        }
+    })?;
+    assert_eq!(bufs, messages.map(|message| *message));
+    Ok(())
+}
+
+#[test]
+fn scoped_handles_outlive_the_scope() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, _server) = AsyncTcpStream::pair()?;
+    let (mut buf, mut hi, mut other) = ([0; 1], &b"hi"[..], [0; 2]);
+    let (joined, stuck, read) = poller.scope(|s| {
+        let panics = s.spawn(PanicsAt(Instant::now()));
+        let stuck = s.spawn(AsyncReadExt::read_exact(&mut client, &mut buf));
+        stuck.abort();
+        let read = s.spawn(AsyncReadExt::read(&mut hi, &mut other));
+        // Joining the panicking task keeps the scope from resuming it.
+        (s.spawn(panics), stuck, read)
+    })?;
+    assert!(matches!(
+        poller.block_on(joined)??,
+        Err(JoinError::Panicked(_))
+    ));
+    assert!(matches!(poller.block_on(stuck)?, Err(JoinError::Aborted)));
+    assert_eq!(poller.block_on(read)???, 2);
+    assert_eq!(&other, b"hi");
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "oh no")]
+fn scope_resumes_unjoined_panics() {
+    let mut poller = Poller::open().unwrap();
+    let _ = poller.scope(|s| {
+        s.spawn(PanicsAt(Instant::now()));
+    });
+}
+
+#[test]
+fn park_hooks_bracket_every_wait() -> io::Result<()> {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let (first, second, unpark) = (log.clone(), log.clone(), log.clone());
+    let mut poller = runtime::Builder::new()
+        .on_park(move || {
+            first.lock().unwrap().push("park 1");
+            None
+        })
+        .on_park(move || {
+            second.lock().unwrap().push("park 2");
+            None
+        })
+        .on_unpark(move || unpark.lock().unwrap().push("unpark"))
+        .build()?;
+    for _ in 0..3 {
+        poller.wait_timeout(Some(Duration::ZERO))?;
+    }
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["park 1", "park 2", "unpark"].repeat(3)
+    );
+
+    // Every turn but the last waits.
+    log.lock().unwrap().clear();
+    poller.block_on(sleep(Duration::from_millis(20)))?;
+    let turns = poller.metrics().turns as usize;
+    let log = log.lock().unwrap();
+    let count = |entry| log.iter().filter(|&&e| e == entry).count();
+    assert_eq!(count("park 1"), turns - 1);
+    assert_eq!(count("park 2"), turns - 1);
+    assert_eq!(count("unpark"), turns - 1);
+    Ok(())
+}
+
+#[test]
+fn max_park_time_bounds_a_park() -> io::Result<()> {
+    let parks = Arc::new(AtomicUsize::new(0));
+    let counted = parks.clone();
+    let mut poller = runtime::Builder::new()
+        .on_park(move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+            Some(MaxParkTime(Duration::from_millis(10)))
+        })
+        .on_park(|| None)
+        .build()?;
+    // Nothing could ever end this wait otherwise.
+    let start = Instant::now();
+    poller.wait()?;
+    assert!(start.elapsed() >= Duration::from_millis(10));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // A read which takes a while parks again and again in the meantime.
+    let (client, mut server) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let peer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        client.write_all(b"!")
+    });
+    let mut buf = [0; 1];
+    poller.block_on(AsyncReadExt::read_exact(&mut server, &mut buf))??;
+    peer.join().unwrap()?;
+    assert!(parks.load(Ordering::Relaxed) >= 5);
+    Ok(())
+}
+
+#[test]
+fn completion_queues_keep_order_alongside_sockets() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut queue = CompletionQueue::new();
+    let handle = queue.handle();
+    let pusher = thread::spawn(move || {
+        // Pauses of up to 2ms, from a fixed seed.
+        let mut seed = 0x9e37_79b9_u32;
+        for i in 0..100 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            thread::sleep(Duration::from_micros(u64::from(seed % 2000)));
+            handle.push(i);
+        }
+    });
+    let (client, mut server) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let writer = thread::spawn(move || -> io::Result<()> {
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(5));
+            client.write_all(b"0123456789")?;
+        }
+        Ok(())
+    });
+
+    let mut values = Vec::new();
+    let mut bytes = [0; 100];
+    poller.scope(|s| {
+        s.spawn(queue.stream().for_each(|value| values.push(value)));
+        s.spawn(AsyncReadExt::read_exact(&mut server, &mut bytes));
+    })?;
+    pusher.join().unwrap();
+    writer.join().unwrap()?;
+    assert_eq!(values, (0..100).collect::<Vec<_>>());
+    assert_eq!(bytes, *b"0123456789".repeat(10));
+    // Every handle is gone, so the queue ended.
+    assert_eq!(poller.block_on(queue.next())?, None);
+    Ok(())
+}
+
+#[test]
+fn completions_reach_tasks_after_block_on_returns() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let event = Event::new();
+    let task = poller.spawn(WaitsFor(event.clone()));
+    let setter = event.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        setter.set();
+    });
+    // Setting it completes it for both at once, and `block_on` returns on
+    // the turn that comes in, without polling the task.
+    poller.block_on(event.wait())?;
+    handle.join().unwrap();
+    let woke = poller.block_on(time::timeout(Duration::from_secs(1), task))?;
+    assert!(woke.is_ok(), "the task never heard the event was set");
+    Ok(())
+}
+
+#[test]
+fn registration_deadlines_end_waits() -> io::Result<()> {
+    let (_client, server) = AsyncTcpStream::pair()?;
+    let fd = server.as_raw_fd();
+    let mut poller = Poller::open()?;
+    let start = Instant::now();
+    poller.register_read_deadline(fd, start + Duration::from_millis(20))?;
+    poller.wait()?;
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // Timing out leaves the registration to whoever waited.
+    assert!(poller.is_registered(fd));
+    poller.unregister(fd)?;
+    Ok(())
+}
+
+#[test]
+fn echoes_recycle_pooled_buffers() -> io::Result<()> {
+    let pool = BufferPool::new(1024, 4);
+    let mut poller = runtime::Builder::new().buffer_pool(pool.clone()).build()?;
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let server = Arc::new(server);
+    let mut misses = Vec::new();
+    for i in 0..100u32 {
+        let message = i.to_string();
+        poller.block_on(client.write_all(message.as_bytes()))??;
+        let buf = poller.handle().buffer_pool().acquire();
+        let (mut buf, n) = poller.block_on(server.clone().read_owned(buf))?;
+        buf.truncate(n?);
+        let (buf, n) = poller.block_on(server.clone().write_owned(buf))?;
+        assert_eq!((&*buf, n?), (message.as_bytes(), message.len()));
+        drop(buf);
+
+        let mut echoed = vec![0; message.len()];
+        poller.block_on(client.read_exact(&mut echoed))??;
+        misses.push(pool.stats().misses);
+    }
+    // Only the first round trip allocated.
+    assert!(misses.iter().all(|&n| n == 1), "{misses:?}");
+    let stats = pool.stats();
+    assert_eq!((stats.hits, stats.live, stats.pooled), (99, 0, 1));
+
+    // Copies take theirs from the pool too, and hand them back.
+    let mut copied = Vec::new();
+    let n = poller.block_on(copy_pooled(&mut &b"data"[..], &mut copied, &pool))??;
+    assert_eq!((n, &copied[..]), (4, &b"data"[..]));
+    assert_eq!(pool.stats().misses, 1);
+    assert_eq!(pool.stats().live, 0);
+    Ok(())
+}
@@ -0,0 +1,68 @@
+#![cfg(all(target_os = "macos", not(feature = "threads-backend")))]
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::signal::{ctrl_c, Signal};
+use playground_future_2_0::stream::StreamExt;
+
+fn raise(signum: i32) {
+    // SAFETY: `raise` has no preconditions.
+    assert_eq!(unsafe { libc::raise(signum) }, 0);
+}
+
+/// Raise `signum` from another thread once the poller had time to register
+/// for it.
+fn raise_later(signum: i32) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        raise(signum);
+    })
+}
+
+#[test]
+fn deliveries_are_counted() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut signal = Signal::new(libc::SIGUSR1)?;
+
+    raise(libc::SIGUSR1);
+    assert_eq!(poller.block_on(signal.next())?, Some(()));
+
+    raise(libc::SIGUSR1);
+    raise(libc::SIGUSR1);
+    assert_eq!(poller.block_on(signal.next())?, Some(()));
+    assert_eq!(poller.block_on(signal.next())?, Some(()));
+    Ok(())
+}
+
+#[test]
+fn uncatchable_signals_are_rejected() {
+    let err = Signal::new(libc::SIGKILL).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn every_signal_sees_each_delivery() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut first = Signal::new(libc::SIGUSR2)?;
+    let mut second = Signal::new(libc::SIGUSR2)?;
+    assert_eq!(second.signum(), libc::SIGUSR2);
+
+    let raising = raise_later(libc::SIGUSR2);
+    assert_eq!(poller.block_on(first.next())?, Some(()));
+    assert_eq!(poller.block_on(second.next())?, Some(()));
+    raising.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn ctrl_c_resolves_on_sigint() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let interrupted = ctrl_c();
+    let raising = raise_later(libc::SIGINT);
+    poller.block_on(interrupted)??;
+    raising.join().unwrap();
+    Ok(())
+}
@@ -0,0 +1,101 @@
+#![cfg(all(unix, feature = "net", feature = "time"))]
+
+use std::io::{self, Write};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use playground_future_2_0::codec::{Framed, LinesCodec};
+use playground_future_2_0::io::mem::duplex;
+use playground_future_2_0::io::{chunks, AsyncRead, AsyncWriteExt};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::{Stream, StreamExt};
+use playground_future_2_0::tcp::AsyncTcpStream;
+use playground_future_2_0::unix::AsyncUnixStream;
+
+#[test]
+fn timeout_fails_when_the_peer_pauses_between_frames() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, server) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let peer = thread::spawn(move || -> io::Result<()> {
+        client.write_all(b"one\ntwo\n")?;
+        thread::sleep(Duration::from_millis(500));
+        client.write_all(b"three\n")
+    });
+    timeout_between_frames(&mut poller, server, peer)
+}
+
+#[test]
+fn timeout_fails_when_a_duplex_peer_pauses() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, server) = duplex(4);
+    let peer = thread::spawn(move || -> io::Result<()> {
+        let mut poller = Poller::open()?;
+        poller.block_on(client.write_all(b"one\ntwo\n"))??;
+        thread::sleep(Duration::from_millis(500));
+        poller.block_on(client.write_all(b"three\n"))??;
+        Ok(())
+    });
+    timeout_between_frames(&mut poller, server, peer)
+}
+
+/// Read the frames `peer` sends `server`, with a pause between the second
+/// and the third.
+fn timeout_between_frames(
+    poller: &mut Poller,
+    server: impl AsyncRead,
+    peer: JoinHandle<io::Result<()>>,
+) -> io::Result<()> {
+    let mut frames = Framed::new(server, LinesCodec::new()).timeout(Duration::from_millis(300));
+    let mut next = |poller: &mut Poller| poller.block_on(frames.next());
+    assert_eq!(next(poller)?.unwrap().unwrap()?, "one");
+    assert_eq!(next(poller)?.unwrap().unwrap()?, "two");
+    assert!(next(poller)?.unwrap().is_err());
+    // The stream picks up where it left off once the peer is back.
+    assert_eq!(next(poller)?.unwrap().unwrap()?, "three");
+    peer.join().unwrap()?;
+    assert!(next(poller)?.is_none());
+
+    // An ended stream doesn't leave a timer behind for the poller.
+    assert_eq!(frames.poll_next(&[]).count(), 0);
+    assert!(frames.take_next().is_none());
+    Ok(())
+}
+
+#[test]
+fn chunks_are_whole_across_uneven_writes() -> io::Result<()> {
+    const LEN: usize = 1024 * 1024;
+    const CHUNK: usize = 1000;
+    let data: Vec<u8> = (0..LEN).map(|i| (i * 7 + i / 251) as u8).collect();
+    let (mut writer, reader) = AsyncUnixStream::pair()?;
+    let sent = data.clone();
+    let peer = thread::spawn(move || -> io::Result<()> {
+        let mut poller = Poller::open()?;
+        // Writes of anywhere from 1 byte to 16KiB, from a fixed seed.
+        let mut seed = 0x2545_f491_u32;
+        let mut rest = &sent[..];
+        while !rest.is_empty() {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let n = (seed as usize % (16 * 1024) + 1).min(rest.len());
+            poller.block_on(writer.write_all(&rest[..n]))??;
+            rest = &rest[n..];
+        }
+        Ok(())
+    });
+
+    let mut poller = Poller::open()?;
+    let mut chunks = chunks(reader, CHUNK);
+    let mut received = Vec::new();
+    while let Some(chunk) = poller.block_on(chunks.next())? {
+        let chunk = chunk?;
+        // Only the last chunk may be short.
+        assert!(chunk.len() == CHUNK || received.len() + chunk.len() == LEN);
+        received.extend_from_slice(&chunk);
+    }
+    peer.join().unwrap()?;
+    assert_eq!(received.len(), LEN);
+    assert!(received == data);
+    Ok(())
+}
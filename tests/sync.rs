@@ -0,0 +1,58 @@
+#![cfg(all(unix, feature = "sync", feature = "time"))]
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::sync::Event;
+use playground_future_2_0::time;
+
+#[test]
+fn set_releases_waiters() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let event = Event::new();
+    assert!(!event.is_set());
+
+    let setter = event.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        setter.set();
+    });
+    poller.block_on(event.wait())?;
+    assert!(event.is_set());
+    handle.join().unwrap();
+
+    // A set event stays set.
+    poller.block_on(event.wait())?;
+    Ok(())
+}
+
+#[test]
+fn waiters_on_other_runtimes() -> io::Result<()> {
+    let event = Event::new();
+    let waiters: Vec<_> = (0..4)
+        .map(|_| {
+            let event = event.clone();
+            thread::spawn(move || Poller::open()?.block_on(event.wait()))
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(20));
+    event.set();
+    for waiter in waiters {
+        waiter.join().unwrap()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn reset_makes_waiters_wait() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let event = Event::new();
+    event.set();
+    event.reset();
+    assert!(!event.is_set());
+    let wait = time::timeout(Duration::from_millis(20), event.wait());
+    assert!(poller.block_on(wait)?.is_err());
+    Ok(())
+}
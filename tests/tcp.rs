@@ -0,0 +1,1167 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::cell::Cell;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use playground_future_2_0::error::{Error, Operation};
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::io::{copy, copy_bidirectional, AsyncFd, ReadBuf, Step};
+use playground_future_2_0::prelude::*;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::{
+    AcceptError, AsyncTcpListener, AsyncTcpStream, ConnectFuture, KeepaliveConfig, ReadOwnedFuture,
+    ReuniteError, WriteOwnedFuture,
+};
+use playground_future_2_0::time;
+
+/// Start a listener on an ephemeral port which echoes everything back to
+/// the first client that connects.
+fn echo_listener() -> io::Result<(SocketAddr, thread::JoinHandle<()>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let handle = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        loop {
+            match conn.read(&mut buf).unwrap() {
+                0 => break,
+                n => conn.write_all(&buf[..n]).unwrap(),
+            }
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// Start a listener which echoes the first `len` bytes the first client
+/// sends, then hangs up.
+fn echo_once(len: usize) -> io::Result<(SocketAddr, thread::JoinHandle<()>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let handle = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let mut left = len;
+        while left > 0 {
+            let n = conn.read(&mut buf[..left.min(1024)]).unwrap();
+            assert!(n > 0, "the client hung up early");
+            conn.write_all(&buf[..n]).unwrap();
+            left -= n;
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// Bytes which differ from one to the next.
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Run `a` and `b` at once, resolving with both outputs.
+struct Join<A: Future, B: Future> {
+    a: A,
+    b: B,
+    outputs: (Option<A::Output>, Option<B::Output>),
+}
+
+fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a,
+        b,
+        outputs: (None, None),
+    }
+}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waiting = Vec::new();
+        if self.outputs.0.is_none() {
+            waiting.extend(self.a.poll(ready));
+            if waiting.is_empty() {
+                self.outputs.0 = self.a.take();
+            }
+        }
+        if self.outputs.1.is_none() {
+            let len = waiting.len();
+            waiting.extend(self.b.poll(ready));
+            if waiting.len() == len {
+                self.outputs.1 = self.b.take();
+            }
+        }
+        waiting.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match &mut self.outputs {
+            (a @ Some(_), b @ Some(_)) => Some((a.take()?, b.take()?)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn echo_roundtrip() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    poller.block_on(client.write_all(b"hello, world!"))??;
+    let mut buf = [0; 13];
+    poller.block_on(client.read_exact(&mut buf))??;
+    assert_eq!(&buf, b"hello, world!");
+
+    poller.block_on(client.disconnect())??;
+    server.join().unwrap();
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn echo_many_writes() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    for i in 0..100u32 {
+        let message = i.to_be_bytes();
+        poller.block_on(client.write_all(&message))??;
+        let mut buf = [0; 4];
+        poller.block_on(client.read_exact(&mut buf))??;
+        assert_eq!(buf, message);
+    }
+
+    poller.block_on(client.disconnect())??;
+    server.join().unwrap();
+    Ok(())
+}
+
+/// Connect to an echo server, send `message` and read it back, without
+/// borrowing anything so it can be spawned.
+struct Roundtrip {
+    message: Vec<u8>,
+    state: RoundtripState,
+    output: Option<Result<Vec<u8>, Error>>,
+}
+
+enum RoundtripState {
+    Connecting(ConnectFuture),
+    Writing(Arc<AsyncTcpStream>, WriteOwnedFuture),
+    Reading(ReadOwnedFuture),
+    Done,
+}
+
+fn roundtrip(addr: SocketAddr, message: &[u8]) -> Roundtrip {
+    Roundtrip {
+        message: message.to_vec(),
+        state: RoundtripState::Connecting(AsyncTcpStream::connect_async(addr)),
+        output: None,
+    }
+}
+
+impl Future for Roundtrip {
+    type Output = Result<Vec<u8>, Error>;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waiting = Vec::new();
+        while waiting.is_empty() && self.output.is_none() {
+            match &mut self.state {
+                RoundtripState::Connecting(connect) => {
+                    waiting.extend(connect.poll(ready));
+                    if let Some(result) = waiting.is_empty().then(|| connect.take()).flatten() {
+                        match result {
+                            Ok(stream) => {
+                                let stream = Arc::new(stream);
+                                let write = stream.clone().write_owned(self.message.clone());
+                                self.state = RoundtripState::Writing(stream, write);
+                            }
+                            Err(e) => self.output = Some(Err(e)),
+                        }
+                    }
+                }
+                RoundtripState::Writing(stream, write) => {
+                    waiting.extend(write.poll(ready));
+                    if let Some((_, result)) = waiting.is_empty().then(|| write.take()).flatten() {
+                        match result {
+                            Ok(_) => {
+                                let buf = vec![0; self.message.len()];
+                                let read = stream.clone().read_owned(buf);
+                                self.state = RoundtripState::Reading(read);
+                            }
+                            Err(e) => self.output = Some(Err(e)),
+                        }
+                    }
+                }
+                RoundtripState::Reading(read) => {
+                    waiting.extend(read.poll(ready));
+                    if let Some((buf, result)) = waiting.is_empty().then(|| read.take()).flatten() {
+                        self.output = Some(result.map(|n| buf[..n].to_vec()));
+                        self.state = RoundtripState::Done;
+                    }
+                }
+                RoundtripState::Done => unreachable!("polled after completion"),
+            }
+        }
+        waiting.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.output.take()
+    }
+}
+
+#[test]
+fn spawned_roundtrips() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut servers = Vec::new();
+    let mut handles = Vec::new();
+    for i in 0..3u32 {
+        let (addr, server) = echo_listener()?;
+        servers.push(server);
+        handles.push(poller.spawn(roundtrip(addr, &i.to_be_bytes())));
+    }
+
+    let (addr, server) = echo_listener()?;
+    servers.push(server);
+    assert_eq!(poller.block_on(roundtrip(addr, b"main"))??, b"main");
+
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(poller.block_on(handle)???, (i as u32).to_be_bytes());
+    }
+    for server in servers {
+        server.join().unwrap();
+    }
+    Ok(())
+}
+
+#[test]
+fn refused_connects_say_where_to() -> io::Result<()> {
+    let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let mut poller = Poller::open()?;
+    let e = match poller.block_on(AsyncTcpStream::connect_async(addr))? {
+        Ok(_) => panic!("connected to a closed port"),
+        Err(e) => e,
+    };
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(e.operation(), Some(Operation::Connect));
+    assert_eq!(e.peer_addr(), Some(addr));
+    let message = e.to_string();
+    assert!(
+        message.starts_with(&format!("connect failed (peer {addr}): ")),
+        "{message}"
+    );
+    let source = std::error::Error::source(&e).and_then(|e| e.downcast_ref::<io::Error>());
+    assert_eq!(
+        source.map(io::Error::kind),
+        Some(io::ErrorKind::ConnectionRefused)
+    );
+
+    // Going through `io::Error` keeps the context.
+    let e = io::Error::from(e);
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(e.to_string(), message);
+    assert_eq!(Error::from(e).peer_addr(), Some(addr));
+    Ok(())
+}
+
+#[test]
+fn reads_after_a_reset_say_what_failed() -> io::Result<()> {
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let fd = client.as_raw_fd();
+    server.abort()?;
+    let mut poller = Poller::open()?;
+    let e = poller.block_on(client.read(&mut [0; 8]))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(e.operation(), Some(Operation::Read));
+    assert_eq!(e.fd(), Some(fd));
+    assert!(
+        e.to_string()
+            .starts_with(&format!("read failed (fd {fd}): ")),
+        "{e}"
+    );
+    Ok(())
+}
+
+/// Counts how often the future inside is polled.
+struct CountPolls<'a, F>(F, &'a Cell<u32>);
+
+impl<F: Future> Future for CountPolls<'_, F> {
+    type Output = F::Output;
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        self.1.set(self.1.get() + 1);
+        self.0.poll(ready).collect::<Vec<_>>().into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        self.0.take()
+    }
+}
+
+#[test]
+fn urgent_data_is_a_priority_event() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, mut server) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let peer = thread::spawn(move || -> io::Result<()> {
+        thread::sleep(Duration::from_millis(50));
+        rustix::net::send(&client, b"u", rustix::net::SendFlags::OOB)?;
+        thread::sleep(Duration::from_millis(100));
+        client.write_all(b"n")
+    });
+
+    // Both wait on the same fd, one for reads and the other for urgent data.
+    let plain = AsyncFd::new(server.as_raw_fd());
+    let plain_polls = Cell::new(0);
+    let mut urgent = [0; 1];
+    poller.scope(|s| {
+        s.spawn(CountPolls(plain.readable(), &plain_polls));
+        s.spawn(server.read_oob(&mut urgent));
+    })?;
+    peer.join().unwrap()?;
+    assert_eq!(&urgent, b"u");
+    // Once to start waiting, and once for the ordinary byte only.
+    assert_eq!(plain_polls.get(), 2);
+
+    let mut ordinary = [0; 2];
+    assert_eq!(poller.block_on(server.read(&mut ordinary))??, 1);
+    assert_eq!(&ordinary[..1], b"n");
+    Ok(())
+}
+
+#[test]
+fn incoming_backs_off_while_out_of_fds() -> io::Result<()> {
+    // Lowering the fd limit would break the other tests running alongside,
+    // so the test runs by itself in a child process.
+    const CHILD: &str = "PLAYGROUND_FD_LIMIT_CHILD";
+    if env::var_os(CHILD).is_none() {
+        let output = Command::new(env::current_exe()?)
+            .args(["incoming_backs_off_while_out_of_fds", "--exact"])
+            .env(CHILD, "1")
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{stdout}");
+        assert!(stdout.contains("1 passed"), "{stdout}");
+        return Ok(());
+    }
+
+    let mut poller = Poller::open()?;
+    let mut listener = AsyncTcpListener::builder(([127, 0, 0, 1], 0).into())
+        .exhausted_backoff(Duration::from_millis(50))
+        .reserve_fd(true)
+        .build()?;
+    let addr = listener.local_addr()?;
+    let mut shed = TcpStream::connect(addr)?;
+
+    // Use up every fd there is under a lowered limit.
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) },
+        0
+    );
+    limit.rlim_cur = 64;
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+    let mut fds = Vec::new();
+    let e = loop {
+        match File::open("/dev/null") {
+            Ok(file) => fds.push(file),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(e.raw_os_error(), Some(libc::EMFILE));
+    let peer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(300));
+        drop(fds);
+        TcpStream::connect(addr)
+    });
+
+    let polls = Cell::new(0);
+    let mut exhausted = 0;
+    let mut incoming = listener.incoming();
+    let mut accepted = loop {
+        match poller
+            .block_on(CountPolls(incoming.next(), &polls))?
+            .unwrap()
+        {
+            Ok(stream) => break stream,
+            Err(AcceptError::ResourceExhausted(_)) => exhausted += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let mut client = peer.join().unwrap()?;
+    client.write_all(b"!")?;
+    let mut buf = [0; 1];
+    poller.block_on(AsyncReadExt::read_exact(&mut accepted, &mut buf))??;
+    // The loop retried every 50ms or so while out of fds, rather than
+    // spinning.
+    assert!((1..=10).contains(&exhausted), "{exhausted}");
+    assert!(polls.get() <= 2 * exhausted + 2, "{}", polls.get());
+    // The spare fd made room to turn away the connection already waiting.
+    assert_eq!(shed.read(&mut [0; 1])?, 0);
+    Ok(())
+}
+
+/// Write to `stream` until the kernel takes no more, returning how much it
+/// took.
+fn fill(stream: &mut AsyncTcpStream) -> usize {
+    let chunk = [7; 64 * 1024];
+    let mut written = 0;
+    while let Step::Done(n) = stream.poll_write(&[], &chunk) {
+        written += n;
+    }
+    written
+}
+
+#[test]
+fn flush_and_shutdown_waits_for_a_slow_reader() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let mut server = poller.block_on(server.into_std())??;
+    let written = fill(&mut client);
+    assert!(client.bytes_unsent()? > 0);
+    let reader = thread::spawn(move || -> io::Result<usize> {
+        thread::sleep(Duration::from_millis(100));
+        let (mut read, mut buf) = (0, [0; 16 * 1024]);
+        loop {
+            match server.read(&mut buf)? {
+                0 => return Ok(read),
+                n => read += n,
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let start = Instant::now();
+    poller.block_on(client.flush_and_shutdown(Duration::from_secs(10)))??;
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    // The reader only sees EOF once it read everything.
+    assert_eq!(reader.join().unwrap()?, written);
+    Ok(())
+}
+
+#[test]
+fn flush_and_shutdown_times_out_on_a_stalled_reader() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    fill(&mut client);
+
+    let start = Instant::now();
+    let flushed = poller.block_on(client.flush_and_shutdown(Duration::from_millis(200)))?;
+    assert_eq!(flushed.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+    // The stream was left alone, so the data still goes out once the reader
+    // catches up.
+    assert!(client.bytes_unsent()? > 0);
+    drop(server);
+    Ok(())
+}
+
+#[test]
+fn read_timeout_races_data_at_the_deadline() -> io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client = TcpStream::connect(listener.local_addr()?)?;
+    let mut server = AsyncTcpStream::from_std(listener.accept()?.0)?;
+    let mut poller = Poller::open()?;
+    for _ in 0..50 {
+        let dur = Duration::from_millis(2);
+        let deadline = Instant::now() + dur;
+        let mut buf = [0; 1];
+        let read = thread::scope(|s| {
+            s.spawn(|| {
+                // Spin, since sleeping isn't anywhere near this precise.
+                while Instant::now() + Duration::from_micros(1) < deadline {}
+                client.write_all(b"x").unwrap();
+            });
+            poller.block_on(server.read_timeout(&mut buf, dur))
+        })?;
+        match read {
+            Ok(n) => assert_eq!((n, buf), (1, *b"x")),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+                // The byte is left for the next read rather than lost.
+                poller.block_on(server.read_exact(&mut buf))??;
+                assert_eq!(buf, *b"x");
+            }
+        }
+    }
+    // Nothing was read twice either.
+    client.write_all(b"y")?;
+    let mut buf = [0; 2];
+    let n = poller.block_on(server.read(&mut buf))??;
+    assert_eq!(&buf[..n], b"y");
+    Ok(())
+}
+
+#[test]
+fn read_timeout_times_out_on_a_silent_peer() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, _server) = AsyncTcpStream::pair()?;
+    let mut buf = [0; 16];
+    let start = Instant::now();
+    let e = poller
+        .block_on(client.read_timeout(&mut buf, Duration::from_millis(50)))?
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn write_timeout_times_out_on_a_silent_peer() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, _server) = AsyncTcpStream::pair()?;
+    let data = vec![7; 1 << 16];
+    // The peer never reads, so the socket buffers fill up eventually.
+    let e = loop {
+        match poller.block_on(client.write_timeout(&data, Duration::from_millis(50)))? {
+            Ok(n) => assert!(n > 0),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
+
+#[test]
+fn reuse_port_listeners_share_the_port() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let first = AsyncTcpListener::builder(([127, 0, 0, 1], 0).into())
+        .reuse_port(true)
+        .build()?;
+    let addr = first.local_addr()?;
+    let second = AsyncTcpListener::builder(addr).reuse_port(true).build()?;
+    assert_eq!(second.local_addr()?, addr);
+
+    let clients = (0..64)
+        .map(|_| TcpStream::connect(addr))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut accepted = [0; 2];
+    for (mut listener, accepted) in [first, second].into_iter().zip(&mut accepted) {
+        let wait = Duration::from_millis(100);
+        while let Ok(stream) = poller.block_on(time::timeout(wait, listener.accept()))? {
+            stream?;
+            *accepted += 1;
+        }
+    }
+    assert_eq!(accepted.iter().sum::<usize>(), clients.len());
+    // Linux spreads connections over the listeners by where they come from;
+    // the BSDs hand them all to the listener bound last.
+    if cfg!(target_os = "linux") {
+        assert!(accepted.iter().all(|&n| n > 0), "{accepted:?}");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn backlog_is_passed_to_listen() -> io::Result<()> {
+    let listener = AsyncTcpListener::builder(([127, 0, 0, 1], 0).into())
+        .backlog(7)
+        .build()?;
+    let _client = TcpStream::connect(listener.local_addr()?)?;
+
+    // For a listener, Linux reports the backlog in `tcpi_sacked`, and how
+    // many connections wait in it in `tcpi_unacked`.
+    // SAFETY: `tcp_info` is plain old data, for which zeroes are fine.
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    // SAFETY: `info` is valid for writes of `len` bytes.
+    let rc = unsafe {
+        libc::getsockopt(
+            listener.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            (&mut info as *mut libc::tcp_info).cast(),
+            &mut len,
+        )
+    };
+    assert_eq!(rc, 0, "{}", io::Error::last_os_error());
+    assert_eq!((info.tcpi_sacked, info.tcpi_unacked), (7, 1));
+    Ok(())
+}
+
+#[test]
+fn split_halves_proxy_both_ways() -> io::Result<()> {
+    let sent = message(64 * 1024);
+    let (addr, server) = echo_once(sent.len())?;
+    let mut poller = Poller::open()?;
+    let (client, proxied) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let upstream = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    let talking = thread::spawn({
+        let sent = sent.clone();
+        move || -> io::Result<Vec<u8>> {
+            client.write_all(&sent)?;
+            client.shutdown(Shutdown::Write)?;
+            let mut echoed = Vec::new();
+            client.read_to_end(&mut echoed)?;
+            Ok(echoed)
+        }
+    });
+
+    // Each direction reads from one stream and writes to the other, so both
+    // streams have a read and a write pending at once.
+    let (mut from_client, mut to_client) = proxied.into_split();
+    let (mut from_server, mut to_server) = upstream.into_split();
+    let (up, down) = poller.block_on(join(
+        copy(&mut from_client, &mut to_server),
+        copy(&mut from_server, &mut to_client),
+    ))?;
+    assert_eq!((up?, down?), (sent.len() as u64, sent.len() as u64));
+    server.join().unwrap();
+
+    // Closing the reunited streams ends the client's read, and leaves
+    // nothing registered.
+    let proxied = from_client.reunite(to_client).unwrap();
+    poller.block_on(proxied.disconnect())??;
+    assert!(talking.join().unwrap()? == sent);
+    let upstream = from_server.reunite(to_server).unwrap();
+    poller.block_on(upstream.disconnect())??;
+    assert_eq!(poller.registration_count(), 0);
+
+    // Halves of different streams don't go back together.
+    let (a, b) = AsyncTcpStream::pair()?;
+    let Err(ReuniteError(..)) = a.into_split().0.reunite(b.into_split().1) else {
+        panic!("reunited halves of different streams");
+    };
+    Ok(())
+}
+
+#[test]
+fn borrowed_halves_read_and_write_at_once() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+
+    // More than the socket buffers hold, so writing it all before reading
+    // any of it back would never finish.
+    let sent = message(4 * 1024 * 1024);
+    let mut echoed = vec![0; sent.len()];
+    let (mut reader, mut writer) = client.split();
+    let (read, written) = poller.block_on(join(
+        reader.read_exact(&mut echoed),
+        writer.write_all(&sent),
+    ))?;
+    read?;
+    written?;
+    assert!(echoed == sent);
+
+    poller.block_on(client.disconnect())??;
+    server.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn shutdown_write_still_reads_to_eof() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    let request = b"GET / HTTP/1.0\r\n\r\n";
+    poller.block_on(client.write_all(request))??;
+    poller.block_on(client.shutdown(Shutdown::Write))??;
+
+    // Writes fail from here on, while reads go on until the echo server
+    // sees the EOF and hangs up too.
+    let e = poller.block_on(client.write(b"more"))?.unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    let mut response = Vec::new();
+    poller.block_on(client.read_to_end(&mut response))??;
+    assert_eq!(response, request);
+    server.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn peek_leaves_the_data_to_read() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (client, mut server) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let sending = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        client.write_all(b"hello, world!")
+    });
+
+    // The peek waits for the data, and the read sees all of it again.
+    let mut peeked = [0; 5];
+    let n = poller.block_on(server.peek(&mut peeked))??;
+    assert_eq!((n, &peeked), (5, b"hello"));
+    sending.join().unwrap()?;
+    let mut buf = [0; 13];
+    poller.block_on(server.read_exact(&mut buf))??;
+    assert_eq!(&buf, b"hello, world!");
+    Ok(())
+}
+
+#[test]
+fn readiness_then_try_io() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    let mut buf = [0; 16];
+    let e = client.try_read(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+
+    poller.block_on(client.writable())??;
+    assert_eq!(client.try_write(b"ping")?, 4);
+    // Readiness may be spurious, so a try can still find nothing there.
+    let n = loop {
+        poller.block_on(client.readable())??;
+        match client.try_read(&mut buf) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            read => break read?,
+        }
+    };
+    assert_eq!(&buf[..n], b"ping");
+
+    poller.block_on(client.disconnect())??;
+    server.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn copy_bidirectional_proxies_to_an_echo_server() -> io::Result<()> {
+    let (addr, server) = echo_listener()?;
+    let mut poller = Poller::open()?;
+    let (client, mut proxied) = AsyncTcpStream::pair()?;
+    let mut client = poller.block_on(client.into_std())??;
+    let mut upstream = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    let sent = message(256 * 1024);
+    let talking = thread::spawn({
+        let sent = sent.clone();
+        move || -> io::Result<Vec<u8>> {
+            client.write_all(&sent)?;
+            client.shutdown(Shutdown::Write)?;
+            let mut echoed = Vec::new();
+            client.read_to_end(&mut echoed)?;
+            Ok(echoed)
+        }
+    });
+
+    // The client's EOF reaches the echo server through the proxy, and the
+    // server hanging up then reaches the client.
+    let copied = poller.block_on(copy_bidirectional(&mut proxied, &mut upstream))??;
+    let len = sent.len() as u64;
+    assert_eq!(copied, (len, len));
+    assert!(talking.join().unwrap()? == sent);
+    server.join().unwrap();
+    Ok(())
+}
+
+/// Only compiles for what can outlive the stack frame, as `spawn` needs.
+fn requires_static<T: 'static>(_: &T) {}
+
+#[test]
+fn owned_reads_and_writes_match_the_borrowed_ones() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, mut server) = AsyncTcpStream::pair()?;
+    let sent = message(64);
+    let mut buf = vec![0; 1024];
+    assert_eq!(poller.block_on(client.write(&sent))??, sent.len());
+    let n = poller.block_on(server.read(&mut buf))??;
+    assert_eq!(buf[..n], sent);
+
+    let (client, server) = (Arc::new(client), Arc::new(server));
+    let write = client.clone().write_owned(sent.clone());
+    requires_static(&write);
+    let (written, n) = poller.block_on(write)?;
+    assert_eq!((written, n?), (sent.clone(), sent.len()));
+
+    // Spawned, since nothing borrows from the stack.
+    let read = server.clone().read_owned(vec![0; 1024]);
+    requires_static(&read);
+    let handle = poller.spawn(read);
+    let (buf, n) = poller.block_on(handle)?.unwrap();
+    assert_eq!(buf[..n?], sent);
+    assert_eq!(buf.len(), 1024);
+
+    // Both see the end of the stream the same way.
+    drop(client);
+    let (_, n) = poller.block_on(server.clone().read_owned(vec![0; 8]))?;
+    assert_eq!(n?, 0);
+    let mut server = Arc::into_inner(server).unwrap();
+    assert_eq!(poller.block_on(server.read(&mut [0; 8]))??, 0);
+    Ok(())
+}
+
+#[test]
+fn read_buf_fills_a_vecs_spare_capacity() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, mut server) = AsyncTcpStream::pair()?;
+    let mut received = Vec::with_capacity(64);
+    for part in [&b"hello"[..], b", world"] {
+        poller.block_on(client.write_all(part))??;
+        let start = received.len();
+        let mut buf = ReadBuf::uninit(received.spare_capacity_mut());
+        let n = poller.block_on(server.read_buf(&mut buf))??;
+        // Exactly what was read counts as initialized, not the whole
+        // spare capacity.
+        assert_eq!(n, part.len());
+        assert_eq!(buf.filled(), part);
+        assert_eq!(buf.initialized_len(), n);
+        assert_eq!(buf.remaining(), 64 - start - n);
+        // SAFETY: the read initialized the `n` bytes past the length.
+        unsafe { received.set_len(start + n) };
+    }
+    assert_eq!(received, b"hello, world");
+    assert_eq!(received.capacity(), 64);
+
+    // A buffer which starts out initialized stays so, however little is read.
+    let mut storage = [0xff; 16];
+    let mut buf = ReadBuf::new(&mut storage);
+    poller.block_on(client.write_all(b"abc"))??;
+    poller.block_on(server.read_buf(&mut buf))??;
+    assert_eq!((buf.filled(), buf.initialized_len()), (&b"abc"[..], 16));
+    Ok(())
+}
+
+#[test]
+fn socket_options_read_back_what_was_set() -> io::Result<()> {
+    let (stream, _peer) = AsyncTcpStream::pair()?;
+    for nodelay in [true, false] {
+        stream.set_nodelay(nodelay)?;
+        assert_eq!(stream.nodelay()?, nodelay);
+    }
+    stream.set_ttl(17)?;
+    assert_eq!(stream.ttl()?, 17);
+
+    let config = KeepaliveConfig {
+        idle: Duration::from_secs(30),
+        interval: Duration::from_secs(5),
+        count: 4,
+    };
+    stream.set_keepalive(Some(config))?;
+    assert_eq!(stream.keepalive()?, Some(config));
+    stream.set_keepalive(None)?;
+    assert_eq!(stream.keepalive()?, None);
+    Ok(())
+}
+
+#[test]
+fn aborting_resets_a_blocked_peer() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let stream = AsyncTcpStream::connect(addr)?;
+    for linger in [Some(Duration::from_secs(3)), None] {
+        stream.set_linger(linger)?;
+        assert_eq!(stream.linger()?, linger);
+    }
+
+    // The peer is already waiting in a read when the RST arrives.
+    let (mut peer, _) = listener.accept()?;
+    let reading = thread::spawn(move || peer.read(&mut [0; 8]));
+    thread::sleep(Duration::from_millis(20));
+    stream.abort()?;
+    let e = reading.join().unwrap().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+
+    // Whereas disconnecting ends the stream cleanly.
+    let stream = AsyncTcpStream::connect(addr)?;
+    let (mut peer, _) = listener.accept()?;
+    poller.block_on(stream.disconnect())??;
+    assert_eq!(peer.read(&mut [0; 8])?, 0);
+    Ok(())
+}
+
+#[test]
+fn streams_convert_to_std_and_back_mid_connection() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, mut b) = AsyncTcpStream::pair()?;
+    let fd = a.as_raw_fd();
+    // Reading before the data is there leaves the stream registered.
+    let mut buf = [0; 4];
+    let (n, _) = poller.block_on(join(a.read(&mut buf), b.write(b"ping")))?;
+    assert_eq!(&buf[..n?], b"ping");
+    assert!(poller.is_registered(fd));
+
+    let mut std_a = poller.block_on(a.into_std())??;
+    assert!(!poller.is_registered(fd));
+    // Blocking again, so this waits for the write.
+    poller.block_on(b.write_all(b"pong"))??;
+    std_a.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"pong");
+
+    let mut a = AsyncTcpStream::from_std(std_a)?;
+    poller.block_on(a.write_all(b"back"))??;
+    poller.block_on(b.read_exact(&mut buf))??;
+    assert_eq!(&buf, b"back");
+    poller.block_on(a.disconnect())??;
+    assert_eq!(poller.block_on(b.read(&mut buf))??, 0);
+    Ok(())
+}
+
+#[test]
+fn listeners_convert_to_std_and_back_mid_accepting() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut listener = AsyncTcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let fd = listener.as_raw_fd();
+    let connecting = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        TcpStream::connect(addr)
+    });
+    let Ok((first, _)) = poller.block_on(listener.accept())? else {
+        panic!("accepting the first client failed");
+    };
+    connecting.join().unwrap()?;
+    assert!(poller.is_registered(fd));
+
+    let std_listener = poller.block_on(listener.into_std())??;
+    assert!(!poller.is_registered(fd));
+    let _second = TcpStream::connect(addr)?;
+    std_listener.accept()?;
+
+    let mut listener = AsyncTcpListener::from_std(std_listener)?;
+    let client = TcpStream::connect(addr)?;
+    let Ok((third, from)) = poller.block_on(listener.accept())? else {
+        panic!("accepting the third client failed");
+    };
+    assert_eq!(client.local_addr()?, from);
+    poller.block_on(first.disconnect())??;
+    poller.block_on(third.disconnect())??;
+    Ok(())
+}
+
+/// FNV-1a, to compare large transfers without keeping both sides around.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Send `len` bytes of `file` from `offset` over a fresh pair, returning
+/// how many were sent and everything the peer received.
+fn send_file_over_pair(file: &File, offset: u64, len: u64) -> io::Result<(u64, Vec<u8>)> {
+    let mut poller = Poller::open()?;
+    let (mut sender, receiver) = AsyncTcpStream::pair()?;
+    let mut receiver = poller.block_on(receiver.into_std())??;
+    let receiving = thread::spawn(move || {
+        let mut received = Vec::new();
+        receiver.read_to_end(&mut received).map(|_| received)
+    });
+    let sent = poller.block_on(sender.send_file(file, offset, len))??;
+    poller.block_on(sender.disconnect())??;
+    Ok((sent, receiving.join().unwrap()?))
+}
+
+#[test]
+fn send_file_sends_a_large_file_intact() -> io::Result<()> {
+    let path = env::temp_dir().join(format!("playground-future-{}-send", std::process::id()));
+    let data = message(5 * 1024 * 1024 + 7);
+    std::fs::write(&path, &data)?;
+    let file = File::open(&path)?;
+    std::fs::remove_file(&path)?;
+
+    // Far more than the socket buffers hold, so it has to wait for the
+    // reader, and from an offset.
+    let (sent, received) = send_file_over_pair(&file, 123, data.len() as u64)?;
+    assert_eq!(sent as usize, data.len() - 123);
+    assert_eq!(received.len(), data.len() - 123);
+    assert_eq!(checksum(&received), checksum(&data[123..]));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn send_file_falls_back_to_copying() -> io::Result<()> {
+    // `sendfile` fails with `EINVAL` for this file, while reads work.
+    let file = File::open("/proc/self/cmdline")?;
+    let expected = std::fs::read("/proc/self/cmdline")?;
+    let (sent, received) = send_file_over_pair(&file, 0, u64::MAX)?;
+    assert_eq!(sent as usize, expected.len());
+    assert_eq!(received, expected);
+    Ok(())
+}
+
+/// The device and inode `fd` refers to, or `None` if it isn't open.
+fn identity(fd: i32) -> Option<(libc::dev_t, libc::ino_t)> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: `stat` is large enough for what `fstat` writes into it.
+    match unsafe { libc::fstat(fd, stat.as_mut_ptr()) } {
+        0 => {
+            // SAFETY: `fstat` succeeded, so it filled in `stat`.
+            let stat = unsafe { stat.assume_init() };
+            Some((stat.st_dev, stat.st_ino))
+        }
+        _ => {
+            assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+            None
+        }
+    }
+}
+
+#[test]
+fn disconnect_deregisters_and_closes_the_fd() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, mut b) = AsyncTcpStream::pair()?;
+    let (fd_a, fd_b) = (a.as_raw_fd(), b.as_raw_fd());
+    let mut buf = [0; 4];
+    let (n, _) = poller.block_on(join(a.read(&mut buf), b.write(b"ping")))?;
+    assert_eq!(n?, 4);
+    let (n, _) = poller.block_on(join(b.read(&mut buf), a.write(b"pong")))?;
+    assert_eq!(n?, 4);
+    assert_eq!(poller.registration_count(), 2);
+    let sockets = (identity(fd_a).unwrap(), identity(fd_b).unwrap());
+
+    poller.block_on(a.disconnect())??;
+    poller.block_on(b.disconnect())??;
+    assert_eq!(poller.registration_count(), 0);
+    // Other tests may have opened something under the same number since,
+    // but it won't be the socket.
+    assert_ne!(identity(fd_a), Some(sockets.0));
+    assert_ne!(identity(fd_b), Some(sockets.1));
+    Ok(())
+}
+
+/// How many turns it takes the poller to sleep for 200ms, which should be a
+/// handful unless something left behind keeps waking it up.
+fn idle_turns(poller: &mut Poller) -> io::Result<u64> {
+    let before = poller.metrics().turns;
+    poller.block_on(time::sleep(Duration::from_millis(200)))?;
+    Ok(poller.metrics().turns - before)
+}
+
+/// Read everything from `stream` on another thread, after a pause so writes
+/// to its peer fill up the socket buffers and block.
+fn drain_later(mut stream: AsyncTcpStream) -> thread::JoinHandle<io::Result<usize>> {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        let mut received = Vec::new();
+        Poller::open()?.block_on(stream.read_to_end(&mut received))??;
+        Ok(received.len())
+    })
+}
+
+#[test]
+fn blocked_writes_leave_nothing_registered() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let draining = drain_later(server);
+
+    let data = vec![7; 8 << 20];
+    let mut written = 0;
+    while written < data.len() {
+        written += poller.block_on(client.write(&data[written..]))??;
+    }
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+
+    drop(client);
+    assert_eq!(draining.join().unwrap()?, data.len());
+    Ok(())
+}
+
+#[test]
+fn readiness_leaves_nothing_registered() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut client, mut server) = AsyncTcpStream::pair()?;
+    poller.block_on(client.writable())??;
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+
+    client.try_write(b"x")?;
+    poller.block_on(server.readable())??;
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+    Ok(())
+}
+
+#[test]
+fn blocked_write_alls_and_copies_leave_nothing_registered() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let data = vec![7; 8 << 20];
+
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let draining = drain_later(server);
+    poller.block_on(client.write_all(&data))??;
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+    drop(client);
+    assert_eq!(draining.join().unwrap()?, data.len());
+
+    let (mut client, server) = AsyncTcpStream::pair()?;
+    let draining = drain_later(server);
+    let copied = poller.block_on(copy(&mut &data[..], &mut client))??;
+    assert_eq!(copied, data.len() as u64);
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+    drop(client);
+    assert_eq!(draining.join().unwrap()?, data.len());
+    Ok(())
+}
+
+/// Start a connect to a listener whose backlog is already full, so it has
+/// to wait for the socket to become writable.
+fn pending_connect(listener: &AsyncTcpListener) -> io::Result<(ConnectFuture, ConnectFuture)> {
+    let addr = listener.local_addr()?;
+    let mut queued = AsyncTcpStream::connect_async(addr);
+    assert_eq!(queued.poll(&[]).count(), 0);
+    let mut pending = AsyncTcpStream::connect_async(addr);
+    assert!(matches!(
+        pending.poll(&[]).collect::<Vec<_>>()[..],
+        [Waitable::Fd(_, _)]
+    ));
+    Ok((queued, pending))
+}
+
+#[test]
+fn pending_connects_leave_nothing_registered() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut listener = AsyncTcpListener::builder(([127, 0, 0, 1], 0).into())
+        .backlog(0)
+        .build()?;
+    let (_queued, pending) = pending_connect(&listener)?;
+    // Accepting the queued connection makes room for the pending one, which
+    // gets in once its SYN is retransmitted.
+    let accepting = thread::spawn(move || -> io::Result<()> {
+        thread::sleep(Duration::from_millis(100));
+        let mut poller = Poller::open()?;
+        poller.block_on(listener.accept())??;
+        poller.block_on(listener.accept())??;
+        Ok(())
+    });
+    let Ok(_stream) = poller.block_on(pending)? else {
+        panic!("connecting failed");
+    };
+    accepting.join().unwrap()?;
+    assert_eq!(poller.registration_count(), 0);
+    let turns = idle_turns(&mut poller)?;
+    assert!(turns < 10, "{turns} turns");
+    Ok(())
+}
+
+#[test]
+fn refused_pending_connects_leave_nothing_registered() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let listener = AsyncTcpListener::builder(([127, 0, 0, 1], 0).into())
+        .backlog(0)
+        .build()?;
+    let (_queued, pending) = pending_connect(&listener)?;
+    drop(listener);
+    let Err(e) = poller.block_on(pending)? else {
+        panic!("connected to a closed listener");
+    };
+    assert_eq!(io::Error::from(e).kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! The `poll(2)` thread backend: the only one off macOS, and the one the
+//! `threads-backend` feature picks on macOS too.
+#![cfg(all(
+    unix,
+    feature = "net",
+    any(not(target_os = "macos"), feature = "threads-backend")
+))]
+
+use std::io;
+use std::thread;
+
+use playground_future_2_0::io::{AsyncReadExt, AsyncWriteExt};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::{AsyncTcpListener, AsyncTcpStream};
+
+#[test]
+fn echo_between_two_pollers() -> io::Result<()> {
+    let mut listener = AsyncTcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = thread::spawn(move || -> io::Result<()> {
+        let mut poller = Poller::open()?;
+        let (mut stream, _) = poller.block_on(listener.accept())??;
+        let mut buf = [0; 1024];
+        loop {
+            match poller.block_on(stream.read(&mut buf))?? {
+                0 => break,
+                n => poller.block_on(stream.write_all(&buf[..n]))??,
+            }
+        }
+        Ok(())
+    });
+
+    let mut poller = Poller::open()?;
+    let mut client = poller.block_on(AsyncTcpStream::connect_async(addr))??;
+    // Each chunk is echoed before the next is sent, so it has to fit in the
+    // socket buffers, but it still takes the server several reads.
+    let sent: Vec<u8> = (0..160_000).map(|i| (i % 251) as u8).collect();
+    for chunk in sent.chunks(16_000) {
+        poller.block_on(client.write_all(chunk))??;
+        let mut received = vec![0; chunk.len()];
+        poller.block_on(client.read_exact(&mut received))??;
+        assert_eq!(received, chunk);
+    }
+    poller.block_on(client.disconnect())??;
+    server.join().unwrap()?;
+    assert_eq!(poller.registration_count(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn watching_files_is_unsupported() -> io::Result<()> {
+    use playground_future_2_0::fs::{watch, WatchKinds};
+    use playground_future_2_0::stream::StreamExt;
+
+    let mut poller = Poller::open()?;
+    let mut watcher = watch(&std::env::temp_dir(), WatchKinds::ALL)?;
+    let e = poller.block_on(watcher.next()).unwrap_err();
+    assert_eq!(io::Error::from(e).kind(), io::ErrorKind::Unsupported);
+    Ok(())
+}
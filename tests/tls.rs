@@ -0,0 +1,98 @@
+#![cfg(all(unix, feature = "tls"))]
+
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use playground_future_2_0::prelude::*;
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+use playground_future_2_0::tls::{self, TlsConnector};
+use rustls::pki_types::PrivatePkcs8KeyDer;
+use rustls::{ClientConfig, RootCertStore, ServerConfig, ServerConnection};
+
+/// A server config with a fresh self-signed certificate for `localhost`,
+/// and a client config which trusts only that certificate.
+fn configs() -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+    let certified = rcgen::generate_simple_self_signed(["localhost".to_owned()]).unwrap();
+    let cert = certified.cert.der().clone();
+    let key = PrivatePkcs8KeyDer::from(certified.signing_key.serialize_der());
+    let server = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], key.into())
+        .unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let client = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    (Arc::new(server), Arc::new(client))
+}
+
+/// Accept a TLS session on `stream` on another thread, echoing everything
+/// back until the client says it's done.
+fn echo_server(config: Arc<ServerConfig>, stream: AsyncTcpStream) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut poller = Poller::open()?;
+        let session = ServerConnection::new(config).map_err(io::Error::other)?;
+        let mut tls = poller.block_on(tls::connect(session, stream))??;
+        let mut buf = vec![0; 4096];
+        loop {
+            match poller.block_on(tls.read(&mut buf))?? {
+                0 => break,
+                n => poller.block_on(tls.write_all(&buf[..n]))??,
+            }
+        }
+        tls.send_close_notify();
+        poller.block_on(tls.flush())?
+    })
+}
+
+#[test]
+fn handshake_and_echo_over_loopback() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (server_config, client_config) = configs();
+    let (client, server) = AsyncTcpStream::pair()?;
+    let serving = echo_server(server_config, server);
+
+    let connector = TlsConnector::new(client_config);
+    let mut tls = poller.block_on(connector.connect("localhost", client))??;
+    // Longer than a TLS record, so it takes several.
+    for len in [5, 40_000] {
+        let sent: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        poller.block_on(tls.write_all(&sent))??;
+        poller.block_on(tls.flush())??;
+        let mut received = vec![0; len];
+        poller.block_on(tls.read_exact(&mut received))??;
+        assert_eq!(received, sent);
+    }
+
+    // Both sides say goodbye, so the stream ends cleanly.
+    tls.send_close_notify();
+    poller.block_on(tls.flush())??;
+    assert_eq!(poller.block_on(tls.read(&mut [0; 8]))??, 0);
+    serving.join().unwrap()
+}
+
+#[test]
+fn certificates_have_to_match_the_domain() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (server_config, client_config) = configs();
+    let connector = TlsConnector::new(client_config);
+    let (client, server) = AsyncTcpStream::pair()?;
+    let serving = echo_server(server_config, server);
+
+    let Err(e) = poller.block_on(connector.connect("example.com", client))? else {
+        panic!("the handshake succeeded for the wrong domain");
+    };
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    // The server hears why through an alert.
+    assert!(serving.join().unwrap().is_err());
+
+    let (client, _server) = AsyncTcpStream::pair()?;
+    let Err(e) = poller.block_on(connector.connect("not a name!", client))? else {
+        panic!("the handshake succeeded without a domain");
+    };
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
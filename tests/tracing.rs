@@ -0,0 +1,86 @@
+#![cfg(all(unix, feature = "tracing", feature = "net"))]
+
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::tcp::AsyncTcpStream;
+use playground_future_2_0::time::sleep;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Collects the messages of every event.
+#[derive(Clone, Default)]
+struct Collect(Arc<Mutex<Vec<String>>>);
+
+struct Message(Option<String>);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl Subscriber for Collect {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message(None);
+        event.record(&mut message);
+        self.0.lock().unwrap().extend(message.0);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn echo_roundtrip_is_traced() -> io::Result<()> {
+    let collect = Collect::default();
+    tracing::subscriber::with_default(collect.clone(), || -> io::Result<()> {
+        let mut poller = Poller::open()?;
+        let (client, server) = AsyncTcpStream::pair()?;
+        let read = poller.spawn(Arc::new(server).read_owned(vec![0; 5]));
+        // Let the read register before there's anything to read.
+        poller.block_on(sleep(Duration::from_millis(10)))?;
+        let (_, written) = poller.block_on(Arc::new(client).write_owned(b"hello".to_vec()))?;
+        assert_eq!(written?, 5);
+        let (buf, read) = poller.block_on(read)??;
+        assert_eq!(&buf[..read?], b"hello");
+        Ok(())
+    })?;
+
+    let messages = collect.0.lock().unwrap().clone();
+    // The expected events show up in this order, with others in between.
+    let expected = [
+        "task spawned",
+        "registered",
+        "woke up",
+        "polling",
+        "task completed",
+    ];
+    let mut remaining = messages.iter();
+    for message in expected {
+        assert!(
+            remaining.any(|m| m == message),
+            "{message:?} missing in order from {messages:?}"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,271 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use playground_future_2_0::future::{Future, Waitable};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::stream::StreamExt;
+use playground_future_2_0::time;
+use playground_future_2_0::udp::{AsyncUdpSocket, DatagramTruncated, RecvMeta};
+
+/// A datagram of `len` bytes which differ from one to the next.
+fn datagram(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Run `a` and `b` at once, resolving with both outputs.
+struct Join<A: Future, B: Future> {
+    a: A,
+    b: B,
+    outputs: (Option<A::Output>, Option<B::Output>),
+}
+
+fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a,
+        b,
+        outputs: (None, None),
+    }
+}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waiting = Vec::new();
+        if self.outputs.0.is_none() {
+            waiting.extend(self.a.poll(ready));
+            if waiting.is_empty() {
+                self.outputs.0 = self.a.take();
+            }
+        }
+        if self.outputs.1.is_none() {
+            let len = waiting.len();
+            waiting.extend(self.b.poll(ready));
+            if waiting.len() == len {
+                self.outputs.1 = self.b.take();
+            }
+        }
+        waiting.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match &mut self.outputs {
+            (a @ Some(_), b @ Some(_)) => Some((a.take()?, b.take()?)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn datagrams_bounce_between_two_sockets() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut a = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let mut b = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let (a_addr, b_addr) = (a.local_addr()?, b.local_addr()?);
+    let mut message = datagram(512);
+    let mut buf = [0; 1024];
+    for _ in 0..3 {
+        // The receive starts out waiting, and the send wakes it up.
+        let (received, sent) =
+            poller.block_on(join(b.recv_from(&mut buf), a.send_to(&message, b_addr)))?;
+        assert_eq!(sent?, message.len());
+        let (n, from) = received?;
+        assert_eq!((&buf[..n], from), (&message[..], a_addr));
+
+        // And back, one byte longer each way.
+        message.push(n as u8);
+        let (received, sent) =
+            poller.block_on(join(a.recv_from(&mut buf), b.send_to(&message, a_addr)))?;
+        assert_eq!(sent?, message.len());
+        let (n, from) = received?;
+        assert_eq!((&buf[..n], from), (&message[..], b_addr));
+        message.push(n as u8);
+    }
+    assert_eq!(poller.registration_count(), 2);
+    Ok(())
+}
+
+#[test]
+fn next_packet_len_leaves_the_datagram_queued() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut receiver = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let sender = UdpSocket::bind("127.0.0.1:0")?;
+    let sent = datagram(9000);
+    let target = receiver.local_addr()?;
+    let sending = thread::spawn({
+        let sender = sender.try_clone()?;
+        let sent = sent.clone();
+        move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send_to(&sent, target)
+        }
+    });
+
+    // It waits for the datagram, and asking again finds the same one.
+    let len = poller.block_on(receiver.next_packet_len())??;
+    assert_eq!(sending.join().unwrap()?, sent.len());
+    assert_eq!(len, sent.len());
+    assert_eq!(poller.block_on(receiver.next_packet_len())??, len);
+
+    let mut buf = vec![0; len];
+    let (n, from) = poller.block_on(receiver.recv_from(&mut buf))??;
+    assert_eq!((n, from), (sent.len(), sender.local_addr()?));
+    assert_eq!(buf, sent);
+    Ok(())
+}
+
+#[test]
+fn peek_from_leaves_the_datagram_queued() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut receiver = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let mut sender = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let sent = datagram(9000);
+    poller.block_on(sender.send_to(&sent, receiver.local_addr()?))??;
+
+    // A short buffer only sees the start, and the receive after it the lot.
+    let mut start = [0; 16];
+    let (n, from) = poller.block_on(receiver.peek_from(&mut start))??;
+    assert_eq!((n, from), (start.len(), sender.local_addr()?));
+    assert_eq!(start, sent[..16]);
+
+    let mut buf = vec![0; sent.len()];
+    let (n, _) = poller.block_on(receiver.peek_from(&mut buf))??;
+    assert_eq!(buf[..n], sent);
+    buf.fill(0);
+    let (n, _) = poller.block_on(receiver.recv_from(&mut buf))??;
+    assert_eq!(buf[..n], sent);
+    Ok(())
+}
+
+/// Whether `e` says a datagram didn't fit.
+fn is_truncated(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::InvalidData
+        && e.get_ref().is_some_and(|e| e.is::<DatagramTruncated>())
+}
+
+#[test]
+fn connected_sockets_report_truncated_datagrams() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut a = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let mut b = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    a.connect(b.local_addr()?)?;
+    b.connect(a.local_addr()?)?;
+    // Only the peer gets through to a connected socket.
+    let stranger = UdpSocket::bind("127.0.0.1:0")?;
+    stranger.send_to(b"hi", b.local_addr()?)?;
+
+    let sizes = [10, 100, 300];
+    for len in sizes {
+        poller.block_on(a.send(&datagram(len)))??;
+    }
+    let mut buf = [0; 128];
+    for len in sizes {
+        match poller.block_on(b.recv(&mut buf))? {
+            Ok(n) => assert_eq!(buf[..n], datagram(len)),
+            // What fit is there regardless.
+            Err(e) if len > buf.len() && is_truncated(&e) => assert_eq!(buf, datagram(128)[..]),
+            Err(e) => return Err(e),
+        }
+    }
+
+    // The stream keeps going after a datagram which didn't fit.
+    for len in sizes.into_iter().chain([1]) {
+        poller.block_on(a.send(&datagram(len)))??;
+    }
+    let mut datagrams = b.recv_stream(128);
+    for len in sizes.into_iter().chain([1]) {
+        match poller.block_on(datagrams.next())?.unwrap() {
+            Ok(received) => assert_eq!(received, datagram(len)),
+            Err(e) if len > 128 && is_truncated(&e) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn multicast_loops_back_to_the_sender() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let group = Ipv4Addr::new(239, 255, 42, 99);
+    let mut receiver = AsyncUdpSocket::bind("0.0.0.0:0")?;
+    receiver.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+    let target = SocketAddr::from((group, receiver.local_addr()?.port()));
+
+    let mut sender = AsyncUdpSocket::bind("0.0.0.0:0")?;
+    sender.set_multicast_ttl_v4(1)?;
+    assert_eq!(sender.multicast_ttl_v4()?, 1);
+    for on in [false, true] {
+        sender.set_multicast_loop_v4(on)?;
+        assert_eq!(sender.multicast_loop_v4()?, on);
+    }
+
+    let sent = datagram(64);
+    let mut buf = [0; 128];
+    let (received, n) = poller.block_on(join(
+        receiver.recv_from(&mut buf),
+        sender.send_to(&sent, target),
+    ))?;
+    assert_eq!(n?, sent.len());
+    let (n, from) = received?;
+    assert_eq!(buf[..n], sent);
+    assert_eq!(from.port(), sender.local_addr()?.port());
+
+    // Once it left the group, nothing more arrives.
+    receiver.leave_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+    poller.block_on(sender.send_to(&sent, target))??;
+    let wait = time::timeout(Duration::from_millis(100), receiver.recv_from(&mut buf));
+    assert!(poller.block_on(wait)?.is_err());
+    Ok(())
+}
+
+#[test]
+fn batches_match_one_datagram_at_a_time() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let mut a = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let mut b = AsyncUdpSocket::bind("127.0.0.1:0")?;
+    let (a_addr, b_addr) = (a.local_addr()?, b.local_addr()?);
+    // The last one doesn't fit in the receive buffers.
+    let sent: Vec<Vec<u8>> = (1..=10).map(|i| datagram(i * 29)).collect();
+    let expected: Vec<_> = sent
+        .iter()
+        .map(|d| (d[..d.len().min(256)].to_vec(), a_addr))
+        .collect();
+
+    // Sent as batches, received one by one...
+    let packets: Vec<(&[u8], SocketAddr)> = sent.iter().map(|d| (&d[..], b_addr)).collect();
+    let mut rest = &packets[..];
+    while !rest.is_empty() {
+        let n = poller.block_on(a.send_batch(rest))??;
+        assert!(n > 0);
+        rest = &rest[n..];
+    }
+    let mut one_by_one = Vec::new();
+    let mut buf = [0; 256];
+    for _ in &sent {
+        let (n, from) = poller.block_on(b.recv_from(&mut buf))??;
+        one_by_one.push((buf[..n].to_vec(), from));
+    }
+    assert_eq!(one_by_one, expected);
+
+    // ...and the other way around.
+    for datagram in &sent {
+        poller.block_on(a.send_to(datagram, b_addr))??;
+    }
+    let mut storage = vec![[0; 256]; 4];
+    let mut meta = [RecvMeta::default(); 4];
+    let mut batched = Vec::new();
+    while batched.len() < sent.len() {
+        let mut bufs: Vec<&mut [u8]> = storage.iter_mut().map(|b| &mut b[..]).collect();
+        let n = poller.block_on(b.recv_batch(&mut bufs, &mut meta))??;
+        assert!((1..=4).contains(&n));
+        for (buf, meta) in storage.iter().zip(&meta).take(n) {
+            batched.push((buf[..meta.len.min(256)].to_vec(), meta.addr));
+        }
+    }
+    assert_eq!(batched, one_by_one);
+    Ok(())
+}
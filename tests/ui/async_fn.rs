@@ -0,0 +1,4 @@
+#[playground_future_2_0::test]
+async fn sleeps() {}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use playground_future_2_0::future::IntoFuture;
+use playground_future_2_0::time::sleep;
+use std::time::Duration;
+
+#[playground_future_2_0::test(timeout = 5)]
+fn sleeps() -> impl IntoFuture<Output = ()> {
+    sleep(Duration::from_millis(10))
+}
+
+#[playground_future_2_0::test(timeout_secs = "5")]
+fn sleeps_too() -> impl IntoFuture<Output = ()> {
+    sleep(Duration::from_millis(10))
+}
+
+fn main() {}
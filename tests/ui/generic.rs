@@ -0,0 +1,8 @@
+use playground_future_2_0::future::IntoFuture;
+
+#[playground_future_2_0::test]
+fn runs<F: IntoFuture<Output = ()>>() -> F {
+    unimplemented!()
+}
+
+fn main() {}
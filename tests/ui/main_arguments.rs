@@ -0,0 +1,4 @@
+#[playground_future_2_0::main(threads = 2)]
+fn main() -> playground_future_2_0::time::Sleep {
+    playground_future_2_0::time::sleep(std::time::Duration::from_millis(10))
+}
@@ -0,0 +1,10 @@
+use playground_future_2_0::future::IntoFuture;
+use playground_future_2_0::time::sleep;
+use std::time::Duration;
+
+#[playground_future_2_0::test]
+fn sleeps() -> impl IntoFuture {
+    sleep(Duration::from_millis(10))
+}
+
+fn main() {}
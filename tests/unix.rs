@@ -0,0 +1,193 @@
+#![cfg(all(unix, feature = "net"))]
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env, process};
+
+use playground_future_2_0::future::{Future, Interest, Waitable};
+use playground_future_2_0::io::{AsyncReadExt, AsyncWriteExt};
+use playground_future_2_0::runtime::Poller;
+use playground_future_2_0::unix::{AsyncUnixDatagram, AsyncUnixListener, AsyncUnixStream};
+
+/// A path in the temporary directory, unique to this process and `name`.
+fn socket_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("playground-future-{}-{name}.sock", process::id()))
+}
+
+/// Run `a` and `b` at once, resolving with both outputs.
+struct Join<A: Future, B: Future> {
+    a: A,
+    b: B,
+    outputs: (Option<A::Output>, Option<B::Output>),
+}
+
+fn join<A: Future, B: Future>(a: A, b: B) -> Join<A, B> {
+    Join {
+        a,
+        b,
+        outputs: (None, None),
+    }
+}
+
+impl<A: Future, B: Future> Future for Join<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn poll(&mut self, ready: &[Waitable]) -> impl Iterator<Item = Waitable> {
+        let mut waiting = Vec::new();
+        if self.outputs.0.is_none() {
+            waiting.extend(self.a.poll(ready));
+            if waiting.is_empty() {
+                self.outputs.0 = self.a.take();
+            }
+        }
+        if self.outputs.1.is_none() {
+            let len = waiting.len();
+            waiting.extend(self.b.poll(ready));
+            if waiting.len() == len {
+                self.outputs.1 = self.b.take();
+            }
+        }
+        waiting.into_iter()
+    }
+
+    fn take(&mut self) -> Option<Self::Output> {
+        match &mut self.outputs {
+            (a @ Some(_), b @ Some(_)) => Some((a.take()?, b.take()?)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn echo_over_a_socket_path() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let path = socket_path("echo");
+    let mut listener = AsyncUnixListener::bind(&path)?;
+    let mut client = AsyncUnixStream::connect(&path)?;
+    let (mut server, _) = poller.block_on(listener.accept())??;
+
+    poller.block_on(client.write_all(b"hello"))??;
+    let mut buf = [0; 5];
+    poller.block_on(server.read_exact(&mut buf))??;
+    poller.block_on(server.write_all(&buf))??;
+    buf.fill(0);
+    poller.block_on(client.read_exact(&mut buf))??;
+    assert_eq!(&buf, b"hello");
+
+    drop(listener);
+    assert!(!path.exists());
+    Ok(())
+}
+
+#[test]
+fn dropping_a_replaced_listener_leaves_the_new_socket() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let path = socket_path("replaced");
+    let old = AsyncUnixListener::bind(&path)?;
+    let mut new = AsyncUnixListener::builder(&path)
+        .unlink_on_bind(true)
+        .build()?;
+
+    drop(old);
+    let _client = AsyncUnixStream::connect(&path)?;
+    poller.block_on(new.accept())??;
+    drop(new);
+    assert!(!path.exists());
+    Ok(())
+}
+
+#[test]
+fn datagram_pairs_ping_pong() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, mut b) = AsyncUnixDatagram::pair()?;
+    let mut buf = [0; 16];
+    for _ in 0..3 {
+        let (received, sent) = poller.block_on(join(b.recv(&mut buf), a.send(b"ping")))?;
+        assert_eq!(sent?, 4);
+        assert_eq!(&buf[..received?], b"ping");
+        let (received, sent) = poller.block_on(join(a.recv(&mut buf), b.send(b"pong")))?;
+        assert_eq!(sent?, 4);
+        assert_eq!(&buf[..received?], b"pong");
+    }
+    Ok(())
+}
+
+#[test]
+fn recv_on_an_empty_datagram_socket_waits() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, b) = AsyncUnixDatagram::pair()?;
+    let fd = a.as_raw_fd();
+    let sending = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        b.as_std().send(b"late")
+    });
+
+    let start = Instant::now();
+    let mut buf = [0; 16];
+    let mut recv = a.recv(&mut buf);
+    let waitables: Vec<_> = recv.poll(&[]).collect();
+    assert_eq!(waitables, [Waitable::Fd(fd, Interest::Read)]);
+    assert!(recv.take().is_none());
+    assert_eq!(poller.block_on(recv)??, 4);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(&buf[..4], b"late");
+    assert_eq!(sending.join().unwrap()?, 4);
+    Ok(())
+}
+
+#[test]
+fn a_pipe_passed_over_a_stream_still_writes() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, mut b) = AsyncUnixStream::pair()?;
+    let (mut reader, writer) = io::pipe()?;
+    poller.block_on(a.send_with_fds(b"w", &[writer.as_fd()]))??;
+    // Only the copy which arrives stays open, so EOF below means it closed.
+    drop(writer);
+
+    let mut buf = [0; 4];
+    let (n, mut fds) = poller.block_on(b.recv_with_fds(&mut buf, 4))??;
+    assert_eq!((&buf[..n], fds.len()), (&b"w"[..], 1));
+    let mut received = File::from(fds.remove(0));
+    received.write_all(b"through the passed fd")?;
+    drop(received);
+
+    let mut read = String::new();
+    reader.read_to_string(&mut read)?;
+    assert_eq!(read, "through the passed fd");
+    Ok(())
+}
+
+#[test]
+fn more_fds_than_max_fds_is_an_error() -> io::Result<()> {
+    let mut poller = Poller::open()?;
+    let (mut a, mut b) = AsyncUnixStream::pair()?;
+    let (_reader, writer) = io::pipe()?;
+    // One too many, and far more than fit in the control buffer at all.
+    for count in [2, 64] {
+        let fds = vec![writer.as_fd(); count];
+        poller.block_on(a.send_with_fds(b"x", &fds))??;
+        let mut buf = [0; 4];
+        let Err(e) = poller.block_on(b.recv_with_fds(&mut buf, 1))? else {
+            panic!("{count} fds arrived for a max of one");
+        };
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+    Ok(())
+}
+
+#[test]
+fn peer_cred_is_this_process() -> io::Result<()> {
+    let (a, _b) = AsyncUnixStream::pair()?;
+    let cred = a.peer_cred()?;
+    // SAFETY: neither call can fail.
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    assert_eq!((cred.uid, cred.gid), (uid, gid));
+    if let Some(pid) = cred.pid {
+        assert_eq!(pid as u32, process::id());
+    }
+    Ok(())
+}